@@ -0,0 +1,105 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use vulkan_video::video::{nal_units, NalSplitter};
+
+/// The scalar scanner `nal_units` used before, kept as a baseline.
+mod scalar {
+    fn nth_nal_index(stream: &[u8], nth: usize) -> Option<usize> {
+        let mut count_0 = 0;
+        let mut n = 0;
+
+        for (i, byte) in stream.iter().enumerate() {
+            match byte {
+                0 => count_0 += 1,
+                1 if count_0 >= 2 => {
+                    if n == nth {
+                        return Some(i - 2);
+                    } else {
+                        count_0 = 0;
+                        n += 1;
+                    }
+                }
+                _ => count_0 = 0,
+            }
+        }
+
+        None
+    }
+
+    pub fn nal_units(mut stream: &[u8]) -> impl Iterator<Item = &[u8]> {
+        std::iter::from_fn(move || {
+            let first = nth_nal_index(stream, 0);
+            let next = nth_nal_index(stream, 1);
+
+            match (first, next) {
+                (Some(f), Some(n)) => {
+                    let rval = &stream[f..n];
+                    stream = &stream[n..];
+                    Some(rval)
+                }
+                (Some(f), None) => {
+                    let rval = &stream[f..];
+                    stream = &stream[f + 2..];
+                    Some(rval)
+                }
+                _ => None,
+            }
+        })
+    }
+}
+
+/// A stream of `count` NAL units with `size` bytes of pseudo random payload each, free of start codes.
+fn stream(count: usize, size: usize) -> Vec<u8> {
+    let mut state = 0x2545_F491_u32;
+    let mut stream = Vec::with_capacity(count * (size + 4));
+
+    for _ in 0..count {
+        stream.extend_from_slice(&[0, 0, 0, 1, 0x65]);
+
+        for _ in 1..size {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+
+            // Like emulation prevention, never let a `1` follow two `0`.
+            let byte = match (state >> 24) as u8 {
+                1 if stream.ends_with(&[0, 0]) => 3,
+                x => x,
+            };
+
+            stream.push(byte);
+        }
+    }
+
+    stream
+}
+
+fn split(c: &mut Criterion) {
+    let mut group = c.benchmark_group("nal_units");
+
+    // Roughly a high bitrate 4K I frame, and many small slices.
+    for (count, size) in [(16, 1 << 20), (16 * 1024, 1024)] {
+        let stream = stream(count, size);
+        let id = format!("{}x{}", count, size);
+
+        assert_eq!(nal_units(&stream).count(), count);
+        assert_eq!(scalar::nal_units(&stream).count(), count);
+
+        group.throughput(Throughput::Bytes(stream.len() as u64));
+        group.bench_with_input(BenchmarkId::new("memchr", &id), &stream, |b, x| b.iter(|| nal_units(x).count()));
+        group.bench_with_input(BenchmarkId::new("scalar", &id), &stream, |b, x| {
+            b.iter(|| scalar::nal_units(x).count())
+        });
+        group.bench_with_input(BenchmarkId::new("splitter", &id), &stream, |b, x| {
+            b.iter(|| {
+                let mut splitter = NalSplitter::new();
+                let count = x.chunks(64 * 1024).map(|x| splitter.push(x).len()).sum::<usize>();
+                count + splitter.finish().map_or(0, |_| 1)
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, split);
+criterion_main!(benches);
@@ -17,7 +17,7 @@ pub(crate) struct AllocationShared {
     shared_instance: Arc<InstanceShared>,
     shared_device: Arc<DeviceShared>,
     device_memory: DeviceMemory,
-    // size: u64,
+    size: u64,
     // type_index: MemoryTypeIndex,
 }
 
@@ -31,7 +31,7 @@ impl AllocationShared {
             shared_instance: shared_device.instance(),
             shared_device,
             device_memory,
-            // size,
+            size,
             // type_index,
         })
     }
@@ -55,7 +55,7 @@ impl AllocationShared {
                 shared_instance: shared_device.instance(),
                 shared_device,
                 device_memory,
-                // size,
+                size,
                 // type_index: MemoryTypeIndex(0), // TODO
             })
         }
@@ -73,6 +73,10 @@ impl AllocationShared {
     pub(crate) fn native(&self) -> DeviceMemory {
         self.device_memory
     }
+
+    pub(crate) fn size(&self) -> u64 {
+        self.size
+    }
 }
 
 impl Drop for AllocationShared {
@@ -0,0 +1,49 @@
+//! Prints NAL units, parameter sets, pictures and GOPs of an Annex B H.264 file, no GPU needed.
+//!
+//! Usage: `h264-analyzer [--json] <file.h264>`
+use std::fs::File;
+use std::process::ExitCode;
+use vulkan_video::video::h264::analyze;
+
+const USAGE: &str = "Usage: h264-analyzer [--json] <file.h264>";
+
+fn main() -> ExitCode {
+    let mut json = false;
+    let mut path = None;
+
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--json" => json = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return ExitCode::SUCCESS;
+            }
+            _ if path.is_none() => path = Some(arg),
+            _ => {
+                eprintln!("{}", USAGE);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    let Some(path) = path else {
+        eprintln!("{}", USAGE);
+        return ExitCode::FAILURE;
+    };
+
+    let analysis = File::open(&path).map_err(Into::into).and_then(analyze);
+
+    match analysis {
+        Ok(analysis) if json => print!("{}", analysis.to_json()),
+        Ok(analysis) => print!("{}", analysis),
+        Err(e) => {
+            match e.message() {
+                Some(message) => eprintln!("Cannot read {}: {} ({:?})", path, message, e.variant()),
+                None => eprintln!("Cannot read {}: {:?}", path, e.variant()),
+            }
+            return ExitCode::FAILURE;
+        }
+    }
+
+    ExitCode::SUCCESS
+}
@@ -24,12 +24,23 @@ impl DeviceShared {
         // let (queue_family_index, queue_index) =
         //     unsafe { video_decode_queue(native_instance.clone(), native_physical_device).ok_or_else(|| error::NoVideoDevice)? };
 
-        let device_extensions = [
+        let mut device_extensions = vec![
             c"VK_KHR_video_queue".as_ptr().cast(),
             c"VK_KHR_video_decode_queue".as_ptr().cast(),
             c"VK_KHR_video_decode_h264".as_ptr().cast(),
         ];
 
+        // SAFETY: Should be safe as native instance and physical device are valid.
+        let available_extensions = unsafe { native_instance.enumerate_device_extension_properties(native_physical_device)? };
+
+        // H.265 is optional, devices without it can still decode H.264.
+        if available_extensions
+            .iter()
+            .any(|x| x.extension_name_as_c_str() == Ok(c"VK_KHR_video_decode_h265"))
+        {
+            device_extensions.push(c"VK_KHR_video_decode_h265".as_ptr().cast());
+        }
+
         let mut create_infos = Vec::new();
 
         for family in queue_families {
@@ -1,8 +1,10 @@
 use ash::vk::CStrTooLargeForStaticArray;
 use ash::LoadingError;
+use h264_reader::rbsp::BitReaderError;
 use std::backtrace::Backtrace;
 use std::ffi::NulError;
 use std::fmt::{Display, Formatter};
+use std::io;
 
 #[derive(Debug)]
 pub enum Variant {
@@ -10,12 +12,28 @@ pub enum Variant {
     CStrTooLargeForStaticArray(CStrTooLargeForStaticArray),
     Loading(LoadingError),
     Vulkan(ash::vk::Result),
+    Bitstream(BitReaderError),
+    Io(io::Error),
     NoVideoDevice,
     NoComputePipeline,
     NoCommandBuffer,
     HeapNotFound,
     QueueNotFound,
     ImageAlreadyBound,
+    MalformedNalHeader,
+    InvalidVps,
+    InvalidSps,
+    InvalidPps,
+    InvalidSliceHeader,
+    InvalidSei,
+    InvalidAvcc,
+    NoFreeDpbSlot,
+    MissingReferences,
+    IncompatibleParameters,
+    MissingParameterSet,
+    UnsupportedFeature,
+    InvalidBitstreamRange,
+    UnsuitableAllocation,
 }
 
 pub struct Error {
@@ -33,6 +51,14 @@ impl Error {
             backtrace: Backtrace::capture(),
         }
     }
+
+    pub fn variant(&self) -> &Variant {
+        &self.variant
+    }
+
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
 }
 
 impl std::fmt::Debug for Error {
@@ -105,6 +131,28 @@ impl From<CStrTooLargeForStaticArray> for Error {
     }
 }
 
+impl From<BitReaderError> for Error {
+    #[track_caller]
+    fn from(e: BitReaderError) -> Self {
+        Self {
+            message: None,
+            variant: Variant::Bitstream(e),
+            backtrace: Backtrace::capture(),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    #[track_caller]
+    fn from(e: io::Error) -> Self {
+        Self {
+            message: None,
+            variant: Variant::Io(e),
+            backtrace: Backtrace::capture(),
+        }
+    }
+}
+
 #[macro_export]
 macro_rules! error {
     ($variant:expr, $($args:tt)*) => {
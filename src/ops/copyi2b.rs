@@ -2,7 +2,7 @@ use crate::error::Error;
 use crate::ops::AddToCommandBuffer;
 use crate::queue::CommandBuilder;
 use crate::resources::{Buffer, BufferShared, Image, ImageShared};
-use ash::vk::{BufferImageCopy, ImageAspectFlags, ImageLayout, ImageSubresourceLayers};
+use ash::vk::{BufferImageCopy, Extent2D, Extent3D, Format, ImageAspectFlags, ImageLayout, ImageSubresourceLayers, Offset3D, Rect2D};
 use std::rc::Rc;
 use std::sync::Arc;
 
@@ -11,6 +11,7 @@ pub struct CopyImage2Buffer {
     image: Rc<ImageShared>,
     buffer: Arc<BufferShared>,
     aspect_mask: ImageAspectFlags,
+    region: Option<Rect2D>,
 }
 
 impl CopyImage2Buffer {
@@ -19,8 +20,17 @@ impl CopyImage2Buffer {
             image: image.shared(),
             buffer: buffer.shared(),
             aspect_mask,
+            region: None,
         }
     }
+
+    /// Only copies `region` of the image, tightly packed, e.g., the `display_rect` of a decoded picture.
+    ///
+    /// The region is given in luma samples, for chroma planes of subsampled formats it is scaled down.
+    pub fn region(mut self, region: Rect2D) -> Self {
+        self.region = Some(region);
+        self
+    }
 }
 
 impl AddToCommandBuffer for CopyImage2Buffer {
@@ -31,11 +41,29 @@ impl AddToCommandBuffer for CopyImage2Buffer {
         let native_buffer = self.buffer.native();
 
         let image_info = self.image.info();
+        let image_extent = image_info.get_extent();
+        let (divisor_x, divisor_y) = plane_divisor(image_info.get_format(), self.aspect_mask);
+
+        let region = self.region.unwrap_or_else(|| {
+            Rect2D::default().extent(Extent2D {
+                width: image_extent.width,
+                height: image_extent.height,
+            })
+        });
 
         let srl = ImageSubresourceLayers::default().aspect_mask(self.aspect_mask).layer_count(1);
 
         let copy = BufferImageCopy::default()
-            .image_extent(image_info.get_extent())
+            .image_offset(Offset3D {
+                x: region.offset.x / divisor_x as i32,
+                y: region.offset.y / divisor_y as i32,
+                z: 0,
+            })
+            .image_extent(Extent3D {
+                width: region.extent.width / divisor_x,
+                height: region.extent.height / divisor_y,
+                depth: image_extent.depth,
+            })
             .image_subresource(srl);
 
         unsafe {
@@ -45,6 +73,27 @@ impl AddToCommandBuffer for CopyImage2Buffer {
     }
 }
 
+/// How much smaller than the image the plane of `aspect_mask` is in multi-planar YCbCr formats.
+fn plane_divisor(format: Format, aspect_mask: ImageAspectFlags) -> (u32, u32) {
+    if !aspect_mask.intersects(ImageAspectFlags::PLANE_1 | ImageAspectFlags::PLANE_2) {
+        return (1, 1);
+    }
+
+    match format {
+        Format::G8_B8R8_2PLANE_420_UNORM
+        | Format::G8_B8_R8_3PLANE_420_UNORM
+        | Format::G10X6_B10X6R10X6_2PLANE_420_UNORM_3PACK16
+        | Format::G12X4_B12X4R12X4_2PLANE_420_UNORM_3PACK16
+        | Format::G16_B16R16_2PLANE_420_UNORM => (2, 2),
+        Format::G8_B8R8_2PLANE_422_UNORM
+        | Format::G8_B8_R8_3PLANE_422_UNORM
+        | Format::G10X6_B10X6R10X6_2PLANE_422_UNORM_3PACK16
+        | Format::G12X4_B12X4R12X4_2PLANE_422_UNORM_3PACK16
+        | Format::G16_B16R16_2PLANE_422_UNORM => (2, 1),
+        _ => (1, 1),
+    }
+}
+
 #[cfg(test)]
 mod test {
     use crate::allocation::Allocation;
@@ -98,4 +147,14 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn chroma_planes_are_subsampled() {
+        use super::plane_divisor;
+
+        assert_eq!(plane_divisor(Format::G8_B8R8_2PLANE_420_UNORM, ImageAspectFlags::PLANE_0), (1, 1));
+        assert_eq!(plane_divisor(Format::G8_B8R8_2PLANE_420_UNORM, ImageAspectFlags::PLANE_1), (2, 2));
+        assert_eq!(plane_divisor(Format::G8_B8_R8_3PLANE_422_UNORM, ImageAspectFlags::PLANE_2), (2, 1));
+        assert_eq!(plane_divisor(Format::R8_UNORM, ImageAspectFlags::COLOR), (1, 1));
+    }
 }
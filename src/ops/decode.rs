@@ -0,0 +1,389 @@
+//! What decoding pictures has in common across codecs.
+use crate::error;
+use crate::error::{Error, Variant};
+use crate::queue::CommandBuilder;
+use crate::resources::{Buffer, BufferShared, ImageView, ImageViewShared};
+use crate::video::inspector::Fingerprints;
+use crate::video::{StreamInspector, VideoSession, VideoSessionParameters, VideoSessionParametersShared};
+use ash::vk::{
+    self, AccessFlags2, BufferMemoryBarrier2, DependencyInfoKHR, ExtendsVideoDecodeInfoKHR, Extent2D, ImageAspectFlags, ImageLayout,
+    ImageMemoryBarrier2, ImageSubresourceRange, PipelineStageFlags2, VideoBeginCodingInfoKHR, VideoCodingControlFlagsKHR,
+    VideoCodingControlInfoKHR, VideoDecodeCapabilityFlagsKHR, VideoDecodeH264PictureLayoutFlagsKHR, VideoDecodeInfoKHR,
+    VideoEndCodingInfoKHR, VideoPictureResourceInfoKHR, VideoReferenceSlotInfoKHR, QUEUE_FAMILY_IGNORED,
+};
+use std::ptr::null;
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// Specifies which part of a buffer to decode.
+#[derive(Clone)]
+pub struct DecodeInfo {
+    pub(super) offset: u64,
+    pub(super) size: u64,
+    pub(super) slice_offsets: Vec<u32>,
+    // The parameter sets the picture was parsed with.
+    fingerprints: Fingerprints,
+}
+
+impl DecodeInfo {
+    /// Decodes `size` bytes at `offset`, holding a picture whose slices `stream_inspector` parsed last.
+    ///
+    /// The offset must be a multiple of [`VideoSession::min_bitstream_buffer_offset_alignment`]. The size is
+    /// padded to [`VideoSession::min_bitstream_buffer_size_alignment`] when decoding, so the buffer should hold
+    /// zeros after the picture, as [`DecodeInfo::upload`] does.
+    ///
+    /// Decoding fails with [`Variant::MissingParameterSet`] if the session parameters don't hold the parameter sets
+    /// the picture uses as `stream_inspector` has them, so create this before feeding the next picture's parameter
+    /// sets.
+    pub fn new(offset: u64, size: u64, stream_inspector: &impl StreamInspector) -> Self {
+        DecodeInfo {
+            offset,
+            size,
+            slice_offsets: vec![0],
+            fingerprints: stream_inspector.fingerprints(),
+        }
+    }
+
+    /// Uploads the picture in `data` to `buffer` at `offset`, zero padded as `video_session` requires, see
+    /// [`DecodeInfo::new`].
+    pub fn upload(
+        buffer: &Buffer,
+        video_session: &VideoSession,
+        stream_inspector: &impl StreamInspector,
+        offset: u64,
+        data: &[u8],
+    ) -> Result<Self, Error> {
+        let shared_video_session = video_session.shared();
+        let range = aligned_range(
+            offset,
+            data.len() as u64,
+            shared_video_session.min_bitstream_buffer_offset_alignment(),
+            shared_video_session.min_bitstream_buffer_size_alignment(),
+            buffer.size(),
+        )?;
+
+        buffer.shared().upload_padded(offset, data, range)?;
+
+        Ok(Self::new(offset, range, stream_inspector))
+    }
+
+    /// Where each slice (segment for H.265) starts, relative to `offset`. Defaults to a single slice at 0.
+    pub fn slice_offsets(mut self, slice_offsets: &[u32]) -> Self {
+        self.slice_offsets = slice_offsets.to_vec();
+        self
+    }
+}
+
+/// Returns `size` padded to `size_alignment`, checking that `offset` is aligned and the range fits into the buffer.
+pub(super) fn aligned_range(offset: u64, size: u64, offset_alignment: u64, size_alignment: u64, buffer_size: u64) -> Result<u64, Error> {
+    if !offset.is_multiple_of(offset_alignment.max(1)) {
+        return Err(error!(
+            Variant::InvalidBitstreamRange,
+            "Bitstream offset {} is not a multiple of {}", offset, offset_alignment
+        ));
+    }
+
+    let range = size.next_multiple_of(size_alignment.max(1));
+
+    if offset.checked_add(range).is_none_or(|x| x > buffer_size) {
+        return Err(error!(
+            Variant::InvalidBitstreamRange,
+            "Bitstream of {} bytes padded to {} at offset {} exceeds buffer of {} bytes", size, range, offset, buffer_size
+        ));
+    }
+
+    Ok(range)
+}
+
+/// The codec independent parts of a picture to decode, see [`DecodeResources::record`].
+pub(super) struct DecodePicture<'a> {
+    pub dst_picture_resource: VideoPictureResourceInfoKHR<'a>,
+    /// Must be chained with the codec's DPB slot info.
+    pub setup_reference_slot: VideoReferenceSlotInfoKHR<'a>,
+    /// Must be chained with the codec's DPB slot infos.
+    pub reference_slots: &'a [VideoReferenceSlotInfoKHR<'a>],
+    /// The picture completes a frame whose first field is already in the setup slot.
+    pub second_field: bool,
+    /// Reset the session, and with it all DPB slots, before decoding.
+    pub reset: bool,
+}
+
+/// Bitstream, session and images a picture is decoded with, shared by [`DecodeH264`](super::DecodeH264) and
+/// [`DecodeH265`](super::DecodeH265).
+pub(super) struct DecodeResources {
+    shared_parameters: Arc<VideoSessionParametersShared>,
+    shared_buffer: Arc<BufferShared>,
+    shared_image_view: Rc<ImageViewShared>,
+    shared_dpb_views: Vec<Rc<ImageViewShared>>,
+    decode_info: DecodeInfo,
+}
+
+impl DecodeResources {
+    pub fn new(
+        buffer: &Buffer,
+        video_session_parameters: &VideoSessionParameters,
+        target_view: &ImageView,
+        dpb_views: &[&ImageView],
+        decode_info: &DecodeInfo,
+    ) -> Self {
+        Self {
+            shared_parameters: video_session_parameters.shared(),
+            shared_buffer: buffer.shared(),
+            shared_image_view: target_view.shared(),
+            shared_dpb_views: dpb_views.iter().map(|x| x.shared()).collect(),
+            decode_info: decode_info.clone(),
+        }
+    }
+
+    /// Checks the parameters hold the picture's parameter sets, and there are image views for all its slots.
+    ///
+    /// Only H.265 pictures have a `video_parameter_set_id`.
+    pub fn check(
+        &self,
+        video_parameter_set_id: Option<u8>,
+        seq_parameter_set_id: u8,
+        pic_parameter_set_id: u8,
+        mut slots: impl Iterator<Item = u8>,
+    ) -> Result<(), Error> {
+        // Vulkan looks up parameter sets by the ids in the picture info, which come from the slice header.
+        self.shared_parameters.check_parameter_sets(
+            &self.decode_info.fingerprints,
+            video_parameter_set_id,
+            seq_parameter_set_id,
+            pic_parameter_set_id,
+        )?;
+
+        if slots.any(|x| usize::from(x) >= self.shared_dpb_views.len()) {
+            return Err(error!(
+                Variant::NoFreeDpbSlot,
+                "Picture uses DPB slots beyond the {} given image views",
+                self.shared_dpb_views.len()
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// The size of the target image.
+    pub fn extent(&self) -> Extent2D {
+        let image_extent = self.shared_image_view.image().info().get_extent();
+
+        Extent2D::default().width(image_extent.width).height(image_extent.height)
+    }
+
+    pub fn target_view(&self) -> vk::ImageView {
+        self.shared_image_view.native()
+    }
+
+    pub fn dpb_view(&self, slot_index: u8) -> vk::ImageView {
+        self.shared_dpb_views[usize::from(slot_index)].native()
+    }
+
+    pub fn slice_offsets(&self) -> &[u32] {
+        &self.decode_info.slice_offsets
+    }
+
+    pub fn picture_layout(&self) -> VideoDecodeH264PictureLayoutFlagsKHR {
+        self.shared_parameters.video_session().picture_layout()
+    }
+
+    /// If the picture is decoded right into its DPB slot, i.e., the setup slot uses the target view.
+    pub fn dpb_and_output_coincide(&self) -> bool {
+        self.shared_parameters
+            .video_session()
+            .decode_capabilities()
+            .flags()
+            .contains(VideoDecodeCapabilityFlagsKHR::DPB_AND_OUTPUT_COINCIDE)
+    }
+
+    /// Records decoding `picture`, with `picture_info` being the codec's picture info, and the barriers around it.
+    pub fn record<T: ExtendsVideoDecodeInfoKHR + ?Sized>(
+        &self,
+        builder: &mut CommandBuilder,
+        picture_info: &mut T,
+        picture: &DecodePicture<'_>,
+    ) -> Result<(), Error> {
+        let shared_video_session = self.shared_parameters.video_session();
+
+        let native_buffer = self.shared_buffer.native();
+        let native_device = shared_video_session.device().native();
+        let native_queue_fns = shared_video_session.queue_fns();
+        let native_decode_fns = shared_video_session.decode_fns();
+        let native_command_buffer = builder.native_command_buffer();
+        let native_image_dst = self.shared_image_view.image().native();
+
+        // Decoders may read up to the next size alignment, so that's what we pass and synchronize.
+        let bitstream_range = aligned_range(
+            self.decode_info.offset,
+            self.decode_info.size,
+            shared_video_session.min_bitstream_buffer_offset_alignment(),
+            shared_video_session.min_bitstream_buffer_size_alignment(),
+            self.shared_buffer.size(),
+        )?;
+
+        let setup_slot = picture.setup_reference_slot.slot_index;
+
+        // All references must be bound when coding begins, the setup slot is not active yet and hence has index -1.
+        // A second field referencing its first field is the exception, its slot is already bound as a reference.
+        let setup_is_reference = picture.reference_slots.iter().any(|x| x.slot_index == setup_slot);
+
+        let begin_reference_slots = picture
+            .reference_slots
+            .iter()
+            .map(|x| VideoReferenceSlotInfoKHR { p_next: null(), ..*x })
+            .chain((!setup_is_reference).then_some(VideoReferenceSlotInfoKHR {
+                p_next: null(),
+                slot_index: -1,
+                ..picture.setup_reference_slot
+            }))
+            .collect::<Vec<_>>();
+
+        let begin_coding_info = VideoBeginCodingInfoKHR::default()
+            .video_session(shared_video_session.native())
+            .video_session_parameters(self.shared_parameters.native())
+            .reference_slots(&begin_reference_slots);
+
+        let end_coding_info = VideoEndCodingInfoKHR::default();
+
+        let video_coding_control = VideoCodingControlInfoKHR::default().flags(VideoCodingControlFlagsKHR::RESET);
+
+        let video_decode_info = VideoDecodeInfoKHR::default()
+            .push_next(picture_info)
+            .src_buffer(native_buffer)
+            .src_buffer_offset(self.decode_info.offset)
+            .src_buffer_range(bitstream_range)
+            .dst_picture_resource(picture.dst_picture_resource)
+            .setup_reference_slot(&picture.setup_reference_slot)
+            .reference_slots(picture.reference_slots);
+
+        unsafe {
+            let ssr = ImageSubresourceRange::default()
+                .aspect_mask(ImageAspectFlags::COLOR)
+                .level_count(1)
+                .layer_count(1);
+
+            // Images are kept in `GENERAL` between decodes. The ones we write don't need their old content, unless
+            // they already hold the first field of the picture.
+            let native_image_setup = self.shared_dpb_views[setup_slot as usize].image().native();
+            let write_layout = if picture.second_field {
+                ImageLayout::GENERAL
+            } else {
+                ImageLayout::UNDEFINED
+            };
+            let mut images = vec![(native_image_dst, write_layout)];
+
+            if !self.dpb_and_output_coincide() && native_image_setup != native_image_dst {
+                images.push((native_image_setup, write_layout));
+            }
+
+            for reference in picture.reference_slots {
+                let native_image = self.shared_dpb_views[reference.slot_index as usize].image().native();
+
+                if images.iter().all(|(x, _)| *x != native_image) {
+                    images.push((native_image, ImageLayout::GENERAL));
+                }
+            }
+
+            let image_barriers = images
+                .iter()
+                .map(|(image, old_layout)| {
+                    ImageMemoryBarrier2::default()
+                        .src_stage_mask(PipelineStageFlags2::NONE)
+                        .src_access_mask(AccessFlags2::NONE)
+                        .src_queue_family_index(QUEUE_FAMILY_IGNORED)
+                        .old_layout(*old_layout)
+                        .dst_stage_mask(PipelineStageFlags2::VIDEO_DECODE_KHR)
+                        .dst_access_mask(AccessFlags2::VIDEO_DECODE_READ_KHR | AccessFlags2::VIDEO_DECODE_WRITE_KHR)
+                        .dst_queue_family_index(QUEUE_FAMILY_IGNORED)
+                        .new_layout(ImageLayout::VIDEO_DECODE_DPB_KHR)
+                        .image(*image)
+                        .subresource_range(ssr)
+                })
+                .collect::<Vec<_>>();
+
+            let image_barriers_release = images
+                .iter()
+                .map(|(image, _)| {
+                    ImageMemoryBarrier2::default()
+                        .src_stage_mask(PipelineStageFlags2::VIDEO_DECODE_KHR)
+                        .src_access_mask(AccessFlags2::VIDEO_DECODE_WRITE_KHR)
+                        .src_queue_family_index(QUEUE_FAMILY_IGNORED)
+                        .old_layout(ImageLayout::VIDEO_DECODE_DPB_KHR)
+                        .dst_stage_mask(PipelineStageFlags2::BOTTOM_OF_PIPE)
+                        .dst_access_mask(AccessFlags2::NONE_KHR)
+                        .dst_queue_family_index(QUEUE_FAMILY_IGNORED)
+                        .new_layout(ImageLayout::GENERAL)
+                        .image(*image)
+                        .subresource_range(ssr)
+                })
+                .collect::<Vec<_>>();
+
+            let buffer_barrier = BufferMemoryBarrier2::default()
+                .src_stage_mask(PipelineStageFlags2::HOST)
+                .src_access_mask(AccessFlags2::HOST_WRITE)
+                .src_queue_family_index(QUEUE_FAMILY_IGNORED)
+                .dst_stage_mask(PipelineStageFlags2::VIDEO_DECODE_KHR)
+                .dst_access_mask(AccessFlags2::VIDEO_DECODE_READ_KHR)
+                .dst_queue_family_index(QUEUE_FAMILY_IGNORED)
+                .buffer(native_buffer)
+                .offset(self.decode_info.offset)
+                .size(bitstream_range);
+
+            let buffer_barrier_release = BufferMemoryBarrier2::default()
+                .src_stage_mask(PipelineStageFlags2::VIDEO_DECODE_KHR)
+                .src_access_mask(AccessFlags2::VIDEO_DECODE_READ_KHR)
+                .src_queue_family_index(QUEUE_FAMILY_IGNORED)
+                .dst_stage_mask(PipelineStageFlags2::TOP_OF_PIPE)
+                .dst_access_mask(AccessFlags2::NONE)
+                .dst_queue_family_index(QUEUE_FAMILY_IGNORED)
+                .buffer(native_buffer)
+                .offset(self.decode_info.offset)
+                .size(bitstream_range);
+
+            let buffer_barriers = &[buffer_barrier];
+            let buffer_barriers_release = &[buffer_barrier_release];
+
+            let dependency_info = DependencyInfoKHR::default()
+                .buffer_memory_barriers(buffer_barriers)
+                .image_memory_barriers(&image_barriers);
+
+            let dependency_info_release = DependencyInfoKHR::default()
+                .buffer_memory_barriers(buffer_barriers_release)
+                .image_memory_barriers(&image_barriers_release);
+
+            native_device.cmd_pipeline_barrier2(native_command_buffer, &dependency_info);
+            (native_queue_fns.cmd_begin_video_coding_khr)(native_command_buffer, &begin_coding_info);
+
+            if picture.reset {
+                (native_queue_fns.cmd_control_video_coding_khr)(native_command_buffer, &video_coding_control);
+            }
+
+            (native_decode_fns.cmd_decode_video_khr)(native_command_buffer, &video_decode_info);
+            (native_queue_fns.cmd_end_video_coding_khr)(native_command_buffer, &end_coding_info);
+            native_device.cmd_pipeline_barrier2(native_command_buffer, &dependency_info_release);
+
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::{Error, Variant};
+    use crate::ops::decode::aligned_range;
+
+    #[test]
+    fn aligns_bitstream_range() -> Result<(), Error> {
+        assert_eq!(aligned_range(0, 1000, 256, 256, 4096)?, 1024);
+        assert_eq!(aligned_range(256, 1024, 256, 256, 4096)?, 1024);
+        assert_eq!(aligned_range(3, 5, 0, 0, 8)?, 5);
+
+        let misaligned = aligned_range(100, 1000, 256, 256, 4096).unwrap_err();
+        let too_large = aligned_range(3328, 1000, 256, 256, 4096).unwrap_err();
+
+        assert!(matches!(misaligned.variant(), Variant::InvalidBitstreamRange));
+        assert!(matches!(too_large.variant(), Variant::InvalidBitstreamRange));
+
+        Ok(())
+    }
+}
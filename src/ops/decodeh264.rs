@@ -1,232 +1,129 @@
 use crate::error::Error;
-use crate::ops::AddToCommandBuffer;
+use crate::ops::decode::{DecodePicture, DecodeResources};
+use crate::ops::{AddToCommandBuffer, DecodeInfo};
 use crate::queue::CommandBuilder;
-use crate::resources::{Buffer, BufferShared, ImageView, ImageViewShared};
-use crate::video::{VideoSessionParameters, VideoSessionParametersShared};
-use ash::vk::native::{
-    StdVideoDecodeH264PictureInfo, StdVideoDecodeH264PictureInfoFlags, StdVideoDecodeH264ReferenceInfo,
-    StdVideoDecodeH264ReferenceInfoFlags,
-};
+use crate::resources::{Buffer, ImageView};
+use crate::video::h264::{std_picture_info, std_reference_info, DpbPicture};
+use crate::video::VideoSessionParameters;
 use ash::vk::{
-    AccessFlags2, BufferMemoryBarrier2, DependencyInfoKHR, Extent2D, ImageAspectFlags, ImageLayout, ImageMemoryBarrier2,
-    ImageSubresourceRange, PipelineStageFlags2, VideoBeginCodingInfoKHR, VideoCodingControlFlagsKHR, VideoCodingControlInfoKHR,
-    VideoDecodeCapabilityFlagsKHR, VideoDecodeH264DpbSlotInfoKHR, VideoDecodeH264PictureInfoKHR, VideoDecodeInfoKHR, VideoEndCodingInfoKHR,
-    VideoPictureResourceInfoKHR, VideoReferenceSlotInfoKHR, QUEUE_FAMILY_IGNORED,
+    self, Extent2D, Offset2D, VideoDecodeH264DpbSlotInfoKHR, VideoDecodeH264PictureInfoKHR, VideoDecodeH264PictureLayoutFlagsKHR,
+    VideoPictureResourceInfoKHR, VideoReferenceSlotInfoKHR,
 };
-use std::rc::Rc;
-use std::sync::Arc;
-
-/// Specifies which part of a buffer to decode.
-#[derive(Copy, Clone)]
-pub struct DecodeInfo {
-    offset: u64,
-    size: u64,
-}
-
-impl DecodeInfo {
-    pub fn new(offset: u64, size: u64) -> Self {
-        DecodeInfo { offset, size }
-    }
-}
 
 /// Decode a H.264 video frame.
 pub struct DecodeH264 {
-    shared_parameters: Arc<VideoSessionParametersShared>,
-    shared_buffer: Arc<BufferShared>,
-    shared_image_view: Rc<ImageViewShared>,
-    shared_ref_view: Rc<ImageViewShared>,
-    decode_info: DecodeInfo,
+    resources: DecodeResources,
+    picture: DpbPicture,
 }
 
 impl DecodeH264 {
+    /// Decodes `picture` into `target_view`, with `dpb_views[i]` backing DPB slot `i`.
+    ///
+    /// If the implementation reports `DPB_AND_OUTPUT_COINCIDE`, the picture is decoded directly into
+    /// `target_view`, which should then be `dpb_views[picture.slot_index]`.
     pub fn new(
         buffer: &Buffer,
         video_session_parameters: &VideoSessionParameters,
         target_view: &ImageView,
-        ref_view: &ImageView,
+        dpb_views: &[&ImageView],
+        picture: &DpbPicture,
         decode_info: &DecodeInfo,
     ) -> Self {
         Self {
-            shared_parameters: video_session_parameters.shared(),
-            shared_buffer: buffer.shared(),
-            shared_image_view: target_view.shared(),
-            shared_ref_view: ref_view.shared(),
-            decode_info: *decode_info,
+            resources: DecodeResources::new(buffer, video_session_parameters, target_view, dpb_views, decode_info),
+            picture: picture.clone(),
         }
     }
 }
 
 impl AddToCommandBuffer for DecodeH264 {
     fn run_in(&self, builder: &mut CommandBuilder) -> Result<(), Error> {
-        let shared_video_session = self.shared_parameters.video_session();
-
-        let native_buffer_h264 = self.shared_buffer.native();
-        let native_device = shared_video_session.device().native();
-        let native_queue_fns = shared_video_session.queue_fns();
-        let native_decode_fns = shared_video_session.decode_fns();
-        let native_command_buffer = builder.native_command_buffer();
-        let native_view_dst = self.shared_image_view.native();
-        let native_view_ref = self.shared_ref_view.native();
-        let native_image_dst = self.shared_image_view.image().native();
-        let native_image_ref = self.shared_ref_view.image().native();
-        let native_video_session = shared_video_session.native();
-        let native_video_session_parameters = self.shared_parameters.native();
-
-        let image_info = self.shared_image_view.image().info();
-        let image_extent = image_info.get_extent();
-        let extent = Extent2D::default().width(image_extent.width).height(image_extent.height);
-
-        let picture_resource_dst = VideoPictureResourceInfoKHR::default()
-            .coded_extent(extent)
-            .image_view_binding(native_view_dst);
-
-        let picture_resource_ref = VideoPictureResourceInfoKHR::default()
-            .coded_extent(extent)
-            .image_view_binding(native_view_ref);
-
-        let mut f = StdVideoDecodeH264ReferenceInfoFlags {
-            _bitfield_align_1: [],
-            _bitfield_1: Default::default(),
-            __bindgen_padding_0: Default::default(),
+        let resources = &self.resources;
+        let picture = &self.picture;
+
+        resources.check(
+            None,
+            picture.seq_parameter_set_id,
+            picture.pic_parameter_set_id,
+            picture.references.iter().map(|x| x.slot_index).chain([picture.slot_index]),
+        )?;
+
+        let extent = resources.extent();
+
+        // With separate planes a field only covers its half of the image, the top field the upper one. `field` is
+        // `Some(bottom)` for a single field, `None` for frames and field pairs.
+        let separate_planes = resources.picture_layout() == VideoDecodeH264PictureLayoutFlagsKHR::INTERLACED_SEPARATE_PLANES;
+        let picture_resource = |view: vk::ImageView, field: Option<bool>| match field {
+            Some(bottom) if separate_planes => VideoPictureResourceInfoKHR::default()
+                .coded_offset(Offset2D::default().y(if bottom { (extent.height / 2) as i32 } else { 0 }))
+                .coded_extent(Extent2D::default().width(extent.width).height(extent.height / 2))
+                .image_view_binding(view),
+            _ => VideoPictureResourceInfoKHR::default().coded_extent(extent).image_view_binding(view),
         };
-        f.set_used_for_long_term_reference(1);
 
-        let s = StdVideoDecodeH264ReferenceInfo {
-            flags: f,
-            FrameNum: 0,
-            reserved: 0,
-            PicOrderCnt: [0, 0],
-        };
+        let field = picture.field_pic_flag.then_some(picture.bottom_field_flag);
+        let picture_resource_dst = picture_resource(resources.target_view(), field);
 
-        let mut video_decode_h264_dpb_slot_info = VideoDecodeH264DpbSlotInfoKHR::default().std_reference_info(&s);
+        let picture_resources_references = picture
+            .references
+            .iter()
+            .map(|x| {
+                let field = (x.top_field != x.bottom_field).then_some(x.bottom_field);
+                picture_resource(resources.dpb_view(x.slot_index), field)
+            })
+            .collect::<Vec<_>>();
 
-        let picture_resource_choice = if self
-            .shared_parameters
-            .video_session()
-            .decode_capabilities()
-            .flags()
-            .contains(VideoDecodeCapabilityFlagsKHR::DPB_AND_OUTPUT_COINCIDE)
-        {
-            &picture_resource_dst
+        let picture_resource_setup = if resources.dpb_and_output_coincide() {
+            picture_resource_dst
         } else {
-            &picture_resource_ref
-        };
-
-        let video_reference_slot = VideoReferenceSlotInfoKHR::default()
-            .push_next(&mut video_decode_h264_dpb_slot_info)
-            .slot_index(0)
-            .picture_resource(picture_resource_choice);
-
-        let begin_coding_info = VideoBeginCodingInfoKHR::default()
-            .video_session(native_video_session)
-            .video_session_parameters(native_video_session_parameters);
-
-        let end_coding_info = VideoEndCodingInfoKHR::default();
-
-        let mut stdflags = StdVideoDecodeH264PictureInfoFlags {
-            _bitfield_align_1: Default::default(),
-            _bitfield_1: Default::default(),
-            __bindgen_padding_0: Default::default(),
+            picture_resource(resources.dpb_view(picture.slot_index), field)
         };
 
-        stdflags.set_is_intra(1);
-        stdflags.set_is_reference(1);
-
-        let std = StdVideoDecodeH264PictureInfo {
-            flags: stdflags,
-            seq_parameter_set_id: 0,
-            pic_parameter_set_id: 0,
-            reserved1: 0,
-            reserved2: 0,
-            frame_num: 0,
-            idr_pic_id: 0,
-            PicOrderCnt: [0, 0], // TODO: ???
+        // The current picture, as it will be referenced by later pictures.
+        let std_setup = std_reference_info(&picture.as_reference());
+
+        let mut dpb_slot_info_setup = VideoDecodeH264DpbSlotInfoKHR::default().std_reference_info(&std_setup);
+
+        let setup_reference_slot = VideoReferenceSlotInfoKHR::default()
+            .push_next(&mut dpb_slot_info_setup)
+            .slot_index(i32::from(picture.slot_index))
+            .picture_resource(&picture_resource_setup);
+
+        let std_references = picture.references.iter().map(std_reference_info).collect::<Vec<_>>();
+
+        let mut dpb_slot_infos = std_references
+            .iter()
+            .map(|x| VideoDecodeH264DpbSlotInfoKHR::default().std_reference_info(x))
+            .collect::<Vec<_>>();
+
+        let reference_slots = dpb_slot_infos
+            .iter_mut()
+            .zip(&picture.references)
+            .zip(&picture_resources_references)
+            .map(|((dpb_slot_info, reference), picture_resource)| {
+                VideoReferenceSlotInfoKHR::default()
+                    .push_next(dpb_slot_info)
+                    .slot_index(i32::from(reference.slot_index))
+                    .picture_resource(picture_resource)
+            })
+            .collect::<Vec<_>>();
+
+        let std = std_picture_info(picture);
+
+        let mut video_decode_info_h264 = VideoDecodeH264PictureInfoKHR::default()
+            .std_picture_info(&std)
+            .slice_offsets(resources.slice_offsets());
+
+        let decode_picture = DecodePicture {
+            dst_picture_resource: picture_resource_dst,
+            setup_reference_slot,
+            reference_slots: &reference_slots,
+            second_field: picture.second_field,
+            // Only at IDR pictures or where decoding starts, as resetting the session invalidates all its slots.
+            reset: picture.reset,
         };
 
-        let video_coding_control = VideoCodingControlInfoKHR::default().flags(VideoCodingControlFlagsKHR::RESET);
-        let mut video_decode_info_h264 = VideoDecodeH264PictureInfoKHR::default().std_picture_info(&std).slice_offsets(&[0]);
-
-        let video_decode_info = VideoDecodeInfoKHR::default()
-            .push_next(&mut video_decode_info_h264)
-            .src_buffer(native_buffer_h264)
-            .src_buffer_offset(self.decode_info.offset)
-            .src_buffer_range(self.decode_info.size)
-            // .src_buffer_range(2736)
-            .dst_picture_resource(picture_resource_dst)
-            .setup_reference_slot(&video_reference_slot);
-
-        unsafe {
-            let ssr = ImageSubresourceRange::default()
-                .aspect_mask(ImageAspectFlags::COLOR)
-                .level_count(1)
-                .layer_count(1);
-
-            let image_barrier_dst = ImageMemoryBarrier2::default()
-                .src_stage_mask(PipelineStageFlags2::NONE)
-                .src_access_mask(AccessFlags2::NONE)
-                .src_queue_family_index(QUEUE_FAMILY_IGNORED)
-                .old_layout(ImageLayout::UNDEFINED)
-                .dst_stage_mask(PipelineStageFlags2::VIDEO_DECODE_KHR)
-                .dst_access_mask(AccessFlags2::VIDEO_DECODE_WRITE_KHR)
-                .dst_queue_family_index(QUEUE_FAMILY_IGNORED)
-                .new_layout(ImageLayout::VIDEO_DECODE_DPB_KHR)
-                .image(native_image_dst)
-                .subresource_range(ssr);
-
-            let image_release_dst = ImageMemoryBarrier2::default()
-                .src_stage_mask(PipelineStageFlags2::VIDEO_DECODE_KHR)
-                .src_access_mask(AccessFlags2::VIDEO_DECODE_WRITE_KHR)
-                .src_queue_family_index(QUEUE_FAMILY_IGNORED)
-                .old_layout(ImageLayout::VIDEO_DECODE_DPB_KHR)
-                .dst_stage_mask(PipelineStageFlags2::BOTTOM_OF_PIPE)
-                .dst_access_mask(AccessFlags2::NONE_KHR)
-                .dst_queue_family_index(QUEUE_FAMILY_IGNORED)
-                .new_layout(ImageLayout::GENERAL)
-                .image(native_image_dst)
-                .subresource_range(ssr);
-
-            let buffer_barrier = BufferMemoryBarrier2::default()
-                .src_stage_mask(PipelineStageFlags2::HOST)
-                .src_access_mask(AccessFlags2::HOST_WRITE)
-                .src_queue_family_index(QUEUE_FAMILY_IGNORED)
-                .dst_stage_mask(PipelineStageFlags2::VIDEO_DECODE_KHR)
-                .dst_access_mask(AccessFlags2::VIDEO_DECODE_READ_KHR)
-                .dst_queue_family_index(QUEUE_FAMILY_IGNORED)
-                .buffer(native_buffer_h264)
-                .size(256 * 16);
-
-            let buffer_barrier_release = BufferMemoryBarrier2::default()
-                .src_stage_mask(PipelineStageFlags2::VIDEO_DECODE_KHR)
-                .src_access_mask(AccessFlags2::VIDEO_DECODE_READ_KHR)
-                .src_queue_family_index(QUEUE_FAMILY_IGNORED)
-                .dst_stage_mask(PipelineStageFlags2::TOP_OF_PIPE)
-                .dst_access_mask(AccessFlags2::NONE)
-                .dst_queue_family_index(QUEUE_FAMILY_IGNORED)
-                .buffer(native_buffer_h264)
-                .size(256 * 16);
-
-            let buffer_barriers = &[buffer_barrier];
-            let buffer_barriers_release = &[buffer_barrier_release];
-            let image_barriers = &[image_barrier_dst];
-            let image_barriers_release = &[image_release_dst];
-
-            let dependency_info = DependencyInfoKHR::default()
-                .buffer_memory_barriers(buffer_barriers)
-                .image_memory_barriers(image_barriers);
-
-            let dependency_info_release = DependencyInfoKHR::default()
-                .buffer_memory_barriers(buffer_barriers_release)
-                .image_memory_barriers(image_barriers_release);
-
-            native_device.cmd_pipeline_barrier2(native_command_buffer, &dependency_info);
-            (native_queue_fns.cmd_begin_video_coding_khr)(native_command_buffer, &begin_coding_info);
-            (native_queue_fns.cmd_control_video_coding_khr)(native_command_buffer, &video_coding_control);
-            (native_decode_fns.cmd_decode_video_khr)(native_command_buffer, &video_decode_info);
-            (native_queue_fns.cmd_end_video_coding_khr)(native_command_buffer, &end_coding_info);
-            native_device.cmd_pipeline_barrier2(native_command_buffer, &dependency_info_release);
-
-            Ok(())
-        }
+        resources.record(builder, &mut video_decode_info_h264, &decode_picture)
     }
 }
 
@@ -238,15 +135,14 @@ mod test {
     use crate::error;
     use crate::error::{Error, Variant};
     use crate::instance::{Instance, InstanceInfo};
-    use crate::ops::decodeh264::DecodeInfo;
-    use crate::ops::{AddToCommandBuffer, CopyImage2Buffer, DecodeH264};
+    use crate::ops::{AddToCommandBuffer, CopyImage2Buffer, DecodeH264, DecodeInfo};
     use crate::physicaldevice::PhysicalDevice;
     use crate::queue::Queue;
     use crate::resources::{Buffer, BufferInfo, Image, ImageInfo, ImageView, ImageViewInfo};
-    use crate::video::h264::H264StreamInspector;
-    use crate::video::{VideoSession, VideoSessionParameters};
+    use crate::video::h264::{DpbPicture, H264StreamInspector, ParsedNal, PicOrderCnt};
+    use crate::video::{nal_units, ColorInfo, VideoSession, VideoSessionParameters};
     use ash::vk::{
-        Extent3D, Format, ImageAspectFlags, ImageLayout, ImageTiling, ImageType, ImageUsageFlags, ImageViewType, SampleCountFlags,
+        Extent3D, ImageAspectFlags, ImageLayout, ImageTiling, ImageType, ImageUsageFlags, ImageViewType, Rect2D, SampleCountFlags,
     };
 
     #[test]
@@ -254,13 +150,24 @@ mod test {
     fn decode_h264() -> Result<(), Error> {
         let h264_data = include_bytes!("../../tests/videos/multi_512x512.h264");
 
-        let stream_inspector = H264StreamInspector::new();
+        let mut stream_inspector = H264StreamInspector::new();
+        let mut first_slice = None;
+
+        for nal in nal_units(h264_data) {
+            if let Some(ParsedNal::Slice(slice)) = stream_inspector.feed_nal(nal)? {
+                first_slice.get_or_insert(slice);
+            }
+        }
+
+        let first_slice = first_slice.ok_or_else(|| error!(Variant::InvalidSliceHeader, "Stream has no slices"))?;
+
         let instance_info = InstanceInfo::new().app_name("MyApp")?.app_version(100).validation(true);
         let instance = Instance::new(&instance_info)?;
         let physical_device = PhysicalDevice::new_any(&instance)?;
         let device = Device::new(&physical_device)?;
+        let video_session = VideoSession::new(&device, &stream_inspector)?;
         let image_dst_info = ImageInfo::new()
-            .format(Format::G8_B8R8_2PLANE_420_UNORM)
+            .format(video_session.picture_format())
             .samples(SampleCountFlags::TYPE_1)
             .usage(
                 ImageUsageFlags::TRANSFER_SRC
@@ -285,7 +192,7 @@ mod test {
 
         let image_view_dst_info = ImageViewInfo::new()
             .aspect_mask(ImageAspectFlags::COLOR)
-            .format(Format::G8_B8R8_2PLANE_420_UNORM)
+            .format(video_session.picture_format())
             .image_view_type(ImageViewType::TYPE_2D)
             .layer_count(1)
             .level_count(1);
@@ -304,7 +211,6 @@ mod test {
         let command_buffer = CommandBuffer::new(&device, queue_video_decode)?;
         let command_buffer_copy = CommandBuffer::new(&device, queue_compute)?;
 
-        // TODO: WHY THIS +256 needed for video buffers?
         let memory_host = physical_device
             .heap_infos()
             .any_host_visible()
@@ -314,25 +220,50 @@ mod test {
         //     .any_device_local()
         //     .ok_or_else(|| error!(Variant::HeapNotFound))?;
 
-        let allocation_h264 = Allocation::new(&device, 1024 * 1024 * 4 + 256, memory_host)?;
         let buffer_info_h264 = BufferInfo::new().size(1024 * 1024 * 4);
+        let requirement_h264 = Buffer::video_decode_memory_requirement(&device, &buffer_info_h264, &stream_inspector)?;
+        let allocation_h264 = Allocation::new(&device, requirement_h264.size(), memory_host)?;
         let buffer_h264 = Buffer::new_video_decode(&allocation_h264, &buffer_info_h264, &stream_inspector)?;
 
-        buffer_h264.upload(&h264_data[0..])?;
-
         let allocation_output = Allocation::new(&device, 512 * 512 * 4, memory_host)?;
         let buffer_info_output = BufferInfo::new().size(512 * 512 * 4);
         let buffer_output = Buffer::new(&allocation_output, &buffer_info_output)?;
 
-        let video_session = VideoSession::new(&device, &stream_inspector)?;
         let video_session_parameters = VideoSessionParameters::new(&video_session, &stream_inspector)?;
-        let decode_info = DecodeInfo::new(0, 16 * 256);
+        let decode_info = DecodeInfo::upload(
+            &buffer_h264,
+            &video_session,
+            &stream_inspector,
+            0,
+            &h264_data[..h264_data.len().min(16 * 256)],
+        )?;
+
+        let picture = DpbPicture {
+            slot_index: 0,
+            seq_parameter_set_id: first_slice.seq_parameter_set_id,
+            pic_parameter_set_id: first_slice.pic_parameter_set_id,
+            frame_num: 0,
+            idr_pic_id: 0,
+            pic_order_cnt: PicOrderCnt::default(),
+            idr: true,
+            reset: true,
+            intra: true,
+            reference: true,
+            long_term_frame_idx: None,
+            field_pic_flag: false,
+            bottom_field_flag: false,
+            second_field: false,
+            display_rect: Rect2D::default(),
+            color_info: ColorInfo::default(),
+            references: Vec::new(),
+        };
 
         let decode = DecodeH264::new(
             &buffer_h264,
             &video_session_parameters,
             &image_view_dst,
-            &image_view_ref,
+            &[&image_view_ref],
+            &picture,
             &decode_info,
         );
         let copy = CopyImage2Buffer::new(&image_dst, &buffer_output, ImageAspectFlags::PLANE_0);
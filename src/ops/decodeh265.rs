@@ -0,0 +1,275 @@
+use crate::error::Error;
+use crate::ops::decode::{DecodePicture, DecodeResources};
+use crate::ops::{AddToCommandBuffer, DecodeInfo};
+use crate::queue::CommandBuilder;
+use crate::resources::{Buffer, ImageView};
+use crate::video::h265::{std_picture_info, std_reference_info, DpbPicture};
+use crate::video::VideoSessionParameters;
+use ash::vk::{VideoDecodeH265DpbSlotInfoKHR, VideoDecodeH265PictureInfoKHR, VideoPictureResourceInfoKHR, VideoReferenceSlotInfoKHR};
+
+/// Decode a H.265 video frame.
+///
+/// The slice offsets of [`DecodeInfo`] are the offsets of the picture's slice segments.
+pub struct DecodeH265 {
+    resources: DecodeResources,
+    picture: DpbPicture,
+}
+
+impl DecodeH265 {
+    /// Decodes `picture` into `target_view`, with `dpb_views[i]` backing DPB slot `i`.
+    ///
+    /// If the implementation reports `DPB_AND_OUTPUT_COINCIDE`, the picture is decoded directly into
+    /// `target_view`, which should then be `dpb_views[picture.slot_index]`.
+    pub fn new(
+        buffer: &Buffer,
+        video_session_parameters: &VideoSessionParameters,
+        target_view: &ImageView,
+        dpb_views: &[&ImageView],
+        picture: &DpbPicture,
+        decode_info: &DecodeInfo,
+    ) -> Self {
+        Self {
+            resources: DecodeResources::new(buffer, video_session_parameters, target_view, dpb_views, decode_info),
+            picture: picture.clone(),
+        }
+    }
+}
+
+impl AddToCommandBuffer for DecodeH265 {
+    fn run_in(&self, builder: &mut CommandBuilder) -> Result<(), Error> {
+        let resources = &self.resources;
+        let picture = &self.picture;
+
+        resources.check(
+            Some(picture.video_parameter_set_id),
+            picture.seq_parameter_set_id,
+            picture.pic_parameter_set_id,
+            picture.references.iter().map(|x| x.slot_index).chain([picture.slot_index]),
+        )?;
+
+        let extent = resources.extent();
+        let picture_resource = |slot_index: u8| {
+            VideoPictureResourceInfoKHR::default()
+                .coded_extent(extent)
+                .image_view_binding(resources.dpb_view(slot_index))
+        };
+
+        let picture_resource_dst = VideoPictureResourceInfoKHR::default()
+            .coded_extent(extent)
+            .image_view_binding(resources.target_view());
+
+        let picture_resources_references = picture
+            .references
+            .iter()
+            .map(|x| picture_resource(x.slot_index))
+            .collect::<Vec<_>>();
+
+        let picture_resource_setup = if resources.dpb_and_output_coincide() {
+            picture_resource_dst
+        } else {
+            picture_resource(picture.slot_index)
+        };
+
+        // The current picture, as it will be referenced by later pictures.
+        let std_setup = std_reference_info(&picture.as_reference());
+
+        let mut dpb_slot_info_setup = VideoDecodeH265DpbSlotInfoKHR::default().std_reference_info(&std_setup);
+
+        let setup_reference_slot = VideoReferenceSlotInfoKHR::default()
+            .push_next(&mut dpb_slot_info_setup)
+            .slot_index(i32::from(picture.slot_index))
+            .picture_resource(&picture_resource_setup);
+
+        let std_references = picture.references.iter().map(std_reference_info).collect::<Vec<_>>();
+
+        let mut dpb_slot_infos = std_references
+            .iter()
+            .map(|x| VideoDecodeH265DpbSlotInfoKHR::default().std_reference_info(x))
+            .collect::<Vec<_>>();
+
+        let reference_slots = dpb_slot_infos
+            .iter_mut()
+            .zip(&picture.references)
+            .zip(&picture_resources_references)
+            .map(|((dpb_slot_info, reference), picture_resource)| {
+                VideoReferenceSlotInfoKHR::default()
+                    .push_next(dpb_slot_info)
+                    .slot_index(i32::from(reference.slot_index))
+                    .picture_resource(picture_resource)
+            })
+            .collect::<Vec<_>>();
+
+        let std = std_picture_info(picture);
+
+        let mut video_decode_info_h265 = VideoDecodeH265PictureInfoKHR::default()
+            .std_picture_info(&std)
+            .slice_segment_offsets(resources.slice_offsets());
+
+        let decode_picture = DecodePicture {
+            dst_picture_resource: picture_resource_dst,
+            setup_reference_slot,
+            reference_slots: &reference_slots,
+            second_field: false,
+            // IRAP pictures starting a coded video sequence drop all references, so that's where we reset the session.
+            reset: picture.no_rasl_output_flag,
+        };
+
+        resources.record(builder, &mut video_decode_info_h265, &decode_picture)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::allocation::Allocation;
+    use crate::commandbuffer::CommandBuffer;
+    use crate::device::Device;
+    use crate::error;
+    use crate::error::{Error, Variant};
+    use crate::instance::{Instance, InstanceInfo};
+    use crate::ops::{AddToCommandBuffer, CopyImage2Buffer, DecodeH265, DecodeInfo};
+    use crate::physicaldevice::PhysicalDevice;
+    use crate::queue::Queue;
+    use crate::resources::{Buffer, BufferInfo, Image, ImageInfo, ImageView, ImageViewInfo};
+    use crate::video::h265::testdata::{self, slice_segment_header};
+    use crate::video::h265::{Dpb, H265StreamInspector, NalUnitType, ParsedNal};
+    use crate::video::inspector::private::Inspector;
+    use crate::video::{check_parameter_sets, nal_units, VideoSession, VideoSessionParameters};
+    use ash::vk::{Extent3D, ImageAspectFlags, ImageLayout, ImageTiling, ImageType, ImageUsageFlags, ImageViewType, SampleCountFlags};
+
+    #[test]
+    fn rejects_unknown_parameter_sets() -> Result<(), Error> {
+        let held = testdata::inspector().fingerprints();
+
+        // The picture refers to a PPS the parameters don't have.
+        let mut slice = slice_segment_header(NalUnitType::IDR_W_RADL, 0);
+        slice.slice_pic_parameter_set_id = 1;
+
+        let picture = Dpb::new(2).add_picture(&testdata::sps(), &slice)?;
+        let vps = Some(picture.video_parameter_set_id);
+        let error = check_parameter_sets(&held, &held, vps, picture.seq_parameter_set_id, picture.pic_parameter_set_id).unwrap_err();
+
+        assert!(matches!(error.variant(), Variant::MissingParameterSet));
+        assert!(check_parameter_sets(&held, &held, vps, picture.seq_parameter_set_id, 0).is_ok());
+
+        // Nor is a picture whose VPS was never added.
+        let mut without_vps = held.clone();
+        without_vps.vps.clear();
+
+        let error = check_parameter_sets(&without_vps, &held, vps, picture.seq_parameter_set_id, 0).unwrap_err();
+
+        assert!(matches!(error.variant(), Variant::MissingParameterSet));
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(not(miri))]
+    fn decode_h265() -> Result<(), Error> {
+        // A 64x64 IDR picture of four PCM coded 32x32 blocks with luma 50, 100, 150 and 200 in raster order.
+        let h265_data = include_bytes!("../../tests/videos/pcm_64x64.h265");
+
+        let mut stream_inspector = H265StreamInspector::new();
+        let mut first_slice = None;
+
+        for nal in nal_units(h265_data) {
+            if let Some(ParsedNal::Slice(slice)) = stream_inspector.feed_nal(nal)? {
+                first_slice.get_or_insert((slice, nal));
+            }
+        }
+
+        let (first_slice, slice_data) = first_slice.ok_or_else(|| error!(Variant::InvalidSliceHeader, "Stream has no slices"))?;
+        let sps = stream_inspector
+            .sps(first_slice.slice_seq_parameter_set_id)
+            .ok_or_else(|| error!(Variant::MissingParameterSet))?;
+
+        let instance_info = InstanceInfo::new().app_name("MyApp")?.app_version(100).validation(true);
+        let instance = Instance::new(&instance_info)?;
+        let physical_device = PhysicalDevice::new_any(&instance)?;
+        let device = Device::new(&physical_device)?;
+        let video_session = VideoSession::new(&device, &stream_inspector)?;
+        let image_info = ImageInfo::new()
+            .format(video_session.picture_format())
+            .samples(SampleCountFlags::TYPE_1)
+            .usage(
+                ImageUsageFlags::TRANSFER_SRC
+                    | ImageUsageFlags::TRANSFER_DST
+                    | ImageUsageFlags::VIDEO_DECODE_DST_KHR
+                    | ImageUsageFlags::VIDEO_DECODE_DPB_KHR,
+            )
+            .mip_levels(1)
+            .array_layers(1)
+            .image_type(ImageType::TYPE_2D)
+            .tiling(ImageTiling::OPTIMAL)
+            .layout(ImageLayout::UNDEFINED)
+            .extent(Extent3D::default().width(64).height(64).depth(1));
+
+        let image_dst = Image::new_video_target(&device, &image_info, &stream_inspector)?;
+        let image_ref = Image::new_video_target(&device, &image_info, &stream_inspector)?;
+        let requirement_image = image_dst.memory_requirement();
+        let allocation_image_dst = Allocation::new(&device, requirement_image.size(), requirement_image.any_heap())?;
+        let allocation_image_ref = Allocation::new(&device, requirement_image.size(), requirement_image.any_heap())?;
+        let image_dst = image_dst.bind(&allocation_image_dst)?;
+        let image_ref = image_ref.bind(&allocation_image_ref)?;
+
+        let image_view_info = ImageViewInfo::new()
+            .aspect_mask(ImageAspectFlags::COLOR)
+            .format(video_session.picture_format())
+            .image_view_type(ImageViewType::TYPE_2D)
+            .layer_count(1)
+            .level_count(1);
+        let image_view_dst = ImageView::new(&image_dst, &image_view_info)?;
+        let image_view_ref = ImageView::new(&image_ref, &image_view_info)?;
+
+        let queue_video_decode = physical_device
+            .queue_family_infos()
+            .any_decode()
+            .ok_or_else(|| error!(Variant::QueueNotFound))?;
+        let queue_compute = physical_device
+            .queue_family_infos()
+            .any_compute()
+            .ok_or_else(|| error!(Variant::QueueNotFound))?;
+        let queue = Queue::new(&device, queue_video_decode, 0)?;
+        let queue_copy = Queue::new(&device, queue_compute, 0)?;
+        let command_buffer = CommandBuffer::new(&device, queue_video_decode)?;
+        let command_buffer_copy = CommandBuffer::new(&device, queue_compute)?;
+        let memory_host = physical_device
+            .heap_infos()
+            .any_host_visible()
+            .ok_or_else(|| error!(Variant::HeapNotFound))?;
+
+        let buffer_info_h265 = BufferInfo::new().size(64 * 1024);
+        let requirement_h265 = Buffer::video_decode_memory_requirement(&device, &buffer_info_h265, &stream_inspector)?;
+        let allocation_h265 = Allocation::new(&device, requirement_h265.size(), memory_host)?;
+        let buffer_h265 = Buffer::new_video_decode(&allocation_h265, &buffer_info_h265, &stream_inspector)?;
+
+        let allocation_output = Allocation::new(&device, 64 * 64, memory_host)?;
+        let buffer_output = Buffer::new(&allocation_output, &BufferInfo::new().size(64 * 64))?;
+
+        let video_session_parameters = VideoSessionParameters::new(&video_session, &stream_inspector)?;
+        let decode_info = DecodeInfo::upload(&buffer_h265, &video_session, &stream_inspector, 0, slice_data)?;
+        let picture = Dpb::new(video_session.max_dpb_slots()).add_picture(sps, &first_slice)?;
+
+        let decode = DecodeH265::new(
+            &buffer_h265,
+            &video_session_parameters,
+            &image_view_dst,
+            &[&image_view_ref],
+            &picture,
+            &decode_info,
+        );
+        let copy = CopyImage2Buffer::new(&image_dst, &buffer_output, ImageAspectFlags::PLANE_0);
+
+        queue.build_and_submit(&command_buffer, |x| decode.run_in(x))?;
+        queue_copy.build_and_submit(&command_buffer_copy, |x| copy.run_in(x))?;
+
+        let mut data_out = [0u8; 64 * 64];
+        buffer_output.download_into(&mut data_out)?;
+
+        assert_eq!(
+            [data_out[0], data_out[32], data_out[32 * 64], data_out[64 * 64 - 1]],
+            [50, 100, 150, 200]
+        );
+
+        Ok(())
+    }
+}
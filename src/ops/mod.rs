@@ -5,7 +5,9 @@ use crate::queue::CommandBuilder;
 mod compute;
 mod copyb2b;
 mod copyi2b;
+mod decode;
 mod decodeh264;
+mod decodeh265;
 mod dummy;
 mod fill;
 
@@ -17,6 +19,8 @@ pub trait AddToCommandBuffer {
 pub use compute::Compute;
 pub use copyb2b::CopyBuffer2Buffer;
 pub use copyi2b::CopyImage2Buffer;
-pub use decodeh264::{DecodeH264, DecodeInfo};
+pub use decode::DecodeInfo;
+pub use decodeh264::DecodeH264;
+pub use decodeh265::DecodeH265;
 pub use dummy::Dummy;
 pub use fill::FillBuffer;
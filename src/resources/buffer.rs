@@ -1,7 +1,9 @@
 use crate::allocation::{Allocation, AllocationShared};
-use crate::device::DeviceShared;
-use crate::error::Error;
-use crate::video::h264::H264StreamInspector;
+use crate::device::{Device, DeviceShared};
+use crate::error;
+use crate::error::{Error, Variant};
+use crate::resources::image::MemoryRequirements;
+use crate::video::{device_profiles, StreamInspector};
 use ash::vk;
 use ash::vk::{
     BufferCreateInfo, BufferUsageFlags, DeviceSize, ExternalMemoryBufferCreateInfo, ExternalMemoryHandleTypeFlags, MappedMemoryRange,
@@ -81,10 +83,58 @@ impl BufferShared {
     pub fn new_video_decode(
         shared_allocation: Arc<AllocationShared>,
         buffer_info: &BufferInfo,
-        stream_inspector: &H264StreamInspector,
+        stream_inspector: &impl StreamInspector,
     ) -> Result<Self, Error> {
         let shared_device = shared_allocation.device();
         let native_device = shared_device.native();
+        let device_buffer = Self::create_video_decode(&shared_device, buffer_info, stream_inspector)?;
+        let offset = buffer_info.offset.unwrap_or(0);
+
+        unsafe {
+            let requirements = native_device.get_buffer_memory_requirements(device_buffer);
+
+            if let Err(e) = check_binding(&requirements.into(), shared_allocation.size(), offset) {
+                native_device.destroy_buffer(device_buffer, None);
+                return Err(e);
+            }
+
+            let device_memory = shared_allocation.native();
+
+            native_device.bind_buffer_memory(device_buffer, device_memory, offset)?;
+
+            Ok(Self {
+                shared_device,
+                shared_allocation,
+                device_buffer,
+                buffer_info: buffer_info.clone(),
+            })
+        }
+    }
+
+    /// The memory a video decode buffer for `buffer_info` needs, which can be more than its size.
+    pub fn video_decode_memory_requirement(
+        shared_device: &Arc<DeviceShared>,
+        buffer_info: &BufferInfo,
+        stream_inspector: &impl StreamInspector,
+    ) -> Result<MemoryRequirements, Error> {
+        let native_device = shared_device.native();
+        let device_buffer = Self::create_video_decode(shared_device, buffer_info, stream_inspector)?;
+
+        unsafe {
+            let requirements = native_device.get_buffer_memory_requirements(device_buffer);
+
+            native_device.destroy_buffer(device_buffer, None);
+
+            Ok(requirements.into())
+        }
+    }
+
+    fn create_video_decode(
+        shared_device: &Arc<DeviceShared>,
+        buffer_info: &BufferInfo,
+        stream_inspector: &impl StreamInspector,
+    ) -> Result<vk::Buffer, Error> {
+        let native_device = shared_device.native();
 
         let usage = BufferUsageFlags::STORAGE_BUFFER
             | BufferUsageFlags::TRANSFER_DST
@@ -94,7 +144,7 @@ impl BufferShared {
         // | BufferUsageFlags::VIDEO_ENCODE_DST_KHR
         // | BufferUsageFlags::VIDEO_ENCODE_SRC_KHR;
 
-        let mut profiles = stream_inspector.profiles();
+        let mut profiles = device_profiles(shared_device, stream_inspector)?;
 
         unsafe {
             let profile_infos = &mut profiles.as_mut().get_unchecked_mut().list;
@@ -104,18 +154,7 @@ impl BufferShared {
                 .usage(usage)
                 .push_next(profile_infos);
 
-            let device_buffer = native_device.create_buffer(&buffer_create_info, None)?;
-            let device_memory = shared_allocation.native();
-            let offset = buffer_info.offset.unwrap_or(0);
-
-            native_device.bind_buffer_memory(device_buffer, device_memory, offset)?;
-
-            Ok(Self {
-                shared_device,
-                shared_allocation,
-                device_buffer,
-                buffer_info: buffer_info.clone(),
-            })
+            Ok(native_device.create_buffer(&buffer_create_info, None)?)
         }
     }
 
@@ -170,6 +209,35 @@ impl BufferShared {
         Ok(())
     }
 
+    /// Writes `data` at `offset` into the buffer and zeros after it, up to `offset + size`.
+    pub(crate) fn upload_padded(&self, offset: u64, data: &[u8], size: u64) -> Result<(), Error> {
+        let native_device = self.shared_device.native();
+        let device_memory = self.shared_allocation.native();
+        let memory_offset = self.buffer_info.offset.unwrap_or(0);
+        let padding = size.saturating_sub(data.len() as u64) as usize;
+
+        unsafe {
+            let mapped_pointer = native_device.map_memory(device_memory, memory_offset, WHOLE_SIZE, MemoryMapFlags::empty())?;
+            let target = mapped_pointer.cast::<u8>().add(offset as usize);
+
+            std::ptr::copy_nonoverlapping::<u8>(data.as_ptr(), target, data.len());
+            std::ptr::write_bytes(target.add(data.len()), 0, padding);
+
+            let mapped_range = MappedMemoryRange::default()
+                .size(WHOLE_SIZE)
+                .memory(device_memory)
+                .offset(memory_offset);
+            let mapped_range_slice = &[mapped_range];
+            let rval = native_device.flush_mapped_memory_ranges(mapped_range_slice);
+
+            native_device.unmap_memory(device_memory);
+
+            rval?;
+        }
+
+        Ok(())
+    }
+
     pub fn download_into(&self, target: &mut [u8]) -> Result<(), Error> {
         let native_device = self.shared_device.native();
         let device_memory = self.shared_allocation.native();
@@ -206,6 +274,30 @@ impl BufferShared {
     }
 }
 
+/// Checks a buffer with `requirements` fits into an allocation of `allocation_size` at `offset`.
+fn check_binding(requirements: &MemoryRequirements, allocation_size: u64, offset: u64) -> Result<(), Error> {
+    if !offset.is_multiple_of(requirements.alignment().max(1)) {
+        return Err(error!(
+            Variant::UnsuitableAllocation,
+            "Buffer offset {} is not aligned to {} bytes",
+            offset,
+            requirements.alignment()
+        ));
+    }
+
+    if allocation_size.saturating_sub(offset) < requirements.size() {
+        return Err(error!(
+            Variant::UnsuitableAllocation,
+            "Buffer needs {} bytes, but only {} are left in the allocation after offset {}",
+            requirements.size(),
+            allocation_size.saturating_sub(offset),
+            offset
+        ));
+    }
+
+    Ok(())
+}
+
 impl Drop for BufferShared {
     fn drop(&mut self) {
         let device = self.shared_device.native();
@@ -230,7 +322,7 @@ impl Buffer {
         })
     }
 
-    pub fn new_video_decode(allocation: &Allocation, info: &BufferInfo, stream_inspector: &H264StreamInspector) -> Result<Self, Error> {
+    pub fn new_video_decode(allocation: &Allocation, info: &BufferInfo, stream_inspector: &impl StreamInspector) -> Result<Self, Error> {
         let buffer_shared = BufferShared::new_video_decode(allocation.shared(), info, stream_inspector)?;
 
         Ok(Self {
@@ -238,6 +330,15 @@ impl Buffer {
         })
     }
 
+    /// The memory [`Buffer::new_video_decode`] needs for `info`, size allocations from this.
+    pub fn video_decode_memory_requirement(
+        device: &Device,
+        info: &BufferInfo,
+        stream_inspector: &impl StreamInspector,
+    ) -> Result<MemoryRequirements, Error> {
+        BufferShared::video_decode_memory_requirement(&device.shared(), info, stream_inspector)
+    }
+
     pub fn external(allocation: &Allocation, pointer: *mut c_void, info: &BufferInfo) -> Result<Self, Error> {
         let buffer_shared = BufferShared::external(allocation.shared(), pointer, info)?;
 
@@ -266,6 +367,7 @@ impl Buffer {
 
 #[cfg(test)]
 mod test {
+    use super::check_binding;
     use crate::allocation::Allocation;
     use crate::device::Device;
     use crate::error;
@@ -273,8 +375,9 @@ mod test {
     use crate::instance::{Instance, InstanceInfo};
     use crate::physicaldevice::PhysicalDevice;
     use crate::resources::buffer::BufferInfo;
+    use crate::resources::image::MemoryRequirements;
     use crate::resources::Buffer;
-    use crate::video::h264::H264StreamInspector;
+    use crate::video::h264::testdata;
 
     #[test]
     #[cfg(not(miri))]
@@ -306,15 +409,42 @@ mod test {
             .heap_infos()
             .any_device_local()
             .ok_or_else(|| error!(Variant::HeapNotFound))?;
-        let allocation = Allocation::new(&device, 16 * 1024, device_local)?;
         let buffer_info = BufferInfo::new().size(1024).alignment(0).offset(0);
-        let h264inspector = H264StreamInspector::new();
+        let h264inspector = testdata::inspector();
+        let requirement = Buffer::video_decode_memory_requirement(&device, &buffer_info, &h264inspector)?;
+
+        let too_small = Allocation::new(&device, requirement.size() - 1, device_local)?;
+        let error = Buffer::new_video_decode(&too_small, &buffer_info, &h264inspector)
+            .map(|_| ())
+            .unwrap_err();
+
+        assert!(matches!(error.variant(), Variant::UnsuitableAllocation));
+
+        let allocation = Allocation::new(&device, requirement.size(), device_local)?;
 
         _ = Buffer::new_video_decode(&allocation, &buffer_info, &h264inspector)?;
 
         Ok(())
     }
 
+    #[test]
+    fn checks_binding() {
+        let requirements = MemoryRequirements::from(ash::vk::MemoryRequirements {
+            size: 1024,
+            alignment: 256,
+            memory_type_bits: 1,
+        });
+
+        assert!(check_binding(&requirements, 1024, 0).is_ok());
+        assert!(check_binding(&requirements, 2048, 1024).is_ok());
+
+        let misaligned = check_binding(&requirements, 4096, 100).unwrap_err();
+        let too_small = check_binding(&requirements, 2048, 1280).unwrap_err();
+
+        assert!(matches!(misaligned.variant(), Variant::UnsuitableAllocation));
+        assert!(matches!(too_small.variant(), Variant::UnsuitableAllocation));
+    }
+
     #[test]
     #[cfg(not(miri))]
     fn upload_download() -> Result<(), Error> {
@@ -8,7 +8,7 @@ use ash::vk::{Extent3D, Format, ImageCreateInfo, ImageLayout, ImageTiling, Image
 use crate::device::{Device, DeviceShared};
 use crate::error;
 use crate::error::{Error, Variant};
-use crate::video::h264::H264StreamInspector;
+use crate::video::{device_profiles, StreamInspector};
 
 pub struct MemoryRequirements {
     size: u64,
@@ -16,6 +16,16 @@ pub struct MemoryRequirements {
     memory_type_bits: u32,
 }
 
+impl From<ash::vk::MemoryRequirements> for MemoryRequirements {
+    fn from(requirements: ash::vk::MemoryRequirements) -> Self {
+        Self {
+            size: requirements.size,
+            alignment: requirements.alignment,
+            memory_type_bits: requirements.memory_type_bits,
+        }
+    }
+}
+
 impl MemoryRequirements {
     pub fn size(&self) -> u64 {
         self.size
@@ -94,6 +104,10 @@ impl ImageInfo {
         self.extent
     }
 
+    pub fn get_format(&self) -> Format {
+        self.format
+    }
+
     pub fn layout(mut self, layout: ImageLayout) -> Self {
         self.layout = layout;
         self
@@ -135,11 +149,15 @@ impl ImageShared {
         }
     }
 
-    fn new_video_target(shared_device: Arc<DeviceShared>, info: &ImageInfo, stream_inspector: &H264StreamInspector) -> Result<Self, Error> {
+    fn new_video_target(
+        shared_device: Arc<DeviceShared>,
+        info: &ImageInfo,
+        stream_inspector: &impl StreamInspector,
+    ) -> Result<Self, Error> {
         let native_device = shared_device.native();
 
         unsafe {
-            let mut profiles = stream_inspector.profiles();
+            let mut profiles = device_profiles(&shared_device, stream_inspector)?;
             let profiles_inner = profiles.as_mut().get_unchecked_mut();
 
             let create_image = ImageCreateInfo::default()
@@ -186,15 +204,7 @@ impl ImageShared {
     pub(crate) fn memory_requirement(&self) -> MemoryRequirements {
         let native_device = self.shared_device.native();
 
-        unsafe {
-            let requirements = native_device.get_image_memory_requirements(self.native_image);
-
-            MemoryRequirements {
-                size: requirements.size,
-                alignment: requirements.alignment,
-                memory_type_bits: requirements.memory_type_bits,
-            }
-        }
+        unsafe { native_device.get_image_memory_requirements(self.native_image).into() }
     }
 
     pub(crate) fn native(&self) -> ash::vk::Image {
@@ -234,7 +244,7 @@ impl Image {
         })
     }
 
-    pub fn new_video_target(device: &Device, info: &ImageInfo, stream_inspector: &H264StreamInspector) -> Result<Self, Error> {
+    pub fn new_video_target(device: &Device, info: &ImageInfo, stream_inspector: &impl StreamInspector) -> Result<Self, Error> {
         let shared_device = ImageShared::new_video_target(device.shared(), info, stream_inspector)?;
 
         Ok(Self {
@@ -0,0 +1,105 @@
+//! How decoded samples map to colours, whatever the codec.
+use ash::vk::{ChromaLocation, SamplerYcbcrModelConversion, SamplerYcbcrRange};
+
+/// Colour description of decoded frames, needed to convert them to RGB correctly.
+///
+/// Codes are those of H.273 as carried in the VUI of the SPS, for H.264 and H.265 alike. If the VUI leaves them out
+/// they have the values both codecs mandate, i.e., `2` (unspecified) for primaries, transfer and matrix, and limited
+/// range.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ColorInfo {
+    /// Chromaticity of the source primaries, e.g., `1` for BT.709 or `9` for BT.2020.
+    pub colour_primaries: u8,
+    /// Opto-electronic transfer function, e.g., `1` for BT.709, `16` for PQ or `18` for HLG.
+    pub transfer_characteristics: u8,
+    /// Matrix to derive luma and chroma from RGB, e.g., `1` for BT.709 or `6` for BT.601.
+    pub matrix_coefficients: u8,
+    /// If samples use the full range, e.g., `0..=255` for 8 bit, instead of `16..=235`.
+    pub full_range: bool,
+    /// Location of chroma samples relative to luma samples in frames and top fields, `0..=5` (figure E-1).
+    pub chroma_sample_loc_type_top_field: u8,
+    /// Location of chroma samples relative to luma samples in bottom fields, `0..=5` (figure E-1).
+    pub chroma_sample_loc_type_bottom_field: u8,
+    /// Sample aspect ratio as `(width, height)`, if specified.
+    pub sample_aspect_ratio: Option<(u16, u16)>,
+}
+
+impl Default for ColorInfo {
+    /// What both codecs mandate without VUI: unspecified primaries, transfer and matrix, and limited range.
+    fn default() -> Self {
+        Self {
+            colour_primaries: 2,
+            transfer_characteristics: 2,
+            matrix_coefficients: 2,
+            full_range: false,
+            chroma_sample_loc_type_top_field: 0,
+            chroma_sample_loc_type_bottom_field: 0,
+            sample_aspect_ratio: None,
+        }
+    }
+}
+
+impl ColorInfo {
+    /// The `VkSamplerYcbcrConversion` model for [`ColorInfo::matrix_coefficients`], if Vulkan has one.
+    ///
+    /// Returns `None` for unspecified matrices, where the application has to guess, e.g., BT.709 for HD content.
+    pub fn ycbcr_model(&self) -> Option<SamplerYcbcrModelConversion> {
+        match self.matrix_coefficients {
+            0 => Some(SamplerYcbcrModelConversion::YCBCR_IDENTITY),
+            1 => Some(SamplerYcbcrModelConversion::YCBCR_709),
+            5 | 6 => Some(SamplerYcbcrModelConversion::YCBCR_601),
+            9 => Some(SamplerYcbcrModelConversion::YCBCR_2020),
+            _ => None,
+        }
+    }
+
+    /// The `VkSamplerYcbcrConversion` range for [`ColorInfo::full_range`].
+    pub fn ycbcr_range(&self) -> SamplerYcbcrRange {
+        match self.full_range {
+            true => SamplerYcbcrRange::ITU_FULL,
+            false => SamplerYcbcrRange::ITU_NARROW,
+        }
+    }
+
+    /// The `VkSamplerYcbcrConversion` chroma offsets `(x, y)` for frames, if Vulkan can express the location.
+    ///
+    /// Vulkan has no equivalent for the chroma sample location types `4` and `5`, which sit below the luma samples.
+    pub fn chroma_location(&self) -> Option<(ChromaLocation, ChromaLocation)> {
+        match self.chroma_sample_loc_type_top_field {
+            0 => Some((ChromaLocation::COSITED_EVEN, ChromaLocation::MIDPOINT)),
+            1 => Some((ChromaLocation::MIDPOINT, ChromaLocation::MIDPOINT)),
+            2 => Some((ChromaLocation::COSITED_EVEN, ChromaLocation::COSITED_EVEN)),
+            3 => Some((ChromaLocation::MIDPOINT, ChromaLocation::COSITED_EVEN)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ColorInfo;
+    use ash::vk::{ChromaLocation, SamplerYcbcrModelConversion, SamplerYcbcrRange};
+
+    #[test]
+    fn maps_to_ycbcr_conversion() {
+        let color_info = ColorInfo {
+            matrix_coefficients: 9,
+            full_range: true,
+            chroma_sample_loc_type_top_field: 2,
+            ..ColorInfo::default()
+        };
+
+        assert_eq!(color_info.ycbcr_model(), Some(SamplerYcbcrModelConversion::YCBCR_2020));
+        assert_eq!(color_info.ycbcr_range(), SamplerYcbcrRange::ITU_FULL);
+        assert_eq!(
+            color_info.chroma_location(),
+            Some((ChromaLocation::COSITED_EVEN, ChromaLocation::COSITED_EVEN))
+        );
+
+        let below_luma = ColorInfo {
+            chroma_sample_loc_type_top_field: 4,
+            ..ColorInfo::default()
+        };
+        assert_eq!(below_luma.chroma_location(), None);
+    }
+}
@@ -0,0 +1,252 @@
+//! Grouping of NAL units into access units, i.e., coded pictures (7.4.1.2).
+use crate::error::Error;
+use crate::video::h264::{H264StreamInspector, ParsedNal, SeiMessage, SliceHeader};
+use crate::video::{strip_annexb, START_CODE};
+use h264_reader::nal::UnitType;
+
+/// All slices of a single primary coded picture, laid out for decoding.
+#[derive(Clone, Debug)]
+pub struct AccessUnit {
+    data: Vec<u8>,
+    slice_offsets: Vec<u32>,
+    slices: Vec<SliceHeader>,
+    sei: Vec<SeiMessage>,
+}
+
+impl AccessUnit {
+    /// The slice NAL units of this picture, each prefixed with a 3 byte start code.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Offset of each slice's start code within [`AccessUnit::data`], as expected by `VideoDecodeH264PictureInfoKHR`.
+    pub fn slice_offsets(&self) -> &[u32] {
+        &self.slice_offsets
+    }
+
+    /// The headers of all slices, in the order they appear in [`AccessUnit::data`].
+    pub fn slices(&self) -> &[SliceHeader] {
+        &self.slices
+    }
+
+    /// The header of the first slice, which has everything needed for POC and reference marking.
+    pub fn first_slice(&self) -> &SliceHeader {
+        &self.slices[0]
+    }
+
+    /// All SEI messages sent since the previous picture, e.g., captions and HDR metadata.
+    pub fn sei(&self) -> &[SeiMessage] {
+        &self.sei
+    }
+
+    /// If all slices are I or SI slices.
+    pub fn is_intra(&self) -> bool {
+        self.slices.iter().all(|x| !x.slice_type.is_inter())
+    }
+
+    pub(crate) fn new(nal: &[u8], slice: SliceHeader, sei: Vec<SeiMessage>) -> Self {
+        let mut access_unit = Self {
+            data: Vec::new(),
+            slice_offsets: Vec::new(),
+            slices: Vec::new(),
+            sei,
+        };
+
+        access_unit.push(nal, slice);
+        access_unit
+    }
+
+    fn push(&mut self, nal: &[u8], slice: SliceHeader) {
+        self.slice_offsets.push(self.data.len() as u32);
+        self.data.extend_from_slice(&START_CODE);
+        self.data.extend_from_slice(nal);
+        self.slices.push(slice);
+    }
+}
+
+/// Collects NAL units until a picture is complete.
+///
+/// NAL units are pushed in stream order and forwarded to a [`H264StreamInspector`], so parameter sets
+/// are known by the time slices referring to them arrive. Once the first NAL unit of the next picture
+/// is seen, the previous picture is returned.
+#[derive(Default)]
+pub struct AccessUnitAssembler {
+    current: Option<AccessUnit>,
+    // SEI messages precede the first slice of the picture they belong to.
+    pending_sei: Vec<SeiMessage>,
+}
+
+impl AccessUnitAssembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds a NAL unit, with or without start code, returns the previous picture if `nal` starts a new one.
+    ///
+    /// If `inspector` rejects the NAL unit the error is returned and the current picture is kept as-is.
+    pub fn push(&mut self, inspector: &mut H264StreamInspector, nal: &[u8]) -> Result<Option<AccessUnit>, Error> {
+        let parsed = inspector.feed_nal(nal)?;
+
+        Ok(self.push_parsed(nal, parsed))
+    }
+
+    /// As [`AccessUnitAssembler::push`], for callers that fed `nal` to the inspector themselves.
+    pub(crate) fn push_parsed(&mut self, nal: &[u8], parsed: Option<ParsedNal>) -> Option<AccessUnit> {
+        let payload = strip_annexb(nal);
+
+        let &header = payload.first()?;
+
+        let slice = match parsed {
+            Some(ParsedNal::Slice(slice)) => slice,
+            other => {
+                if let Some(ParsedNal::Sei(messages)) = other {
+                    self.pending_sei.extend(messages);
+                }
+
+                // 7.4.1.2.3: These may only appear before the first slice of a picture.
+                return match UnitType::for_id(header & 0x1F) {
+                    Ok(
+                        UnitType::SEI
+                        | UnitType::SeqParameterSet
+                        | UnitType::PicParameterSet
+                        | UnitType::AccessUnitDelimiter
+                        | UnitType::EndOfSeq
+                        | UnitType::EndOfStream
+                        | UnitType::PrefixNALUnit
+                        | UnitType::SubsetSeqParameterSet
+                        | UnitType::DepthParameterSet
+                        | UnitType::Reserved(17..=18),
+                    ) => self.current.take(),
+                    _ => None,
+                };
+            }
+        };
+
+        // Redundant slices are optional to decode, and Vulkan has no use for them.
+        if slice.redundant_pic_cnt > 0 {
+            return None;
+        }
+
+        match &mut self.current {
+            Some(current) if !is_new_picture(current.first_slice(), &slice) => {
+                current.push(payload, slice);
+                None
+            }
+            _ => {
+                let sei = std::mem::take(&mut self.pending_sei);
+                self.current.replace(AccessUnit::new(payload, slice, sei))
+            }
+        }
+    }
+
+    /// Returns the last picture, e.g., at the end of a stream.
+    pub fn flush(&mut self) -> Option<AccessUnit> {
+        self.pending_sei.clear();
+        self.current.take()
+    }
+}
+
+/// Detects the first slice of a new primary coded picture (7.4.1.2.4).
+fn is_new_picture(previous: &SliceHeader, slice: &SliceHeader) -> bool {
+    slice.first_mb_in_slice == 0
+        || slice.frame_num != previous.frame_num
+        || slice.pic_parameter_set_id != previous.pic_parameter_set_id
+        || slice.field_pic_flag != previous.field_pic_flag
+        || slice.bottom_field_flag != previous.bottom_field_flag
+        || slice.is_reference() != previous.is_reference()
+        || slice.pic_order_cnt_lsb != previous.pic_order_cnt_lsb
+        || slice.delta_pic_order_cnt_bottom != previous.delta_pic_order_cnt_bottom
+        || slice.delta_pic_order_cnt != previous.delta_pic_order_cnt
+        || slice.idr != previous.idr
+        || (slice.idr && slice.idr_pic_id != previous.idr_pic_id)
+}
+
+#[cfg(test)]
+mod test {
+    use super::AccessUnitAssembler;
+    use crate::error::Error;
+    use crate::video::h264::testdata::nal;
+    use crate::video::h264::{H264StreamInspector, SeiMessage};
+
+    /// Baseline, 4 bit `frame_num`, POC type 0 with 4 bit LSB, 2 ref frames, 4x4 MBs; PPS 0 and 1.
+    fn parameter_sets() -> Vec<Vec<u8>> {
+        vec![
+            nal(0x67, "01000010 00000000 00011110 1 1 1 1 010 0 00100 00100 1 1 0 0"),
+            nal(0x68, "1 1 0 0 1 1 1 0 00 1 1 1 1 0 0"),
+            nal(0x68, "010 1 0 0 1 1 1 0 00 1 1 1 1 0 0"),
+        ]
+    }
+
+    /// An I slice of PPS 0 starting at `first_mb`, `idr` with `idr_pic_id` 0 or non-reference.
+    fn i_slice(first_mb: &str, idr: bool, frame_num: &str, lsb: &str) -> Vec<u8> {
+        if idr {
+            nal(0x65, &format!("{} 0001000 1 {} 1 {} 0 0 1 010", first_mb, frame_num, lsb))
+        } else {
+            nal(0x01, &format!("{} 0001000 1 {} {} 1 010", first_mb, frame_num, lsb))
+        }
+    }
+
+    fn assemble(nals: &[Vec<u8>]) -> Result<Vec<super::AccessUnit>, Error> {
+        let mut inspector = H264StreamInspector::new();
+        let mut assembler = AccessUnitAssembler::new();
+        let mut access_units = Vec::new();
+
+        for nal in nals {
+            access_units.extend(assembler.push(&mut inspector, nal)?);
+        }
+
+        access_units.extend(assembler.flush());
+
+        Ok(access_units)
+    }
+
+    #[test]
+    fn groups_slices_into_pictures() -> Result<(), Error> {
+        let mut nals = parameter_sets();
+
+        // IDR with two slices, then a non-reference picture with two slices.
+        nals.push(i_slice("1", true, "0000", "0000"));
+        nals.push(i_slice("00101", true, "0000", "0000"));
+        nals.push(i_slice("1", false, "0001", "0010"));
+        nals.push(i_slice("00101", false, "0001", "0010"));
+
+        let access_units = assemble(&nals)?;
+
+        assert_eq!(access_units.len(), 2);
+        assert_eq!(access_units[0].slices().len(), 2);
+        assert!(access_units[0].first_slice().idr);
+        assert!(access_units[0].is_intra());
+        assert_eq!(access_units[0].slice_offsets()[0], 0);
+        assert_eq!(access_units[0].slice_offsets()[1] as usize, nals[3].len() - 1);
+        assert_eq!(&access_units[0].data()[..4], &[0, 0, 1, 0x65]);
+        assert_eq!(access_units[1].first_slice().pic_order_cnt_lsb, 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn splits_on_non_vcl_and_header_changes() -> Result<(), Error> {
+        let mut nals = parameter_sets();
+
+        nals.push(i_slice("1", true, "0000", "0000"));
+        // Access unit delimiter, then a recovery point SEI for the next picture.
+        nals.push(nal(0x09, "000"));
+        nals.push(nal(0x06, "00000110 00000001 1 1 0 00 100"));
+        nals.push(i_slice("00101", false, "0001", "0010"));
+        // Same picture parameters but a different PPS.
+        nals.push(nal(0x01, "00101 0001000 010 0001 0010 1 010"));
+        // New POC, even though the slice does not start at MB 0.
+        nals.push(i_slice("00101", false, "0001", "0100"));
+
+        let access_units = assemble(&nals)?;
+        let slice_counts = access_units.iter().map(|x| x.slices().len()).collect::<Vec<_>>();
+
+        assert_eq!(slice_counts, vec![1, 1, 1, 1]);
+        assert!(access_units[0].sei().is_empty());
+        assert!(matches!(access_units[1].sei(), [SeiMessage::RecoveryPoint(_)]));
+        assert!(access_units[2].sei().is_empty());
+        assert_eq!(access_units[2].first_slice().pic_parameter_set_id, 1);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,652 @@
+//! Reports what is in a H.264 stream without decoding it, e.g., to debug streams that fail to decode.
+use crate::error::Error;
+use crate::video::h264::crop::display_rect;
+use crate::video::h264::{
+    AccessUnit, AccessUnitAssembler, DecRefPicMarking, H264StreamInspector, MemoryManagementControlOperation, ParsedNal, PicOrderCnt,
+    PocCalculator, SeiMessage, SliceType,
+};
+use crate::video::{strip_annexb, NalReader};
+use h264_reader::nal::pps::PicParameterSet;
+use h264_reader::nal::sps::{ChromaFormat, FrameMbsFlags, PicOrderCntType, SeqParameterSet};
+use h264_reader::nal::UnitType;
+use std::fmt::{Display, Formatter, Write};
+use std::io::Read;
+
+/// A single NAL unit, in stream order.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NalSummary {
+    pub nal_unit_type: u8,
+    pub nal_ref_idc: u8,
+    /// Size in bytes, without start code.
+    pub size: usize,
+    /// Why the NAL unit could not be parsed, if it could not.
+    pub error: Option<String>,
+}
+
+/// The parts of a SPS that matter when debugging a stream.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpsSummary {
+    pub id: u8,
+    pub profile_idc: u8,
+    pub level_idc: u8,
+    pub chroma_format_idc: u8,
+    pub bit_depth_luma: u8,
+    pub bit_depth_chroma: u8,
+    /// Size of the decoded images, in whole macroblocks.
+    pub coded_width: u32,
+    pub coded_height: u32,
+    /// Size after cropping.
+    pub display_width: u32,
+    pub display_height: u32,
+    pub frame_mbs_only: bool,
+    pub pic_order_cnt_type: u8,
+    pub max_num_ref_frames: u32,
+    pub max_frame_num: u32,
+}
+
+/// The parts of a PPS that matter when debugging a stream.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PpsSummary {
+    pub id: u8,
+    pub sps_id: u8,
+    /// If CABAC is used instead of CAVLC.
+    pub cabac: bool,
+    pub num_ref_idx_l0_default_active: u32,
+    pub num_ref_idx_l1_default_active: u32,
+    pub weighted_pred: bool,
+    pub weighted_bipred_idc: u8,
+    pub transform_8x8_mode: bool,
+}
+
+/// If a picture is a frame or a single field.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PictureStructure {
+    Frame,
+    TopField,
+    BottomField,
+}
+
+/// A single picture, in decoding order.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PictureSummary {
+    pub sps_id: u8,
+    pub pps_id: u8,
+    /// The type of each slice, in the order they appear.
+    pub slice_types: Vec<SliceType>,
+    pub frame_num: u16,
+    pub idr: bool,
+    /// If other pictures may reference this one, i.e., `nal_ref_idc` is not 0.
+    pub reference: bool,
+    pub structure: PictureStructure,
+    pub pic_order_cnt: PicOrderCnt,
+    /// How references are marked after decoding, only present for reference pictures.
+    pub marking: Option<DecRefPicMarking>,
+    /// If a recovery point SEI precedes this picture.
+    pub recovery_point: bool,
+    /// Size of all slices in bytes, including start codes.
+    pub size: usize,
+}
+
+impl PictureSummary {
+    /// The slice type of the whole picture, i.e., `B` if any slice is a B slice, `P` if any is a P slice, else `I`.
+    pub fn picture_type(&self) -> SliceType {
+        if self.slice_types.contains(&SliceType::B) {
+            SliceType::B
+        } else if self.slice_types.iter().any(|x| x.is_inter()) {
+            SliceType::P
+        } else {
+            SliceType::I
+        }
+    }
+}
+
+/// A group of pictures, starting at an IDR picture or recovery point.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GopSummary {
+    /// Index of the first picture in [`StreamAnalysis::pictures`].
+    pub first_picture: usize,
+    pub pictures: usize,
+    /// If the group starts with an IDR picture, so no picture references a previous group.
+    pub closed: bool,
+    /// The [`PictureSummary::picture_type`] of all pictures in decoding order, e.g., `IPBB`.
+    pub pattern: String,
+}
+
+/// Everything [`StreamAnalyzer`] found in a stream.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StreamAnalysis {
+    pub nals: Vec<NalSummary>,
+    pub sps: Vec<SpsSummary>,
+    pub pps: Vec<PpsSummary>,
+    pub pictures: Vec<PictureSummary>,
+    pub gops: Vec<GopSummary>,
+}
+
+/// Collects a [`StreamAnalysis`] from NAL units, needs no GPU.
+///
+/// Unlike decoding, malformed NAL units do not stop the analysis, they are recorded in [`NalSummary::error`].
+#[derive(Default)]
+pub struct StreamAnalyzer {
+    inspector: H264StreamInspector,
+    assembler: AccessUnitAssembler,
+    poc: PocCalculator,
+    analysis: StreamAnalysis,
+}
+
+impl StreamAnalyzer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds a NAL unit, with or without start code.
+    pub fn push(&mut self, nal: &[u8]) {
+        let payload = strip_annexb(nal);
+
+        let Some(&header) = payload.first() else {
+            return;
+        };
+
+        let mut summary = NalSummary {
+            nal_unit_type: header & 0x1F,
+            nal_ref_idc: (header >> 5) & 0x3,
+            size: payload.len(),
+            error: None,
+        };
+
+        match self.inspector.feed_nal(nal) {
+            Ok(parsed) => {
+                match &parsed {
+                    Some(ParsedNal::Sps(sps)) => self.analysis.sps.push(SpsSummary::new(sps)),
+                    Some(ParsedNal::Pps(pps)) => self.analysis.pps.push(PpsSummary::new(pps)),
+                    _ => {}
+                }
+
+                if let Some(access_unit) = self.assembler.push_parsed(nal, parsed) {
+                    self.add_picture(&access_unit);
+                }
+            }
+            Err(e) => summary.error = Some(describe(&e)),
+        }
+
+        self.analysis.nals.push(summary);
+    }
+
+    /// Finishes the last picture and groups all pictures.
+    pub fn finish(mut self) -> StreamAnalysis {
+        if let Some(access_unit) = self.assembler.flush() {
+            self.add_picture(&access_unit);
+        }
+
+        self.analysis.gops = group_pictures(&self.analysis.pictures);
+        self.analysis
+    }
+
+    fn add_picture(&mut self, access_unit: &AccessUnit) {
+        let slice = access_unit.first_slice();
+
+        let pic_order_cnt = self
+            .inspector
+            .sps(slice.seq_parameter_set_id)
+            .map(|sps| self.poc.compute(sps, slice))
+            .unwrap_or_default();
+
+        let structure = match (slice.field_pic_flag, slice.bottom_field_flag) {
+            (false, _) => PictureStructure::Frame,
+            (true, false) => PictureStructure::TopField,
+            (true, true) => PictureStructure::BottomField,
+        };
+
+        self.analysis.pictures.push(PictureSummary {
+            sps_id: slice.seq_parameter_set_id,
+            pps_id: slice.pic_parameter_set_id,
+            slice_types: access_unit.slices().iter().map(|x| x.slice_type).collect(),
+            frame_num: slice.frame_num,
+            idr: slice.idr,
+            reference: slice.is_reference(),
+            structure,
+            pic_order_cnt,
+            marking: slice.dec_ref_pic_marking.clone(),
+            recovery_point: access_unit.sei().iter().any(|x| matches!(x, SeiMessage::RecoveryPoint(_))),
+            size: access_unit.data().len(),
+        });
+    }
+}
+
+/// Analyzes an Annex B stream, e.g., a `.h264` file.
+///
+/// Only fails if `reader` does, problems with the stream itself are part of the [`StreamAnalysis`].
+pub fn analyze<R: Read>(reader: R) -> Result<StreamAnalysis, Error> {
+    let mut analyzer = StreamAnalyzer::new();
+
+    for nal in NalReader::new(reader) {
+        analyzer.push(&nal?);
+    }
+
+    Ok(analyzer.finish())
+}
+
+impl SpsSummary {
+    fn new(sps: &SeqParameterSet) -> Self {
+        let frame_mbs_factor = match sps.frame_mbs_flags {
+            FrameMbsFlags::Frames => 1,
+            FrameMbsFlags::Fields { .. } => 2,
+        };
+
+        let pic_order_cnt_type = match sps.pic_order_cnt {
+            PicOrderCntType::TypeZero { .. } => 0,
+            PicOrderCntType::TypeOne { .. } => 1,
+            PicOrderCntType::TypeTwo => 2,
+        };
+
+        let chroma_format_idc = match sps.chroma_info.chroma_format {
+            ChromaFormat::Monochrome => 0,
+            ChromaFormat::YUV420 => 1,
+            ChromaFormat::YUV422 => 2,
+            ChromaFormat::YUV444 => 3,
+            ChromaFormat::Invalid(x) => x as u8,
+        };
+
+        let display = display_rect(sps).extent;
+
+        Self {
+            id: sps.id().id(),
+            profile_idc: sps.profile_idc.into(),
+            level_idc: sps.level_idc,
+            chroma_format_idc,
+            bit_depth_luma: sps.chroma_info.bit_depth_luma_minus8 + 8,
+            bit_depth_chroma: sps.chroma_info.bit_depth_chroma_minus8 + 8,
+            // Both are read as unbounded Exp-Golomb codes, a corrupt SPS must not overflow.
+            coded_width: sps.pic_width_in_mbs_minus1.saturating_add(1).saturating_mul(16),
+            coded_height: sps
+                .pic_height_in_map_units_minus1
+                .saturating_add(1)
+                .saturating_mul(16)
+                .saturating_mul(frame_mbs_factor),
+            display_width: display.width,
+            display_height: display.height,
+            frame_mbs_only: frame_mbs_factor == 1,
+            pic_order_cnt_type,
+            max_num_ref_frames: sps.max_num_ref_frames,
+            max_frame_num: 1 << (sps.log2_max_frame_num_minus4 + 4),
+        }
+    }
+}
+
+impl PpsSummary {
+    fn new(pps: &PicParameterSet) -> Self {
+        Self {
+            id: pps.pic_parameter_set_id.id(),
+            sps_id: pps.seq_parameter_set_id.id(),
+            cabac: pps.entropy_coding_mode_flag,
+            num_ref_idx_l0_default_active: pps.num_ref_idx_l0_default_active_minus1 + 1,
+            num_ref_idx_l1_default_active: pps.num_ref_idx_l1_default_active_minus1 + 1,
+            weighted_pred: pps.weighted_pred_flag,
+            weighted_bipred_idc: pps.weighted_bipred_idc,
+            transform_8x8_mode: pps.extension.as_ref().is_some_and(|x| x.transform_8x8_mode_flag),
+        }
+    }
+}
+
+impl StreamAnalysis {
+    /// The analysis as a single JSON object, with `nals`, `sps`, `pps`, `pictures` and `gops` arrays.
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+
+        self.write_json(&mut json).expect("Writing to a String must not fail");
+        json
+    }
+
+    fn write_json(&self, out: &mut String) -> std::fmt::Result {
+        writeln!(out, "{{")?;
+
+        write_json_array(out, "nals", &self.nals, |out, x| {
+            write!(
+                out,
+                r#"{{"nal_unit_type": {}, "name": {}, "nal_ref_idc": {}, "size": {}, "error": {}}}"#,
+                x.nal_unit_type,
+                json_string(&unit_type_name(x.nal_unit_type)),
+                x.nal_ref_idc,
+                x.size,
+                x.error.as_deref().map_or("null".to_string(), json_string)
+            )
+        })?;
+        writeln!(out, ",")?;
+
+        write_json_array(out, "sps", &self.sps, |out, x| {
+            write!(
+                out,
+                r#"{{"id": {}, "profile_idc": {}, "level_idc": {}, "chroma_format_idc": {}, "bit_depth_luma": {}, "bit_depth_chroma": {}, "coded_width": {}, "coded_height": {}, "display_width": {}, "display_height": {}, "frame_mbs_only": {}, "pic_order_cnt_type": {}, "max_num_ref_frames": {}, "max_frame_num": {}}}"#,
+                x.id,
+                x.profile_idc,
+                x.level_idc,
+                x.chroma_format_idc,
+                x.bit_depth_luma,
+                x.bit_depth_chroma,
+                x.coded_width,
+                x.coded_height,
+                x.display_width,
+                x.display_height,
+                x.frame_mbs_only,
+                x.pic_order_cnt_type,
+                x.max_num_ref_frames,
+                x.max_frame_num
+            )
+        })?;
+        writeln!(out, ",")?;
+
+        write_json_array(out, "pps", &self.pps, |out, x| {
+            write!(
+                out,
+                r#"{{"id": {}, "sps_id": {}, "cabac": {}, "num_ref_idx_l0_default_active": {}, "num_ref_idx_l1_default_active": {}, "weighted_pred": {}, "weighted_bipred_idc": {}, "transform_8x8_mode": {}}}"#,
+                x.id,
+                x.sps_id,
+                x.cabac,
+                x.num_ref_idx_l0_default_active,
+                x.num_ref_idx_l1_default_active,
+                x.weighted_pred,
+                x.weighted_bipred_idc,
+                x.transform_8x8_mode
+            )
+        })?;
+        writeln!(out, ",")?;
+
+        write_json_array(out, "pictures", &self.pictures, |out, x| {
+            let slice_types = x.slice_types.iter().map(|x| format!("\"{:?}\"", x)).collect::<Vec<_>>();
+
+            write!(
+                out,
+                r#"{{"type": "{:?}", "sps_id": {}, "pps_id": {}, "slice_types": [{}], "frame_num": {}, "idr": {}, "reference": {}, "structure": "{:?}", "poc_top": {}, "poc_bottom": {}, "marking": {}, "recovery_point": {}, "size": {}}}"#,
+                x.picture_type(),
+                x.sps_id,
+                x.pps_id,
+                slice_types.join(", "),
+                x.frame_num,
+                x.idr,
+                x.reference,
+                x.structure,
+                x.pic_order_cnt.top,
+                x.pic_order_cnt.bottom,
+                x.marking.as_ref().map_or("null".to_string(), |x| json_string(&marking_name(x))),
+                x.recovery_point,
+                x.size
+            )
+        })?;
+        writeln!(out, ",")?;
+
+        write_json_array(out, "gops", &self.gops, |out, x| {
+            write!(
+                out,
+                r#"{{"first_picture": {}, "pictures": {}, "closed": {}, "pattern": {}}}"#,
+                x.first_picture,
+                x.pictures,
+                x.closed,
+                json_string(&x.pattern)
+            )
+        })?;
+
+        writeln!(out, "\n}}")
+    }
+}
+
+impl Display for StreamAnalysis {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for x in &self.sps {
+            writeln!(
+                f,
+                "SPS {}: profile {}, level {}.{}, chroma format {}, {}/{} bit, {}x{} (coded {}x{}), {}, POC type {}, {} refs, max frame_num {}",
+                x.id,
+                x.profile_idc,
+                x.level_idc / 10,
+                x.level_idc % 10,
+                x.chroma_format_idc,
+                x.bit_depth_luma,
+                x.bit_depth_chroma,
+                x.display_width,
+                x.display_height,
+                x.coded_width,
+                x.coded_height,
+                if x.frame_mbs_only { "progressive" } else { "interlaced" },
+                x.pic_order_cnt_type,
+                x.max_num_ref_frames,
+                x.max_frame_num
+            )?;
+        }
+
+        for x in &self.pps {
+            writeln!(
+                f,
+                "PPS {}: SPS {}, {}, {}/{} default refs, weighted pred {}, weighted bipred {}, 8x8 transform {}",
+                x.id,
+                x.sps_id,
+                if x.cabac { "CABAC" } else { "CAVLC" },
+                x.num_ref_idx_l0_default_active,
+                x.num_ref_idx_l1_default_active,
+                x.weighted_pred,
+                x.weighted_bipred_idc,
+                x.transform_8x8_mode
+            )?;
+        }
+
+        for (i, x) in self.nals.iter().enumerate() {
+            write!(
+                f,
+                "NAL {}: {} ({}), nal_ref_idc {}, {} bytes",
+                i,
+                unit_type_name(x.nal_unit_type),
+                x.nal_unit_type,
+                x.nal_ref_idc,
+                x.size
+            )?;
+
+            match &x.error {
+                Some(error) => writeln!(f, ", error: {}", error)?,
+                None => writeln!(f)?,
+            }
+        }
+
+        for (i, x) in self.pictures.iter().enumerate() {
+            let slice_types = x.slice_types.iter().map(|x| format!("{:?}", x)).collect::<Vec<_>>();
+
+            writeln!(
+                f,
+                "Picture {}: {:?}{} {:?}, frame_num {}, POC {}/{}, {}, marking {}{}, slices [{}], {} bytes",
+                i,
+                x.picture_type(),
+                if x.idr { " IDR" } else { "" },
+                x.structure,
+                x.frame_num,
+                x.pic_order_cnt.top,
+                x.pic_order_cnt.bottom,
+                if x.reference { "reference" } else { "non-reference" },
+                x.marking.as_ref().map_or("none".to_string(), marking_name),
+                if x.recovery_point { ", recovery point" } else { "" },
+                slice_types.join(" "),
+                x.size
+            )?;
+        }
+
+        for (i, x) in self.gops.iter().enumerate() {
+            writeln!(
+                f,
+                "GOP {}: pictures {}..{}, {}, {}",
+                i,
+                x.first_picture,
+                x.first_picture + x.pictures,
+                if x.closed { "closed" } else { "open" },
+                x.pattern
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Starts a new group at every IDR picture and recovery point.
+fn group_pictures(pictures: &[PictureSummary]) -> Vec<GopSummary> {
+    let mut gops: Vec<GopSummary> = Vec::new();
+
+    for (i, picture) in pictures.iter().enumerate() {
+        if gops.is_empty() || picture.idr || picture.recovery_point {
+            gops.push(GopSummary {
+                first_picture: i,
+                pictures: 0,
+                closed: picture.idr,
+                pattern: String::new(),
+            });
+        }
+
+        if let Some(gop) = gops.last_mut() {
+            gop.pictures += 1;
+            let _ = write!(gop.pattern, "{:?}", picture.picture_type());
+        }
+    }
+
+    gops
+}
+
+fn unit_type_name(nal_unit_type: u8) -> String {
+    match UnitType::for_id(nal_unit_type) {
+        Ok(x) => format!("{:?}", x),
+        Err(_) => "Invalid".to_string(),
+    }
+}
+
+fn marking_name(marking: &DecRefPicMarking) -> String {
+    match marking {
+        DecRefPicMarking::Idr {
+            long_term_reference_flag: true,
+            ..
+        } => "IDR long-term".to_string(),
+        DecRefPicMarking::Idr { .. } => "IDR".to_string(),
+        DecRefPicMarking::SlidingWindow => "sliding window".to_string(),
+        DecRefPicMarking::Adaptive(operations) => {
+            let operations = operations.iter().map(|x| mmco_id(x).to_string()).collect::<Vec<_>>();
+            format!("MMCO {}", operations.join(","))
+        }
+    }
+}
+
+fn mmco_id(operation: &MemoryManagementControlOperation) -> u8 {
+    match operation {
+        MemoryManagementControlOperation::ShortTermUnusedForReference { .. } => 1,
+        MemoryManagementControlOperation::LongTermUnusedForReference { .. } => 2,
+        MemoryManagementControlOperation::ShortTermToLongTerm { .. } => 3,
+        MemoryManagementControlOperation::MaxLongTermFrameIdx { .. } => 4,
+        MemoryManagementControlOperation::AllUnusedForReference => 5,
+        MemoryManagementControlOperation::CurrentToLongTerm { .. } => 6,
+    }
+}
+
+/// The error message without backtrace.
+fn describe(error: &Error) -> String {
+    match error.message() {
+        Some(message) => format!("{}: {:?}", message, error.variant()),
+        None => format!("{:?}", error.variant()),
+    }
+}
+
+fn write_json_array<T>(
+    out: &mut String,
+    name: &str,
+    items: &[T],
+    write_item: impl Fn(&mut String, &T) -> std::fmt::Result,
+) -> std::fmt::Result {
+    write!(out, "  \"{}\": [", name)?;
+
+    for (i, item) in items.iter().enumerate() {
+        write!(out, "{}\n    ", if i == 0 { "" } else { "," })?;
+        write_item(out, item)?;
+    }
+
+    if !items.is_empty() {
+        write!(out, "\n  ")?;
+    }
+
+    write!(out, "]")
+}
+
+fn json_string(value: &str) -> String {
+    let mut json = String::with_capacity(value.len() + 2);
+
+    json.push('"');
+
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod test {
+    use super::{analyze, PictureStructure, StreamAnalyzer};
+    use crate::error::Error;
+    use crate::video::h264::testdata::nal;
+    use crate::video::h264::SliceType;
+
+    /// Baseline, 4 bit `frame_num`, POC type 0 with 4 bit LSB, 2 ref frames, 4x4 MBs; PPS 0.
+    fn parameter_sets() -> Vec<Vec<u8>> {
+        vec![
+            nal(0x67, "01000010 00000000 00011110 1 1 1 1 010 0 00100 00100 1 1 0 0"),
+            nal(0x68, "1 1 0 0 1 1 1 0 00 1 1 1 1 0 0"),
+        ]
+    }
+
+    #[test]
+    fn analyzes_stream() -> Result<(), Error> {
+        let mut nals = parameter_sets();
+
+        // IDR, then a P reference picture, a malformed NAL, and a recovery point before a non-reference I picture.
+        nals.push(nal(0x65, "1 0001000 1 0000 1 0000 0 0 1 010"));
+        nals.push(nal(0x41, "1 00110 1 0001 0010 0 0 0 1 010"));
+        nals.push(vec![0, 0, 1, 0x80]);
+        nals.push(nal(0x06, "00000110 00000001 1 1 0 00 100"));
+        nals.push(nal(0x01, "1 0001000 1 0010 0100 1 010"));
+
+        let stream = nals.concat();
+        let analysis = analyze(stream.as_slice())?;
+
+        assert_eq!(analysis.nals.len(), 7);
+        assert_eq!(analysis.nals[2].nal_unit_type, 5);
+        assert!(analysis.nals[4].error.is_some());
+        assert_eq!(analysis.sps[0].display_width, 64);
+        assert_eq!(analysis.sps[0].max_frame_num, 16);
+        assert!(!analysis.pps[0].cabac);
+
+        assert_eq!(analysis.pictures.len(), 3);
+        assert!(analysis.pictures[0].idr);
+        assert_eq!(analysis.pictures[1].picture_type(), SliceType::P);
+        assert_eq!(analysis.pictures[1].pic_order_cnt.top, 2);
+        assert_eq!(analysis.pictures[2].structure, PictureStructure::Frame);
+        assert!(!analysis.pictures[2].reference);
+        assert!(analysis.pictures[2].recovery_point);
+
+        assert_eq!(analysis.gops.len(), 2);
+        assert_eq!(analysis.gops[0].pattern, "IP");
+        assert!(analysis.gops[0].closed);
+        assert!(!analysis.gops[1].closed);
+
+        let json = analysis.to_json();
+
+        assert!(json.starts_with('{') && json.trim_end().ends_with('}'));
+        assert!(json.contains(r#""pattern": "IP""#));
+        assert!(analysis.to_string().contains("GOP 1: pictures 2..3, open, I"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn empty_stream() {
+        let analysis = StreamAnalyzer::new().finish();
+
+        assert!(analysis.pictures.is_empty());
+        assert!(analysis.to_json().contains(r#""gops": []"#));
+    }
+}
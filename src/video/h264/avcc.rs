@@ -0,0 +1,400 @@
+//! Length-prefixed NAL units as stored in MP4 files, and their `avcC` configuration (ISO/IEC 14496-15, 5.3).
+use crate::error;
+use crate::error::{Error, Variant};
+use crate::video::{nal_units, strip_annexb, START_CODE};
+use h264_reader::nal::sps::{ChromaFormat, SeqParameterSet};
+use h264_reader::nal::{Nal, RefNal};
+
+/// The `avcC` box of an MP4 track, describing how its samples are coded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AvcDecoderConfigurationRecord {
+    pub profile_indication: u8,
+    pub profile_compatibility: u8,
+    pub level_indication: u8,
+    /// Size of the NAL unit length prefix of each sample, 1, 2 or 4 bytes.
+    pub nal_length_size: u8,
+    /// SPS NAL units, without start code.
+    pub sps: Vec<Vec<u8>>,
+    /// PPS NAL units, without start code.
+    pub pps: Vec<Vec<u8>>,
+    /// Only present for High profiles, and even then often missing.
+    pub high_profile: Option<AvcHighProfileExtension>,
+}
+
+/// The part of an `avcC` record only High profiles have.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AvcHighProfileExtension {
+    pub chroma_format: u8,
+    pub bit_depth_luma_minus8: u8,
+    pub bit_depth_chroma_minus8: u8,
+    /// SPS extension NAL units, without start code.
+    pub sps_ext: Vec<Vec<u8>>,
+}
+
+impl AvcDecoderConfigurationRecord {
+    /// Builds a record for samples with 4 byte length prefixes, taking profile and level from the first SPS.
+    pub fn new(sps: &[&[u8]], pps: &[&[u8]]) -> Result<Self, Error> {
+        let first = sps.first().ok_or_else(|| error!(Variant::InvalidAvcc, "Need at least one SPS"))?;
+
+        let [_, profile_indication, profile_compatibility, level_indication, ..] = **first else {
+            return Err(error!(Variant::InvalidSps, "SPS is truncated"));
+        };
+
+        let high_profile = if has_high_profile_extension(profile_indication) {
+            let parsed = SeqParameterSet::from_bits(RefNal::new(first, &[], true).rbsp_bits())
+                .map_err(|e| error!(Variant::InvalidSps, "{:?}", e))?;
+
+            let chroma_format = match parsed.chroma_info.chroma_format {
+                ChromaFormat::Monochrome => 0,
+                ChromaFormat::YUV420 => 1,
+                ChromaFormat::YUV422 => 2,
+                ChromaFormat::YUV444 => 3,
+                ChromaFormat::Invalid(x) => return Err(error!(Variant::InvalidSps, "Invalid chroma_format_idc {}", x)),
+            };
+
+            Some(AvcHighProfileExtension {
+                chroma_format,
+                bit_depth_luma_minus8: parsed.chroma_info.bit_depth_luma_minus8,
+                bit_depth_chroma_minus8: parsed.chroma_info.bit_depth_chroma_minus8,
+                sps_ext: Vec::new(),
+            })
+        } else {
+            None
+        };
+
+        Ok(Self {
+            profile_indication,
+            profile_compatibility,
+            level_indication,
+            nal_length_size: 4,
+            sps: sps.iter().map(|x| x.to_vec()).collect(),
+            pps: pps.iter().map(|x| x.to_vec()).collect(),
+            high_profile,
+        })
+    }
+
+    /// Parses the contents of an `avcC` box.
+    pub fn from_bytes(data: &[u8]) -> Result<Self, Error> {
+        let mut r = data;
+
+        let configuration_version = read_u8(&mut r)?;
+
+        if configuration_version != 1 {
+            return Err(error!(
+                Variant::InvalidAvcc,
+                "Unknown configurationVersion {}", configuration_version
+            ));
+        }
+
+        let profile_indication = read_u8(&mut r)?;
+        let profile_compatibility = read_u8(&mut r)?;
+        let level_indication = read_u8(&mut r)?;
+        let nal_length_size = (read_u8(&mut r)? & 0x03) + 1;
+
+        if nal_length_size == 3 {
+            return Err(error!(Variant::InvalidAvcc, "NAL unit length prefix of 3 bytes"));
+        }
+
+        let num_sps = read_u8(&mut r)? & 0x1F;
+        let sps = read_nal_units(&mut r, num_sps)?;
+        let num_pps = read_u8(&mut r)?;
+        let pps = read_nal_units(&mut r, num_pps)?;
+
+        let high_profile = if has_high_profile_extension(profile_indication) && r.len() >= 4 {
+            let chroma_format = read_u8(&mut r)? & 0x03;
+            let bit_depth_luma_minus8 = read_u8(&mut r)? & 0x07;
+            let bit_depth_chroma_minus8 = read_u8(&mut r)? & 0x07;
+            let num_sps_ext = read_u8(&mut r)?;
+
+            Some(AvcHighProfileExtension {
+                chroma_format,
+                bit_depth_luma_minus8,
+                bit_depth_chroma_minus8,
+                sps_ext: read_nal_units(&mut r, num_sps_ext)?,
+            })
+        } else {
+            None
+        };
+
+        Ok(Self {
+            profile_indication,
+            profile_compatibility,
+            level_indication,
+            nal_length_size,
+            sps,
+            pps,
+            high_profile,
+        })
+    }
+
+    /// Writes the contents of an `avcC` box.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        if !matches!(self.nal_length_size, 1 | 2 | 4) {
+            return Err(error!(
+                Variant::InvalidAvcc,
+                "NAL unit length prefix of {} bytes", self.nal_length_size
+            ));
+        }
+
+        let mut data = vec![
+            1,
+            self.profile_indication,
+            self.profile_compatibility,
+            self.level_indication,
+            0xFC | (self.nal_length_size - 1),
+        ];
+
+        write_nal_units(&mut data, &self.sps, 0x1F, 0xE0)?;
+        write_nal_units(&mut data, &self.pps, 0xFF, 0)?;
+
+        if let Some(x) = &self.high_profile {
+            data.push(0xFC | x.chroma_format);
+            data.push(0xF8 | x.bit_depth_luma_minus8);
+            data.push(0xF8 | x.bit_depth_chroma_minus8);
+            write_nal_units(&mut data, &x.sps_ext, 0xFF, 0)?;
+        }
+
+        Ok(data)
+    }
+
+    /// All parameter sets in the order they have to be fed to a decoder, without start codes.
+    pub fn parameter_sets(&self) -> impl Iterator<Item = &[u8]> {
+        let sps_ext = self.high_profile.iter().flat_map(|x| &x.sps_ext);
+
+        self.sps.iter().chain(sps_ext).chain(&self.pps).map(|x| x.as_slice())
+    }
+}
+
+/// Splits an MP4 sample into its NAL units, which have no start codes.
+///
+/// The iterator ends after the first error, e.g., if a length prefix points past the end of `sample`.
+pub fn avcc_nal_units(sample: &[u8], nal_length_size: u8) -> impl Iterator<Item = Result<&[u8], Error>> {
+    let mut rest = sample;
+
+    std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+
+        let result = read_length_prefixed(&mut rest, nal_length_size);
+
+        if result.is_err() {
+            rest = &[];
+        }
+
+        Some(result)
+    })
+}
+
+/// Converts an MP4 sample with length-prefixed NAL units into an Annex B byte stream.
+pub fn avcc_to_annexb(sample: &[u8], nal_length_size: u8) -> Result<Vec<u8>, Error> {
+    let mut stream = Vec::with_capacity(sample.len() + 16);
+
+    for nal in avcc_nal_units(sample, nal_length_size) {
+        // With `zero_byte`, as parameter sets and the first NAL unit of an access unit need it (B.1.2).
+        stream.push(0);
+        stream.extend_from_slice(&START_CODE);
+        stream.extend_from_slice(nal?);
+    }
+
+    Ok(stream)
+}
+
+/// Converts an Annex B byte stream into length-prefixed NAL units, e.g., for an MP4 sample.
+pub fn annexb_to_avcc(stream: &[u8], nal_length_size: u8) -> Result<Vec<u8>, Error> {
+    let mut sample = Vec::with_capacity(stream.len());
+
+    for nal in nal_units(stream) {
+        let nal = strip_annexb(nal);
+
+        if !nal.is_empty() {
+            write_length_prefixed(&mut sample, nal, nal_length_size)?;
+        }
+    }
+
+    Ok(sample)
+}
+
+/// Profiles that have the `avcC` extension (ISO/IEC 14496-15, 5.3.3.1.2), i.e., all whose SPS has chroma format and
+/// bit depths (7.3.2.1.1), and the withdrawn High 4:4:4 profile 144.
+fn has_high_profile_extension(profile_indication: u8) -> bool {
+    matches!(
+        profile_indication,
+        44 | 83 | 86 | 100 | 110 | 118 | 122 | 128 | 134 | 135 | 138 | 139 | 144 | 244
+    )
+}
+
+fn read_u8(r: &mut &[u8]) -> Result<u8, Error> {
+    let (byte, rest) = r
+        .split_first()
+        .ok_or_else(|| error!(Variant::InvalidAvcc, "avcC record is truncated"))?;
+    *r = rest;
+    Ok(*byte)
+}
+
+fn read_nal_units(r: &mut &[u8], count: u8) -> Result<Vec<Vec<u8>>, Error> {
+    (0..count).map(|_| read_length_prefixed(r, 2).map(|x| x.to_vec())).collect()
+}
+
+fn write_nal_units(data: &mut Vec<u8>, nal_units: &[Vec<u8>], max_count: u8, reserved: u8) -> Result<(), Error> {
+    let count = u8::try_from(nal_units.len())
+        .ok()
+        .filter(|x| *x <= max_count)
+        .ok_or_else(|| error!(Variant::InvalidAvcc, "Too many parameter sets ({})", nal_units.len()))?;
+
+    data.push(reserved | count);
+
+    for nal in nal_units {
+        write_length_prefixed(data, nal, 2)?;
+    }
+
+    Ok(())
+}
+
+fn read_length_prefixed<'a>(r: &mut &'a [u8], length_size: u8) -> Result<&'a [u8], Error> {
+    let length_size = usize::from(length_size);
+
+    if !matches!(length_size, 1 | 2 | 4) {
+        return Err(error!(Variant::InvalidAvcc, "NAL unit length prefix of {} bytes", length_size));
+    }
+
+    if r.len() < length_size {
+        return Err(error!(Variant::InvalidAvcc, "Truncated NAL unit length prefix"));
+    }
+
+    let (prefix, rest) = r.split_at(length_size);
+    let length = prefix.iter().fold(0usize, |acc, x| (acc << 8) | usize::from(*x));
+
+    if length > rest.len() {
+        return Err(error!(
+            Variant::InvalidAvcc,
+            "NAL unit of {} bytes, but only {} left",
+            length,
+            rest.len()
+        ));
+    }
+
+    let (nal, rest) = rest.split_at(length);
+    *r = rest;
+
+    Ok(nal)
+}
+
+fn write_length_prefixed(data: &mut Vec<u8>, nal: &[u8], length_size: u8) -> Result<(), Error> {
+    let length_size = usize::from(length_size);
+
+    if !matches!(length_size, 1 | 2 | 4) || (length_size < 4 && nal.len() >> (8 * length_size) != 0) {
+        return Err(error!(
+            Variant::InvalidAvcc,
+            "NAL unit of {} bytes does not fit a {} byte prefix",
+            nal.len(),
+            length_size
+        ));
+    }
+
+    data.extend_from_slice(&(nal.len() as u32).to_be_bytes()[4 - length_size..]);
+    data.extend_from_slice(nal);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{annexb_to_avcc, avcc_nal_units, avcc_to_annexb, AvcDecoderConfigurationRecord};
+    use crate::error::{Error, Variant};
+    use crate::video::h264::testdata::{nal, PPS, SPS};
+    use crate::video::h264::H264StreamInspector;
+
+    #[test]
+    fn converts_samples() -> Result<(), Error> {
+        let annexb = [0, 0, 0, 1, 0x09, 0xF0, 0, 0, 1, 0x65, 0x88, 0x84, 0x00, 0x00, 0x03, 0x01, 0, 0];
+        let avcc = [0, 0, 0, 2, 0x09, 0xF0, 0, 0, 0, 7, 0x65, 0x88, 0x84, 0x00, 0x00, 0x03, 0x01];
+
+        assert_eq!(annexb_to_avcc(&annexb, 4)?, avcc);
+        assert_eq!(avcc_to_annexb(&avcc, 4)?, [&annexb[..6], &[0], &annexb[6..16]].concat());
+        assert_eq!(
+            annexb_to_avcc(&avcc_to_annexb(&avcc, 4)?, 2)?,
+            [&[0, 2][..], &avcc[4..6], &[0, 7], &avcc[10..]].concat()
+        );
+
+        let nals = avcc_nal_units(&avcc, 4).collect::<Result<Vec<_>, _>>()?;
+
+        assert_eq!(nals, vec![&avcc[4..6], &avcc[10..]]);
+
+        // Truncated, and a NAL too large for its prefix.
+        let truncated = avcc_nal_units(&avcc[..12], 4).collect::<Result<Vec<_>, _>>().unwrap_err();
+        let too_large = annexb_to_avcc(&[&[0, 0, 1][..], &[0x65; 300]].concat(), 1).unwrap_err();
+
+        assert!(matches!(truncated.variant(), Variant::InvalidAvcc));
+        assert!(matches!(too_large.variant(), Variant::InvalidAvcc));
+
+        Ok(())
+    }
+
+    #[test]
+    fn reads_and_writes_records() -> Result<(), Error> {
+        let record = AvcDecoderConfigurationRecord::new(&[&SPS], &[&PPS])?;
+        let high_profile = record.high_profile.as_ref().unwrap();
+
+        assert_eq!(
+            (record.profile_indication, record.profile_compatibility, record.level_indication),
+            (100, 0, 10)
+        );
+        assert_eq!((high_profile.chroma_format, high_profile.bit_depth_luma_minus8), (1, 0));
+
+        let bytes = record.to_bytes()?;
+
+        assert_eq!(&bytes[..7], &[1, 100, 0, 10, 0xFF, 0xE1, 0]);
+        assert_eq!(AvcDecoderConfigurationRecord::from_bytes(&bytes)?, record);
+
+        // Without the optional High profile part, as many muxers write it.
+        let short = AvcDecoderConfigurationRecord::from_bytes(&bytes[..bytes.len() - 4])?;
+
+        assert!(short.high_profile.is_none());
+
+        let mut inspector = H264StreamInspector::new();
+
+        for nal in record.parameter_sets() {
+            inspector.feed_nal(nal)?;
+        }
+
+        assert_eq!(inspector.std_pps().len(), 1);
+
+        let bad_version = AvcDecoderConfigurationRecord::from_bytes(&[2, 100, 0, 10, 0xFF, 0xE0, 0]).unwrap_err();
+
+        assert!(matches!(bad_version.variant(), Variant::InvalidAvcc));
+
+        Ok(())
+    }
+
+    #[test]
+    fn round_trips_high_444_records() -> Result<(), Error> {
+        // High 4:4:4 Predictive, 4:4:4 with 10 bit luma and chroma, and a SPS extension.
+        let sps = nal(
+            0x67,
+            "11110100 00000000 00011110 1 00100 0 011 011 0 0 1 1 1 010 0 00100 00100 1 1 0 0",
+        );
+        let sps_ext = nal(0x6D, "1 1 0");
+        let mut record = AvcDecoderConfigurationRecord::new(&[&sps[4..]], &[&PPS])?;
+        let high_profile = record.high_profile.as_mut().unwrap();
+
+        assert_eq!(
+            (
+                high_profile.chroma_format,
+                high_profile.bit_depth_luma_minus8,
+                high_profile.bit_depth_chroma_minus8
+            ),
+            (3, 2, 2)
+        );
+
+        high_profile.sps_ext.push(sps_ext[4..].to_vec());
+
+        let bytes = record.to_bytes()?;
+
+        assert_eq!(bytes[1], 244);
+        assert_eq!(AvcDecoderConfigurationRecord::from_bytes(&bytes)?, record);
+        assert_eq!(record.parameter_sets().count(), 3);
+
+        Ok(())
+    }
+}
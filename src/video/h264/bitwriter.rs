@@ -0,0 +1,192 @@
+//! Writing of H.264 syntax elements (7.2) and NAL unit framing (7.3.1).
+use crate::video::START_CODE;
+use h264_reader::nal::UnitType;
+
+/// Writes syntax elements MSB first into a RBSP, the counterpart of `h264-reader`'s `BitRead`.
+#[derive(Clone, Debug, Default)]
+pub struct BitWriter {
+    data: Vec<u8>,
+    // Bits of the last, incomplete byte, aligned to the LSB.
+    current: u8,
+    current_bits: u32,
+}
+
+impl BitWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Writes the lowest `bits` bits of `value`, i.e., `u(n)` (7.2).
+    pub fn write_bits(&mut self, value: u32, bits: u32) {
+        self.write_bits_u64(u64::from(value), bits);
+    }
+
+    /// Writes a single flag, i.e., `u(1)`.
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_bits(value.into(), 1);
+    }
+
+    /// Writes an unsigned Exp-Golomb code, i.e., `ue(v)` (9.1).
+    pub fn write_ue(&mut self, value: u32) {
+        let code = u64::from(value) + 1;
+        let bits = 64 - code.leading_zeros();
+
+        self.write_bits_u64(0, bits - 1);
+        self.write_bits_u64(code, bits);
+    }
+
+    /// Writes a signed Exp-Golomb code, i.e., `se(v)` (9.1.1).
+    pub fn write_se(&mut self, value: i32) {
+        let code = match value {
+            1.. => 2 * i64::from(value) - 1,
+            _ => -2 * i64::from(value),
+        };
+
+        // Fits, `code` is at most 2^32.
+        let code = code as u64 + 1;
+        let bits = 64 - code.leading_zeros();
+
+        self.write_bits_u64(0, bits - 1);
+        self.write_bits_u64(code, bits);
+    }
+
+    /// Writes whole bytes, e.g., SEI payloads.
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        if self.is_byte_aligned() {
+            self.data.extend_from_slice(bytes);
+        } else {
+            bytes.iter().for_each(|x| self.write_bits((*x).into(), 8));
+        }
+    }
+
+    /// Writes `rbsp_trailing_bits()` (7.3.2.11), i.e., a `1` and zeros up to the next byte.
+    pub fn write_trailing_bits(&mut self) {
+        self.write_bool(true);
+
+        while !self.is_byte_aligned() {
+            self.write_bool(false);
+        }
+    }
+
+    /// If the next bit starts a new byte.
+    pub fn is_byte_aligned(&self) -> bool {
+        self.current_bits == 0
+    }
+
+    /// Number of bits written so far.
+    pub fn bit_len(&self) -> usize {
+        self.data.len() * 8 + self.current_bits as usize
+    }
+
+    /// Returns the RBSP, padding an incomplete last byte with zeros.
+    pub fn into_rbsp(mut self) -> Vec<u8> {
+        if !self.is_byte_aligned() {
+            self.data.push(self.current << (8 - self.current_bits));
+        }
+
+        self.data
+    }
+
+    /// Returns an Annex B NAL unit with 4 byte start code holding the RBSP, inserting emulation prevention bytes.
+    ///
+    /// The RBSP should end with [`BitWriter::write_trailing_bits`], or be padded with zeros to a whole byte.
+    pub fn into_nal(self, nal_ref_idc: u8, nal_unit_type: UnitType) -> Vec<u8> {
+        let header = (nal_ref_idc & 0x3) << 5 | nal_unit_type.id();
+        let rbsp = self.into_rbsp();
+        let mut nal = Vec::with_capacity(1 + START_CODE.len() + 1 + rbsp.len() + rbsp.len() / 64);
+
+        // The `zero_byte` makes this a 4 byte start code, as B.1.2 requires for parameter sets.
+        nal.push(0);
+        nal.extend_from_slice(&START_CODE);
+        nal.push(header);
+
+        let mut zeros = 0;
+
+        for byte in &rbsp {
+            // 7.4.1: `0x000000` to `0x000003` must not appear within a NAL unit.
+            if zeros >= 2 && *byte <= 3 {
+                nal.push(3);
+                zeros = 0;
+            }
+
+            nal.push(*byte);
+            zeros = if *byte == 0 { zeros + 1 } else { 0 };
+        }
+
+        // 7.4.1: A NAL unit must not end in a zero byte, e.g., after `cabac_zero_word`s.
+        if rbsp.last() == Some(&0) {
+            nal.push(3);
+        }
+
+        nal
+    }
+
+    fn write_bits_u64(&mut self, value: u64, bits: u32) {
+        for i in (0..bits).rev() {
+            self.current = self.current << 1 | (value >> i & 1) as u8;
+            self.current_bits += 1;
+
+            if self.current_bits == 8 {
+                self.data.push(self.current);
+                self.current = 0;
+                self.current_bits = 0;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::BitWriter;
+    use h264_reader::nal::UnitType;
+    use h264_reader::rbsp::{BitRead, BitReader};
+
+    #[test]
+    fn writes_exp_golomb() {
+        let unsigned = [0, 1, 2, 3, 7, 255, 65535, u32::MAX - 1];
+        let signed = [0, 1, -1, 2, -2, 1000, -1000, i32::MAX, i32::MIN + 1];
+
+        let mut w = BitWriter::new();
+
+        w.write_ue(0);
+        w.write_ue(3);
+        w.write_se(-2);
+        assert_eq!(w.bit_len(), 1 + 5 + 5);
+
+        unsigned.iter().for_each(|x| w.write_ue(*x));
+        signed.iter().for_each(|x| w.write_se(*x));
+        w.write_bits(0b101, 3);
+        w.write_trailing_bits();
+
+        let rbsp = w.into_rbsp();
+        let mut r = BitReader::new(rbsp.as_slice());
+
+        assert_eq!(rbsp[0] >> 5, 0b100);
+        assert_eq!(r.read_ue("").unwrap(), 0);
+        assert_eq!(r.read_ue("").unwrap(), 3);
+        assert_eq!(r.read_se("").unwrap(), -2);
+
+        for x in unsigned {
+            assert_eq!(r.read_ue("").unwrap(), x);
+        }
+
+        for x in signed {
+            assert_eq!(r.read_se("").unwrap(), x);
+        }
+
+        assert_eq!(r.read_u8(3, "").unwrap(), 0b101);
+        assert!(!r.has_more_rbsp_data("").unwrap());
+    }
+
+    #[test]
+    fn inserts_emulation_prevention() {
+        let mut w = BitWriter::new();
+
+        w.write_bytes(&[0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00]);
+
+        assert_eq!(
+            w.into_nal(3, UnitType::SeqParameterSet),
+            vec![0, 0, 0, 1, 0x67, 0x00, 0x00, 0x03, 0x01, 0x00, 0x00, 0x03, 0x00, 0x00, 0x03, 0x00, 0x04, 0x00, 0x03]
+        );
+    }
+}
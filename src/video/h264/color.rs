@@ -0,0 +1,75 @@
+//! How decoded samples map to colours, as signalled in the SPS VUI (E.2.1).
+use crate::video::ColorInfo;
+use h264_reader::nal::sps::SeqParameterSet;
+
+/// The colour description in the VUI of `sps`, or what E.2.1 mandates without one.
+pub(crate) fn color_info(sps: &SeqParameterSet) -> ColorInfo {
+    let mut color_info = ColorInfo::default();
+
+    let Some(vui) = &sps.vui_parameters else {
+        return color_info;
+    };
+
+    color_info.sample_aspect_ratio = vui.aspect_ratio_info.as_ref().and_then(|x| x.get());
+
+    if let Some(signal_type) = &vui.video_signal_type {
+        color_info.full_range = signal_type.video_full_range_flag;
+
+        if let Some(colour) = &signal_type.colour_description {
+            color_info.colour_primaries = colour.colour_primaries;
+            color_info.transfer_characteristics = colour.transfer_characteristics;
+            color_info.matrix_coefficients = colour.matrix_coefficients;
+        }
+    }
+
+    if let Some(chroma_loc_info) = &vui.chroma_loc_info {
+        color_info.chroma_sample_loc_type_top_field = chroma_loc_info.chroma_sample_loc_type_top_field as u8;
+        color_info.chroma_sample_loc_type_bottom_field = chroma_loc_info.chroma_sample_loc_type_bottom_field as u8;
+    }
+
+    color_info
+}
+
+#[cfg(test)]
+mod test {
+    use super::color_info;
+    use crate::video::h264::testdata::sps;
+    use crate::video::ColorInfo;
+    use ash::vk::{ChromaLocation, SamplerYcbcrModelConversion, SamplerYcbcrRange};
+
+    #[test]
+    fn reads_colour_description() {
+        // Baseline, 4x4 MBs, without VUI.
+        let absent = sps("01000010 00000000 00011110 1 1 1 1 011 0 00100 00100 1 1 0 0");
+        let info = color_info(&absent);
+
+        assert_eq!(info.matrix_coefficients, 2);
+        assert_eq!(info.ycbcr_model(), None);
+        assert_eq!(info.ycbcr_range(), SamplerYcbcrRange::ITU_NARROW);
+        assert_eq!(info.sample_aspect_ratio, None);
+
+        // As above with a VUI: SAR 1:1, full range BT.709, chroma sample location type 1.
+        let vui = sps("01000010 00000000 00011110 1 1 1 1 011 0 00100 00100 1 1 0 1 \
+             1 00000001 0 1 101 1 1 00000001 00000001 00000001 1 010 010 0 0 0 0 0");
+        let info = color_info(&vui);
+
+        assert_eq!(info.colour_primaries, 1);
+        assert_eq!(info.transfer_characteristics, 1);
+        assert_eq!(info.ycbcr_model(), Some(SamplerYcbcrModelConversion::YCBCR_709));
+        assert_eq!(info.ycbcr_range(), SamplerYcbcrRange::ITU_FULL);
+        assert_eq!(info.chroma_location(), Some((ChromaLocation::MIDPOINT, ChromaLocation::MIDPOINT)));
+        assert_eq!(info.sample_aspect_ratio, Some((1, 1)));
+    }
+
+    #[test]
+    fn default_is_unspecified() {
+        let info = ColorInfo::default();
+
+        assert_eq!(
+            info,
+            color_info(&sps("01000010 00000000 00011110 1 1 1 1 011 0 00100 00100 1 1 0 0"))
+        );
+        assert_ne!(info.ycbcr_model(), Some(SamplerYcbcrModelConversion::YCBCR_IDENTITY));
+        assert_eq!(info.ycbcr_range(), SamplerYcbcrRange::ITU_NARROW);
+    }
+}
@@ -0,0 +1,77 @@
+//! The part of a decoded frame meant for display, as given by the SPS frame cropping (7.4.2.1.1).
+use ash::vk::{Extent2D, Offset2D, Rect2D};
+use h264_reader::nal::sps::{ChromaFormat, FrameMbsFlags, SeqParameterSet};
+
+/// The display window of frames coded with `sps`, in luma samples of the decoded image.
+///
+/// Frames are coded in whole macroblocks, so e.g. 1080p is coded as 1088 lines and cropped back. Crop offsets
+/// count in chroma samples, and in field pairs for interlaced streams, which this converts to luma samples.
+pub(crate) fn display_rect(sps: &SeqParameterSet) -> Rect2D {
+    let frame_mbs_factor = match sps.frame_mbs_flags {
+        FrameMbsFlags::Frames => 1,
+        FrameMbsFlags::Fields { .. } => 2,
+    };
+
+    // `CropUnitX` and `CropUnitY` (7-19 to 7-22), where separate colour planes count as monochrome.
+    let (crop_unit_x, crop_unit_y) = match sps.chroma_info.chroma_format {
+        _ if sps.chroma_info.separate_colour_plane_flag => (1, frame_mbs_factor),
+        ChromaFormat::YUV420 => (2, 2 * frame_mbs_factor),
+        ChromaFormat::YUV422 => (2, frame_mbs_factor),
+        _ => (1, frame_mbs_factor),
+    };
+
+    let width = sps.pic_width_in_mbs_minus1.saturating_add(1).saturating_mul(16);
+    let height = sps
+        .pic_height_in_map_units_minus1
+        .saturating_add(1)
+        .saturating_mul(16 * frame_mbs_factor);
+
+    let crop = sps.frame_cropping.clone().unwrap_or_default();
+    let left = crop.left_offset.saturating_mul(crop_unit_x).min(width);
+    let right = crop.right_offset.saturating_mul(crop_unit_x).min(width - left);
+    let top = crop.top_offset.saturating_mul(crop_unit_y).min(height);
+    let bottom = crop.bottom_offset.saturating_mul(crop_unit_y).min(height - top);
+
+    Rect2D {
+        offset: Offset2D {
+            x: left as i32,
+            y: top as i32,
+        },
+        extent: Extent2D {
+            width: width - left - right,
+            height: height - top - bottom,
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::display_rect;
+    use crate::video::h264::testdata::sps;
+    use ash::vk::{Extent2D, Offset2D, Rect2D};
+
+    #[test]
+    fn crops_in_chroma_units() {
+        // Baseline 4:2:0, 120x68 MBs, i.e., 1920x1088 cropped by 4 chroma lines at the bottom.
+        let progressive = sps("01000010 00000000 00101000 1 1 1 1 011 0 0000001111000 0000001000100 1 1 1 1 1 1 00101 0");
+
+        assert_eq!(
+            display_rect(&progressive),
+            Rect2D {
+                offset: Offset2D { x: 0, y: 0 },
+                extent: Extent2D { width: 1920, height: 1080 },
+            }
+        );
+
+        // Interlaced 4x2 map units, i.e., 64x64, cropped by one crop unit left, right and top: 2, 2 and 4 luma samples.
+        let interlaced = sps("01000010 00000000 00011110 1 1 1 1 011 0 00100 010 0 0 1 1 010 010 010 1 0");
+
+        assert_eq!(
+            display_rect(&interlaced),
+            Rect2D {
+                offset: Offset2D { x: 2, y: 4 },
+                extent: Extent2D { width: 60, height: 60 },
+            }
+        );
+    }
+}
@@ -0,0 +1,1127 @@
+//! Reference picture marking (8.2.5) and DPB slot management.
+use crate::error;
+use crate::error::{Error, Variant};
+use crate::video::h264::color::color_info;
+use crate::video::h264::crop::display_rect;
+use crate::video::h264::slice::FirstField;
+use crate::video::h264::{DecRefPicMarking, MemoryManagementControlOperation, PicOrderCnt, SliceHeader, SliceType};
+use crate::video::ColorInfo;
+use ash::vk::Rect2D;
+use h264_reader::nal::sps::SeqParameterSet;
+
+/// A picture the current picture can reference, and the DPB slot it lives in.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DpbReference {
+    pub slot_index: u8,
+    pub frame_num: u16,
+    /// Set if this is a long-term reference.
+    pub long_term_frame_idx: Option<u32>,
+    pub pic_order_cnt: PicOrderCnt,
+    /// If the top field of a field-coded picture is used for reference. Unset for frames.
+    pub top_field: bool,
+    /// If the bottom field of a field-coded picture is used for reference. Unset for frames.
+    pub bottom_field: bool,
+    /// A frame inferred for a gap in `frame_num` (8.2.5.2). It was never decoded, so its slot holds no meaningful content.
+    pub non_existing: bool,
+}
+
+/// Everything needed to decode a picture: its own parameters, the DPB slot to decode into, and its references.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DpbPicture {
+    /// The slot the picture is decoded into, i.e., Vulkan's setup reference slot.
+    pub slot_index: u8,
+    pub seq_parameter_set_id: u8,
+    pub pic_parameter_set_id: u8,
+    pub frame_num: u16,
+    pub idr_pic_id: u16,
+    pub pic_order_cnt: PicOrderCnt,
+    pub idr: bool,
+    /// Reset the video session before decoding, which invalidates all DPB slots. Set for IDR pictures and the first
+    /// picture after [`Dpb::new`] or [`Dpb::reset`], e.g., when decoding starts at a recovery point.
+    pub reset: bool,
+    pub intra: bool,
+    pub reference: bool,
+    /// Set if the picture was marked as long-term reference, by `long_term_reference_flag` or MMCO 6.
+    pub long_term_frame_idx: Option<u32>,
+    pub field_pic_flag: bool,
+    pub bottom_field_flag: bool,
+    /// If this is the second field of a frame, decoded into the same slot as the first one.
+    pub second_field: bool,
+    /// The part of the decoded image to display, i.e., without the padding to whole macroblocks.
+    pub display_rect: Rect2D,
+    /// How to convert the decoded samples to RGB.
+    pub color_info: ColorInfo,
+    /// All pictures marked as used for reference before this picture was decoded.
+    pub references: Vec<DpbReference>,
+}
+
+impl DpbPicture {
+    /// How later pictures will see this picture when referencing it, i.e., Vulkan's setup reference.
+    pub fn as_reference(&self) -> DpbReference {
+        DpbReference {
+            slot_index: self.slot_index,
+            frame_num: self.frame_num,
+            long_term_frame_idx: self.long_term_frame_idx,
+            pic_order_cnt: self.pic_order_cnt,
+            top_field: self.field_pic_flag && !self.bottom_field_flag,
+            bottom_field: self.field_pic_flag && self.bottom_field_flag,
+            non_existing: false,
+        }
+    }
+}
+
+/// How [`Dpb::add_picture`] deals with lost frames, i.e., `frame_num` jumping ahead when the SPS does not allow gaps.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Concealment {
+    /// Reject all pictures with [`Variant::MissingReferences`] until the next IDR picture.
+    #[default]
+    SkipToIdr,
+    /// Let the most recent short-term reference stand in for the missing frame and keep decoding.
+    ///
+    /// Pictures predicted from the missing frame use the last good frame instead, which is usually much
+    /// less visible than decoding from garbage. The last good frame keeps its own `frame_num`, the missing frame
+    /// is marked as a reference sharing its slot. Without any short-term reference this falls back to
+    /// [`Concealment::SkipToIdr`].
+    ReuseLastFrame,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Marking {
+    ShortTerm,
+    LongTerm(u32),
+}
+
+impl Marking {
+    fn long_term_frame_idx(self) -> Option<u32> {
+        match self {
+            Self::LongTerm(x) => Some(x),
+            Self::ShortTerm => None,
+        }
+    }
+}
+
+/// A frame buffer, holding a frame, a field pair or a single field, and how each field is marked.
+#[derive(Copy, Clone, Debug)]
+struct Entry {
+    slot_index: u8,
+    frame_num: u16,
+    pic_order_cnt: PicOrderCnt,
+    // Decoded as a frame rather than as individual fields.
+    frame: bool,
+    top: Option<Marking>,
+    bottom: Option<Marking>,
+    // Inferred for a `frame_num` gap rather than decoded.
+    non_existing: bool,
+    // Stands in for a lost frame, sharing the slot of the frame it copies.
+    stand_in: bool,
+}
+
+impl Entry {
+    fn field(&mut self, bottom: bool) -> &mut Option<Marking> {
+        if bottom {
+            &mut self.bottom
+        } else {
+            &mut self.top
+        }
+    }
+
+    fn is_reference(&self) -> bool {
+        self.top.is_some() || self.bottom.is_some()
+    }
+
+    fn has_short_term(&self) -> bool {
+        self.top == Some(Marking::ShortTerm) || self.bottom == Some(Marking::ShortTerm)
+    }
+
+    /// A short-term reference frame or complementary field pair, as frame decoding sees it.
+    fn is_short_term_frame(&self) -> bool {
+        self.top == Some(Marking::ShortTerm) && self.bottom == Some(Marking::ShortTerm)
+    }
+
+    fn long_term_frame_idx(&self) -> Option<u32> {
+        [self.top, self.bottom].into_iter().flatten().find_map(Marking::long_term_frame_idx)
+    }
+
+    fn unmark_long_term(&mut self, long_term_frame_idx: u32) {
+        for field in [&mut self.top, &mut self.bottom] {
+            if *field == Some(Marking::LongTerm(long_term_frame_idx)) {
+                *field = None;
+            }
+        }
+    }
+}
+
+/// Tracks short- and long-term references and assigns Vulkan DPB slots to pictures.
+///
+/// Each picture in decoding order is passed to [`Dpb::add_picture`], which returns the slot to decode into along
+/// with the current references, and then applies the picture's `dec_ref_pic_marking()`. Both fields of a frame
+/// share one slot.
+///
+/// A slot is only reused once its picture is no longer a reference and has been output, so the next picture can't
+/// overwrite a frame still waiting in the [`OutputQueue`](crate::video::h264::OutputQueue). Pass each frame's slot
+/// to [`Dpb::release`] once it has been displayed.
+///
+/// Gaps in `frame_num` are filled with "non-existing" frames if the SPS allows them, otherwise frames were lost
+/// and the [`Concealment`] policy decides how to go on. Non-existing frames take a slot each, but nothing is ever
+/// decoded into it, so its content is undefined and must not be displayed.
+#[derive(Debug)]
+pub struct Dpb {
+    max_slots: u8,
+    entries: Vec<Entry>,
+    // Slots of pictures which have not been output yet.
+    awaiting_output: Vec<u8>,
+    // `None` means "no long-term frame indices".
+    max_long_term_frame_idx: Option<u32>,
+    // The previous picture if it was a field that is still waiting for its second field, and its slot.
+    first_field: Option<(FirstField, u8)>,
+    // `PrevRefFrameNum` (7.4.3), `None` before the first picture.
+    prev_ref_frame_num: Option<u16>,
+    concealment: Concealment,
+    // Frames were lost and we wait for the next IDR picture.
+    skipping: bool,
+    // No picture was added since the DPB was created or reset, so the session holds nothing we know of.
+    needs_reset: bool,
+}
+
+impl Dpb {
+    /// Creates a DPB with `max_slots` Vulkan slots, which should match [`VideoSession::max_dpb_slots`](crate::video::VideoSession::max_dpb_slots).
+    pub fn new(max_slots: u8) -> Self {
+        Self {
+            max_slots,
+            entries: Vec::new(),
+            awaiting_output: Vec::new(),
+            max_long_term_frame_idx: None,
+            first_field: None,
+            prev_ref_frame_num: None,
+            concealment: Concealment::default(),
+            skipping: false,
+            needs_reset: true,
+        }
+    }
+
+    /// Sets how to continue after frames were lost, defaults to [`Concealment::SkipToIdr`].
+    pub fn concealment(mut self, concealment: Concealment) -> Self {
+        self.concealment = concealment;
+        self
+    }
+
+    /// Drops all references and forgets about pictures waiting for output, e.g., after seeking.
+    ///
+    /// The next picture resets the video session.
+    pub fn reset(&mut self) {
+        self.awaiting_output.clear();
+        self.clear_references();
+        self.needs_reset = true;
+    }
+
+    /// Frees the slot of a picture once it has been output, i.e., returned by [`OutputQueue`](crate::video::h264::OutputQueue).
+    ///
+    /// Both fields of a frame are output together and released once.
+    pub fn release(&mut self, slot_index: u8) {
+        if let Some(i) = self.awaiting_output.iter().position(|x| *x == slot_index) {
+            self.awaiting_output.swap_remove(i);
+        }
+    }
+
+    fn clear_references(&mut self) {
+        self.entries.clear();
+        self.max_long_term_frame_idx = None;
+        self.first_field = None;
+        self.prev_ref_frame_num = None;
+        self.skipping = false;
+    }
+
+    /// Returns all pictures currently marked as used for reference.
+    pub fn references(&self) -> Vec<DpbReference> {
+        self.entries
+            .iter()
+            .enumerate()
+            // Vulkan takes one reference per slot, a frame standing in for a lost one only shows up as the latter.
+            .filter(|(i, x)| !self.entries[i + 1..].iter().any(|y| y.stand_in && y.slot_index == x.slot_index))
+            .map(|(_, x)| {
+                // Vulkan wants both field flags unset for frames, but a frame might also have lost one field's marking.
+                let fields = !(x.frame && x.top.is_some() && x.bottom.is_some());
+
+                DpbReference {
+                    slot_index: x.slot_index,
+                    frame_num: x.frame_num,
+                    long_term_frame_idx: x.long_term_frame_idx(),
+                    pic_order_cnt: x.pic_order_cnt,
+                    top_field: fields && x.top.is_some(),
+                    bottom_field: fields && x.bottom.is_some(),
+                    non_existing: x.non_existing,
+                }
+            })
+            .collect()
+    }
+
+    /// Assigns a slot to the picture starting with `slice`, returns what is needed to decode it, and updates the
+    /// reference marking for the following pictures.
+    ///
+    /// Fails with [`Variant::MissingReferences`] if the picture should not be decoded because frames were lost,
+    /// see [`Concealment`].
+    pub fn add_picture(&mut self, sps: &SeqParameterSet, slice: &SliceHeader, pic_order_cnt: PicOrderCnt) -> Result<DpbPicture, Error> {
+        let first_field = self.first_field.take().filter(|(x, _)| x.is_completed_by(slice));
+
+        if slice.idr {
+            self.clear_references();
+
+            // The output queue discards these pictures, nobody is going to release them.
+            if let Some(DecRefPicMarking::Idr {
+                no_output_of_prior_pics_flag: true,
+                ..
+            }) = slice.dec_ref_pic_marking
+            {
+                self.awaiting_output.clear();
+            }
+        } else {
+            self.handle_frame_num_gap(sps, slice)?;
+        }
+
+        if self.skipping {
+            return Err(error!(
+                Variant::MissingReferences,
+                "Skipping picture with frame_num {} until the next IDR picture", slice.frame_num
+            ));
+        }
+
+        let references = self.references();
+
+        let slot_index = match first_field {
+            Some((_, slot_index)) => slot_index,
+            None => {
+                let slot_index = self.free_slot()?;
+                self.awaiting_output.push(slot_index);
+                slot_index
+            }
+        };
+
+        let long_term_frame_idx = match &slice.dec_ref_pic_marking {
+            Some(marking) => self.mark(sps, slice, marking, slot_index, pic_order_cnt, first_field.is_some()),
+            None => None,
+        };
+
+        let picture = DpbPicture {
+            slot_index,
+            seq_parameter_set_id: slice.seq_parameter_set_id,
+            pic_parameter_set_id: slice.pic_parameter_set_id,
+            frame_num: slice.frame_num,
+            idr_pic_id: slice.idr_pic_id,
+            pic_order_cnt,
+            idr: slice.idr,
+            reset: slice.idr || self.needs_reset,
+            intra: matches!(slice.slice_type, SliceType::I | SliceType::SI),
+            reference: slice.is_reference(),
+            long_term_frame_idx,
+            field_pic_flag: slice.field_pic_flag,
+            bottom_field_flag: slice.bottom_field_flag,
+            second_field: first_field.is_some(),
+            display_rect: display_rect(sps),
+            color_info: color_info(sps),
+            references,
+        };
+
+        if first_field.is_none() {
+            self.first_field = FirstField::new(slice).map(|x| (x, slot_index));
+        }
+
+        self.needs_reset = false;
+
+        if slice.is_reference() {
+            let mmco5 = slice.dec_ref_pic_marking.as_ref().is_some_and(|x| x.has_mmco5());
+            self.prev_ref_frame_num = Some(if mmco5 { 0 } else { slice.frame_num });
+        }
+
+        Ok(picture)
+    }
+
+    fn free_slot(&self) -> Result<u8, Error> {
+        (0..self.max_slots)
+            .find(|x| self.entries.iter().all(|e| e.slot_index != *x) && !self.awaiting_output.contains(x))
+            .ok_or_else(|| {
+                error!(
+                    Variant::NoFreeDpbSlot,
+                    "All {} DPB slots hold references or pictures waiting for output", self.max_slots
+                )
+            })
+    }
+
+    /// Detects `frame_num` skipping ahead of `PrevRefFrameNum` (7.4.3), and fills the gap or conceals it.
+    fn handle_frame_num_gap(&mut self, sps: &SeqParameterSet, slice: &SliceHeader) -> Result<(), Error> {
+        let Some(prev_ref_frame_num) = self.prev_ref_frame_num else {
+            return Ok(());
+        };
+
+        let max_frame_num = 1i32 << sps.log2_max_frame_num();
+        let frame_num = i32::from(slice.frame_num);
+        let missing = (frame_num - i32::from(prev_ref_frame_num) - 1).rem_euclid(max_frame_num);
+
+        if self.skipping || frame_num == i32::from(prev_ref_frame_num) || missing == 0 {
+            return Ok(());
+        }
+
+        if sps.gaps_in_frame_num_value_allowed_flag {
+            self.add_non_existing(sps, prev_ref_frame_num, missing, max_frame_num)?;
+        } else if self.concealment == Concealment::SkipToIdr || !self.reuse_last_frame(sps, slice.frame_num, max_frame_num) {
+            self.skipping = true;
+            return Ok(());
+        }
+
+        self.prev_ref_frame_num = Some((frame_num - 1).rem_euclid(max_frame_num) as u16);
+
+        Ok(())
+    }
+
+    /// 8.2.5.2, marks `missing` non-existing frames following `prev_ref_frame_num` as short-term references.
+    fn add_non_existing(&mut self, sps: &SeqParameterSet, prev_ref_frame_num: u16, missing: i32, max_frame_num: i32) -> Result<(), Error> {
+        let max_refs = sps.max_num_ref_frames.max(1) as usize;
+
+        // The sliding window would remove all but the last `max_refs` frames of a long gap again right away.
+        for i in (missing - max_refs as i32).max(0)..missing {
+            let frame_num = (i32::from(prev_ref_frame_num) + 1 + i).rem_euclid(max_frame_num) as u16;
+
+            if !self.make_room(max_refs, frame_num, max_frame_num) {
+                break;
+            }
+
+            let slot_index = self.free_slot()?;
+
+            self.entries.push(Entry {
+                slot_index,
+                frame_num,
+                pic_order_cnt: PicOrderCnt::default(),
+                frame: true,
+                top: Some(Marking::ShortTerm),
+                bottom: Some(Marking::ShortTerm),
+                non_existing: true,
+                stand_in: false,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Marks the frame right before `frame_num` as short-term reference, sharing the slot and picture order count of
+    /// the most recent decoded short-term reference. Returns `false` if there is none.
+    fn reuse_last_frame(&mut self, sps: &SeqParameterSet, frame_num: u16, max_frame_num: i32) -> bool {
+        let max_refs = sps.max_num_ref_frames.max(1) as usize;
+        let last = self
+            .entries
+            .iter()
+            .filter(|x| x.has_short_term() && !x.non_existing)
+            .max_by_key(|x| frame_num_wrap(x.frame_num, frame_num, max_frame_num))
+            .copied();
+
+        let Some(last) = last else {
+            return false;
+        };
+
+        let missing = (i32::from(frame_num) - 1).rem_euclid(max_frame_num) as u16;
+
+        if self.make_room(max_refs, missing, max_frame_num) {
+            self.entries.push(Entry {
+                frame_num: missing,
+                frame: true,
+                top: Some(Marking::ShortTerm),
+                bottom: Some(Marking::ShortTerm),
+                stand_in: true,
+                ..last
+            });
+        }
+
+        true
+    }
+
+    /// 8.2.5.3, applies the sliding window until there is room for another reference, returns `false` if all
+    /// references are long-term.
+    fn make_room(&mut self, max_refs: usize, frame_num: u16, max_frame_num: i32) -> bool {
+        while self.entries.len() >= max_refs && self.entries.iter().any(|x| x.has_short_term()) {
+            self.remove_oldest_short_term(frame_num, max_frame_num);
+        }
+
+        self.entries.len() < max_refs
+    }
+
+    /// 8.2.5.1, marks the current picture as reference after applying `marking`, returns its long-term frame index if
+    /// it became a long-term reference.
+    fn mark(
+        &mut self,
+        sps: &SeqParameterSet,
+        slice: &SliceHeader,
+        marking: &DecRefPicMarking,
+        slot_index: u8,
+        mut pic_order_cnt: PicOrderCnt,
+        second_field: bool,
+    ) -> Option<u32> {
+        let max_frame_num = 1i32 << sps.log2_max_frame_num();
+        let mut current = Marking::ShortTerm;
+        let mut frame_num = slice.frame_num;
+
+        match marking {
+            DecRefPicMarking::Idr {
+                long_term_reference_flag, ..
+            } => {
+                if *long_term_reference_flag {
+                    current = Marking::LongTerm(0);
+                    self.max_long_term_frame_idx = Some(0);
+                } else {
+                    self.max_long_term_frame_idx = None;
+                }
+            }
+            DecRefPicMarking::SlidingWindow => {}
+            DecRefPicMarking::Adaptive(operations) => {
+                for operation in operations {
+                    if *operation == MemoryManagementControlOperation::AllUnusedForReference {
+                        self.entries.clear();
+                        self.max_long_term_frame_idx = None;
+
+                        // The current picture now counts as `frame_num` 0 with POCs relative to itself (8.2.1).
+                        match (slice.field_pic_flag, slice.bottom_field_flag) {
+                            (false, _) => {
+                                let temp_pic_order_cnt = pic_order_cnt.frame();
+                                pic_order_cnt.top -= temp_pic_order_cnt;
+                                pic_order_cnt.bottom -= temp_pic_order_cnt;
+                            }
+                            (true, false) => pic_order_cnt.top = 0,
+                            (true, true) => pic_order_cnt.bottom = 0,
+                        }
+
+                        frame_num = 0;
+                    } else {
+                        self.mmco(*operation, slice, max_frame_num, slot_index, &mut current);
+                    }
+                }
+
+                self.entries.retain(|x| x.is_reference());
+            }
+        }
+
+        // The second field joins its first field's frame buffer, which might have lost its marking in the meantime.
+        if let Some(entry) = self.entries.iter_mut().find(|x| second_field && x.slot_index == slot_index) {
+            *entry.field(slice.bottom_field_flag) = Some(current);
+            entry.frame_num = frame_num;
+
+            if slice.bottom_field_flag {
+                entry.pic_order_cnt.bottom = pic_order_cnt.bottom;
+            } else {
+                entry.pic_order_cnt.top = pic_order_cnt.top;
+            }
+
+            return current.long_term_frame_idx();
+        }
+
+        // Sliding window (8.2.5.3), which also keeps broken streams using adaptive marking within `max_num_ref_frames`.
+        let max_refs = sps.max_num_ref_frames.max(1) as usize;
+
+        while self.entries.len() >= max_refs && self.entries.iter().any(|x| x.has_short_term()) {
+            self.remove_oldest_short_term(slice.frame_num, max_frame_num);
+        }
+
+        if self.entries.len() < max_refs {
+            let mut entry = Entry {
+                slot_index,
+                frame_num,
+                pic_order_cnt,
+                frame: !slice.field_pic_flag,
+                top: None,
+                bottom: None,
+                non_existing: false,
+                stand_in: false,
+            };
+
+            if slice.field_pic_flag {
+                *entry.field(slice.bottom_field_flag) = Some(current);
+            } else {
+                entry.top = Some(current);
+                entry.bottom = Some(current);
+            }
+
+            self.entries.push(entry);
+        }
+
+        current.long_term_frame_idx()
+    }
+
+    /// 8.2.5.4, except for MMCO 5 which the caller handles.
+    fn mmco(
+        &mut self,
+        operation: MemoryManagementControlOperation,
+        slice: &SliceHeader,
+        max_frame_num: i32,
+        slot_index: u8,
+        current: &mut Marking,
+    ) {
+        use MemoryManagementControlOperation as Mmco;
+
+        let frame_num = slice.frame_num;
+        // `None` when decoding a frame, otherwise the parity of the current field.
+        let field = slice.field_pic_flag.then_some(slice.bottom_field_flag);
+
+        let curr_pic_num = match field {
+            None => i32::from(frame_num),
+            Some(_) => 2 * i32::from(frame_num) + 1,
+        };
+
+        // `PicNum` (8-28 to 8-30) of a short-term field with parity `bottom` in a frame buffer with `frame_num`.
+        let pic_num = |entry_frame_num: u16, bottom: bool| {
+            let frame_num_wrap = frame_num_wrap(entry_frame_num, frame_num, max_frame_num);
+
+            match field {
+                None => frame_num_wrap,
+                Some(x) => 2 * frame_num_wrap + i32::from(x == bottom),
+            }
+        };
+
+        // `LongTermPicNum` (8-31 to 8-33) of a long-term field with parity `bottom` and `long_term_frame_idx`.
+        let long_term_pic_num = |long_term_frame_idx: u32, bottom: bool| match field {
+            None => long_term_frame_idx,
+            Some(x) => 2 * long_term_frame_idx + u32::from(x == bottom),
+        };
+
+        match operation {
+            Mmco::ShortTermUnusedForReference {
+                difference_of_pic_nums_minus1,
+            } => {
+                let pic_num_x = curr_pic_num.wrapping_sub(difference_of_pic_nums_minus1 as i32).wrapping_sub(1);
+
+                for entry in &mut self.entries {
+                    match field {
+                        None if entry.is_short_term_frame() && pic_num(entry.frame_num, false) == pic_num_x => {
+                            entry.top = None;
+                            entry.bottom = None;
+                        }
+                        None => {}
+                        Some(_) => {
+                            for bottom in [false, true] {
+                                if *entry.field(bottom) == Some(Marking::ShortTerm) && pic_num(entry.frame_num, bottom) == pic_num_x {
+                                    *entry.field(bottom) = None;
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            Mmco::LongTermUnusedForReference { long_term_pic_num: x } => {
+                for entry in &mut self.entries {
+                    for bottom in [false, true] {
+                        let matches = match *entry.field(bottom) {
+                            Some(Marking::LongTerm(idx)) => long_term_pic_num(idx, bottom) == x,
+                            _ => false,
+                        };
+
+                        // Frames are only ever unmarked as a whole.
+                        if matches && field.is_none() {
+                            entry.top = None;
+                            entry.bottom = None;
+                        } else if matches {
+                            *entry.field(bottom) = None;
+                        }
+                    }
+                }
+            }
+            Mmco::ShortTermToLongTerm {
+                difference_of_pic_nums_minus1,
+                long_term_frame_idx,
+            } => {
+                let pic_num_x = curr_pic_num.wrapping_sub(difference_of_pic_nums_minus1 as i32).wrapping_sub(1);
+
+                let target = self.entries.iter().enumerate().find_map(|(i, x)| match field {
+                    None if x.is_short_term_frame() && pic_num(x.frame_num, false) == pic_num_x => Some((i, None)),
+                    None => None,
+                    Some(_) => [false, true]
+                        .into_iter()
+                        .find(|bottom| {
+                            let marking = if *bottom { x.bottom } else { x.top };
+                            marking == Some(Marking::ShortTerm) && pic_num(x.frame_num, *bottom) == pic_num_x
+                        })
+                        .map(|bottom| (i, Some(bottom))),
+                });
+
+                // The index moves here, unless it already belongs to the other field of the same frame.
+                for (i, entry) in self.entries.iter_mut().enumerate() {
+                    if target.map(|(t, _)| t) != Some(i) || field.is_none() {
+                        entry.unmark_long_term(long_term_frame_idx);
+                    }
+                }
+
+                match target {
+                    Some((i, None)) => {
+                        self.entries[i].top = Some(Marking::LongTerm(long_term_frame_idx));
+                        self.entries[i].bottom = Some(Marking::LongTerm(long_term_frame_idx));
+                    }
+                    Some((i, Some(bottom))) => *self.entries[i].field(bottom) = Some(Marking::LongTerm(long_term_frame_idx)),
+                    None => {}
+                }
+            }
+            Mmco::MaxLongTermFrameIdx {
+                max_long_term_frame_idx_plus1,
+            } => {
+                self.max_long_term_frame_idx = max_long_term_frame_idx_plus1.checked_sub(1);
+                let max = self.max_long_term_frame_idx;
+
+                for entry in &mut self.entries {
+                    for bottom in [false, true] {
+                        if let Some(Marking::LongTerm(idx)) = *entry.field(bottom) {
+                            if max.is_none_or(|max| idx > max) {
+                                *entry.field(bottom) = None;
+                            }
+                        }
+                    }
+                }
+            }
+            Mmco::AllUnusedForReference => {}
+            Mmco::CurrentToLongTerm { long_term_frame_idx } => {
+                // Keep the index if the first field of the current frame holds it.
+                for entry in &mut self.entries {
+                    if field.is_none() || entry.slot_index != slot_index {
+                        entry.unmark_long_term(long_term_frame_idx);
+                    }
+                }
+
+                *current = Marking::LongTerm(long_term_frame_idx);
+            }
+        }
+    }
+
+    /// Unmarks the short-term frame, field pair or field with the smallest `FrameNumWrap`.
+    fn remove_oldest_short_term(&mut self, frame_num: u16, max_frame_num: i32) {
+        let oldest = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| x.has_short_term())
+            .min_by_key(|(_, x)| frame_num_wrap(x.frame_num, frame_num, max_frame_num))
+            .map(|(i, _)| i);
+
+        if let Some(i) = oldest {
+            let entry = &mut self.entries[i];
+
+            for bottom in [false, true] {
+                if *entry.field(bottom) == Some(Marking::ShortTerm) {
+                    *entry.field(bottom) = None;
+                }
+            }
+
+            self.entries.retain(|x| x.is_reference());
+        }
+    }
+}
+
+/// `FrameNumWrap` of a short-term reference with `frame_num`, while decoding `current_frame_num` (8-27).
+fn frame_num_wrap(frame_num: u16, current_frame_num: u16, max_frame_num: i32) -> i32 {
+    if frame_num > current_frame_num {
+        i32::from(frame_num) - max_frame_num
+    } else {
+        i32::from(frame_num)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Concealment, Dpb, DpbPicture};
+    use crate::error::{Error, Variant};
+    use crate::video::h264::testdata::{idr_slice_header, slice_header, sps};
+    use crate::video::h264::{DecRefPicMarking, MemoryManagementControlOperation, PicOrderCnt, SliceHeader};
+    use h264_reader::nal::sps::SeqParameterSet;
+
+    // Baseline, 4 bit `frame_num`, POC type 2, 2 ref frames, 4x4 MBs, frame MBs only
+    fn sps_2_refs() -> SeqParameterSet {
+        sps("01000010 00000000 00011110 1 1 011 011 0 00100 00100 1 1 0 0")
+    }
+
+    // As above, but with `gaps_in_frame_num_value_allowed_flag` set.
+    fn sps_2_refs_gaps() -> SeqParameterSet {
+        sps("01000010 00000000 00011110 1 1 011 011 1 00100 00100 1 1 0 0")
+    }
+
+    /// Adds a picture which is output right away, as if the stream had no reordering.
+    fn add(dpb: &mut Dpb, sps: &SeqParameterSet, slice: &SliceHeader, pic_order_cnt: PicOrderCnt) -> Result<DpbPicture, Error> {
+        let picture = dpb.add_picture(sps, slice, pic_order_cnt)?;
+        dpb.release(picture.slot_index);
+
+        Ok(picture)
+    }
+
+    fn poc(x: i32) -> PicOrderCnt {
+        PicOrderCnt { top: x, bottom: x }
+    }
+
+    fn adaptive(frame_num: u16, operations: &[MemoryManagementControlOperation]) -> SliceHeader {
+        SliceHeader {
+            dec_ref_pic_marking: Some(DecRefPicMarking::Adaptive(operations.to_vec())),
+            ..slice_header(frame_num, 1)
+        }
+    }
+
+    fn frame_nums(dpb: &Dpb) -> Vec<u16> {
+        dpb.references().iter().map(|x| x.frame_num).collect()
+    }
+
+    fn field(slice: SliceHeader, bottom_field_flag: bool) -> SliceHeader {
+        SliceHeader {
+            field_pic_flag: true,
+            bottom_field_flag,
+            ..slice
+        }
+    }
+
+    fn fields(dpb: &Dpb) -> Vec<(bool, bool)> {
+        dpb.references().iter().map(|x| (x.top_field, x.bottom_field)).collect()
+    }
+
+    #[test]
+    fn sliding_window() -> Result<(), Error> {
+        let sps = sps_2_refs();
+        let mut dpb = Dpb::new(3);
+
+        let idr = add(&mut dpb, &sps, &idr_slice_header(0), poc(0))?;
+        let p1 = add(&mut dpb, &sps, &slice_header(1, 1), poc(2))?;
+        let b = add(&mut dpb, &sps, &slice_header(2, 0), poc(3))?;
+        let p2 = add(&mut dpb, &sps, &slice_header(2, 1), poc(4))?;
+
+        assert!(idr.references.is_empty());
+        assert_eq!(p1.references.len(), 1);
+        assert_eq!(b.references.len(), 2);
+        assert_eq!(p2.references.len(), 2);
+
+        // Output right away, the non-reference picture doesn't hold on to its slot. The IDR's slot was freed by the sliding window.
+        assert_eq!(b.slot_index, 2);
+        assert_eq!(p2.slot_index, 2);
+        assert_eq!(frame_nums(&dpb), vec![1, 2]);
+
+        // Wrap: 0 is newer than 15.
+        for frame_num in 3..16 {
+            add(&mut dpb, &sps, &slice_header(frame_num, 1), poc(0))?;
+        }
+
+        add(&mut dpb, &sps, &slice_header(0, 1), poc(0))?;
+        add(&mut dpb, &sps, &slice_header(1, 1), poc(0))?;
+
+        assert_eq!(frame_nums(&dpb), vec![0, 1]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn slots_wait_for_output() -> Result<(), Error> {
+        let sps = sps_2_refs();
+        let mut dpb = Dpb::new(3);
+
+        let idr = dpb.add_picture(&sps, &idr_slice_header(0), poc(0))?;
+        let b = dpb.add_picture(&sps, &slice_header(1, 0), poc(1))?;
+        let p = dpb.add_picture(&sps, &slice_header(1, 1), poc(4))?;
+
+        // The non-reference picture keeps its slot until it was output.
+        assert_eq!((idr.slot_index, b.slot_index, p.slot_index), (0, 1, 2));
+
+        let full = dpb.add_picture(&sps, &slice_header(2, 1), poc(6)).map(|_| ()).unwrap_err();
+
+        assert!(matches!(full.variant(), Variant::NoFreeDpbSlot));
+
+        dpb.release(b.slot_index);
+
+        assert_eq!(dpb.add_picture(&sps, &slice_header(2, 1), poc(6))?.slot_index, 1);
+
+        // Pictures an IDR picture discards without output don't hold on to their slots.
+        let no_output = SliceHeader {
+            dec_ref_pic_marking: Some(DecRefPicMarking::Idr {
+                no_output_of_prior_pics_flag: true,
+                long_term_reference_flag: false,
+            }),
+            ..idr_slice_header(1)
+        };
+
+        assert_eq!(dpb.add_picture(&sps, &no_output, poc(0))?.slot_index, 0);
+        assert_eq!(dpb.add_picture(&sps, &slice_header(1, 1), poc(2))?.slot_index, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn mmco_long_term() -> Result<(), Error> {
+        use MemoryManagementControlOperation as Mmco;
+
+        let sps = sps_2_refs();
+        let mut dpb = Dpb::new(3);
+
+        add(&mut dpb, &sps, &idr_slice_header(0), poc(0))?;
+
+        // Allow long-term index 0, then move the current picture there.
+        let slice = adaptive(
+            1,
+            &[
+                Mmco::MaxLongTermFrameIdx {
+                    max_long_term_frame_idx_plus1: 1,
+                },
+                Mmco::CurrentToLongTerm { long_term_frame_idx: 0 },
+            ],
+        );
+        let long_term = add(&mut dpb, &sps, &slice, poc(2))?;
+
+        assert_eq!(long_term.as_reference().long_term_frame_idx, Some(0));
+
+        // Sliding window must not evict the long-term reference.
+        add(&mut dpb, &sps, &slice_header(2, 1), poc(4))?;
+        add(&mut dpb, &sps, &slice_header(3, 1), poc(6))?;
+
+        let references = dpb.references();
+
+        assert_eq!(references.len(), 2);
+        assert_eq!(references[0].long_term_frame_idx, Some(0));
+        assert_eq!(references[0].frame_num, 1);
+        assert_eq!(references[1].frame_num, 3);
+
+        // Drop frame 3 (picNumX = 4 - 0 - 1) and the long-term picture.
+        let slice = adaptive(
+            4,
+            &[
+                Mmco::ShortTermUnusedForReference {
+                    difference_of_pic_nums_minus1: 0,
+                },
+                Mmco::LongTermUnusedForReference { long_term_pic_num: 0 },
+            ],
+        );
+        add(&mut dpb, &sps, &slice, poc(8))?;
+
+        assert_eq!(frame_nums(&dpb), vec![4]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn mmco_short_to_long_and_reset() -> Result<(), Error> {
+        use MemoryManagementControlOperation as Mmco;
+
+        let sps = sps_2_refs();
+        let mut dpb = Dpb::new(3);
+
+        add(&mut dpb, &sps, &idr_slice_header(0), poc(0))?;
+        dpb.add_picture(
+            &sps,
+            &adaptive(
+                1,
+                &[
+                    Mmco::MaxLongTermFrameIdx {
+                        max_long_term_frame_idx_plus1: 2,
+                    },
+                    Mmco::ShortTermToLongTerm {
+                        difference_of_pic_nums_minus1: 0,
+                        long_term_frame_idx: 1,
+                    },
+                ],
+            ),
+            poc(2),
+        )?;
+
+        assert_eq!(dpb.references()[0].long_term_frame_idx, Some(1));
+
+        let picture = add(&mut dpb, &sps, &adaptive(2, &[Mmco::AllUnusedForReference]), poc(4))?;
+        let references = dpb.references();
+
+        assert_eq!(picture.references.len(), 2);
+        assert_eq!(references.len(), 1);
+        assert_eq!(references[0].frame_num, 0);
+        assert_eq!(references[0].pic_order_cnt, poc(0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn idr_clears_references() -> Result<(), Error> {
+        let sps = sps_2_refs();
+        let mut dpb = Dpb::new(3);
+
+        add(&mut dpb, &sps, &idr_slice_header(0), poc(0))?;
+        let p = add(&mut dpb, &sps, &slice_header(1, 1), poc(2))?;
+        let idr = add(&mut dpb, &sps, &idr_slice_header(1), poc(0))?;
+
+        assert!(!p.reset);
+        assert!(idr.idr && idr.reset);
+
+        // Decoding from a recovery point, e.g., after seeking, starts with a session reset as well.
+        let mut seeking = Dpb::new(3);
+        let first = add(&mut seeking, &sps, &slice_header(1, 1), poc(2))?;
+        let second = add(&mut seeking, &sps, &slice_header(2, 1), poc(4))?;
+
+        seeking.reset();
+
+        let after_seek = add(&mut seeking, &sps, &slice_header(5, 1), poc(10))?;
+
+        assert!(!first.idr && first.reset);
+        assert!(!second.reset);
+        assert!(after_seek.reset);
+        assert!(idr.references.is_empty());
+        assert_eq!(idr.slot_index, 0);
+        assert_eq!(dpb.references().len(), 1);
+
+        // The setup reference of a long-term IDR picture is long-term as well.
+        let long_term_idr = SliceHeader {
+            dec_ref_pic_marking: Some(DecRefPicMarking::Idr {
+                no_output_of_prior_pics_flag: false,
+                long_term_reference_flag: true,
+            }),
+            ..idr_slice_header(2)
+        };
+        let idr = add(&mut dpb, &sps, &long_term_idr, poc(0))?;
+
+        assert_eq!(idr.as_reference().long_term_frame_idx, Some(0));
+        assert_eq!(dpb.references()[0].long_term_frame_idx, Some(0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn field_pairs_share_slots() -> Result<(), Error> {
+        let sps = sps_2_refs();
+        let mut dpb = Dpb::new(3);
+
+        let top = add(&mut dpb, &sps, &field(idr_slice_header(0), false), poc(0))?;
+        let bottom = add(&mut dpb, &sps, &field(slice_header(0, 1), true), poc(1))?;
+
+        assert!(!top.second_field);
+        assert!(bottom.second_field);
+        assert_eq!(bottom.slot_index, top.slot_index);
+        assert_eq!(bottom.references.len(), 1);
+        assert_eq!((bottom.references[0].top_field, bottom.references[0].bottom_field), (true, false));
+        assert_eq!(dpb.references()[0].pic_order_cnt, PicOrderCnt { top: 0, bottom: 1 });
+        assert_eq!(fields(&dpb), vec![(true, true)]);
+
+        // A non-reference field pair, then a lone bottom field which must not pair with the earlier top field.
+        let non_ref_top = add(&mut dpb, &sps, &field(slice_header(1, 0), false), poc(2))?;
+        let non_ref_bottom = add(&mut dpb, &sps, &field(slice_header(1, 0), true), poc(3))?;
+        let lone = add(&mut dpb, &sps, &field(slice_header(1, 1), true), poc(5))?;
+
+        assert_eq!(non_ref_bottom.slot_index, non_ref_top.slot_index);
+        assert!(non_ref_bottom.second_field);
+        assert!(!lone.second_field);
+
+        // Frame 0 and the lone field fill up the window, so frame 0 goes once the new field is decoded.
+        let p = add(&mut dpb, &sps, &field(slice_header(2, 1), false), poc(6))?;
+
+        assert_eq!(p.slot_index, 2);
+        assert_eq!(frame_nums(&dpb), vec![1, 2]);
+        assert_eq!(fields(&dpb), vec![(false, true), (true, false)]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn field_mmco() -> Result<(), Error> {
+        use MemoryManagementControlOperation as Mmco;
+
+        let sps = sps_2_refs();
+        let mut dpb = Dpb::new(3);
+
+        add(&mut dpb, &sps, &field(idr_slice_header(0), false), poc(0))?;
+        add(&mut dpb, &sps, &field(slice_header(0, 1), true), poc(1))?;
+
+        // `CurrPicNum` is 3, so the bottom field of frame 0 has `PicNum` 0 and the top field 1.
+        let slice = field(
+            adaptive(
+                1,
+                &[Mmco::ShortTermUnusedForReference {
+                    difference_of_pic_nums_minus1: 2,
+                }],
+            ),
+            false,
+        );
+        add(&mut dpb, &sps, &slice, poc(2))?;
+
+        assert_eq!(fields(&dpb), vec![(true, false), (true, false)]);
+
+        // The second field turns its first field long-term (`PicNum` 2 * 1 + 0), and then itself.
+        let slice = field(
+            adaptive(
+                1,
+                &[
+                    Mmco::MaxLongTermFrameIdx {
+                        max_long_term_frame_idx_plus1: 1,
+                    },
+                    Mmco::ShortTermToLongTerm {
+                        difference_of_pic_nums_minus1: 0,
+                        long_term_frame_idx: 0,
+                    },
+                    Mmco::CurrentToLongTerm { long_term_frame_idx: 0 },
+                ],
+            ),
+            true,
+        );
+        let second = add(&mut dpb, &sps, &slice, poc(3))?;
+        let references = dpb.references();
+
+        assert!(second.second_field);
+        assert_eq!(references.len(), 2);
+        assert_eq!(references[1].long_term_frame_idx, Some(0));
+        assert_eq!((references[1].top_field, references[1].bottom_field), (true, true));
+
+        Ok(())
+    }
+
+    #[test]
+    fn frame_num_gaps() -> Result<(), Error> {
+        use MemoryManagementControlOperation as Mmco;
+
+        // Allowed gaps are filled with non-existing frames, of which only the last 2 fit.
+        let sps = sps_2_refs_gaps();
+        let mut dpb = Dpb::new(3);
+
+        add(&mut dpb, &sps, &idr_slice_header(0), poc(0))?;
+        let p = add(&mut dpb, &sps, &slice_header(5, 1), poc(10))?;
+
+        assert_eq!(
+            p.references.iter().map(|x| (x.frame_num, x.non_existing)).collect::<Vec<_>>(),
+            vec![(3, true), (4, true)]
+        );
+        assert_eq!(frame_nums(&dpb), vec![4, 5]);
+        assert!(!dpb.references()[1].non_existing);
+
+        // Lost frames skip everything until the next IDR by default.
+        let sps = sps_2_refs();
+        let mut dpb = Dpb::new(3);
+
+        add(&mut dpb, &sps, &idr_slice_header(0), poc(0))?;
+        add(&mut dpb, &sps, &slice_header(1, 1), poc(2))?;
+
+        let lost = add(&mut dpb, &sps, &slice_header(3, 1), poc(6)).map(|_| ()).unwrap_err();
+        let skipped = add(&mut dpb, &sps, &slice_header(4, 1), poc(8)).map(|_| ()).unwrap_err();
+
+        assert!(matches!(lost.variant(), Variant::MissingReferences));
+        assert!(matches!(skipped.variant(), Variant::MissingReferences));
+        assert!(add(&mut dpb, &sps, &idr_slice_header(1), poc(0))?.references.is_empty());
+        assert_eq!(add(&mut dpb, &sps, &slice_header(1, 1), poc(2))?.references.len(), 1);
+
+        // Or the last good frame stands in for the lost one, sharing its slot.
+        let mut dpb = Dpb::new(3).concealment(Concealment::ReuseLastFrame);
+
+        add(&mut dpb, &sps, &idr_slice_header(0), poc(0))?;
+        let good = add(&mut dpb, &sps, &slice_header(1, 1), poc(2))?;
+        let p = add(&mut dpb, &sps, &slice_header(3, 1), poc(6))?;
+
+        assert_eq!(
+            p.references
+                .iter()
+                .map(|x| (x.slot_index, x.frame_num, x.non_existing))
+                .collect::<Vec<_>>(),
+            vec![(good.slot_index, 2, false)]
+        );
+        assert_eq!(frame_nums(&dpb), vec![2, 3]);
+        assert_eq!(add(&mut dpb, &sps, &slice_header(4, 1), poc(8))?.references.len(), 2);
+
+        // The last good frame keeps its own `frame_num`, so unmarking the lost frame (`PicNum` 2) leaves it alone.
+        let mut dpb = Dpb::new(3).concealment(Concealment::ReuseLastFrame);
+
+        add(&mut dpb, &sps, &idr_slice_header(0), poc(0))?;
+        add(&mut dpb, &sps, &slice_header(1, 1), poc(2))?;
+        let slice = adaptive(
+            3,
+            &[Mmco::ShortTermUnusedForReference {
+                difference_of_pic_nums_minus1: 0,
+            }],
+        );
+        add(&mut dpb, &sps, &slice, poc(6))?;
+
+        assert_eq!(frame_nums(&dpb), vec![1, 3]);
+
+        Ok(())
+    }
+}
@@ -1,93 +1,257 @@
-use crate::Error;
+use crate::error;
+use crate::error::{Error, Variant};
+use crate::video::h264::scaling::{pps_scaling_lists, sps_scaling_lists};
+use crate::video::h264::sei::{parse_sei, SeiMessage};
+use crate::video::h264::slice::SliceHeader;
+use crate::video::h264::stdvideo::{StdPps, StdSps};
+use crate::video::profile::{component_bit_depth, VideoProfileInfoBundle};
+use crate::video::{strip_annexb, SessionRequirements};
+use ash::vk::native::{
+    StdVideoH264ProfileIdc_STD_VIDEO_H264_PROFILE_IDC_BASELINE, StdVideoH264ProfileIdc_STD_VIDEO_H264_PROFILE_IDC_HIGH,
+    StdVideoH264ProfileIdc_STD_VIDEO_H264_PROFILE_IDC_HIGH_444_PREDICTIVE, StdVideoH264ProfileIdc_STD_VIDEO_H264_PROFILE_IDC_MAIN,
+    StdVideoH264ScalingLists,
+};
 use ash::vk::{
     VideoChromaSubsamplingFlagsKHR, VideoCodecOperationFlagsKHR, VideoComponentBitDepthFlagsKHR, VideoDecodeH264PictureLayoutFlagsKHR,
     VideoDecodeH264ProfileInfoKHR, VideoProfileInfoKHR, VideoProfileListInfoKHR,
 };
-use h264_reader::annexb::AnnexBReader;
-use h264_reader::nal::pps::PicParameterSet;
-use h264_reader::nal::sps::SeqParameterSet;
-use h264_reader::nal::{Nal, NalHeader, NalHeaderError, RefNal, UnitType};
-use h264_reader::push::{NalFragmentHandler, NalInterest};
+use h264_reader::nal::pps::{ParamSetId, PicParameterSet};
+use h264_reader::nal::sps::{ChromaFormat, FrameMbsFlags, SeqParameterSet};
+use h264_reader::nal::{Nal, RefNal, UnitType};
+use h264_reader::rbsp::{BitRead, BitReaderError};
 use h264_reader::Context;
-use std::marker::PhantomPinned;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::io::Read;
 use std::pin::Pin;
 use std::ptr::addr_of;
 
-#[derive(Default)]
-pub struct VideoProfileInfoBundle<'a> {
-    pub(crate) info_h264: VideoDecodeH264ProfileInfoKHR<'a>,
-    pub(crate) info: VideoProfileInfoKHR<'a>,
-    pub(crate) list: VideoProfileListInfoKHR<'a>,
-    _pinned: PhantomPinned,
-}
-
 /// Parses H.264 NAL units and returns mata data we need to feed into Vulkan.
 #[derive(Default)]
 pub struct H264StreamInspector {
     h264_context: Context,
-    h264_feeding_vec: Vec<u8>,
+    sps_scaling_lists: HashMap<u8, StdVideoH264ScalingLists>,
+    pps_scaling_lists: HashMap<u8, StdVideoH264ScalingLists>,
+    last_sps_id: Option<u8>,
+    // A hash of each parameter set's content by id, so session parameters can tell what changed.
+    sps_fingerprints: HashMap<u8, u64>,
+    pps_fingerprints: HashMap<u8, u64>,
 }
 
-pub enum XXX {
+/// A NAL unit [`H264StreamInspector::feed_nal`] understood and extracted information from.
+#[derive(Debug)]
+pub enum ParsedNal {
     Sps(SeqParameterSet),
     Pps(PicParameterSet),
+    Sei(Vec<SeiMessage>),
+    Slice(SliceHeader),
 }
 
 impl H264StreamInspector {
     pub fn new() -> Self {
         Self {
             h264_context: Default::default(),
-            h264_feeding_vec: Vec::with_capacity(32 * 1024),
+            sps_scaling_lists: HashMap::new(),
+            pps_scaling_lists: HashMap::new(),
+            last_sps_id: None,
+            sps_fingerprints: HashMap::new(),
+            pps_fingerprints: HashMap::new(),
         }
     }
 
-    pub fn feed_nal(&mut self, nal: &[u8]) -> Option<XXX> {
-        let rval = None;
+    /// Feeds a single NAL unit, with or without Annex B start code, and records any parameter sets in it.
+    ///
+    /// Returns `Ok(None)` for NAL units we don't care about. Malformed or unsupported input results
+    /// in an error and leaves the inspector unchanged, so callers can skip the offending NAL and continue.
+    pub fn feed_nal(&mut self, nal: &[u8]) -> Result<Option<ParsedNal>, Error> {
+        let payload = strip_annexb(nal);
 
-        // TODO: This is ugly as there does not seem to be a good way to signal errors within this accumulate function.
-        let mut reader = AnnexBReader::accumulate(|nal: RefNal<'_>| {
-            let nal_unit_type = nal.header().unwrap().nal_unit_type(); // TODO: Remove unwrap(), see above.
-            let bits = nal.rbsp_bits();
+        if payload.is_empty() {
+            return Err(error!(Variant::MalformedNalHeader, "NAL unit is empty"));
+        }
 
-            match nal_unit_type {
-                UnitType::SeqParameterSet => {
-                    let sps = SeqParameterSet::from_bits(bits).unwrap(); // TODO: Remove unwrap(), see above.
+        let nal = RefNal::new(payload, &[], true);
+        let header = nal
+            .header()
+            .map_err(|e| error!(Variant::MalformedNalHeader, "Invalid NAL header {:#04x}: {:?}", payload[0], e))?;
 
-                    dbg!(&sps.chroma_info);
+        match header.nal_unit_type() {
+            UnitType::SeqParameterSet => {
+                let sps = SeqParameterSet::from_bits(nal.rbsp_bits()).map_err(|e| error!(Variant::InvalidSps, "{:?}", e))?;
+                let scaling_lists = sps_scaling_lists(nal.rbsp_bits()).map_err(|e| error!(Variant::InvalidSps, "{:?}", e))?;
 
-                    self.h264_context.put_seq_param_set(sps);
+                if let ChromaFormat::Invalid(x) = sps.chroma_info.chroma_format {
+                    return Err(error!(Variant::InvalidSps, "Invalid chroma_format_idc {}", x));
                 }
-                UnitType::PicParameterSet => {
-                    // TODO: Remove unwrap(), see above.
-                    let _pps = PicParameterSet::from_bits(&self.h264_context, bits).unwrap();
+
+                match scaling_lists {
+                    Some(x) => self.sps_scaling_lists.insert(sps.id().id(), x),
+                    None => self.sps_scaling_lists.remove(&sps.id().id()),
+                };
+
+                self.last_sps_id = Some(sps.id().id());
+                self.sps_fingerprints.insert(sps.id().id(), fingerprint(payload));
+                self.h264_context.put_seq_param_set(sps.clone());
+
+                Ok(Some(ParsedNal::Sps(sps)))
+            }
+            UnitType::PicParameterSet => {
+                // `h264-reader` mis-parses some slice group maps, so bail before it gets to see them.
+                if pps_has_slice_groups(nal.rbsp_bits()).map_err(|e| error!(Variant::InvalidPps, "{:?}", e))? {
+                    return Err(error!(Variant::UnsupportedFeature, "PPS uses slice groups (FMO)"));
                 }
-                _ => {} // _ => NalInterest::Ignore,
+
+                let pps =
+                    PicParameterSet::from_bits(&self.h264_context, nal.rbsp_bits()).map_err(|e| error!(Variant::InvalidPps, "{:?}", e))?;
+
+                // Always present, otherwise the PPS above would not have parsed.
+                let sps = self
+                    .h264_context
+                    .sps_by_id(pps.seq_parameter_set_id)
+                    .ok_or_else(|| error!(Variant::InvalidPps, "Unknown SPS {}", pps.seq_parameter_set_id.id()))?;
+
+                let scaling_lists = pps_scaling_lists(nal.rbsp_bits(), sps).map_err(|e| error!(Variant::InvalidPps, "{:?}", e))?;
+
+                match scaling_lists {
+                    Some(x) => self.pps_scaling_lists.insert(pps.pic_parameter_set_id.id(), x),
+                    None => self.pps_scaling_lists.remove(&pps.pic_parameter_set_id.id()),
+                };
+
+                self.pps_fingerprints.insert(pps.pic_parameter_set_id.id(), fingerprint(payload));
+                self.h264_context.put_pic_param_set(pps.clone());
+
+                Ok(Some(ParsedNal::Pps(pps)))
             }
+            UnitType::SEI => {
+                let mut rbsp = Vec::new();
+                nal.rbsp_bytes()
+                    .read_to_end(&mut rbsp)
+                    .map_err(|e| error!(Variant::InvalidSei, "{:?}", e))?;
 
-            NalInterest::Ignore // TODO: What's the right choice?
-        });
+                let messages = parse_sei(&rbsp, self.last_sps())?;
 
-        self.h264_feeding_vec.clear();
-        self.h264_feeding_vec.extend_from_slice(nal);
-        self.h264_feeding_vec.extend_from_slice(&[0x00, 0x00]); // For whatever reason we need these as well
-        reader.push(self.h264_feeding_vec.as_slice());
+                Ok(Some(ParsedNal::Sei(messages)))
+            }
+            UnitType::SliceLayerWithoutPartitioningNonIdr | UnitType::SliceLayerWithoutPartitioningIdr => {
+                let slice = SliceHeader::from_bits(&self.h264_context, header, nal.rbsp_bits())?;
 
-        rval
+                Ok(Some(ParsedNal::Slice(slice)))
+            }
+            UnitType::SliceDataPartitionALayer | UnitType::SliceDataPartitionBLayer | UnitType::SliceDataPartitionCLayer => {
+                Err(error!(Variant::UnsupportedFeature, "Slice data partitioning is not supported"))
+            }
+            _ => Ok(None),
+        }
     }
 
-    pub fn profiles<'f>(&self) -> Pin<Box<VideoProfileInfoBundle<'f>>> {
+    /// The most recent SPS with the given id.
+    pub fn sps(&self, id: u8) -> Option<&SeqParameterSet> {
+        self.h264_context.sps_by_id(ParamSetId::from_u32(id.into()).ok()?)
+    }
+
+    /// The most recent PPS with the given id.
+    pub fn pps(&self, id: u8) -> Option<&PicParameterSet> {
+        self.h264_context.pps_by_id(ParamSetId::from_u32(id.into()).ok()?)
+    }
+
+    /// The scaling lists of the most recent SPS with the given id, if it has any.
+    pub fn sps_scaling_lists(&self, id: u8) -> Option<&StdVideoH264ScalingLists> {
+        self.sps_scaling_lists.get(&id)
+    }
+
+    /// The scaling lists of the most recent PPS with the given id, if it has any.
+    pub fn pps_scaling_lists(&self, id: u8) -> Option<&StdVideoH264ScalingLists> {
+        self.pps_scaling_lists.get(&id)
+    }
+
+    /// Returns all SPS seen so far, translated for Vulkan.
+    pub(crate) fn std_sps(&self) -> Vec<StdSps> {
+        self.h264_context
+            .sps()
+            .map(|sps| StdSps::new(sps, self.sps_scaling_lists.get(&sps.id().id())))
+            .collect()
+    }
+
+    /// Returns all PPS seen so far, translated for Vulkan.
+    pub(crate) fn std_pps(&self) -> Vec<StdPps> {
+        self.h264_context
+            .pps()
+            .map(|pps| StdPps::new(pps, self.pps_scaling_lists.get(&pps.pic_parameter_set_id.id())))
+            .collect()
+    }
+
+    /// Identifies the content of each SPS seen so far, by id.
+    pub(crate) fn sps_fingerprints(&self) -> &HashMap<u8, u64> {
+        &self.sps_fingerprints
+    }
+
+    /// Identifies the content of each PPS seen so far, by id.
+    pub(crate) fn pps_fingerprints(&self) -> &HashMap<u8, u64> {
+        &self.pps_fingerprints
+    }
+
+    /// The most recently seen SPS.
+    fn last_sps(&self) -> Option<&SeqParameterSet> {
+        self.last_sps_id.and_then(|id| self.sps(id))
+    }
+
+    /// Returns the session resources needed for the most recently seen SPS.
+    pub(crate) fn session_requirements(&self) -> Result<SessionRequirements, Error> {
+        self.last_sps()
+            .map(SessionRequirements::new_h264)
+            .ok_or_else(|| error!(Variant::InvalidSps, "No SPS seen yet, cannot size a video session"))
+    }
+
+    /// Returns the Vulkan video profile of the most recently seen SPS.
+    ///
+    /// Sessions, images and buffers must all be created with the same profile, so they should all get it from here.
+    /// Interlaced streams ask for interleaved lines, which devices lacking them replace with separate planes, see
+    /// [`VideoSession::picture_layout`](crate::video::VideoSession::picture_layout).
+    pub fn profiles<'f>(&self) -> Result<Pin<Box<VideoProfileInfoBundle<'f>>>, Error> {
+        let sps = self
+            .last_sps()
+            .ok_or_else(|| error!(Variant::InvalidSps, "No SPS seen yet, cannot derive a video profile"))?;
+
+        let std_profile_idc = match u8::from(sps.profile_idc) {
+            66 => StdVideoH264ProfileIdc_STD_VIDEO_H264_PROFILE_IDC_BASELINE,
+            77 => StdVideoH264ProfileIdc_STD_VIDEO_H264_PROFILE_IDC_MAIN,
+            100 => StdVideoH264ProfileIdc_STD_VIDEO_H264_PROFILE_IDC_HIGH,
+            244 => StdVideoH264ProfileIdc_STD_VIDEO_H264_PROFILE_IDC_HIGH_444_PREDICTIVE,
+            x => return Err(error!(Variant::UnsupportedFeature, "profile_idc {} has no Vulkan video profile", x)),
+        };
+
+        let chroma_subsampling = match sps.chroma_info.chroma_format {
+            ChromaFormat::Monochrome => VideoChromaSubsamplingFlagsKHR::MONOCHROME,
+            ChromaFormat::YUV420 => VideoChromaSubsamplingFlagsKHR::TYPE_420,
+            ChromaFormat::YUV422 => VideoChromaSubsamplingFlagsKHR::TYPE_422,
+            ChromaFormat::YUV444 => VideoChromaSubsamplingFlagsKHR::TYPE_444,
+            ChromaFormat::Invalid(x) => return Err(error!(Variant::InvalidSps, "Invalid chroma_format_idc {}", x)),
+        };
+
+        let luma_bit_depth = component_bit_depth(sps.chroma_info.bit_depth_luma_minus8)?;
+        let chroma_bit_depth = match sps.chroma_info.chroma_format {
+            ChromaFormat::Monochrome => VideoComponentBitDepthFlagsKHR::INVALID,
+            _ => component_bit_depth(sps.chroma_info.bit_depth_chroma_minus8)?,
+        };
+
+        let picture_layout = match sps.frame_mbs_flags {
+            FrameMbsFlags::Frames => VideoDecodeH264PictureLayoutFlagsKHR::PROGRESSIVE,
+            FrameMbsFlags::Fields { .. } => VideoDecodeH264PictureLayoutFlagsKHR::INTERLACED_INTERLEAVED_LINES,
+        };
+
         let mut inner = Box::pin(VideoProfileInfoBundle::default());
 
         let m = unsafe { inner.as_mut().get_unchecked_mut() };
 
-        m.info_h264.picture_layout = VideoDecodeH264PictureLayoutFlagsKHR::INTERLACED_INTERLEAVED_LINES;
-        m.info_h264.std_profile_idc = 100;
+        m.info_h264.picture_layout = picture_layout;
+        m.info_h264.std_profile_idc = std_profile_idc;
 
         m.info.p_next = addr_of!(m.info_h264).cast();
         m.info.video_codec_operation = VideoCodecOperationFlagsKHR::DECODE_H264;
-        m.info.chroma_subsampling = VideoChromaSubsamplingFlagsKHR::TYPE_420;
-        m.info.luma_bit_depth = VideoComponentBitDepthFlagsKHR::TYPE_8;
-        m.info.chroma_bit_depth = VideoComponentBitDepthFlagsKHR::TYPE_8;
+        m.info.chroma_subsampling = chroma_subsampling;
+        m.info.luma_bit_depth = luma_bit_depth;
+        m.info.chroma_bit_depth = chroma_bit_depth;
 
         m.list = VideoProfileListInfoKHR {
             p_profiles: addr_of!(m.info),
@@ -95,31 +259,109 @@ impl H264StreamInspector {
             ..Default::default()
         };
 
-        inner
+        Ok(inner)
     }
 }
 
+pub(crate) fn fingerprint(payload: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    payload.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Reads just enough of a PPS to tell if it uses slice groups.
+fn pps_has_slice_groups<R: BitRead>(mut r: R) -> Result<bool, BitReaderError> {
+    let _pic_parameter_set_id = r.read_ue("pic_parameter_set_id")?;
+    let _seq_parameter_set_id = r.read_ue("seq_parameter_set_id")?;
+    let _entropy_coding_mode_flag = r.read_bool("entropy_coding_mode_flag")?;
+    let _bottom_field_pic_order_in_frame_present_flag = r.read_bool("bottom_field_pic_order_in_frame_present_flag")?;
+
+    Ok(r.read_ue("num_slice_groups_minus1")? > 0)
+}
+
 #[cfg(test)]
 mod test {
-    use crate::error::Error;
-    use crate::video::h264::H264StreamInspector;
+    use crate::error::{Error, Variant};
+    use crate::video::h264::testdata::{nal, SPS};
+    use crate::video::h264::{H264StreamInspector, ParsedNal};
     use crate::video::nal_units;
-    use ash::vk::VideoCodecOperationFlagsKHR;
+    use ash::vk::native::{
+        StdVideoH264ProfileIdc_STD_VIDEO_H264_PROFILE_IDC_BASELINE, StdVideoH264ProfileIdc_STD_VIDEO_H264_PROFILE_IDC_HIGH,
+    };
+    use ash::vk::{
+        VideoChromaSubsamplingFlagsKHR, VideoCodecOperationFlagsKHR, VideoComponentBitDepthFlagsKHR, VideoDecodeH264PictureLayoutFlagsKHR,
+        VideoDecodeH264ProfileInfoKHR,
+    };
 
     #[test]
     fn get_profile_info_list() -> Result<(), Error> {
-        let inspector = H264StreamInspector::new();
-        let mut profiles = inspector.profiles();
+        let mut inspector = H264StreamInspector::new();
+
+        assert!(matches!(
+            inspector.profiles().map(|_| ()).unwrap_err().variant(),
+            Variant::InvalidSps
+        ));
+
+        inspector.feed_nal(&SPS)?;
+
+        let mut profiles = inspector.profiles()?;
         let infos = unsafe { &mut profiles.as_mut().get_unchecked_mut().list };
 
         unsafe {
+            let info = &*infos.p_profiles;
+            let info_h264 = &*info.p_next.cast::<VideoDecodeH264ProfileInfoKHR>();
+
             assert_eq!(infos.profile_count, 1);
-            assert_eq!((*infos.p_profiles).video_codec_operation, VideoCodecOperationFlagsKHR::DECODE_H264);
+            assert_eq!(info.video_codec_operation, VideoCodecOperationFlagsKHR::DECODE_H264);
+            assert_eq!(info.chroma_subsampling, VideoChromaSubsamplingFlagsKHR::TYPE_420);
+            assert_eq!(info.luma_bit_depth, VideoComponentBitDepthFlagsKHR::TYPE_8);
+            assert_eq!(info_h264.std_profile_idc, StdVideoH264ProfileIdc_STD_VIDEO_H264_PROFILE_IDC_HIGH);
+            assert_eq!(info_h264.picture_layout, VideoDecodeH264PictureLayoutFlagsKHR::PROGRESSIVE);
         }
 
         Ok(())
     }
 
+    #[test]
+    fn profile_follows_latest_sps() -> Result<(), Error> {
+        let mut inspector = H264StreamInspector::new();
+
+        // Baseline 4:2:0, then a monochrome High profile SPS with interlaced coding tools.
+        inspector.feed_nal(&nal(0x67, "01000010 00000000 00011110 1 1 1 1 010 0 00100 00100 1 1 0 0"))?;
+        assert_eq!(
+            inspector.profiles()?.info_h264.std_profile_idc,
+            StdVideoH264ProfileIdc_STD_VIDEO_H264_PROFILE_IDC_BASELINE
+        );
+
+        inspector.feed_nal(&nal(
+            0x67,
+            "01100100 00000000 00011110 010 1 1 1 0 0 1 1 1 010 0 00100 00100 0 1 1 0 0",
+        ))?;
+
+        let profiles = inspector.profiles()?;
+
+        assert_eq!(
+            profiles.info_h264.std_profile_idc,
+            StdVideoH264ProfileIdc_STD_VIDEO_H264_PROFILE_IDC_HIGH
+        );
+        assert_eq!(
+            profiles.info_h264.picture_layout,
+            VideoDecodeH264PictureLayoutFlagsKHR::INTERLACED_INTERLEAVED_LINES
+        );
+        assert_eq!(profiles.info.chroma_subsampling, VideoChromaSubsamplingFlagsKHR::MONOCHROME);
+        assert_eq!(profiles.info.chroma_bit_depth, VideoComponentBitDepthFlagsKHR::INVALID);
+
+        // Extended profile has no Vulkan counterpart.
+        inspector.feed_nal(&nal(0x67, "01011000 00000000 00011110 1 1 1 1 010 0 00100 00100 1 1 0 0"))?;
+
+        assert!(matches!(
+            inspector.profiles().map(|_| ()).unwrap_err().variant(),
+            Variant::UnsupportedFeature
+        ));
+
+        Ok(())
+    }
+
     #[test]
     fn inspect_h264_stream() -> Result<(), Error> {
         let h264_data = include_bytes!("../../../tests/videos/multi_512x512.h264");
@@ -128,9 +370,87 @@ mod test {
 
         // Push a couple NALs. Pushes don't have to match up to Annex B framing.
         for nal in nal_units(h264_data) {
-            inspector.feed_nal(nal);
+            inspector.feed_nal(nal)?;
         }
 
         Ok(())
     }
+
+    #[test]
+    fn parses_parameter_sets() -> Result<(), Error> {
+        let sps = [
+            0x00, 0x00, 0x00, 0x01, 0x67, 0x64, 0x00, 0x0A, 0xAC, 0x72, 0x84, 0x44, 0x26, 0x84, 0x00, 0x00, 0x03, 0x00, 0x04, 0x00, 0x00,
+            0x03, 0x00, 0xCA, 0x3C, 0x48, 0x96, 0x11, 0x80,
+        ];
+        let pps = [0x68, 0xE8, 0x43, 0x8F, 0x13, 0x21, 0x30, 0x00];
+
+        let mut inspector = H264StreamInspector::new();
+
+        assert!(matches!(inspector.feed_nal(&sps)?, Some(ParsedNal::Sps(_))));
+        assert!(matches!(inspector.feed_nal(&pps)?, Some(ParsedNal::Pps(_))));
+        assert!(inspector.feed_nal(&[0x00, 0x00, 0x01, 0x09, 0xF0])?.is_none());
+
+        // Repeating a parameter set keeps its fingerprint, new content for the same id changes it.
+        let fingerprint = inspector.sps_fingerprints()[&0];
+
+        inspector.feed_nal(&sps[4..])?;
+        assert_eq!(inspector.sps_fingerprints()[&0], fingerprint);
+
+        inspector.feed_nal(&nal(0x67, "01000010 00000000 00011110 1 1 1 1 010 0 00100 00100 1 1 0 0"))?;
+        assert_ne!(inspector.sps_fingerprints()[&0], fingerprint);
+        assert_eq!(inspector.pps_fingerprints().len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_malformed_nals() {
+        let mut inspector = H264StreamInspector::new();
+
+        let empty = inspector.feed_nal(&[0x00, 0x00, 0x01]).unwrap_err();
+        let forbidden_bit = inspector.feed_nal(&[0x00, 0x00, 0x01, 0xE7, 0x42]).unwrap_err();
+        let truncated_sps = inspector.feed_nal(&[0x00, 0x00, 0x01, 0x67, 0x64]).unwrap_err();
+        let unknown_sps = inspector.feed_nal(&[0x00, 0x00, 0x01, 0x68, 0xCE, 0x38, 0x80]).unwrap_err();
+        let partitioned = inspector.feed_nal(&[0x00, 0x00, 0x01, 0x02, 0x80]).unwrap_err();
+
+        assert!(matches!(empty.variant(), Variant::MalformedNalHeader));
+        assert!(matches!(forbidden_bit.variant(), Variant::MalformedNalHeader));
+        assert!(matches!(truncated_sps.variant(), Variant::InvalidSps));
+        assert!(matches!(unknown_sps.variant(), Variant::InvalidPps));
+        assert!(matches!(partitioned.variant(), Variant::UnsupportedFeature));
+    }
+
+    #[test]
+    fn rejects_slice_groups() {
+        // ids 0, CAVLC, num_slice_groups_minus1 1, slice_group_map_type 1 (dispersed), then the rest of a baseline PPS.
+        let pps = [0x00, 0x00, 0x01, 0x68, 0xC2, 0x5C, 0xE3, 0x88];
+        let mut inspector = H264StreamInspector::new();
+
+        let error = inspector.feed_nal(&pps).unwrap_err();
+
+        assert!(matches!(error.variant(), Variant::UnsupportedFeature));
+    }
+
+    #[test]
+    fn survives_garbage() {
+        let mut inspector = H264StreamInspector::new();
+        let mut state = 0x2545_F491_u32;
+
+        // Random payloads behind every NAL unit type, with and without parameter sets present.
+        for i in 0..20_000 {
+            let len = 1 + (i % 64);
+            let mut nal = vec![0, 0, 1, (i % 32) as u8 | 0x60];
+
+            for _ in 0..len {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                nal.push(state as u8);
+            }
+
+            _ = inspector.feed_nal(&nal);
+            _ = inspector.std_sps();
+            _ = inspector.std_pps();
+        }
+    }
 }
@@ -0,0 +1,101 @@
+//! Limits derived from `level_idc` (Annex A).
+use crate::video::h264::stdvideo::std_chroma_format_idc;
+use crate::video::SessionRequirements;
+use ash::vk::VideoCodecOperationFlagsKHR;
+use h264_reader::nal::sps::{FrameMbsFlags, SeqParameterSet};
+
+/// `MaxDpbMbs` of Table A-1 for the level of `sps`.
+pub(crate) fn max_dpb_mbs(sps: &SeqParameterSet) -> u32 {
+    let level_1b =
+        sps.level_idc == 9 || (sps.level_idc == 11 && sps.constraint_flags.flag3() && matches!(u8::from(sps.profile_idc), 66 | 77 | 88));
+
+    if level_1b {
+        return 396;
+    }
+
+    match sps.level_idc {
+        0..=10 => 396,
+        11 => 900,
+        12 | 13 | 20 => 2376,
+        21 => 4752,
+        22 | 30 => 8100,
+        31 => 18000,
+        32 => 20480,
+        40 | 41 => 32768,
+        42 => 34816,
+        50 => 110400,
+        51 | 52 => 184320,
+        _ => 696320,
+    }
+}
+
+/// `MaxDpbFrames` (A.3.1), i.e., how many frames of this size fit into the level's DPB, at most 16.
+pub(crate) fn max_dpb_frames(sps: &SeqParameterSet) -> u32 {
+    let (width_in_mbs, frame_height_in_mbs) = frame_size_in_mbs(sps);
+    let frame_size_in_mbs = width_in_mbs.saturating_mul(frame_height_in_mbs).max(1);
+
+    (max_dpb_mbs(sps) / frame_size_in_mbs).clamp(1, 16)
+}
+
+impl SessionRequirements {
+    /// What a video session needs to decode a H.264 stream of `sps`.
+    pub fn new_h264(sps: &SeqParameterSet) -> Self {
+        let (width_in_mbs, frame_height_in_mbs) = frame_size_in_mbs(sps);
+        let max_active_reference_pictures = sps.max_num_ref_frames.max(1);
+
+        Self {
+            coded_width: width_in_mbs.saturating_mul(16),
+            coded_height: frame_height_in_mbs.saturating_mul(16),
+            max_dpb_slots: max_dpb_frames(sps).max(max_active_reference_pictures) + 1,
+            min_dpb_slots: max_active_reference_pictures + 1,
+            max_active_reference_pictures,
+            codec: VideoCodecOperationFlagsKHR::DECODE_H264,
+            profile_idc: u8::from(sps.profile_idc),
+            chroma_format_idc: std_chroma_format_idc(sps.chroma_info.chroma_format) as u8,
+            bit_depth_luma_minus8: sps.chroma_info.bit_depth_luma_minus8,
+            bit_depth_chroma_minus8: sps.chroma_info.bit_depth_chroma_minus8,
+        }
+    }
+}
+
+/// `PicWidthInMbs` and `FrameHeightInMbs` (7-13, 7-18).
+fn frame_size_in_mbs(sps: &SeqParameterSet) -> (u32, u32) {
+    let width_in_mbs = sps.pic_width_in_mbs_minus1.saturating_add(1);
+    let height_in_map_units = sps.pic_height_in_map_units_minus1.saturating_add(1);
+    let frame_height_in_mbs = match sps.frame_mbs_flags {
+        FrameMbsFlags::Frames => height_in_map_units,
+        FrameMbsFlags::Fields { .. } => height_in_map_units.saturating_mul(2),
+    };
+
+    (width_in_mbs, frame_height_in_mbs)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::video::h264::testdata::sps;
+    use crate::video::SessionRequirements;
+
+    #[test]
+    fn session_requirements_follow_level() {
+        // Level 1.0, 2 refs, 18x22 MBs, so exactly one frame fits into the DPB.
+        let small = SessionRequirements::new_h264(&sps("01000010 00000000 00001010 1 1 1 1 011 0 000010010 000010110 1 1 0 0"));
+
+        assert_eq!((small.coded_width, small.coded_height), (288, 352));
+        assert_eq!(
+            (small.max_dpb_slots, small.min_dpb_slots, small.max_active_reference_pictures),
+            (3, 3, 2)
+        );
+
+        // Level 3.0, 4x4 MBs in field pairs, so 16 frames fit.
+        let interlaced = SessionRequirements::new_h264(&sps("01000010 00000000 00011110 1 1 1 1 011 0 00100 00100 0 0 1 0 0"));
+
+        assert_eq!((interlaced.coded_width, interlaced.coded_height), (64, 128));
+        assert_eq!((interlaced.max_dpb_slots, interlaced.min_dpb_slots), (17, 3));
+
+        // References don't matter for the format, sizes and layouts do.
+        let fewer_refs = SessionRequirements::new_h264(&sps("01000010 00000000 00001010 1 1 1 1 010 0 000010010 000010110 1 1 0 0"));
+
+        assert!(small.same_format(&fewer_refs));
+        assert!(!small.same_format(&interlaced));
+    }
+}
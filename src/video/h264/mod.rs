@@ -1,4 +1,40 @@
 //! Operations related to H.264 codecs.
+mod accessunit;
+mod analyzer;
+mod avcc;
+mod bitwriter;
+mod color;
+mod crop;
+mod dpb;
 mod h264inspector;
+mod level;
+mod output;
+mod poc;
+mod randomaccess;
+mod scaling;
+mod sei;
+mod slice;
+mod stdvideo;
+#[cfg(test)]
+pub(crate) mod testdata;
+mod writer;
 
-pub use h264inspector::H264StreamInspector;
+pub use accessunit::{AccessUnit, AccessUnitAssembler};
+pub use analyzer::{
+    analyze, GopSummary, NalSummary, PictureStructure, PictureSummary, PpsSummary, SpsSummary, StreamAnalysis, StreamAnalyzer,
+};
+pub use avcc::{annexb_to_avcc, avcc_nal_units, avcc_to_annexb, AvcDecoderConfigurationRecord, AvcHighProfileExtension};
+pub use bitwriter::BitWriter;
+pub use dpb::{Concealment, Dpb, DpbPicture, DpbReference};
+pub(crate) use h264inspector::fingerprint;
+pub use h264inspector::{H264StreamInspector, ParsedNal};
+pub use output::OutputQueue;
+pub use poc::{PicOrderCnt, PocCalculator};
+pub use randomaccess::{Access, RandomAccess};
+pub use sei::{
+    write_sei, ClockTimestamp, ContentLightLevel, MasteringDisplayColourVolume, PicTiming, RecoveryPoint, SeiMessage, UserDataRegistered,
+    UserDataUnregistered,
+};
+pub use slice::{DecRefPicMarking, MemoryManagementControlOperation, RefPicListModification, SliceHeader, SliceType};
+pub(crate) use stdvideo::{std_picture_info, std_reference_info, StdPps, StdSps};
+pub use writer::{write_aud, write_pps, write_slice_header, write_sps};
@@ -0,0 +1,229 @@
+//! Output of decoded frames in display order, following the bumping process (C.4.5.3).
+use crate::video::h264::level::max_dpb_frames;
+use crate::video::h264::slice::FirstField;
+use crate::video::h264::{DecRefPicMarking, PicOrderCnt, SliceHeader};
+use h264_reader::nal::sps::SeqParameterSet;
+
+/// Holds decoded frames and releases them in display order.
+///
+/// Frames are pushed in decoding order together with their slice header and POC, and come out once
+/// enough later frames have been seen that nothing can be displayed before them anymore. `T` is
+/// whatever the caller uses to represent a decoded frame, e.g., an image or a downloaded buffer.
+///
+/// Field pairs are output as one frame: the first field is held back until its second field has been
+/// pushed, whose `frame` is dropped since both fields live in the same frame.
+#[derive(Debug)]
+pub struct OutputQueue<T> {
+    pending: Vec<(i32, T)>,
+    // The last pushed picture, if it was a first field waiting for its second field.
+    first_field: Option<FirstField>,
+}
+
+impl<T> Default for OutputQueue<T> {
+    fn default() -> Self {
+        Self {
+            pending: Vec::new(),
+            first_field: None,
+        }
+    }
+}
+
+impl<T> OutputQueue<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a decoded frame, returns all frames that are now ready for display, in display order.
+    pub fn push(&mut self, sps: &SeqParameterSet, slice: &SliceHeader, pic_order_cnt: PicOrderCnt, frame: T) -> Vec<T> {
+        let mut output = Vec::new();
+        let mmco5 = slice.dec_ref_pic_marking.as_ref().is_some_and(|x| x.has_mmco5());
+
+        // The first field was the last one pushed, and a frame's POC is that of its earlier field.
+        if self.first_field.take().is_some_and(|x| x.is_completed_by(slice)) {
+            if let Some((poc, _)) = self.pending.last_mut() {
+                *poc = (*poc).min(field_order_cnt(slice, pic_order_cnt));
+            }
+
+            self.bump_while_full(sps, &mut output);
+
+            return output;
+        }
+
+        // IDR and MMCO 5 pictures start a new POC sequence, so everything before them goes first (C.4.4).
+        if slice.idr || mmco5 {
+            let no_output_of_prior_pics = matches!(
+                slice.dec_ref_pic_marking,
+                Some(DecRefPicMarking::Idr {
+                    no_output_of_prior_pics_flag: true,
+                    ..
+                })
+            );
+
+            if no_output_of_prior_pics {
+                self.pending.clear();
+            } else {
+                output.extend(self.flush());
+            }
+        }
+
+        // After MMCO 5 a picture's POC counts relative to itself (8.2.1).
+        let pic_order_cnt = if mmco5 { 0 } else { field_order_cnt(slice, pic_order_cnt) };
+
+        self.pending.push((pic_order_cnt, frame));
+        self.first_field = FirstField::new(slice);
+
+        if self.first_field.is_none() {
+            self.bump_while_full(sps, &mut output);
+        }
+
+        output
+    }
+
+    /// Returns all remaining frames in display order, e.g., at the end of a stream.
+    pub fn flush(&mut self) -> Vec<T> {
+        let mut output = Vec::new();
+
+        self.first_field = None;
+
+        while let Some(x) = self.bump() {
+            output.push(x);
+        }
+
+        output
+    }
+
+    /// Number of frames waiting for output.
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    fn bump_while_full(&mut self, sps: &SeqParameterSet, output: &mut Vec<T>) {
+        let (max_num_reorder_frames, max_dec_frame_buffering) = reorder_limits(sps);
+
+        while self.pending.len() > max_num_reorder_frames || self.pending.len() > max_dec_frame_buffering {
+            output.extend(self.bump());
+        }
+    }
+
+    /// Removes the frame with the smallest POC, the first one on ties.
+    fn bump(&mut self) -> Option<T> {
+        let index = self.pending.iter().enumerate().min_by_key(|(_, (poc, _))| *poc).map(|(i, _)| i)?;
+
+        Some(self.pending.remove(index).1)
+    }
+}
+
+/// The POC of a frame, or of the field `slice` belongs to, where [`PicOrderCnt`] leaves the other field at 0.
+pub(crate) fn field_order_cnt(slice: &SliceHeader, pic_order_cnt: PicOrderCnt) -> i32 {
+    match (slice.field_pic_flag, slice.bottom_field_flag) {
+        (false, _) => pic_order_cnt.frame(),
+        (true, false) => pic_order_cnt.top,
+        (true, true) => pic_order_cnt.bottom,
+    }
+}
+
+/// `max_num_reorder_frames` and `max_dec_frame_buffering`, inferred as of E.2.1 if the VUI does not have them.
+fn reorder_limits(sps: &SeqParameterSet) -> (usize, usize) {
+    let restrictions = sps.vui_parameters.as_ref().and_then(|x| x.bitstream_restrictions.as_ref());
+
+    if let Some(x) = restrictions {
+        return (x.max_num_reorder_frames as usize, x.max_dec_frame_buffering as usize);
+    }
+
+    let intra_only = sps.constraint_flags.flag3() && matches!(u8::from(sps.profile_idc), 44 | 86 | 100 | 110 | 122 | 244);
+
+    if intra_only {
+        (0, 0)
+    } else {
+        let max_dpb_frames = max_dpb_frames(sps) as usize;
+        (max_dpb_frames, max_dpb_frames)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::OutputQueue;
+    use crate::video::h264::testdata::{idr_slice_header, slice_header, sps};
+    use crate::video::h264::{DecRefPicMarking, PicOrderCnt, SliceHeader};
+
+    fn poc(x: i32) -> PicOrderCnt {
+        PicOrderCnt { top: x, bottom: x }
+    }
+
+    #[test]
+    fn reorders_b_frames() {
+        // Baseline, level 3.0, 4x4 MBs, POC type 0, VUI with bitstream restrictions: max_num_reorder_frames 1, max_dec_frame_buffering 2
+        let sps = sps("01000010 00000000 00011110 1 1 1 1 011 0 00100 00100 1 1 0 1 0 0 0 0 0 0 0 0 1 1 1 1 1 1 010 011");
+        let mut queue = OutputQueue::new();
+        let mut output = Vec::new();
+
+        // Decode order I0 P4 B2 P8 B6, display order I0 B2 P4 B6 P8.
+        output.extend(queue.push(&sps, &idr_slice_header(0), poc(0), 0));
+        output.extend(queue.push(&sps, &slice_header(1, 1), poc(8), 4));
+        output.extend(queue.push(&sps, &slice_header(2, 0), poc(4), 2));
+        output.extend(queue.push(&sps, &slice_header(2, 1), poc(16), 8));
+        output.extend(queue.push(&sps, &slice_header(3, 0), poc(12), 6));
+
+        assert_eq!(output, vec![0, 2, 4, 6]);
+        assert_eq!(queue.flush(), vec![8]);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn infers_limits_from_level() {
+        // Baseline, level 1.0 (396 MBs) with 18x22 MBs, i.e., a single frame fits.
+        let sps = sps("01000010 00000000 00001010 1 1 1 1 011 0 000010010 000010110 1 1 0 0");
+        let mut queue = OutputQueue::new();
+
+        assert_eq!(queue.push(&sps, &idr_slice_header(0), poc(0), 0), Vec::<i32>::new());
+        assert_eq!(queue.push(&sps, &slice_header(1, 1), poc(2), 1), vec![0]);
+    }
+
+    #[test]
+    fn idr_flushes_or_discards() {
+        let sps = sps("01000010 00000000 00011110 1 1 1 1 011 0 00100 00100 1 1 0 0");
+        let mut queue = OutputQueue::new();
+
+        queue.push(&sps, &idr_slice_header(0), poc(0), 0);
+        queue.push(&sps, &slice_header(1, 1), poc(4), 1);
+
+        assert_eq!(queue.push(&sps, &idr_slice_header(1), poc(0), 2), vec![0, 1]);
+
+        let no_output = SliceHeader {
+            dec_ref_pic_marking: Some(DecRefPicMarking::Idr {
+                no_output_of_prior_pics_flag: true,
+                long_term_reference_flag: false,
+            }),
+            ..idr_slice_header(2)
+        };
+
+        assert!(queue.push(&sps, &no_output, poc(0), 3).is_empty());
+        assert_eq!(queue.flush(), vec![3]);
+    }
+
+    #[test]
+    fn pairs_fields() {
+        // Same as above, one frame fits.
+        let sps = sps("01000010 00000000 00001010 1 1 1 1 011 0 000010010 000010110 1 1 0 0");
+        let mut queue = OutputQueue::new();
+
+        let field = |slice: SliceHeader, bottom_field_flag: bool| SliceHeader {
+            field_pic_flag: true,
+            bottom_field_flag,
+            ..slice
+        };
+
+        assert!(queue.push(&sps, &field(idr_slice_header(0), false), poc(0), 0).is_empty());
+        assert!(queue.push(&sps, &field(slice_header(0, 1), true), poc(1), 0).is_empty());
+        assert_eq!(queue.len(), 1);
+
+        // Frame 0 may only go once the next frame is complete, not just its first field.
+        assert!(queue.push(&sps, &field(slice_header(1, 1), true), poc(5), 1).is_empty());
+        assert_eq!(queue.push(&sps, &field(slice_header(1, 1), false), poc(4), 1), vec![0]);
+        assert_eq!(queue.flush(), vec![1]);
+    }
+}
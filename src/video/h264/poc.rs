@@ -0,0 +1,343 @@
+//! Picture order count derivation (8.2.1).
+use crate::video::h264::SliceHeader;
+use h264_reader::nal::sps::{PicOrderCntType, SeqParameterSet};
+
+/// The picture order counts of a picture.
+///
+/// For field pictures only the count of the decoded field is set, the other one is 0.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PicOrderCnt {
+    pub top: i32,
+    pub bottom: i32,
+}
+
+impl PicOrderCnt {
+    /// The order count of a frame or complementary field pair, i.e., the smaller of both fields (8-1).
+    pub fn frame(&self) -> i32 {
+        self.top.min(self.bottom)
+    }
+}
+
+/// Computes picture order counts for all three `pic_order_cnt_type`s.
+///
+/// Feed the first slice of every picture in decoding order, the calculator keeps track of whatever
+/// state of previous pictures is needed, including `frame_num` wraps and MMCO 5 resets.
+#[derive(Debug, Default)]
+pub struct PocCalculator {
+    // Of the previous reference picture, for type 0.
+    prev_pic_order_cnt_msb: i64,
+    prev_pic_order_cnt_lsb: i64,
+    // Of the previous picture, for types 1 and 2.
+    prev_frame_num_offset: i64,
+    prev_frame_num: i64,
+}
+
+impl PocCalculator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Computes the order count of the picture starting with `slice`, and updates the state for following pictures.
+    pub fn compute(&mut self, sps: &SeqParameterSet, slice: &SliceHeader) -> PicOrderCnt {
+        let (top, bottom) = match &sps.pic_order_cnt {
+            PicOrderCntType::TypeZero {
+                log2_max_pic_order_cnt_lsb_minus4,
+            } => self.type_0(*log2_max_pic_order_cnt_lsb_minus4, slice),
+            PicOrderCntType::TypeOne {
+                offset_for_non_ref_pic,
+                offset_for_top_to_bottom_field,
+                offsets_for_ref_frame,
+                ..
+            } => self.type_1(
+                sps,
+                *offset_for_non_ref_pic,
+                *offset_for_top_to_bottom_field,
+                offsets_for_ref_frame,
+                slice,
+            ),
+            PicOrderCntType::TypeTwo => self.type_2(sps, slice),
+        };
+
+        let mmco5 = slice.dec_ref_pic_marking.as_ref().is_some_and(|x| x.has_mmco5());
+
+        if mmco5 {
+            // After decoding, a picture with MMCO 5 counts as having `frame_num` 0 and POC relative to itself (8.2.1).
+            let temp_pic_order_cnt = match (slice.field_pic_flag, slice.bottom_field_flag) {
+                (false, _) => top.min(bottom),
+                (true, false) => top,
+                (true, true) => bottom,
+            };
+
+            self.prev_frame_num_offset = 0;
+            self.prev_frame_num = 0;
+
+            if slice.is_reference() {
+                self.prev_pic_order_cnt_msb = 0;
+                self.prev_pic_order_cnt_lsb = if slice.bottom_field_flag { 0 } else { top - temp_pic_order_cnt };
+            }
+        }
+
+        match (slice.field_pic_flag, slice.bottom_field_flag) {
+            (false, _) => PicOrderCnt {
+                top: top as i32,
+                bottom: bottom as i32,
+            },
+            (true, false) => PicOrderCnt {
+                top: top as i32,
+                bottom: 0,
+            },
+            (true, true) => PicOrderCnt {
+                top: 0,
+                bottom: bottom as i32,
+            },
+        }
+    }
+
+    /// 8.2.1.1
+    fn type_0(&mut self, log2_max_pic_order_cnt_lsb_minus4: u8, slice: &SliceHeader) -> (i64, i64) {
+        let max_pic_order_cnt_lsb = 1i64 << (log2_max_pic_order_cnt_lsb_minus4 + 4);
+        let pic_order_cnt_lsb = i64::from(slice.pic_order_cnt_lsb);
+
+        if slice.idr {
+            self.prev_pic_order_cnt_msb = 0;
+            self.prev_pic_order_cnt_lsb = 0;
+        }
+
+        let prev_msb = self.prev_pic_order_cnt_msb;
+        let prev_lsb = self.prev_pic_order_cnt_lsb;
+
+        let pic_order_cnt_msb = if pic_order_cnt_lsb < prev_lsb && prev_lsb - pic_order_cnt_lsb >= max_pic_order_cnt_lsb / 2 {
+            prev_msb + max_pic_order_cnt_lsb
+        } else if pic_order_cnt_lsb > prev_lsb && pic_order_cnt_lsb - prev_lsb > max_pic_order_cnt_lsb / 2 {
+            prev_msb - max_pic_order_cnt_lsb
+        } else {
+            prev_msb
+        };
+
+        let top = pic_order_cnt_msb + pic_order_cnt_lsb;
+        let bottom = if slice.field_pic_flag {
+            top
+        } else {
+            top + i64::from(slice.delta_pic_order_cnt_bottom)
+        };
+
+        if slice.is_reference() {
+            self.prev_pic_order_cnt_msb = pic_order_cnt_msb;
+            self.prev_pic_order_cnt_lsb = pic_order_cnt_lsb;
+        }
+
+        (top, bottom)
+    }
+
+    /// 8.2.1.2
+    fn type_1(
+        &mut self,
+        sps: &SeqParameterSet,
+        offset_for_non_ref_pic: i32,
+        offset_for_top_to_bottom_field: i32,
+        offsets_for_ref_frame: &[i32],
+        slice: &SliceHeader,
+    ) -> (i64, i64) {
+        let frame_num_offset = self.frame_num_offset(sps, slice);
+        let num_ref_frames_in_pic_order_cnt_cycle = offsets_for_ref_frame.len() as i64;
+
+        let mut abs_frame_num = if num_ref_frames_in_pic_order_cnt_cycle != 0 {
+            frame_num_offset + i64::from(slice.frame_num)
+        } else {
+            0
+        };
+
+        if !slice.is_reference() && abs_frame_num > 0 {
+            abs_frame_num -= 1;
+        }
+
+        let mut expected_pic_order_cnt = if abs_frame_num > 0 {
+            let pic_order_cnt_cycle_cnt = (abs_frame_num - 1) / num_ref_frames_in_pic_order_cnt_cycle;
+            let frame_num_in_pic_order_cnt_cycle = (abs_frame_num - 1) % num_ref_frames_in_pic_order_cnt_cycle;
+            let expected_delta_per_pic_order_cnt_cycle = offsets_for_ref_frame.iter().map(|x| i64::from(*x)).sum::<i64>();
+            let in_cycle = offsets_for_ref_frame[..=frame_num_in_pic_order_cnt_cycle as usize]
+                .iter()
+                .map(|x| i64::from(*x))
+                .sum::<i64>();
+
+            pic_order_cnt_cycle_cnt.wrapping_mul(expected_delta_per_pic_order_cnt_cycle) + in_cycle
+        } else {
+            0
+        };
+
+        if !slice.is_reference() {
+            expected_pic_order_cnt += i64::from(offset_for_non_ref_pic);
+        }
+
+        let delta_0 = i64::from(slice.delta_pic_order_cnt[0]);
+        let delta_1 = i64::from(slice.delta_pic_order_cnt[1]);
+        let top_to_bottom = i64::from(offset_for_top_to_bottom_field);
+
+        let (top, bottom) = match (slice.field_pic_flag, slice.bottom_field_flag) {
+            (false, _) => {
+                let top = expected_pic_order_cnt + delta_0;
+                (top, top + top_to_bottom + delta_1)
+            }
+            (true, false) => (expected_pic_order_cnt + delta_0, 0),
+            (true, true) => (0, expected_pic_order_cnt + top_to_bottom + delta_0),
+        };
+
+        self.prev_frame_num_offset = frame_num_offset;
+        self.prev_frame_num = i64::from(slice.frame_num);
+
+        (top, bottom)
+    }
+
+    /// 8.2.1.3
+    fn type_2(&mut self, sps: &SeqParameterSet, slice: &SliceHeader) -> (i64, i64) {
+        let frame_num_offset = self.frame_num_offset(sps, slice);
+
+        let temp_pic_order_cnt = if slice.idr {
+            0
+        } else if !slice.is_reference() {
+            2 * (frame_num_offset + i64::from(slice.frame_num)) - 1
+        } else {
+            2 * (frame_num_offset + i64::from(slice.frame_num))
+        };
+
+        self.prev_frame_num_offset = frame_num_offset;
+        self.prev_frame_num = i64::from(slice.frame_num);
+
+        (temp_pic_order_cnt, temp_pic_order_cnt)
+    }
+
+    /// `FrameNumOffset` as shared by types 1 and 2, handling `frame_num` wraps.
+    fn frame_num_offset(&self, sps: &SeqParameterSet, slice: &SliceHeader) -> i64 {
+        let max_frame_num = 1i64 << sps.log2_max_frame_num();
+
+        if slice.idr {
+            0
+        } else if self.prev_frame_num > i64::from(slice.frame_num) {
+            self.prev_frame_num_offset + max_frame_num
+        } else {
+            self.prev_frame_num_offset
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PicOrderCnt, PocCalculator};
+    use crate::video::h264::testdata::{idr_slice_header, slice_header, sps};
+    use crate::video::h264::{DecRefPicMarking, MemoryManagementControlOperation};
+    use h264_reader::nal::sps::SeqParameterSet;
+
+    // Baseline, 4 bit `frame_num`, 2 ref frames, 4x4 MBs, frame MBs only; followed by the given POC type.
+    fn sps_with_poc(poc_bits: &str) -> SeqParameterSet {
+        sps(&format!("01000010 00000000 00011110 1 1 {} 010 0 00100 00100 1 1 0 0", poc_bits))
+    }
+
+    fn mmco5() -> Option<DecRefPicMarking> {
+        Some(DecRefPicMarking::Adaptive(vec![
+            MemoryManagementControlOperation::AllUnusedForReference,
+        ]))
+    }
+
+    /// Computes the frame POC of a non-IDR frame with type 0 `lsb`.
+    fn frame_poc(poc: &mut PocCalculator, sps: &SeqParameterSet, frame_num: u16, nal_ref_idc: u8, lsb: u16) -> i32 {
+        let mut slice = slice_header(frame_num, nal_ref_idc);
+        slice.pic_order_cnt_lsb = lsb;
+        poc.compute(sps, &slice).frame()
+    }
+
+    #[test]
+    fn type_0_wraps_and_resets() {
+        // POC type 0, 4 bit LSB
+        let sps = sps_with_poc("1 1");
+        let mut poc = PocCalculator::new();
+
+        assert_eq!(poc.compute(&sps, &idr_slice_header(0)), PicOrderCnt { top: 0, bottom: 0 });
+        assert_eq!(frame_poc(&mut poc, &sps, 1, 1, 4), 4);
+        assert_eq!(frame_poc(&mut poc, &sps, 2, 1, 8), 8);
+        assert_eq!(frame_poc(&mut poc, &sps, 3, 1, 12), 12);
+        assert_eq!(frame_poc(&mut poc, &sps, 4, 1, 0), 16);
+        assert_eq!(frame_poc(&mut poc, &sps, 5, 1, 4), 20);
+        assert_eq!(frame_poc(&mut poc, &sps, 6, 0, 2), 18);
+        assert_eq!(frame_poc(&mut poc, &sps, 6, 1, 8), 24);
+    }
+
+    #[test]
+    fn type_0_mmco5_and_bottom_delta() {
+        let sps = sps_with_poc("1 1");
+        let mut poc = PocCalculator::new();
+
+        poc.compute(&sps, &idr_slice_header(0));
+
+        let mut slice = slice_header(1, 1);
+        slice.pic_order_cnt_lsb = 6;
+        slice.delta_pic_order_cnt_bottom = -1;
+        slice.dec_ref_pic_marking = mmco5();
+
+        // POC of the MMCO 5 picture itself is computed as usual ...
+        assert_eq!(poc.compute(&sps, &slice), PicOrderCnt { top: 6, bottom: 5 });
+
+        // ... but afterwards it counts as top POC 1 (6 - min(6, 5)), so LSB 2 is POC 2.
+        let mut slice = slice_header(1, 1);
+        slice.pic_order_cnt_lsb = 2;
+
+        assert_eq!(poc.compute(&sps, &slice).frame(), 2);
+    }
+
+    #[test]
+    fn type_0_fields() {
+        let sps = sps_with_poc("1 1");
+        let mut poc = PocCalculator::new();
+
+        let mut top = idr_slice_header(0);
+        top.field_pic_flag = true;
+
+        let mut bottom = slice_header(0, 1);
+        bottom.field_pic_flag = true;
+        bottom.bottom_field_flag = true;
+        bottom.pic_order_cnt_lsb = 1;
+
+        assert_eq!(poc.compute(&sps, &top), PicOrderCnt { top: 0, bottom: 0 });
+        assert_eq!(poc.compute(&sps, &bottom), PicOrderCnt { top: 0, bottom: 1 });
+    }
+
+    #[test]
+    fn type_1_cycles() {
+        // POC type 1, offset_for_non_ref_pic -1, offset_for_top_to_bottom_field 1, one ref frame per cycle with offset 2
+        let sps = sps_with_poc("010 0 011 010 010 00100");
+        let mut poc = PocCalculator::new();
+
+        assert_eq!(poc.compute(&sps, &idr_slice_header(0)), PicOrderCnt { top: 0, bottom: 1 });
+        assert_eq!(poc.compute(&sps, &slice_header(1, 1)), PicOrderCnt { top: 2, bottom: 3 });
+        assert_eq!(poc.compute(&sps, &slice_header(2, 1)), PicOrderCnt { top: 4, bottom: 5 });
+        assert_eq!(poc.compute(&sps, &slice_header(3, 0)), PicOrderCnt { top: 3, bottom: 4 });
+
+        // Wrap of the 4 bit `frame_num`.
+        for frame_num in 3..16 {
+            poc.compute(&sps, &slice_header(frame_num, 1));
+        }
+
+        assert_eq!(poc.compute(&sps, &slice_header(0, 1)).frame(), 32);
+    }
+
+    #[test]
+    fn type_2_wraps_and_resets() {
+        let sps = sps_with_poc("011");
+        let mut poc = PocCalculator::new();
+
+        assert_eq!(poc.compute(&sps, &idr_slice_header(0)).frame(), 0);
+        assert_eq!(poc.compute(&sps, &slice_header(1, 1)).frame(), 2);
+        assert_eq!(poc.compute(&sps, &slice_header(2, 0)).frame(), 3);
+
+        for frame_num in 2..16 {
+            poc.compute(&sps, &slice_header(frame_num, 1));
+        }
+
+        assert_eq!(poc.compute(&sps, &slice_header(0, 1)).frame(), 32);
+
+        let mut slice = slice_header(3, 1);
+        slice.dec_ref_pic_marking = mmco5();
+
+        assert_eq!(poc.compute(&sps, &slice).frame(), 38);
+        assert_eq!(poc.compute(&sps, &slice_header(1, 1)).frame(), 2);
+    }
+}
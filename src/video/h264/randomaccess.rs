@@ -0,0 +1,241 @@
+//! Starting to decode mid-stream, at IDR pictures or recovery points (D.2.8).
+use crate::video::h264::output::field_order_cnt;
+use crate::video::h264::slice::FirstField;
+use crate::video::h264::{AccessUnit, PicOrderCnt, SeiMessage};
+use h264_reader::nal::sps::SeqParameterSet;
+
+/// What to do with a picture, as decided by [`RandomAccess::push`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Access {
+    /// The picture precedes the first random access point and cannot be decoded.
+    Skip,
+    /// Decode the picture, and show it if `display` is set.
+    ///
+    /// Pictures between a recovery point SEI and the frame it announces are decoded to build up references,
+    /// but their content is not correct yet. `first` is set for the picture decoding starts with, which must be the
+    /// first picture added to a new or reset [`Dpb`](crate::video::h264::Dpb), so that it resets the video session,
+    /// see [`DpbPicture::reset`](crate::video::h264::DpbPicture::reset).
+    Decode { display: bool, first: bool },
+}
+
+#[derive(Copy, Clone, Debug)]
+enum State {
+    Searching,
+    // Waiting for `recovery_frame_cnt` frames after the picture with `frame_num`.
+    Recovering { frame_num: u16, recovery_frame_cnt: u32 },
+    // Pictures before `min_pic_order_cnt` in output order were predicted from garbage.
+    Decoding { min_pic_order_cnt: Option<i32> },
+}
+
+/// Finds where to start decoding a stream joined mid-way, e.g., after seeking or when tuning into a live feed.
+///
+/// Access units are passed in decoding order together with their POC. Everything before the first IDR picture or
+/// recovery point SEI is skipped, and pictures are only displayed once the recovery point has been reached. The
+/// same applies after frames were lost: once [`Dpb::add_picture`](crate::video::h264::Dpb::add_picture) fails with
+/// [`Variant::MissingReferences`](crate::error::Variant::MissingReferences), reset both to resume at the next
+/// recovery point rather than the next IDR picture.
+#[derive(Debug)]
+pub struct RandomAccess {
+    state: State,
+    // The previous picture if it was a first field, and what we decided for it.
+    first_field: Option<(FirstField, Access)>,
+}
+
+impl Default for RandomAccess {
+    fn default() -> Self {
+        Self {
+            state: State::Searching,
+            first_field: None,
+        }
+    }
+}
+
+impl RandomAccess {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts looking for the next random access point again, e.g., after seeking.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// If a random access point was found, i.e., pictures are being decoded.
+    pub fn is_decoding(&self) -> bool {
+        !matches!(self.state, State::Searching)
+    }
+
+    /// Decides whether to decode and display `access_unit`.
+    pub fn push(&mut self, sps: &SeqParameterSet, access_unit: &AccessUnit, pic_order_cnt: PicOrderCnt) -> Access {
+        let slice = access_unit.first_slice();
+
+        // Both fields of a frame are displayed together, so the second field follows the first one.
+        if let Some((_, access)) = self.first_field.take().filter(|(x, _)| x.is_completed_by(slice)) {
+            return match access {
+                Access::Decode { display, .. } => Access::Decode { display, first: false },
+                Access::Skip => Access::Skip,
+            };
+        }
+
+        let access = self.decide(sps, access_unit, field_order_cnt(slice, pic_order_cnt));
+
+        self.first_field = FirstField::new(slice).map(|x| (x, access));
+
+        access
+    }
+
+    fn decide(&mut self, sps: &SeqParameterSet, access_unit: &AccessUnit, pic_order_cnt: i32) -> Access {
+        let slice = access_unit.first_slice();
+        let mmco5 = slice.dec_ref_pic_marking.as_ref().is_some_and(|x| x.has_mmco5());
+        let first = !self.is_decoding();
+
+        // Everything after an IDR picture can be decoded, and MMCO 5 restarts POCs just the same.
+        if slice.idr || (mmco5 && self.is_decoding()) {
+            self.state = State::Decoding { min_pic_order_cnt: None };
+            return Access::Decode { display: true, first };
+        }
+
+        let max_frame_num = 1i32 << sps.log2_max_frame_num();
+
+        match self.state {
+            State::Searching => {
+                let recovery_point = access_unit.sei().iter().find_map(|x| match x {
+                    SeiMessage::RecoveryPoint(x) => Some(x.recovery_frame_cnt),
+                    _ => None,
+                });
+
+                let Some(recovery_frame_cnt) = recovery_point else {
+                    return Access::Skip;
+                };
+
+                self.state = State::Recovering {
+                    frame_num: slice.frame_num,
+                    recovery_frame_cnt,
+                };
+            }
+            State::Recovering { .. } => {}
+            State::Decoding { min_pic_order_cnt } => {
+                let display = min_pic_order_cnt.is_none_or(|x| pic_order_cnt >= x);
+                return Access::Decode { display, first };
+            }
+        }
+
+        // Pictures are correct from the first one `recovery_frame_cnt` frames after the recovery point SEI onwards
+        // in output order, which earlier decoded pictures of course are not.
+        if let State::Recovering {
+            frame_num,
+            recovery_frame_cnt,
+        } = self.state
+        {
+            let frames = (i32::from(slice.frame_num) - i32::from(frame_num)).rem_euclid(max_frame_num);
+
+            if i64::from(frames) >= i64::from(recovery_frame_cnt) {
+                self.state = State::Decoding {
+                    min_pic_order_cnt: Some(pic_order_cnt),
+                };
+
+                return Access::Decode { display: true, first };
+            }
+        }
+
+        Access::Decode { display: false, first }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Access, RandomAccess};
+    use crate::video::h264::testdata::{idr_slice_header, slice_header, sps};
+    use crate::video::h264::{AccessUnit, PicOrderCnt, RecoveryPoint, SeiMessage, SliceHeader};
+    use h264_reader::nal::sps::SeqParameterSet;
+
+    // Baseline, 4 bit `frame_num`, POC type 0, 2 ref frames, 4x4 MBs
+    fn sps_2_refs() -> SeqParameterSet {
+        sps("01000010 00000000 00011110 1 1 1 1 011 0 00100 00100 1 1 0 0")
+    }
+
+    fn poc(x: i32) -> PicOrderCnt {
+        PicOrderCnt { top: x, bottom: x }
+    }
+
+    fn recovery_point(slice: SliceHeader, recovery_frame_cnt: u32) -> AccessUnit {
+        let sei = SeiMessage::RecoveryPoint(RecoveryPoint {
+            recovery_frame_cnt,
+            ..Default::default()
+        });
+
+        AccessUnit::new(&[0x01], slice, vec![sei])
+    }
+
+    fn access_unit(slice: SliceHeader) -> AccessUnit {
+        AccessUnit::new(&[0x01], slice, Vec::new())
+    }
+
+    const fn decode(display: bool, first: bool) -> Access {
+        Access::Decode { display, first }
+    }
+
+    #[test]
+    fn starts_at_idr() {
+        let sps = sps_2_refs();
+        let mut random_access = RandomAccess::new();
+
+        assert_eq!(random_access.push(&sps, &access_unit(slice_header(3, 1)), poc(6)), Access::Skip);
+        assert!(!random_access.is_decoding());
+        assert_eq!(
+            random_access.push(&sps, &access_unit(idr_slice_header(0)), poc(0)),
+            decode(true, true)
+        );
+        assert_eq!(
+            random_access.push(&sps, &access_unit(slice_header(1, 1)), poc(2)),
+            decode(true, false)
+        );
+
+        random_access.reset();
+
+        assert_eq!(random_access.push(&sps, &access_unit(slice_header(2, 1)), poc(4)), Access::Skip);
+    }
+
+    #[test]
+    fn recovers_at_recovery_point() {
+        let sps = sps_2_refs();
+        let mut random_access = RandomAccess::new();
+
+        // Recovery point at frame_num 14, complete 3 frames later at frame_num 1, after a wrap.
+        assert_eq!(random_access.push(&sps, &access_unit(slice_header(13, 1)), poc(20)), Access::Skip);
+        assert_eq!(
+            random_access.push(&sps, &recovery_point(slice_header(14, 1), 3), poc(24)),
+            decode(false, true)
+        );
+        assert_eq!(
+            random_access.push(&sps, &access_unit(slice_header(15, 1)), poc(28)),
+            decode(false, false)
+        );
+        assert_eq!(
+            random_access.push(&sps, &access_unit(slice_header(0, 1)), poc(32)),
+            decode(false, false)
+        );
+        assert_eq!(
+            random_access.push(&sps, &access_unit(slice_header(1, 1)), poc(36)),
+            decode(true, false)
+        );
+
+        // A B frame decoded after the recovery point but shown before it is still broken.
+        assert_eq!(
+            random_access.push(&sps, &access_unit(slice_header(2, 0)), poc(34)),
+            decode(false, false)
+        );
+        assert_eq!(
+            random_access.push(&sps, &access_unit(slice_header(2, 1)), poc(40)),
+            decode(true, false)
+        );
+
+        // Pictures are good right away without a recovery count.
+        let mut random_access = RandomAccess::new();
+
+        assert_eq!(
+            random_access.push(&sps, &recovery_point(slice_header(5, 1), 0), poc(10)),
+            decode(true, true)
+        );
+    }
+}
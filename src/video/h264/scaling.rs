@@ -0,0 +1,191 @@
+//! Scaling list parsing, as `h264-reader` validates scaling matrices but discards their values.
+use ash::vk::native::StdVideoH264ScalingLists;
+use h264_reader::nal::sps::{ChromaFormat, ProfileIdc, SeqParameterSet};
+use h264_reader::rbsp::{BitRead, BitReaderError};
+
+/// Reads a single `scaling_list()` (7.3.2.1.1.1), returns `true` if the default matrix should be used.
+fn read_scaling_list<R: BitRead>(r: &mut R, list: &mut [u8]) -> Result<bool, BitReaderError> {
+    let mut last_scale: i32 = 8;
+    let mut next_scale = 8;
+    let mut use_default = false;
+
+    for (j, value) in list.iter_mut().enumerate() {
+        if next_scale != 0 {
+            let delta_scale = r.read_se("delta_scale")?;
+            next_scale = last_scale.wrapping_add(delta_scale).rem_euclid(256);
+            use_default = j == 0 && next_scale == 0;
+        }
+
+        if next_scale != 0 {
+            last_scale = next_scale;
+        }
+
+        *value = last_scale as u8;
+    }
+
+    Ok(use_default)
+}
+
+/// Reads `count` scaling lists (6 4x4 lists followed by up to 6 8x8 lists) into their Vulkan representation.
+fn read_scaling_lists<R: BitRead>(r: &mut R, count: usize) -> Result<StdVideoH264ScalingLists, BitReaderError> {
+    let mut lists = StdVideoH264ScalingLists {
+        scaling_list_present_mask: 0,
+        use_default_scaling_matrix_mask: 0,
+        ScalingList4x4: [[16; 16]; 6],
+        ScalingList8x8: [[16; 64]; 6],
+    };
+
+    for i in 0..count {
+        if !r.read_bool("scaling_list_present_flag")? {
+            continue;
+        }
+
+        let use_default = if i < 6 {
+            read_scaling_list(r, &mut lists.ScalingList4x4[i])?
+        } else {
+            read_scaling_list(r, &mut lists.ScalingList8x8[i - 6])?
+        };
+
+        lists.scaling_list_present_mask |= 1 << i;
+
+        if use_default {
+            lists.use_default_scaling_matrix_mask |= 1 << i;
+        }
+    }
+
+    Ok(lists)
+}
+
+/// Re-reads the beginning of a SPS RBSP and returns its scaling lists, if any.
+///
+/// List values are stored in the order they appear in the bitstream (zig-zag scan), which is what
+/// Vulkan expects.
+pub(crate) fn sps_scaling_lists<R: BitRead>(mut r: R) -> Result<Option<StdVideoH264ScalingLists>, BitReaderError> {
+    let profile_idc = ProfileIdc::from(r.read_u8(8, "profile_idc")?);
+    let _constraint_flags = r.read_u8(8, "constraint_flags")?;
+    let _level_idc = r.read_u8(8, "level_idc")?;
+    let _seq_parameter_set_id = r.read_ue("seq_parameter_set_id")?;
+
+    if !profile_idc.has_chroma_info() {
+        return Ok(None);
+    }
+
+    let chroma_format_idc = r.read_ue("chroma_format_idc")?;
+
+    if chroma_format_idc == 3 {
+        let _separate_colour_plane_flag = r.read_bool("separate_colour_plane_flag")?;
+    }
+
+    let _bit_depth_luma_minus8 = r.read_ue("bit_depth_luma_minus8")?;
+    let _bit_depth_chroma_minus8 = r.read_ue("bit_depth_chroma_minus8")?;
+    let _qpprime_y_zero_transform_bypass_flag = r.read_bool("qpprime_y_zero_transform_bypass_flag")?;
+
+    if !r.read_bool("seq_scaling_matrix_present_flag")? {
+        return Ok(None);
+    }
+
+    let count = if chroma_format_idc == 3 { 12 } else { 8 };
+
+    read_scaling_lists(&mut r, count).map(Some)
+}
+
+/// Re-reads a PPS RBSP and returns its scaling lists, if any.
+///
+/// The `sps` must be the one referenced by the PPS, as the number of 8x8 lists depends on its chroma format.
+pub(crate) fn pps_scaling_lists<R: BitRead>(mut r: R, sps: &SeqParameterSet) -> Result<Option<StdVideoH264ScalingLists>, BitReaderError> {
+    let _pic_parameter_set_id = r.read_ue("pic_parameter_set_id")?;
+    let _seq_parameter_set_id = r.read_ue("seq_parameter_set_id")?;
+    let _entropy_coding_mode_flag = r.read_bool("entropy_coding_mode_flag")?;
+    let _bottom_field_pic_order_in_frame_present_flag = r.read_bool("bottom_field_pic_order_in_frame_present_flag")?;
+
+    // Slice groups only exist in profiles without scaling matrices, and Vulkan does not support them either.
+    if r.read_ue("num_slice_groups_minus1")? > 0 {
+        return Ok(None);
+    }
+
+    let _num_ref_idx_l0_default_active_minus1 = r.read_ue("num_ref_idx_l0_default_active_minus1")?;
+    let _num_ref_idx_l1_default_active_minus1 = r.read_ue("num_ref_idx_l1_default_active_minus1")?;
+    let _weighted_pred_flag = r.read_bool("weighted_pred_flag")?;
+    let _weighted_bipred_idc = r.read_u8(2, "weighted_bipred_idc")?;
+    let _pic_init_qp_minus26 = r.read_se("pic_init_qp_minus26")?;
+    let _pic_init_qs_minus26 = r.read_se("pic_init_qs_minus26")?;
+    let _chroma_qp_index_offset = r.read_se("chroma_qp_index_offset")?;
+    let _deblocking_filter_control_present_flag = r.read_bool("deblocking_filter_control_present_flag")?;
+    let _constrained_intra_pred_flag = r.read_bool("constrained_intra_pred_flag")?;
+    let _redundant_pic_cnt_present_flag = r.read_bool("redundant_pic_cnt_present_flag")?;
+
+    if !r.has_more_rbsp_data("transform_8x8_mode_flag")? {
+        return Ok(None);
+    }
+
+    let transform_8x8_mode_flag = r.read_bool("transform_8x8_mode_flag")?;
+
+    if !r.read_bool("pic_scaling_matrix_present_flag")? {
+        return Ok(None);
+    }
+
+    let count = match (transform_8x8_mode_flag, sps.chroma_info.chroma_format) {
+        (false, _) => 6,
+        (true, ChromaFormat::YUV444) => 12,
+        (true, _) => 8,
+    };
+
+    read_scaling_lists(&mut r, count).map(Some)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{pps_scaling_lists, sps_scaling_lists};
+    use crate::video::h264::testdata::rbsp;
+    use h264_reader::nal::sps::SeqParameterSet;
+    use h264_reader::rbsp::BitReader;
+
+    #[test]
+    fn no_scaling_lists_in_baseline() {
+        // profile_idc 66, no constraints, level_idc 30, seq_parameter_set_id 0
+        let sps = rbsp("01000010 00000000 00011110 1");
+        let lists = sps_scaling_lists(BitReader::new(&sps[..])).unwrap();
+
+        assert!(lists.is_none());
+    }
+
+    #[test]
+    fn no_scaling_matrix_present() {
+        // profile_idc 100, 4:2:0, 8 bit, no scaling matrix
+        let sps = rbsp("01100100 00000000 00011111 1 010 1 1 0 0");
+        let lists = sps_scaling_lists(BitReader::new(&sps[..])).unwrap();
+
+        assert!(lists.is_none());
+    }
+
+    #[test]
+    fn reads_sps_scaling_lists() {
+        // profile_idc 100, 4:2:0, 8 bit, scaling matrix present, then:
+        // - list 0 signals the default matrix (delta_scale -8),
+        // - list 1 is explicit (delta_scale +2, then -10 to repeat the last value),
+        // - lists 2..8 are absent.
+        let sps = rbsp("01100100 00000000 00011111 1 010 1 1 0 1 1 000010001 1 00100 000010101 000000");
+        let lists = sps_scaling_lists(BitReader::new(&sps[..])).unwrap().unwrap();
+
+        assert_eq!(lists.scaling_list_present_mask, 0b11);
+        assert_eq!(lists.use_default_scaling_matrix_mask, 0b01);
+        assert_eq!(lists.ScalingList4x4[1], [10; 16]);
+    }
+
+    #[test]
+    fn reads_pps_scaling_lists() {
+        // profile_idc 100, 4:2:0, 8 bit, no scaling matrix, log2_max_frame_num_minus4 0, POC type 2,
+        // 1 ref frame, no gaps, 4x4 MBs, frame MBs only, direct 8x8, no cropping, no VUI
+        let sps = rbsp("01100100 00000000 00011111 1 010 1 1 0 0 1 011 010 0 00100 00100 1 1 0 0");
+        let sps = SeqParameterSet::from_bits(BitReader::new(&sps[..])).unwrap();
+
+        // ids 0, CABAC, no slice groups, 1 ref idx each, no weighted prediction, QPs 0, flags, transform 8x8,
+        // scaling matrix present with only the first 8x8 list (index 6) being explicit and flat.
+        let pps = rbsp("1 1 1 0 1 1 1 0 00 1 1 1 1 0 0 1 1 000000 1 00100 000010101 0");
+        let lists = pps_scaling_lists(BitReader::new(&pps[..]), &sps).unwrap().unwrap();
+
+        assert_eq!(lists.scaling_list_present_mask, 1 << 6);
+        assert_eq!(lists.use_default_scaling_matrix_mask, 0);
+        assert_eq!(lists.ScalingList8x8[0], [10; 64]);
+    }
+}
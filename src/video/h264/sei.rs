@@ -0,0 +1,748 @@
+//! Supplemental enhancement information (7.3.2.3, Annex D).
+use crate::error;
+use crate::error::{Error, Variant};
+use crate::video::h264::BitWriter;
+use h264_reader::nal::sps::{SeqParameterSet, VuiParameters};
+use h264_reader::rbsp::{BitRead, BitReader, BitReaderError};
+
+const PIC_TIMING: u32 = 1;
+const USER_DATA_REGISTERED_ITU_T_T35: u32 = 4;
+const USER_DATA_UNREGISTERED: u32 = 5;
+const RECOVERY_POINT: u32 = 6;
+const MASTERING_DISPLAY_COLOUR_VOLUME: u32 = 137;
+const CONTENT_LIGHT_LEVEL_INFO: u32 = 144;
+
+/// A SEI message we understand.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SeiMessage {
+    PicTiming(PicTiming),
+    RecoveryPoint(RecoveryPoint),
+    UserDataRegistered(UserDataRegistered),
+    UserDataUnregistered(UserDataUnregistered),
+    MasteringDisplayColourVolume(MasteringDisplayColourVolume),
+    ContentLightLevel(ContentLightLevel),
+}
+
+/// Timing and field structure of a picture (D.2.3).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PicTiming {
+    /// Only present if the SPS has HRD parameters.
+    pub cpb_removal_delay: Option<u32>,
+    pub dpb_output_delay: Option<u32>,
+    /// Only present if the SPS has `pic_struct_present_flag` set, see Table D-1.
+    pub pic_struct: Option<u8>,
+    /// One entry per field or frame repetition implied by `pic_struct`, `None` if no timestamp was sent for it.
+    pub clock_timestamps: Vec<Option<ClockTimestamp>>,
+}
+
+/// A SMPTE style time code (D.2.3).
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ClockTimestamp {
+    pub ct_type: u8,
+    pub nuit_field_based_flag: bool,
+    pub counting_type: u8,
+    pub discontinuity_flag: bool,
+    pub cnt_dropped_flag: bool,
+    pub n_frames: u8,
+    /// `None` if not sent, in which case it is the same as in the previous timestamp.
+    pub seconds: Option<u8>,
+    pub minutes: Option<u8>,
+    pub hours: Option<u8>,
+    pub time_offset: i32,
+}
+
+/// Where decoding can start without an IDR picture (D.2.7).
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct RecoveryPoint {
+    /// Number of frames, in output order, until pictures are correct.
+    pub recovery_frame_cnt: u32,
+    pub exact_match_flag: bool,
+    pub broken_link_flag: bool,
+    pub changing_slice_group_idc: u8,
+}
+
+/// User data registered by ITU-T T.35 (D.2.5), e.g., closed captions.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UserDataRegistered {
+    pub country_code: u8,
+    /// Only present if `country_code` is `0xFF`.
+    pub country_code_extension: Option<u8>,
+    /// Everything after the country code, starting with the provider code.
+    pub payload: Vec<u8>,
+}
+
+impl UserDataRegistered {
+    /// Returns the `cc_data_pkt`s of ATSC A/53 closed captions, 3 bytes each, if this is one.
+    pub fn a53_cc_data(&self) -> Option<&[u8]> {
+        // United States, ATSC provider code, 'GA94', `user_data_type_code` for `cc_data()`.
+        let data = match (self.country_code, self.payload.as_slice()) {
+            (0xB5, [0x00, 0x31, b'G', b'A', b'9', b'4', 0x03, data @ ..]) => data,
+            _ => return None,
+        };
+
+        let [flags, _em_data, packets @ ..] = data else {
+            return None;
+        };
+
+        let process_cc_data_flag = flags & 0x40 != 0;
+        let cc_count = usize::from(flags & 0x1F);
+
+        (process_cc_data_flag && packets.len() >= cc_count * 3).then(|| &packets[..cc_count * 3])
+    }
+}
+
+/// User data identified by a UUID (D.2.6).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UserDataUnregistered {
+    pub uuid: [u8; 16],
+    pub payload: Vec<u8>,
+}
+
+/// Colour volume of the mastering display, i.e., HDR metadata (D.2.29).
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct MasteringDisplayColourVolume {
+    /// `(x, y)` of the primaries in units of 0.00002, usually green, blue and red.
+    pub display_primaries: [(u16, u16); 3],
+    pub white_point: (u16, u16),
+    /// In units of 0.0001 cd/m².
+    pub max_display_mastering_luminance: u32,
+    pub min_display_mastering_luminance: u32,
+}
+
+/// Light levels of the content, i.e., HDR metadata (D.2.35).
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ContentLightLevel {
+    /// In cd/m².
+    pub max_content_light_level: u16,
+    pub max_pic_average_light_level: u16,
+}
+
+/// Parses all messages in a SEI RBSP, skipping those we don't know.
+///
+/// `pic_timing()` can only be interpreted with the active SPS, which for SEI is the one of the following
+/// slices. Messages that fail to parse, e.g., because `sps` turned out to be the wrong one, are skipped.
+pub(crate) fn parse_sei(rbsp: &[u8], sps: Option<&SeqParameterSet>) -> Result<Vec<SeiMessage>, Error> {
+    let mut messages = Vec::new();
+    let mut rest = rbsp;
+
+    // `more_rbsp_data()`, i.e., anything left except the trailing bits.
+    while !matches!(rest, [] | [0x80]) {
+        let payload_type = read_sei_value(&mut rest)?;
+        let payload_size = read_sei_value(&mut rest)? as usize;
+
+        if payload_size > rest.len() {
+            return Err(error!(
+                Variant::InvalidSei,
+                "Payload of {} bytes, but only {} left",
+                payload_size,
+                rest.len()
+            ));
+        }
+
+        let (payload, remainder) = rest.split_at(payload_size);
+        rest = remainder;
+
+        if let Ok(Some(message)) = parse_payload(payload_type, payload, sps) {
+            messages.push(message);
+        }
+    }
+
+    Ok(messages)
+}
+
+/// Reads `payload_type` or `payload_size`, a sum of bytes ended by the first one that is not `0xFF`.
+fn read_sei_value(data: &mut &[u8]) -> Result<u32, Error> {
+    let mut value = 0u32;
+
+    loop {
+        let Some((byte, rest)) = data.split_first() else {
+            return Err(error!(Variant::InvalidSei, "SEI message header is truncated"));
+        };
+
+        *data = rest;
+        value = value.saturating_add(u32::from(*byte));
+
+        if *byte != 0xFF {
+            return Ok(value);
+        }
+    }
+}
+
+fn parse_payload(payload_type: u32, payload: &[u8], sps: Option<&SeqParameterSet>) -> Result<Option<SeiMessage>, BitReaderError> {
+    let mut r = BitReader::new(payload);
+
+    let message = match payload_type {
+        PIC_TIMING => match sps {
+            Some(sps) => SeiMessage::PicTiming(read_pic_timing(&mut r, sps)?),
+            None => return Ok(None),
+        },
+        RECOVERY_POINT => SeiMessage::RecoveryPoint(RecoveryPoint {
+            recovery_frame_cnt: r.read_ue("recovery_frame_cnt")?,
+            exact_match_flag: r.read_bool("exact_match_flag")?,
+            broken_link_flag: r.read_bool("broken_link_flag")?,
+            changing_slice_group_idc: r.read_u8(2, "changing_slice_group_idc")?,
+        }),
+        USER_DATA_REGISTERED_ITU_T_T35 => {
+            let (country_code, country_code_extension, payload) = match payload {
+                [0xFF, extension, payload @ ..] => (0xFF, Some(*extension), payload),
+                [country_code, payload @ ..] => (*country_code, None, payload),
+                [] => return Ok(None),
+            };
+
+            SeiMessage::UserDataRegistered(UserDataRegistered {
+                country_code,
+                country_code_extension,
+                payload: payload.to_vec(),
+            })
+        }
+        USER_DATA_UNREGISTERED => {
+            let Some((uuid, payload)) = payload.split_first_chunk::<16>() else {
+                return Ok(None);
+            };
+
+            SeiMessage::UserDataUnregistered(UserDataUnregistered {
+                uuid: *uuid,
+                payload: payload.to_vec(),
+            })
+        }
+        MASTERING_DISPLAY_COLOUR_VOLUME => {
+            let mut display_primaries = [(0, 0); 3];
+
+            for primary in &mut display_primaries {
+                *primary = (r.read_u16(16, "display_primaries_x")?, r.read_u16(16, "display_primaries_y")?);
+            }
+
+            SeiMessage::MasteringDisplayColourVolume(MasteringDisplayColourVolume {
+                display_primaries,
+                white_point: (r.read_u16(16, "white_point_x")?, r.read_u16(16, "white_point_y")?),
+                max_display_mastering_luminance: r.read_u32(32, "max_display_mastering_luminance")?,
+                min_display_mastering_luminance: r.read_u32(32, "min_display_mastering_luminance")?,
+            })
+        }
+        CONTENT_LIGHT_LEVEL_INFO => SeiMessage::ContentLightLevel(ContentLightLevel {
+            max_content_light_level: r.read_u16(16, "max_content_light_level")?,
+            max_pic_average_light_level: r.read_u16(16, "max_pic_average_light_level")?,
+        }),
+        _ => return Ok(None),
+    };
+
+    Ok(Some(message))
+}
+
+/// D.1.3
+fn read_pic_timing<R: BitRead>(r: &mut R, sps: &SeqParameterSet) -> Result<PicTiming, BitReaderError> {
+    let mut pic_timing = PicTiming::default();
+
+    let Some(vui) = &sps.vui_parameters else {
+        return Ok(pic_timing);
+    };
+
+    // `CpbDpbDelaysPresentFlag`, both HRDs have the same lengths if present (E.2.2).
+    if let Some(hrd) = vui.nal_hrd_parameters.as_ref().or(vui.vcl_hrd_parameters.as_ref()) {
+        pic_timing.cpb_removal_delay = Some(r.read_u32(u32::from(hrd.cpb_removal_delay_length_minus1) + 1, "cpb_removal_delay")?);
+        pic_timing.dpb_output_delay = Some(r.read_u32(u32::from(hrd.dpb_output_delay_length_minus1) + 1, "dpb_output_delay")?);
+    }
+
+    if !vui.pic_struct_present_flag {
+        return Ok(pic_timing);
+    }
+
+    let pic_struct = r.read_u8(4, "pic_struct")?;
+    let time_offset_length = time_offset_length(vui);
+
+    pic_timing.pic_struct = Some(pic_struct);
+
+    for _ in 0..num_clock_ts(pic_struct) {
+        let clock_timestamp = if r.read_bool("clock_timestamp_flag")? {
+            Some(read_clock_timestamp(r, time_offset_length)?)
+        } else {
+            None
+        };
+
+        pic_timing.clock_timestamps.push(clock_timestamp);
+    }
+
+    Ok(pic_timing)
+}
+
+/// Table D-1
+fn num_clock_ts(pic_struct: u8) -> usize {
+    match pic_struct {
+        0..=2 => 1,
+        3 | 4 | 7 => 2,
+        5 | 6 | 8 => 3,
+        _ => 0,
+    }
+}
+
+fn time_offset_length(vui: &VuiParameters) -> u32 {
+    vui.nal_hrd_parameters
+        .as_ref()
+        .or(vui.vcl_hrd_parameters.as_ref())
+        .map_or(24, |x| u32::from(x.time_offset_length))
+}
+
+fn read_clock_timestamp<R: BitRead>(r: &mut R, time_offset_length: u32) -> Result<ClockTimestamp, BitReaderError> {
+    let mut clock_timestamp = ClockTimestamp {
+        ct_type: r.read_u8(2, "ct_type")?,
+        nuit_field_based_flag: r.read_bool("nuit_field_based_flag")?,
+        counting_type: r.read_u8(5, "counting_type")?,
+        ..Default::default()
+    };
+
+    let full_timestamp_flag = r.read_bool("full_timestamp_flag")?;
+
+    clock_timestamp.discontinuity_flag = r.read_bool("discontinuity_flag")?;
+    clock_timestamp.cnt_dropped_flag = r.read_bool("cnt_dropped_flag")?;
+    clock_timestamp.n_frames = r.read_u8(8, "n_frames")?;
+
+    if full_timestamp_flag {
+        clock_timestamp.seconds = Some(r.read_u8(6, "seconds_value")?);
+        clock_timestamp.minutes = Some(r.read_u8(6, "minutes_value")?);
+        clock_timestamp.hours = Some(r.read_u8(5, "hours_value")?);
+    } else if r.read_bool("seconds_flag")? {
+        clock_timestamp.seconds = Some(r.read_u8(6, "seconds_value")?);
+
+        if r.read_bool("minutes_flag")? {
+            clock_timestamp.minutes = Some(r.read_u8(6, "minutes_value")?);
+
+            if r.read_bool("hours_flag")? {
+                clock_timestamp.hours = Some(r.read_u8(5, "hours_value")?);
+            }
+        }
+    }
+
+    if time_offset_length > 0 {
+        // i(v), two's complement with `time_offset_length` bits.
+        let raw = r.read_u32(time_offset_length, "time_offset")?;
+        let shift = 32 - time_offset_length;
+        clock_timestamp.time_offset = ((raw << shift) as i32) >> shift;
+    }
+
+    Ok(clock_timestamp)
+}
+
+/// Writes the RBSP of a SEI NAL unit (7.3.2.3) holding `messages`, including trailing bits.
+///
+/// As for parsing, `pic_timing()` needs the SPS of the following slices to know which fields are present.
+pub fn write_sei(w: &mut BitWriter, messages: &[SeiMessage], sps: Option<&SeqParameterSet>) -> Result<(), Error> {
+    for message in messages {
+        let (payload_type, payload) = write_payload(message, sps)?;
+
+        write_sei_value(w, payload_type);
+        write_sei_value(w, payload.len() as u32);
+        w.write_bytes(&payload);
+    }
+
+    w.write_trailing_bits();
+
+    Ok(())
+}
+
+/// Writes `payload_type` or `payload_size` as a run of `0xFF` bytes and a remainder.
+fn write_sei_value(w: &mut BitWriter, mut value: u32) {
+    while value >= 0xFF {
+        w.write_bits(0xFF, 8);
+        value -= 0xFF;
+    }
+
+    w.write_bits(value, 8);
+}
+
+fn write_payload(message: &SeiMessage, sps: Option<&SeqParameterSet>) -> Result<(u32, Vec<u8>), Error> {
+    let mut w = BitWriter::new();
+
+    let payload_type = match message {
+        SeiMessage::PicTiming(pic_timing) => {
+            let sps = sps.ok_or_else(|| error!(Variant::InvalidSei, "Writing pic_timing() needs the active SPS"))?;
+
+            write_pic_timing(&mut w, pic_timing, sps)?;
+            PIC_TIMING
+        }
+        SeiMessage::RecoveryPoint(recovery_point) => {
+            w.write_ue(recovery_point.recovery_frame_cnt);
+            w.write_bool(recovery_point.exact_match_flag);
+            w.write_bool(recovery_point.broken_link_flag);
+            w.write_bits(recovery_point.changing_slice_group_idc.into(), 2);
+            RECOVERY_POINT
+        }
+        SeiMessage::UserDataRegistered(user_data) => {
+            w.write_bits(user_data.country_code.into(), 8);
+
+            if let Some(extension) = user_data.country_code_extension {
+                w.write_bits(extension.into(), 8);
+            }
+
+            w.write_bytes(&user_data.payload);
+            USER_DATA_REGISTERED_ITU_T_T35
+        }
+        SeiMessage::UserDataUnregistered(user_data) => {
+            w.write_bytes(&user_data.uuid);
+            w.write_bytes(&user_data.payload);
+            USER_DATA_UNREGISTERED
+        }
+        SeiMessage::MasteringDisplayColourVolume(colour_volume) => {
+            for (x, y) in colour_volume.display_primaries.iter().chain([&colour_volume.white_point]) {
+                w.write_bits((*x).into(), 16);
+                w.write_bits((*y).into(), 16);
+            }
+
+            w.write_bits(colour_volume.max_display_mastering_luminance, 32);
+            w.write_bits(colour_volume.min_display_mastering_luminance, 32);
+            MASTERING_DISPLAY_COLOUR_VOLUME
+        }
+        SeiMessage::ContentLightLevel(light_level) => {
+            w.write_bits(light_level.max_content_light_level.into(), 16);
+            w.write_bits(light_level.max_pic_average_light_level.into(), 16);
+            CONTENT_LIGHT_LEVEL_INFO
+        }
+    };
+
+    // D.1.1: Payloads ending mid-byte get a `1` and zeros, just like RBSP trailing bits.
+    if !w.is_byte_aligned() {
+        w.write_trailing_bits();
+    }
+
+    Ok((payload_type, w.into_rbsp()))
+}
+
+/// D.1.3
+fn write_pic_timing(w: &mut BitWriter, pic_timing: &PicTiming, sps: &SeqParameterSet) -> Result<(), Error> {
+    let vui = sps.vui_parameters.as_ref();
+    let hrd = vui.and_then(|x| x.nal_hrd_parameters.as_ref().or(x.vcl_hrd_parameters.as_ref()));
+
+    match (hrd, pic_timing.cpb_removal_delay, pic_timing.dpb_output_delay) {
+        (Some(hrd), Some(cpb_removal_delay), Some(dpb_output_delay)) => {
+            w.write_bits(cpb_removal_delay, u32::from(hrd.cpb_removal_delay_length_minus1) + 1);
+            w.write_bits(dpb_output_delay, u32::from(hrd.dpb_output_delay_length_minus1) + 1);
+        }
+        (None, None, None) => {}
+        _ => {
+            return Err(error!(
+                Variant::InvalidSei,
+                "cpb_removal_delay and dpb_output_delay must be set exactly if the SPS has HRD parameters"
+            ))
+        }
+    }
+
+    let (vui, pic_struct) = match (vui.filter(|x| x.pic_struct_present_flag), pic_timing.pic_struct) {
+        (Some(vui), Some(pic_struct)) => (vui, pic_struct),
+        (None, None) => return Ok(()),
+        _ => {
+            return Err(error!(
+                Variant::InvalidSei,
+                "pic_struct must be set exactly if the SPS has pic_struct_present_flag set"
+            ))
+        }
+    };
+
+    let time_offset_length = time_offset_length(vui);
+
+    w.write_bits(pic_struct.into(), 4);
+
+    for i in 0..num_clock_ts(pic_struct) {
+        let clock_timestamp = pic_timing.clock_timestamps.get(i).copied().flatten();
+
+        w.write_bool(clock_timestamp.is_some());
+
+        if let Some(clock_timestamp) = clock_timestamp {
+            write_clock_timestamp(w, &clock_timestamp, time_offset_length);
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes a full timestamp if hours, minutes and seconds are known, otherwise as much as the flags allow.
+fn write_clock_timestamp(w: &mut BitWriter, clock_timestamp: &ClockTimestamp, time_offset_length: u32) {
+    let full_timestamp_flag = clock_timestamp.seconds.is_some() && clock_timestamp.minutes.is_some() && clock_timestamp.hours.is_some();
+
+    w.write_bits(clock_timestamp.ct_type.into(), 2);
+    w.write_bool(clock_timestamp.nuit_field_based_flag);
+    w.write_bits(clock_timestamp.counting_type.into(), 5);
+    w.write_bool(full_timestamp_flag);
+    w.write_bool(clock_timestamp.discontinuity_flag);
+    w.write_bool(clock_timestamp.cnt_dropped_flag);
+    w.write_bits(clock_timestamp.n_frames.into(), 8);
+
+    if full_timestamp_flag {
+        w.write_bits(clock_timestamp.seconds.unwrap_or_default().into(), 6);
+        w.write_bits(clock_timestamp.minutes.unwrap_or_default().into(), 6);
+        w.write_bits(clock_timestamp.hours.unwrap_or_default().into(), 5);
+    } else {
+        // Each value is only sent if the previous one is.
+        for (value, bits) in [
+            (clock_timestamp.seconds, 6),
+            (clock_timestamp.minutes, 6),
+            (clock_timestamp.hours, 5),
+        ] {
+            w.write_bool(value.is_some());
+
+            let Some(value) = value else {
+                break;
+            };
+
+            w.write_bits(value.into(), bits);
+        }
+    }
+
+    if time_offset_length > 0 {
+        // i(v), two's complement with `time_offset_length` bits.
+        w.write_bits(clock_timestamp.time_offset as u32, time_offset_length);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        parse_sei, write_sei, ClockTimestamp, ContentLightLevel, MasteringDisplayColourVolume, PicTiming, RecoveryPoint, SeiMessage,
+        UserDataRegistered, UserDataUnregistered,
+    };
+    use crate::error;
+    use crate::error::{Error, Variant};
+    use crate::video::h264::testdata::{rbsp, sps};
+    use crate::video::h264::BitWriter;
+    use h264_reader::nal::sei::pic_timing::{self, CountingType, CtType, PicStructType, SecMinHour};
+    use h264_reader::nal::sei::{HeaderType, SeiReader};
+    use h264_reader::nal::UnitType;
+    use h264_reader::rbsp::decode_nal;
+
+    #[test]
+    fn parses_messages() -> Result<(), Error> {
+        let mut data = Vec::new();
+
+        // Recovery point: recovery_frame_cnt 2, exact_match, no broken link, changing_slice_group_idc 0.
+        data.extend([6, 1, 0b0111_0000]);
+        // Unregistered user data with a UUID of 0..16 and 2 bytes of payload.
+        data.extend([5, 18]);
+        data.extend(0..16);
+        data.extend([0xAB, 0xCD]);
+        // A message type we don't know, with a 0xFF-extended type.
+        data.extend([0xFF, 0x01, 1, 0x42]);
+        // Mastering display and content light level.
+        data.extend([137, 24]);
+        data.extend([
+            0x33, 0xC2, 0x86, 0xC4, 0x1D, 0x4C, 0x0B, 0xB8, 0x84, 0xD0, 0x3E, 0x80, 0x3D, 0x13, 0x40, 0x42,
+        ]);
+        data.extend([0x00, 0x98, 0x96, 0x80, 0x00, 0x00, 0x00, 0x32]);
+        data.extend([144, 4, 0x03, 0xE8, 0x01, 0x90]);
+        data.push(0x80);
+
+        let messages = parse_sei(&data, None)?;
+
+        assert_eq!(
+            messages,
+            vec![
+                SeiMessage::RecoveryPoint(RecoveryPoint {
+                    recovery_frame_cnt: 2,
+                    exact_match_flag: true,
+                    broken_link_flag: false,
+                    changing_slice_group_idc: 0,
+                }),
+                SeiMessage::UserDataUnregistered(UserDataUnregistered {
+                    uuid: core::array::from_fn(|i| i as u8),
+                    payload: vec![0xAB, 0xCD],
+                }),
+                SeiMessage::MasteringDisplayColourVolume(MasteringDisplayColourVolume {
+                    display_primaries: [(13250, 34500), (7500, 3000), (34000, 16000)],
+                    white_point: (15635, 16450),
+                    max_display_mastering_luminance: 10_000_000,
+                    min_display_mastering_luminance: 50,
+                }),
+                SeiMessage::ContentLightLevel(ContentLightLevel {
+                    max_content_light_level: 1000,
+                    max_pic_average_light_level: 400,
+                }),
+            ]
+        );
+
+        let truncated = parse_sei(&[5, 20, 0, 0], None).unwrap_err();
+
+        assert!(matches!(truncated.variant(), Variant::InvalidSei));
+
+        Ok(())
+    }
+
+    #[test]
+    fn parses_pic_timing_and_captions() -> Result<(), Error> {
+        // Baseline, 4x4 MBs, VUI with only pic_struct_present_flag set.
+        let sps = sps("01000010 00000000 00011110 1 1 1 1 010 0 00100 00100 1 1 0 1 0 0 0 0 0 0 0 1 0");
+
+        // pic_struct 3 (top, bottom), a full timestamp 01:02:03 frame 4 for the first field, none for the second.
+        let pic_timing = rbsp("0011 1 00 0 00000 1 0 0 00000100 000011 000010 00001 000000000000000000000001 0");
+        let mut data = vec![1, pic_timing.len() as u8];
+        data.extend(&pic_timing);
+
+        // A/53 captions with 2 packets.
+        data.extend([
+            4, 16, 0xB5, 0x00, 0x31, b'G', b'A', b'9', b'4', 0x03, 0x42, 0xFF, 0xFC, 0x94, 0x2C, 0xFC, 0x80, 0x80,
+        ]);
+        data.push(0x80);
+
+        let messages = parse_sei(&data, Some(&sps))?;
+
+        let SeiMessage::PicTiming(pic_timing) = &messages[0] else {
+            panic!("Expected pic_timing, got {:?}", messages[0]);
+        };
+
+        let SeiMessage::UserDataRegistered(captions) = &messages[1] else {
+            panic!("Expected registered user data, got {:?}", messages[1]);
+        };
+
+        let timestamp = pic_timing.clock_timestamps[0].unwrap();
+
+        assert_eq!(pic_timing.cpb_removal_delay, None);
+        assert_eq!(pic_timing.pic_struct, Some(3));
+        assert_eq!(pic_timing.clock_timestamps.len(), 2);
+        assert_eq!(pic_timing.clock_timestamps[1], None);
+        assert_eq!((timestamp.hours, timestamp.minutes, timestamp.seconds), (Some(1), Some(2), Some(3)));
+        assert_eq!(timestamp.n_frames, 4);
+        assert_eq!(timestamp.time_offset, 1);
+        assert_eq!(captions.a53_cc_data(), Some(&[0xFC, 0x94, 0x2C, 0xFC, 0x80, 0x80][..]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn writes_messages() -> Result<(), Error> {
+        // Baseline, 4x4 MBs, VUI with only pic_struct_present_flag set.
+        let no_hrd = sps("01000010 00000000 00011110 1 1 1 1 010 0 00100 00100 1 1 0 1 0 0 0 0 0 0 0 1 0");
+        // As above, with NAL HRD (16 bit cpb, 8 bit dpb delays, 8 bit time offset) and pic_struct.
+        let sps = sps("01000010 00000000 00011110 1 1 1 1 010 0 00100 00100 1 1 0 1 0 0 0 0 0 \
+             1 1 0000 0000 1 1 0 10111 01111 00111 01000 0 0 1 0");
+
+        let full = ClockTimestamp {
+            ct_type: 1,
+            counting_type: 4,
+            n_frames: 29,
+            seconds: Some(59),
+            minutes: Some(30),
+            hours: Some(23),
+            time_offset: -3,
+            ..ClockTimestamp::default()
+        };
+
+        let partial = ClockTimestamp {
+            nuit_field_based_flag: true,
+            cnt_dropped_flag: true,
+            seconds: Some(1),
+            minutes: Some(2),
+            ..ClockTimestamp::default()
+        };
+
+        let messages = vec![
+            SeiMessage::PicTiming(PicTiming {
+                cpb_removal_delay: Some(1000),
+                dpb_output_delay: Some(2),
+                pic_struct: Some(5),
+                clock_timestamps: vec![Some(full), Some(partial), None],
+            }),
+            SeiMessage::RecoveryPoint(RecoveryPoint {
+                recovery_frame_cnt: 300,
+                exact_match_flag: false,
+                broken_link_flag: true,
+                changing_slice_group_idc: 2,
+            }),
+            // Long enough for the payload size to need a 0xFF byte.
+            SeiMessage::UserDataRegistered(UserDataRegistered {
+                country_code: 0xB5,
+                country_code_extension: None,
+                payload: (0..300).map(|x| x as u8).collect(),
+            }),
+            SeiMessage::UserDataUnregistered(UserDataUnregistered {
+                uuid: [0xAA; 16],
+                payload: vec![0, 0, 1],
+            }),
+            SeiMessage::MasteringDisplayColourVolume(MasteringDisplayColourVolume {
+                display_primaries: [(13250, 34500), (7500, 3000), (34000, 16000)],
+                white_point: (15635, 16450),
+                max_display_mastering_luminance: 10_000_000,
+                min_display_mastering_luminance: 50,
+            }),
+            SeiMessage::ContentLightLevel(ContentLightLevel {
+                max_content_light_level: 1000,
+                max_pic_average_light_level: 400,
+            }),
+        ];
+
+        let mut w = BitWriter::new();
+        write_sei(&mut w, &messages, Some(&sps))?;
+
+        let nal = w.into_nal(0, UnitType::SEI);
+        let rbsp = decode_nal(&nal[4..])?;
+
+        assert_eq!(parse_sei(&rbsp, Some(&sps))?, messages);
+
+        // The same NAL unit as `h264-reader` sees it.
+        let mut scratch = Vec::new();
+        let mut reader = SeiReader::from_rbsp_bytes(&rbsp[..], &mut scratch);
+        let mut payloads = Vec::new();
+        let mut h264_reader_pic_timing = None;
+
+        while let Some(message) = reader.next()? {
+            payloads.push((message.payload_type, message.payload.len()));
+
+            if message.payload_type == HeaderType::PicTiming {
+                let parsed = pic_timing::PicTiming::read(&sps, &message).map_err(|e| error!(Variant::InvalidSei, "{:?}", e))?;
+                h264_reader_pic_timing = Some(parsed);
+            }
+        }
+
+        assert_eq!(
+            payloads,
+            vec![
+                (HeaderType::PicTiming, 15),
+                (HeaderType::RecoveryPoint, 3),
+                (HeaderType::UserDataRegisteredItuTT35, 301),
+                (HeaderType::UserDataUnregistered, 19),
+                (HeaderType::MasteringDisplayColourVolume, 24),
+                (HeaderType::ReservedSeiMessage(144), 4),
+            ]
+        );
+
+        let h264_reader_pic_timing = h264_reader_pic_timing.unwrap();
+        let pic_struct = h264_reader_pic_timing.pic_struct.unwrap();
+
+        // `Delays` has no public fields.
+        assert_eq!(
+            format!("{:?}", h264_reader_pic_timing.delays),
+            "Some(Delays { cpb_removal_delay: 1000, dpb_output_delay: 2 })"
+        );
+        assert_eq!(pic_struct.pic_struct, PicStructType::TopFieldBottomFieldTopFieldRepeated);
+        assert_eq!(
+            pic_struct.clock_timestamps,
+            vec![
+                Some(pic_timing::ClockTimestamp {
+                    ct_type: CtType::Interlaced,
+                    nuit_field_based_flag: false,
+                    counting_type: CountingType::DroppingTwoLowest,
+                    discontinuity_flag: false,
+                    cnt_dropped_flag: false,
+                    n_frames: 29,
+                    smh: SecMinHour::SMH(59, 30, 23),
+                    // `h264-reader` doesn't sign extend `i(n)`, this is -3 in 8 bits.
+                    time_offset: Some(253),
+                }),
+                Some(pic_timing::ClockTimestamp {
+                    ct_type: CtType::Progressive,
+                    nuit_field_based_flag: true,
+                    counting_type: CountingType::NoDroppingNoOffset,
+                    discontinuity_flag: false,
+                    cnt_dropped_flag: true,
+                    n_frames: 0,
+                    smh: SecMinHour::SM(1, 2),
+                    time_offset: Some(0),
+                }),
+                None,
+            ]
+        );
+
+        // Delays without HRD parameters can't be written.
+        let mut w = BitWriter::new();
+        let error = write_sei(&mut w, &messages[..1], Some(&no_hrd)).unwrap_err();
+
+        assert!(matches!(error.variant(), Variant::InvalidSei));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,573 @@
+//! Slice header parsing (7.3.3), as `h264-reader` hides most fields and rejects some B-slices.
+use crate::error;
+use crate::error::{Error, Variant};
+use h264_reader::nal::pps::ParamSetId;
+use h264_reader::nal::sps::{ChromaFormat, FrameMbsFlags, PicOrderCntType};
+use h264_reader::nal::{NalHeader, UnitType};
+use h264_reader::rbsp::BitRead;
+use h264_reader::Context;
+
+/// The `slice_type` of a slice, with values 5..9 folded into 0..4 (Table 7-6).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SliceType {
+    P,
+    B,
+    I,
+    SP,
+    SI,
+}
+
+impl SliceType {
+    fn from_id(id: u32) -> Option<Self> {
+        match id % 5 {
+            _ if id > 9 => None,
+            0 => Some(Self::P),
+            1 => Some(Self::B),
+            2 => Some(Self::I),
+            3 => Some(Self::SP),
+            _ => Some(Self::SI),
+        }
+    }
+
+    /// If slices of this type reference list 0, i.e., are not intra.
+    pub fn is_inter(self) -> bool {
+        matches!(self, Self::P | Self::SP | Self::B)
+    }
+}
+
+/// A single entry of `ref_pic_list_modification()` (7.3.3.1).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RefPicListModification {
+    /// `modification_of_pic_nums_idc` 0, carrying `abs_diff_pic_num_minus1`.
+    ShortTermSubtract(u32),
+    /// `modification_of_pic_nums_idc` 1, carrying `abs_diff_pic_num_minus1`.
+    ShortTermAdd(u32),
+    /// `modification_of_pic_nums_idc` 2, carrying `long_term_pic_num`.
+    LongTerm(u32),
+}
+
+/// A memory management control operation of `dec_ref_pic_marking()` (7.3.3.3, Table 7-9).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MemoryManagementControlOperation {
+    /// MMCO 1
+    ShortTermUnusedForReference { difference_of_pic_nums_minus1: u32 },
+    /// MMCO 2
+    LongTermUnusedForReference { long_term_pic_num: u32 },
+    /// MMCO 3
+    ShortTermToLongTerm {
+        difference_of_pic_nums_minus1: u32,
+        long_term_frame_idx: u32,
+    },
+    /// MMCO 4
+    MaxLongTermFrameIdx { max_long_term_frame_idx_plus1: u32 },
+    /// MMCO 5
+    AllUnusedForReference,
+    /// MMCO 6
+    CurrentToLongTerm { long_term_frame_idx: u32 },
+}
+
+/// The parsed `dec_ref_pic_marking()` of a reference slice (7.3.3.3).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DecRefPicMarking {
+    Idr {
+        no_output_of_prior_pics_flag: bool,
+        long_term_reference_flag: bool,
+    },
+    SlidingWindow,
+    Adaptive(Vec<MemoryManagementControlOperation>),
+}
+
+impl DecRefPicMarking {
+    /// If this marking contains a MMCO 5, which resets `frame_num` and POC state.
+    pub fn has_mmco5(&self) -> bool {
+        match self {
+            Self::Adaptive(x) => x.contains(&MemoryManagementControlOperation::AllUnusedForReference),
+            _ => false,
+        }
+    }
+}
+
+/// A parsed slice header, resolved against the SPS and PPS it refers to.
+#[derive(Clone, Debug)]
+pub struct SliceHeader {
+    pub nal_ref_idc: u8,
+    pub idr: bool,
+    pub first_mb_in_slice: u32,
+    pub slice_type: SliceType,
+    pub pic_parameter_set_id: u8,
+    pub seq_parameter_set_id: u8,
+    pub colour_plane_id: u8,
+    pub frame_num: u16,
+    pub field_pic_flag: bool,
+    pub bottom_field_flag: bool,
+    pub idr_pic_id: u16,
+    pub pic_order_cnt_lsb: u16,
+    pub delta_pic_order_cnt_bottom: i32,
+    pub delta_pic_order_cnt: [i32; 2],
+    pub redundant_pic_cnt: u32,
+    pub direct_spatial_mv_pred_flag: bool,
+    pub num_ref_idx_l0_active_minus1: u32,
+    pub num_ref_idx_l1_active_minus1: u32,
+    pub ref_pic_list_modification_l0: Vec<RefPicListModification>,
+    pub ref_pic_list_modification_l1: Vec<RefPicListModification>,
+    /// Only present for reference slices, i.e., if `nal_ref_idc` is not 0.
+    pub dec_ref_pic_marking: Option<DecRefPicMarking>,
+    pub cabac_init_idc: u32,
+    pub slice_qp_delta: i32,
+    pub sp_for_switch_flag: bool,
+    pub slice_qs_delta: i32,
+    pub disable_deblocking_filter_idc: u32,
+    pub slice_alpha_c0_offset_div2: i32,
+    pub slice_beta_offset_div2: i32,
+}
+
+impl SliceHeader {
+    /// Parses the header of a slice NAL unit with the given `header`, `r` reading its RBSP.
+    pub(crate) fn from_bits<R: BitRead>(ctx: &Context, header: NalHeader, mut r: R) -> Result<Self, Error> {
+        let idr = header.nal_unit_type() == UnitType::SliceLayerWithoutPartitioningIdr;
+
+        let first_mb_in_slice = r.read_ue("first_mb_in_slice")?;
+        let slice_type_id = r.read_ue("slice_type")?;
+        let slice_type =
+            SliceType::from_id(slice_type_id).ok_or_else(|| error!(Variant::InvalidSliceHeader, "Invalid slice_type {}", slice_type_id))?;
+        let pic_parameter_set_id = r.read_ue("pic_parameter_set_id")?;
+
+        let pps = ParamSetId::from_u32(pic_parameter_set_id)
+            .ok()
+            .and_then(|x| ctx.pps_by_id(x))
+            .ok_or_else(|| error!(Variant::InvalidSliceHeader, "Unknown PPS {}", pic_parameter_set_id))?;
+
+        let sps = ctx
+            .sps_by_id(pps.seq_parameter_set_id)
+            .ok_or_else(|| error!(Variant::InvalidSliceHeader, "Unknown SPS {}", pps.seq_parameter_set_id.id()))?;
+
+        let chroma_array_type_present =
+            !sps.chroma_info.separate_colour_plane_flag && sps.chroma_info.chroma_format != ChromaFormat::Monochrome;
+
+        let colour_plane_id = if sps.chroma_info.separate_colour_plane_flag {
+            r.read_u8(2, "colour_plane_id")?
+        } else {
+            0
+        };
+
+        let frame_num = r.read_u16(u32::from(sps.log2_max_frame_num()), "frame_num")?;
+
+        let mut field_pic_flag = false;
+        let mut bottom_field_flag = false;
+
+        if let FrameMbsFlags::Fields { .. } = sps.frame_mbs_flags {
+            field_pic_flag = r.read_bool("field_pic_flag")?;
+
+            if field_pic_flag {
+                bottom_field_flag = r.read_bool("bottom_field_flag")?;
+            }
+        }
+
+        let idr_pic_id = if idr { r.read_ue("idr_pic_id")? as u16 } else { 0 };
+
+        let mut pic_order_cnt_lsb = 0;
+        let mut delta_pic_order_cnt_bottom = 0;
+        let mut delta_pic_order_cnt = [0; 2];
+
+        match sps.pic_order_cnt {
+            PicOrderCntType::TypeZero {
+                log2_max_pic_order_cnt_lsb_minus4,
+            } => {
+                pic_order_cnt_lsb = r.read_u16(u32::from(log2_max_pic_order_cnt_lsb_minus4) + 4, "pic_order_cnt_lsb")?;
+
+                if pps.bottom_field_pic_order_in_frame_present_flag && !field_pic_flag {
+                    delta_pic_order_cnt_bottom = r.read_se("delta_pic_order_cnt_bottom")?;
+                }
+            }
+            PicOrderCntType::TypeOne {
+                delta_pic_order_always_zero_flag: false,
+                ..
+            } => {
+                delta_pic_order_cnt[0] = r.read_se("delta_pic_order_cnt[0]")?;
+
+                if pps.bottom_field_pic_order_in_frame_present_flag && !field_pic_flag {
+                    delta_pic_order_cnt[1] = r.read_se("delta_pic_order_cnt[1]")?;
+                }
+            }
+            _ => {}
+        }
+
+        let redundant_pic_cnt = if pps.redundant_pic_cnt_present_flag {
+            r.read_ue("redundant_pic_cnt")?
+        } else {
+            0
+        };
+
+        let direct_spatial_mv_pred_flag = if slice_type == SliceType::B {
+            r.read_bool("direct_spatial_mv_pred_flag")?
+        } else {
+            false
+        };
+
+        let mut num_ref_idx_l0_active_minus1 = pps.num_ref_idx_l0_default_active_minus1;
+        let mut num_ref_idx_l1_active_minus1 = pps.num_ref_idx_l1_default_active_minus1;
+
+        if slice_type.is_inter() && r.read_bool("num_ref_idx_active_override_flag")? {
+            num_ref_idx_l0_active_minus1 = r.read_ue("num_ref_idx_l0_active_minus1")?;
+
+            if slice_type == SliceType::B {
+                num_ref_idx_l1_active_minus1 = r.read_ue("num_ref_idx_l1_active_minus1")?;
+            }
+        }
+
+        // 7.4.3: Frames can have at most 16 active references per list, fields 32. PPS defaults of lists
+        // the slice doesn't use may exceed that.
+        let max_num_ref_idx_minus1 = if field_pic_flag { 31 } else { 15 };
+        let l0_exceeded = slice_type.is_inter() && num_ref_idx_l0_active_minus1 > max_num_ref_idx_minus1;
+        let l1_exceeded = slice_type == SliceType::B && num_ref_idx_l1_active_minus1 > max_num_ref_idx_minus1;
+
+        if l0_exceeded || l1_exceeded {
+            return Err(error!(
+                Variant::InvalidSliceHeader,
+                "Too many active references ({}, {})", num_ref_idx_l0_active_minus1, num_ref_idx_l1_active_minus1
+            ));
+        }
+
+        let mut ref_pic_list_modification_l0 = Vec::new();
+        let mut ref_pic_list_modification_l1 = Vec::new();
+
+        if !matches!(slice_type, SliceType::I | SliceType::SI) {
+            ref_pic_list_modification_l0 = read_ref_pic_list_modification(&mut r)?;
+        }
+
+        if slice_type == SliceType::B {
+            ref_pic_list_modification_l1 = read_ref_pic_list_modification(&mut r)?;
+        }
+
+        if (pps.weighted_pred_flag && matches!(slice_type, SliceType::P | SliceType::SP))
+            || (pps.weighted_bipred_idc == 1 && slice_type == SliceType::B)
+        {
+            skip_pred_weight_table(
+                &mut r,
+                slice_type,
+                chroma_array_type_present,
+                num_ref_idx_l0_active_minus1,
+                num_ref_idx_l1_active_minus1,
+            )?;
+        }
+
+        let dec_ref_pic_marking = if header.nal_ref_idc() != 0 {
+            Some(read_dec_ref_pic_marking(&mut r, idr)?)
+        } else {
+            None
+        };
+
+        let cabac_init_idc = if pps.entropy_coding_mode_flag && slice_type.is_inter() {
+            r.read_ue("cabac_init_idc")?
+        } else {
+            0
+        };
+
+        let slice_qp_delta = r.read_se("slice_qp_delta")?;
+
+        let mut sp_for_switch_flag = false;
+        let mut slice_qs_delta = 0;
+
+        if matches!(slice_type, SliceType::SP | SliceType::SI) {
+            if slice_type == SliceType::SP {
+                sp_for_switch_flag = r.read_bool("sp_for_switch_flag")?;
+            }
+
+            slice_qs_delta = r.read_se("slice_qs_delta")?;
+        }
+
+        let mut disable_deblocking_filter_idc = 0;
+        let mut slice_alpha_c0_offset_div2 = 0;
+        let mut slice_beta_offset_div2 = 0;
+
+        if pps.deblocking_filter_control_present_flag {
+            disable_deblocking_filter_idc = r.read_ue("disable_deblocking_filter_idc")?;
+
+            if disable_deblocking_filter_idc != 1 {
+                slice_alpha_c0_offset_div2 = r.read_se("slice_alpha_c0_offset_div2")?;
+                slice_beta_offset_div2 = r.read_se("slice_beta_offset_div2")?;
+            }
+        }
+
+        // Slice groups are rejected when parsing the PPS, so there is no `slice_group_change_cycle`.
+
+        Ok(Self {
+            nal_ref_idc: header.nal_ref_idc(),
+            idr,
+            first_mb_in_slice,
+            slice_type,
+            pic_parameter_set_id: pps.pic_parameter_set_id.id(),
+            seq_parameter_set_id: pps.seq_parameter_set_id.id(),
+            colour_plane_id,
+            frame_num,
+            field_pic_flag,
+            bottom_field_flag,
+            idr_pic_id,
+            pic_order_cnt_lsb,
+            delta_pic_order_cnt_bottom,
+            delta_pic_order_cnt,
+            redundant_pic_cnt,
+            direct_spatial_mv_pred_flag,
+            num_ref_idx_l0_active_minus1,
+            num_ref_idx_l1_active_minus1,
+            ref_pic_list_modification_l0,
+            ref_pic_list_modification_l1,
+            dec_ref_pic_marking,
+            cabac_init_idc,
+            slice_qp_delta,
+            sp_for_switch_flag,
+            slice_qs_delta,
+            disable_deblocking_filter_idc,
+            slice_alpha_c0_offset_div2,
+            slice_beta_offset_div2,
+        })
+    }
+
+    /// If this slice belongs to a picture other pictures can reference.
+    pub fn is_reference(&self) -> bool {
+        self.nal_ref_idc != 0
+    }
+}
+
+/// What is needed of a first field to tell if the next picture completes it to a field pair (3.30, 3.31).
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct FirstField {
+    frame_num: u16,
+    bottom_field_flag: bool,
+    reference: bool,
+}
+
+impl FirstField {
+    /// Returns `Some` if `slice` belongs to a field a following field can pair with.
+    pub(crate) fn new(slice: &SliceHeader) -> Option<Self> {
+        let mmco5 = slice.dec_ref_pic_marking.as_ref().is_some_and(|x| x.has_mmco5());
+
+        (slice.field_pic_flag && !mmco5).then_some(Self {
+            frame_num: slice.frame_num,
+            bottom_field_flag: slice.bottom_field_flag,
+            reference: slice.is_reference(),
+        })
+    }
+
+    /// If `slice`, directly following this field in decoding order, is its second field.
+    pub(crate) fn is_completed_by(&self, slice: &SliceHeader) -> bool {
+        slice.field_pic_flag
+            && !slice.idr
+            && slice.bottom_field_flag != self.bottom_field_flag
+            && slice.frame_num == self.frame_num
+            && slice.is_reference() == self.reference
+    }
+}
+
+/// Reads one list of `ref_pic_list_modification()` (7.3.3.1).
+fn read_ref_pic_list_modification<R: BitRead>(r: &mut R) -> Result<Vec<RefPicListModification>, Error> {
+    let mut modifications = Vec::new();
+
+    if !r.read_bool("ref_pic_list_modification_flag")? {
+        return Ok(modifications);
+    }
+
+    loop {
+        let modification = match r.read_ue("modification_of_pic_nums_idc")? {
+            0 => RefPicListModification::ShortTermSubtract(r.read_ue("abs_diff_pic_num_minus1")?),
+            1 => RefPicListModification::ShortTermAdd(r.read_ue("abs_diff_pic_num_minus1")?),
+            2 => RefPicListModification::LongTerm(r.read_ue("long_term_pic_num")?),
+            3 => return Ok(modifications),
+            x => return Err(error!(Variant::InvalidSliceHeader, "Invalid modification_of_pic_nums_idc {}", x)),
+        };
+
+        modifications.push(modification);
+    }
+}
+
+/// Reads past `pred_weight_table()` (7.3.3.2), Vulkan parses the weights from the slice itself.
+fn skip_pred_weight_table<R: BitRead>(r: &mut R, slice_type: SliceType, chroma: bool, l0_minus1: u32, l1_minus1: u32) -> Result<(), Error> {
+    let _luma_log2_weight_denom = r.read_ue("luma_log2_weight_denom")?;
+
+    if chroma {
+        let _chroma_log2_weight_denom = r.read_ue("chroma_log2_weight_denom")?;
+    }
+
+    let num_lists = if slice_type == SliceType::B { 2 } else { 1 };
+
+    for num_ref_idx_active_minus1 in [l0_minus1, l1_minus1].into_iter().take(num_lists) {
+        for _ in 0..=num_ref_idx_active_minus1 {
+            if r.read_bool("luma_weight_flag")? {
+                let _luma_weight = r.read_se("luma_weight")?;
+                let _luma_offset = r.read_se("luma_offset")?;
+            }
+
+            if chroma && r.read_bool("chroma_weight_flag")? {
+                for _ in 0..2 {
+                    let _chroma_weight = r.read_se("chroma_weight")?;
+                    let _chroma_offset = r.read_se("chroma_offset")?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads `dec_ref_pic_marking()` (7.3.3.3).
+fn read_dec_ref_pic_marking<R: BitRead>(r: &mut R, idr: bool) -> Result<DecRefPicMarking, Error> {
+    use MemoryManagementControlOperation as Mmco;
+
+    if idr {
+        return Ok(DecRefPicMarking::Idr {
+            no_output_of_prior_pics_flag: r.read_bool("no_output_of_prior_pics_flag")?,
+            long_term_reference_flag: r.read_bool("long_term_reference_flag")?,
+        });
+    }
+
+    if !r.read_bool("adaptive_ref_pic_marking_mode_flag")? {
+        return Ok(DecRefPicMarking::SlidingWindow);
+    }
+
+    let mut operations = Vec::new();
+
+    loop {
+        let operation = match r.read_ue("memory_management_control_operation")? {
+            0 => return Ok(DecRefPicMarking::Adaptive(operations)),
+            1 => Mmco::ShortTermUnusedForReference {
+                difference_of_pic_nums_minus1: r.read_ue("difference_of_pic_nums_minus1")?,
+            },
+            2 => Mmco::LongTermUnusedForReference {
+                long_term_pic_num: r.read_ue("long_term_pic_num")?,
+            },
+            3 => Mmco::ShortTermToLongTerm {
+                difference_of_pic_nums_minus1: r.read_ue("difference_of_pic_nums_minus1")?,
+                long_term_frame_idx: r.read_ue("long_term_frame_idx")?,
+            },
+            4 => Mmco::MaxLongTermFrameIdx {
+                max_long_term_frame_idx_plus1: r.read_ue("max_long_term_frame_idx_plus1")?,
+            },
+            5 => Mmco::AllUnusedForReference,
+            6 => Mmco::CurrentToLongTerm {
+                long_term_frame_idx: r.read_ue("long_term_frame_idx")?,
+            },
+            x => {
+                return Err(error!(
+                    Variant::InvalidSliceHeader,
+                    "Invalid memory_management_control_operation {}", x
+                ))
+            }
+        };
+
+        operations.push(operation);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::{Error, Variant};
+    use crate::video::h264::testdata::nal;
+    use crate::video::h264::{
+        DecRefPicMarking, H264StreamInspector, MemoryManagementControlOperation, ParsedNal, RefPicListModification, SliceHeader, SliceType,
+    };
+
+    /// Baseline, 4 bit `frame_num`, POC type 0 with 4 bit LSB, 2 ref frames, 4x4 MBs, and a matching CAVLC PPS with deblocking control.
+    fn inspector() -> Result<H264StreamInspector, Error> {
+        let mut inspector = H264StreamInspector::new();
+
+        inspector.feed_nal(&nal(0x67, "01000010 00000000 00011110 1 1 1 1 010 0 00100 00100 1 1 0 0"))?;
+        inspector.feed_nal(&nal(0x68, "1 1 0 0 1 1 1 0 00 1 1 1 1 0 0"))?;
+
+        Ok(inspector)
+    }
+
+    fn slice(inspector: &mut H264StreamInspector, nal: &[u8]) -> Result<SliceHeader, Error> {
+        match inspector.feed_nal(nal)? {
+            Some(ParsedNal::Slice(x)) => Ok(x),
+            x => panic!("Expected slice, got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn parses_idr_slice() -> Result<(), Error> {
+        let mut inspector = inspector()?;
+
+        // first_mb 0, I (7), PPS 0, frame_num 0, idr_pic_id 3, POC LSB 0, no_output_of_prior_pics, QP delta 0, deblocking 0 / 0 / 0
+        let header = slice(&mut inspector, &nal(0x65, "1 0001000 1 0000 00100 0000 1 0 1 1 1 1 1"))?;
+
+        assert!(header.idr);
+        assert_eq!(header.slice_type, SliceType::I);
+        assert_eq!(header.idr_pic_id, 3);
+        assert_eq!(header.frame_num, 0);
+        assert_eq!(
+            header.dec_ref_pic_marking,
+            Some(DecRefPicMarking::Idr {
+                no_output_of_prior_pics_flag: true,
+                long_term_reference_flag: false
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn parses_p_slice() -> Result<(), Error> {
+        let mut inspector = inspector()?;
+
+        // first_mb 2, P (5), PPS 0, frame_num 1, POC LSB 2, 2 active refs, reorder (0, 0) and end,
+        // MMCO 1 (0), MMCO 5, end, QP delta -1, deblocking disabled
+        let bits = "011 00110 1 0001 0010 1 010 1 1 1 00100 1 010 1 00110 1 011 010";
+        let header = slice(&mut inspector, &nal(0x41, bits))?;
+
+        assert!(!header.idr);
+        assert_eq!(header.nal_ref_idc, 2);
+        assert_eq!(header.first_mb_in_slice, 2);
+        assert_eq!(header.slice_type, SliceType::P);
+        assert_eq!(header.frame_num, 1);
+        assert_eq!(header.pic_order_cnt_lsb, 2);
+        assert_eq!(header.num_ref_idx_l0_active_minus1, 1);
+        assert_eq!(
+            header.ref_pic_list_modification_l0,
+            vec![RefPicListModification::ShortTermSubtract(0)]
+        );
+        assert_eq!(header.slice_qp_delta, -1);
+        assert_eq!(header.disable_deblocking_filter_idc, 1);
+
+        let marking = header.dec_ref_pic_marking.unwrap();
+
+        assert!(marking.has_mmco5());
+        assert_eq!(
+            marking,
+            DecRefPicMarking::Adaptive(vec![
+                MemoryManagementControlOperation::ShortTermUnusedForReference {
+                    difference_of_pic_nums_minus1: 0
+                },
+                MemoryManagementControlOperation::AllUnusedForReference,
+            ])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn ignores_defaults_of_unused_lists() -> Result<(), Error> {
+        let mut inspector = inspector()?;
+
+        // As above, but `num_ref_idx_l1_default_active_minus1` is 20, which only B slices would have to obey.
+        inspector.feed_nal(&nal(0x68, "1 1 0 0 1 1 000010101 0 00 1 1 1 1 0 0"))?;
+
+        let header = slice(&mut inspector, &nal(0x65, "1 0001000 1 0000 00100 0000 1 0 1 1 1 1 1"))?;
+
+        assert_eq!(header.slice_type, SliceType::I);
+        assert_eq!(header.num_ref_idx_l1_active_minus1, 20);
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_unknown_pps() -> Result<(), Error> {
+        let mut inspector = inspector()?;
+
+        // PPS 1 was never sent.
+        let error = inspector.feed_nal(&nal(0x65, "1 0001000 010 0000 1 0000 1 0 1 1"));
+
+        assert!(matches!(error.unwrap_err().variant(), Variant::InvalidSliceHeader));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,530 @@
+//! Translation of parsed H.264 parameter sets into their Vulkan `StdVideoH264*` counterparts.
+use crate::video::h264::{DpbPicture, DpbReference};
+use ash::vk::native::{
+    StdVideoDecodeH264PictureInfo, StdVideoDecodeH264PictureInfoFlags, StdVideoDecodeH264ReferenceInfo,
+    StdVideoDecodeH264ReferenceInfoFlags, StdVideoH264AspectRatioIdc, StdVideoH264ChromaFormatIdc, StdVideoH264HrdParameters,
+    StdVideoH264LevelIdc, StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_1_0, StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_1_1,
+    StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_1_2, StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_1_3,
+    StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_2_0, StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_2_1,
+    StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_2_2, StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_3_0,
+    StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_3_1, StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_3_2,
+    StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_4_0, StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_4_1,
+    StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_4_2, StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_5_0,
+    StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_5_1, StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_5_2,
+    StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_6_0, StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_6_1,
+    StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_6_2, StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_INVALID,
+    StdVideoH264PictureParameterSet, StdVideoH264PocType, StdVideoH264PpsFlags, StdVideoH264ProfileIdc, StdVideoH264ScalingLists,
+    StdVideoH264SequenceParameterSet, StdVideoH264SequenceParameterSetVui, StdVideoH264SpsFlags, StdVideoH264SpsVuiFlags,
+    StdVideoH264WeightedBipredIdc,
+};
+use h264_reader::nal::pps::PicParameterSet;
+use h264_reader::nal::sps::{
+    AspectRatioInfo, ChromaFormat, FrameMbsFlags, HrdParameters, OverscanAppropriate, PicOrderCntType, SeqParameterSet, VideoFormat,
+    VuiParameters,
+};
+use std::ptr::null;
+
+/// A `StdVideoH264SequenceParameterSet` that owns everything its pointers refer to.
+///
+/// All referenced structures live on the heap, so the native struct stays valid when this is moved.
+pub struct StdSps {
+    native: StdVideoH264SequenceParameterSet,
+    _vui: Option<Box<StdVideoH264SequenceParameterSetVui>>,
+    _hrd: Option<Box<StdVideoH264HrdParameters>>,
+    _offsets_for_ref_frame: Vec<i32>,
+    _scaling_lists: Option<Box<StdVideoH264ScalingLists>>,
+}
+
+impl StdSps {
+    pub fn new(sps: &SeqParameterSet, scaling_lists: Option<&StdVideoH264ScalingLists>) -> Self {
+        let mut flags = StdVideoH264SpsFlags {
+            _bitfield_align_1: [],
+            _bitfield_1: Default::default(),
+            __bindgen_padding_0: 0,
+        };
+
+        let constraint_flags = sps.constraint_flags;
+        let chroma_info = &sps.chroma_info;
+
+        flags.set_constraint_set0_flag(constraint_flags.flag0().into());
+        flags.set_constraint_set1_flag(constraint_flags.flag1().into());
+        flags.set_constraint_set2_flag(constraint_flags.flag2().into());
+        flags.set_constraint_set3_flag(constraint_flags.flag3().into());
+        flags.set_constraint_set4_flag(constraint_flags.flag4().into());
+        flags.set_constraint_set5_flag(constraint_flags.flag5().into());
+        flags.set_direct_8x8_inference_flag(sps.direct_8x8_inference_flag.into());
+        flags.set_separate_colour_plane_flag(chroma_info.separate_colour_plane_flag.into());
+        flags.set_gaps_in_frame_num_value_allowed_flag(sps.gaps_in_frame_num_value_allowed_flag.into());
+        flags.set_qpprime_y_zero_transform_bypass_flag(chroma_info.qpprime_y_zero_transform_bypass_flag.into());
+        flags.set_frame_cropping_flag(sps.frame_cropping.is_some().into());
+        flags.set_seq_scaling_matrix_present_flag(scaling_lists.is_some().into());
+        flags.set_vui_parameters_present_flag(sps.vui_parameters.is_some().into());
+
+        match sps.frame_mbs_flags {
+            FrameMbsFlags::Frames => flags.set_frame_mbs_only_flag(1),
+            FrameMbsFlags::Fields {
+                mb_adaptive_frame_field_flag,
+            } => flags.set_mb_adaptive_frame_field_flag(mb_adaptive_frame_field_flag.into()),
+        }
+
+        let mut offset_for_non_ref_pic = 0;
+        let mut offset_for_top_to_bottom_field = 0;
+        let mut log2_max_pic_order_cnt_lsb_minus4 = 0;
+        let mut offsets_for_ref_frame = Vec::new();
+
+        let pic_order_cnt_type = match &sps.pic_order_cnt {
+            PicOrderCntType::TypeZero {
+                log2_max_pic_order_cnt_lsb_minus4: x,
+            } => {
+                log2_max_pic_order_cnt_lsb_minus4 = *x;
+                0
+            }
+            PicOrderCntType::TypeOne {
+                delta_pic_order_always_zero_flag,
+                offset_for_non_ref_pic: non_ref,
+                offset_for_top_to_bottom_field: top_to_bottom,
+                offsets_for_ref_frame: offsets,
+            } => {
+                flags.set_delta_pic_order_always_zero_flag((*delta_pic_order_always_zero_flag).into());
+                offset_for_non_ref_pic = *non_ref;
+                offset_for_top_to_bottom_field = *top_to_bottom;
+                offsets_for_ref_frame = offsets.clone();
+                1
+            }
+            PicOrderCntType::TypeTwo => 2,
+        };
+
+        let crop = sps.frame_cropping.clone().unwrap_or_default();
+        let hrd = sps.vui_parameters.as_ref().and_then(std_hrd).map(Box::new);
+        let vui = sps.vui_parameters.as_ref().map(|x| Box::new(std_vui(x, hrd.as_deref())));
+        let scaling_lists = scaling_lists.map(|x| Box::new(*x));
+
+        let native = StdVideoH264SequenceParameterSet {
+            flags,
+            profile_idc: u8::from(sps.profile_idc) as StdVideoH264ProfileIdc,
+            level_idc: std_level_idc(sps),
+            chroma_format_idc: std_chroma_format_idc(chroma_info.chroma_format),
+            seq_parameter_set_id: sps.seq_parameter_set_id.id(),
+            bit_depth_luma_minus8: chroma_info.bit_depth_luma_minus8,
+            bit_depth_chroma_minus8: chroma_info.bit_depth_chroma_minus8,
+            log2_max_frame_num_minus4: sps.log2_max_frame_num_minus4,
+            pic_order_cnt_type: pic_order_cnt_type as StdVideoH264PocType,
+            offset_for_non_ref_pic,
+            offset_for_top_to_bottom_field,
+            log2_max_pic_order_cnt_lsb_minus4,
+            num_ref_frames_in_pic_order_cnt_cycle: offsets_for_ref_frame.len() as u8,
+            max_num_ref_frames: sps.max_num_ref_frames as u8,
+            reserved1: 0,
+            pic_width_in_mbs_minus1: sps.pic_width_in_mbs_minus1,
+            pic_height_in_map_units_minus1: sps.pic_height_in_map_units_minus1,
+            frame_crop_left_offset: crop.left_offset,
+            frame_crop_right_offset: crop.right_offset,
+            frame_crop_top_offset: crop.top_offset,
+            frame_crop_bottom_offset: crop.bottom_offset,
+            reserved2: 0,
+            pOffsetForRefFrame: if offsets_for_ref_frame.is_empty() {
+                null()
+            } else {
+                offsets_for_ref_frame.as_ptr()
+            },
+            pScalingLists: scaling_lists.as_deref().map_or(null(), |x| x as *const _),
+            pSequenceParameterSetVui: vui.as_deref().map_or(null(), |x| x as *const _),
+        };
+
+        Self {
+            native,
+            _vui: vui,
+            _hrd: hrd,
+            _offsets_for_ref_frame: offsets_for_ref_frame,
+            _scaling_lists: scaling_lists,
+        }
+    }
+
+    pub fn native(&self) -> &StdVideoH264SequenceParameterSet {
+        &self.native
+    }
+}
+
+/// A `StdVideoH264PictureParameterSet` that owns its scaling lists.
+pub struct StdPps {
+    native: StdVideoH264PictureParameterSet,
+    _scaling_lists: Option<Box<StdVideoH264ScalingLists>>,
+}
+
+impl StdPps {
+    pub fn new(pps: &PicParameterSet, scaling_lists: Option<&StdVideoH264ScalingLists>) -> Self {
+        let mut flags = StdVideoH264PpsFlags {
+            _bitfield_align_1: Default::default(),
+            _bitfield_1: Default::default(),
+            __bindgen_padding_0: Default::default(),
+        };
+
+        let transform_8x8_mode_flag = pps.extension.as_ref().is_some_and(|x| x.transform_8x8_mode_flag);
+
+        // 7.4.2.2: When not present, second_chroma_qp_index_offset is inferred to be chroma_qp_index_offset.
+        let second_chroma_qp_index_offset = pps
+            .extension
+            .as_ref()
+            .map_or(pps.chroma_qp_index_offset, |x| x.second_chroma_qp_index_offset);
+
+        flags.set_transform_8x8_mode_flag(transform_8x8_mode_flag.into());
+        flags.set_redundant_pic_cnt_present_flag(pps.redundant_pic_cnt_present_flag.into());
+        flags.set_constrained_intra_pred_flag(pps.constrained_intra_pred_flag.into());
+        flags.set_deblocking_filter_control_present_flag(pps.deblocking_filter_control_present_flag.into());
+        flags.set_weighted_pred_flag(pps.weighted_pred_flag.into());
+        flags.set_bottom_field_pic_order_in_frame_present_flag(pps.bottom_field_pic_order_in_frame_present_flag.into());
+        flags.set_entropy_coding_mode_flag(pps.entropy_coding_mode_flag.into());
+        flags.set_pic_scaling_matrix_present_flag(scaling_lists.is_some().into());
+
+        let scaling_lists = scaling_lists.map(|x| Box::new(*x));
+
+        let native = StdVideoH264PictureParameterSet {
+            flags,
+            seq_parameter_set_id: pps.seq_parameter_set_id.id(),
+            pic_parameter_set_id: pps.pic_parameter_set_id.id(),
+            num_ref_idx_l0_default_active_minus1: pps.num_ref_idx_l0_default_active_minus1 as u8,
+            num_ref_idx_l1_default_active_minus1: pps.num_ref_idx_l1_default_active_minus1 as u8,
+            weighted_bipred_idc: pps.weighted_bipred_idc as StdVideoH264WeightedBipredIdc,
+            pic_init_qp_minus26: pps.pic_init_qp_minus26 as i8,
+            pic_init_qs_minus26: pps.pic_init_qs_minus26 as i8,
+            chroma_qp_index_offset: pps.chroma_qp_index_offset as i8,
+            second_chroma_qp_index_offset: second_chroma_qp_index_offset as i8,
+            pScalingLists: scaling_lists.as_deref().map_or(null(), |x| x as *const _),
+        };
+
+        Self {
+            native,
+            _scaling_lists: scaling_lists,
+        }
+    }
+
+    pub fn native(&self) -> &StdVideoH264PictureParameterSet {
+        &self.native
+    }
+}
+
+/// Translates a picture about to be decoded into its `StdVideoDecodeH264PictureInfo`.
+pub(crate) fn std_picture_info(picture: &DpbPicture) -> StdVideoDecodeH264PictureInfo {
+    let mut flags = StdVideoDecodeH264PictureInfoFlags {
+        _bitfield_align_1: Default::default(),
+        _bitfield_1: Default::default(),
+        __bindgen_padding_0: Default::default(),
+    };
+
+    flags.set_is_intra(picture.intra.into());
+    flags.set_IdrPicFlag(picture.idr.into());
+    flags.set_is_reference(picture.reference.into());
+    flags.set_field_pic_flag(picture.field_pic_flag.into());
+    flags.set_bottom_field_flag(picture.bottom_field_flag.into());
+    flags.set_complementary_field_pair(picture.second_field.into());
+
+    StdVideoDecodeH264PictureInfo {
+        flags,
+        seq_parameter_set_id: picture.seq_parameter_set_id,
+        pic_parameter_set_id: picture.pic_parameter_set_id,
+        reserved1: 0,
+        reserved2: 0,
+        frame_num: picture.frame_num,
+        idr_pic_id: picture.idr_pic_id,
+        PicOrderCnt: [picture.pic_order_cnt.top, picture.pic_order_cnt.bottom],
+    }
+}
+
+/// Translates a reference into its `StdVideoDecodeH264ReferenceInfo`.
+pub(crate) fn std_reference_info(reference: &DpbReference) -> StdVideoDecodeH264ReferenceInfo {
+    let mut flags = StdVideoDecodeH264ReferenceInfoFlags {
+        _bitfield_align_1: [],
+        _bitfield_1: Default::default(),
+        __bindgen_padding_0: Default::default(),
+    };
+
+    flags.set_used_for_long_term_reference(reference.long_term_frame_idx.is_some().into());
+    flags.set_top_field_flag(reference.top_field.into());
+    flags.set_bottom_field_flag(reference.bottom_field.into());
+    flags.set_is_non_existing(reference.non_existing.into());
+
+    // For long-term references Vulkan expects `LongTermFrameIdx` in place of `FrameNum`.
+    let frame_num = match reference.long_term_frame_idx {
+        Some(x) => x as u16,
+        None => reference.frame_num,
+    };
+
+    StdVideoDecodeH264ReferenceInfo {
+        flags,
+        FrameNum: frame_num,
+        reserved: 0,
+        PicOrderCnt: [reference.pic_order_cnt.top, reference.pic_order_cnt.bottom],
+    }
+}
+
+fn std_level_idc(sps: &SeqParameterSet) -> StdVideoH264LevelIdc {
+    // Level 1b (`level_idc` 11 plus `constraint_set3_flag`, or 9) has no Vulkan equivalent, we round up to 1.1.
+    match sps.level_idc {
+        9 => StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_1_1,
+        10 => StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_1_0,
+        11 => StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_1_1,
+        12 => StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_1_2,
+        13 => StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_1_3,
+        20 => StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_2_0,
+        21 => StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_2_1,
+        22 => StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_2_2,
+        30 => StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_3_0,
+        31 => StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_3_1,
+        32 => StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_3_2,
+        40 => StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_4_0,
+        41 => StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_4_1,
+        42 => StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_4_2,
+        50 => StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_5_0,
+        51 => StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_5_1,
+        52 => StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_5_2,
+        60 => StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_6_0,
+        61 => StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_6_1,
+        62 => StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_6_2,
+        _ => StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_INVALID,
+    }
+}
+
+pub(crate) fn std_chroma_format_idc(chroma_format: ChromaFormat) -> StdVideoH264ChromaFormatIdc {
+    match chroma_format {
+        ChromaFormat::Monochrome => 0,
+        ChromaFormat::YUV420 => 1,
+        ChromaFormat::YUV422 => 2,
+        ChromaFormat::YUV444 => 3,
+        ChromaFormat::Invalid(x) => x,
+    }
+}
+
+pub(crate) fn std_aspect_ratio_idc(aspect_ratio_info: &AspectRatioInfo) -> (StdVideoH264AspectRatioIdc, u16, u16) {
+    match aspect_ratio_info {
+        AspectRatioInfo::Unspecified => (0, 0, 0),
+        AspectRatioInfo::Ratio1_1 => (1, 0, 0),
+        AspectRatioInfo::Ratio12_11 => (2, 0, 0),
+        AspectRatioInfo::Ratio10_11 => (3, 0, 0),
+        AspectRatioInfo::Ratio16_11 => (4, 0, 0),
+        AspectRatioInfo::Ratio40_33 => (5, 0, 0),
+        AspectRatioInfo::Ratio24_11 => (6, 0, 0),
+        AspectRatioInfo::Ratio20_11 => (7, 0, 0),
+        AspectRatioInfo::Ratio32_11 => (8, 0, 0),
+        AspectRatioInfo::Ratio80_33 => (9, 0, 0),
+        AspectRatioInfo::Ratio18_11 => (10, 0, 0),
+        AspectRatioInfo::Ratio15_11 => (11, 0, 0),
+        AspectRatioInfo::Ratio64_33 => (12, 0, 0),
+        AspectRatioInfo::Ratio160_99 => (13, 0, 0),
+        AspectRatioInfo::Ratio4_3 => (14, 0, 0),
+        AspectRatioInfo::Ratio3_2 => (15, 0, 0),
+        AspectRatioInfo::Ratio2_1 => (16, 0, 0),
+        AspectRatioInfo::Reserved(x) => (*x as StdVideoH264AspectRatioIdc, 0, 0),
+        AspectRatioInfo::Extended(w, h) => (255, *w, *h),
+    }
+}
+
+pub(crate) fn std_video_format(video_format: &VideoFormat) -> u8 {
+    match video_format {
+        VideoFormat::Component => 0,
+        VideoFormat::PAL => 1,
+        VideoFormat::NTSC => 2,
+        VideoFormat::SECAM => 3,
+        VideoFormat::MAC => 4,
+        VideoFormat::Unspecified => 5,
+        VideoFormat::Reserved(x) => *x,
+    }
+}
+
+/// Vulkan only has room for a single HRD, we prefer the NAL HRD over the VCL one.
+fn std_hrd(vui: &VuiParameters) -> Option<StdVideoH264HrdParameters> {
+    let hrd: &HrdParameters = vui.nal_hrd_parameters.as_ref().or(vui.vcl_hrd_parameters.as_ref())?;
+
+    let mut bit_rate_value_minus1 = [0; 32];
+    let mut cpb_size_value_minus1 = [0; 32];
+    let mut cbr_flag = [0; 32];
+
+    for (i, spec) in hrd.cpb_specs.iter().take(32).enumerate() {
+        bit_rate_value_minus1[i] = spec.bit_rate_value_minus1;
+        cpb_size_value_minus1[i] = spec.cpb_size_value_minus1;
+        cbr_flag[i] = spec.cbr_flag.into();
+    }
+
+    Some(StdVideoH264HrdParameters {
+        cpb_cnt_minus1: hrd.cpb_specs.len().saturating_sub(1) as u8,
+        bit_rate_scale: hrd.bit_rate_scale,
+        cpb_size_scale: hrd.cpb_size_scale,
+        reserved1: 0,
+        bit_rate_value_minus1,
+        cpb_size_value_minus1,
+        cbr_flag,
+        initial_cpb_removal_delay_length_minus1: hrd.initial_cpb_removal_delay_length_minus1.into(),
+        cpb_removal_delay_length_minus1: hrd.cpb_removal_delay_length_minus1.into(),
+        dpb_output_delay_length_minus1: hrd.dpb_output_delay_length_minus1.into(),
+        time_offset_length: hrd.time_offset_length.into(),
+    })
+}
+
+fn std_vui(vui: &VuiParameters, hrd: Option<&StdVideoH264HrdParameters>) -> StdVideoH264SequenceParameterSetVui {
+    let mut flags = StdVideoH264SpsVuiFlags {
+        _bitfield_align_1: [],
+        _bitfield_1: Default::default(),
+        __bindgen_padding_0: 0,
+    };
+
+    flags.set_aspect_ratio_info_present_flag(vui.aspect_ratio_info.is_some().into());
+    flags.set_video_signal_type_present_flag(vui.video_signal_type.is_some().into());
+    flags.set_chroma_loc_info_present_flag(vui.chroma_loc_info.is_some().into());
+    flags.set_timing_info_present_flag(vui.timing_info.is_some().into());
+    flags.set_bitstream_restriction_flag(vui.bitstream_restrictions.is_some().into());
+    // Only the HRD `std_hrd` picked is passed, so only that one is present.
+    flags.set_nal_hrd_parameters_present_flag(vui.nal_hrd_parameters.is_some().into());
+    flags.set_vcl_hrd_parameters_present_flag((vui.nal_hrd_parameters.is_none() && vui.vcl_hrd_parameters.is_some()).into());
+
+    match vui.overscan_appropriate {
+        OverscanAppropriate::Unspecified => {}
+        OverscanAppropriate::Appropriate => {
+            flags.set_overscan_info_present_flag(1);
+            flags.set_overscan_appropriate_flag(1);
+        }
+        OverscanAppropriate::Inappropriate => flags.set_overscan_info_present_flag(1),
+    }
+
+    let (aspect_ratio_idc, sar_width, sar_height) = vui.aspect_ratio_info.as_ref().map_or((0, 0, 0), std_aspect_ratio_idc);
+
+    // Values mandated by E.2.1 when the respective syntax elements are absent.
+    let mut video_format = 5;
+    let mut colour_primaries = 2;
+    let mut transfer_characteristics = 2;
+    let mut matrix_coefficients = 2;
+
+    if let Some(signal_type) = &vui.video_signal_type {
+        flags.set_video_full_range_flag(signal_type.video_full_range_flag.into());
+        video_format = std_video_format(&signal_type.video_format);
+
+        if let Some(colour) = &signal_type.colour_description {
+            flags.set_color_description_present_flag(1);
+            colour_primaries = colour.colour_primaries;
+            transfer_characteristics = colour.transfer_characteristics;
+            matrix_coefficients = colour.matrix_coefficients;
+        }
+    }
+
+    let timing_info = vui.timing_info.clone().unwrap_or_default();
+    let chroma_loc_info = vui.chroma_loc_info.clone().unwrap_or_default();
+    let restrictions = vui.bitstream_restrictions.clone().unwrap_or_default();
+
+    flags.set_fixed_frame_rate_flag(timing_info.fixed_frame_rate_flag.into());
+
+    StdVideoH264SequenceParameterSetVui {
+        flags,
+        aspect_ratio_idc,
+        sar_width,
+        sar_height,
+        video_format,
+        colour_primaries,
+        transfer_characteristics,
+        matrix_coefficients,
+        num_units_in_tick: timing_info.num_units_in_tick,
+        time_scale: timing_info.time_scale,
+        max_num_reorder_frames: restrictions.max_num_reorder_frames as u8,
+        max_dec_frame_buffering: restrictions.max_dec_frame_buffering as u8,
+        chroma_sample_loc_type_top_field: chroma_loc_info.chroma_sample_loc_type_top_field as u8,
+        chroma_sample_loc_type_bottom_field: chroma_loc_info.chroma_sample_loc_type_bottom_field as u8,
+        reserved1: 0,
+        pHrdParameters: hrd.map_or(null(), |x| x as *const _),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::Error;
+    use crate::video::h264::stdvideo::StdSps;
+    use crate::video::h264::testdata::sps;
+    use crate::video::h264::H264StreamInspector;
+    use crate::video::nal_units;
+    use ash::vk::native::{StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_1_0, StdVideoH264ProfileIdc_STD_VIDEO_H264_PROFILE_IDC_HIGH};
+
+    #[test]
+    fn sps_from_stream() -> Result<(), Error> {
+        // SPS of a 64x64 High profile stream, as used in the `h264-reader` docs.
+        let h264_data = [
+            0x00, 0x00, 0x00, 0x01, 0x67, 0x64, 0x00, 0x0A, 0xAC, 0x72, 0x84, 0x44, 0x26, 0x84, 0x00, 0x00, 0x03, 0x00, 0x04, 0x00, 0x00,
+            0x03, 0x00, 0xCA, 0x3C, 0x48, 0x96, 0x11, 0x80,
+        ];
+
+        let mut inspector = H264StreamInspector::new();
+
+        for nal in nal_units(&h264_data) {
+            inspector.feed_nal(nal)?;
+        }
+
+        let sps = inspector.std_sps();
+        assert_eq!(sps.len(), 1);
+
+        let native = sps[0].native();
+        assert_eq!(native.profile_idc, StdVideoH264ProfileIdc_STD_VIDEO_H264_PROFILE_IDC_HIGH);
+        assert_eq!(native.level_idc, StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_1_0);
+        assert_eq!(native.chroma_format_idc, 1);
+        assert_eq!(native.pic_width_in_mbs_minus1, 3);
+        assert_eq!(native.pic_height_in_map_units_minus1, 3);
+        assert_eq!(native.flags.frame_mbs_only_flag(), 1);
+        assert_eq!(native.flags.vui_parameters_present_flag(), 1);
+
+        let vui = unsafe { &*native.pSequenceParameterSetVui };
+        assert_eq!(vui.flags.timing_info_present_flag(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn pps_from_stream() -> Result<(), Error> {
+        // SPS and PPS of a 64x64 High profile stream, as used in the `h264-reader` docs.
+        let h264_data = [
+            0x00, 0x00, 0x00, 0x01, 0x67, 0x64, 0x00, 0x0A, 0xAC, 0x72, 0x84, 0x44, 0x26, 0x84, 0x00, 0x00, 0x03, 0x00, 0x04, 0x00, 0x00,
+            0x03, 0x00, 0xCA, 0x3C, 0x48, 0x96, 0x11, 0x80, 0x00, 0x00, 0x01, 0x68, 0xE8, 0x43, 0x8F, 0x13, 0x21, 0x30,
+        ];
+
+        let mut inspector = H264StreamInspector::new();
+
+        for nal in nal_units(&h264_data) {
+            inspector.feed_nal(nal)?;
+        }
+
+        let pps = inspector.std_pps();
+        assert_eq!(pps.len(), 1);
+
+        let native = pps[0].native();
+        assert_eq!(native.pic_parameter_set_id, 0);
+        assert_eq!(native.seq_parameter_set_id, 0);
+        assert_eq!(native.flags.entropy_coding_mode_flag(), 1);
+        assert_eq!(native.flags.transform_8x8_mode_flag(), 1);
+        assert_eq!(native.flags.pic_scaling_matrix_present_flag(), 0);
+        assert_eq!(native.second_chroma_qp_index_offset, native.chroma_qp_index_offset);
+        assert!(native.pScalingLists.is_null());
+
+        Ok(())
+    }
+
+    #[test]
+    fn passes_one_hrd() {
+        // Baseline with VUI holding a NAL and a VCL HRD, which differ in `cpb_removal_delay_length_minus1`.
+        let hrd =
+            |cpb_removal_delay_length_minus1: &str| format!("1 0000 0000 1 1 0 10111 {} 10111 11000", cpb_removal_delay_length_minus1);
+        let both = sps(&format!(
+            "01000010 00000000 00011110 1 1 1 1 010 0 00100 00100 1 1 0 1 0 0 0 0 0 1 {} 1 {} 0 0 0",
+            hrd("10111"),
+            hrd("00011")
+        ));
+        let vcl_only = sps(&format!(
+            "01000010 00000000 00011110 1 1 1 1 010 0 00100 00100 1 1 0 1 0 0 0 0 0 0 1 {} 0 0 0",
+            hrd("00011")
+        ));
+
+        let std_both = StdSps::new(&both, None);
+        let std_vcl_only = StdSps::new(&vcl_only, None);
+
+        for (std, nal, cpb_removal_delay_length_minus1) in [(&std_both, 1, 23), (&std_vcl_only, 0, 3)] {
+            let vui = unsafe { &*std.native().pSequenceParameterSetVui };
+            let hrd = unsafe { &*vui.pHrdParameters };
+
+            assert_eq!(vui.flags.nal_hrd_parameters_present_flag(), nal);
+            assert_eq!(vui.flags.vcl_hrd_parameters_present_flag(), 1 - nal);
+            assert_eq!(hrd.cpb_removal_delay_length_minus1, cpb_removal_delay_length_minus1);
+        }
+    }
+}
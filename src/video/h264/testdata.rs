@@ -0,0 +1,107 @@
+//! Helpers to hand-craft H.264 bitstreams in tests.
+use crate::video::h264::{DecRefPicMarking, H264StreamInspector, SliceHeader, SliceType};
+use h264_reader::nal::sps::SeqParameterSet;
+use h264_reader::rbsp::BitReader;
+
+/// A real High profile SPS, 4:2:0 8-bit, progressive.
+pub const SPS: [u8; 25] = [
+    0x67, 0x64, 0x00, 0x0A, 0xAC, 0x72, 0x84, 0x44, 0x26, 0x84, 0x00, 0x00, 0x03, 0x00, 0x04, 0x00, 0x00, 0x03, 0x00, 0xCA, 0x3C, 0x48,
+    0x96, 0x11, 0x80,
+];
+
+/// A real PPS belonging to [`SPS`].
+pub const PPS: [u8; 7] = [0x68, 0xE8, 0x43, 0x8F, 0x13, 0x21, 0x30];
+
+/// An inspector that has seen [`SPS`] and [`PPS`], enough to create sessions, images and buffers.
+pub fn inspector() -> H264StreamInspector {
+    let mut inspector = H264StreamInspector::new();
+
+    inspector.feed_nal(&SPS).unwrap();
+    inspector.feed_nal(&PPS).unwrap();
+    inspector
+}
+
+/// Turns a string of `0` and `1` into bytes, appending RBSP trailing bits.
+pub fn rbsp(bits: &str) -> Vec<u8> {
+    let mut bits = bits.chars().filter(|c| !c.is_whitespace()).collect::<String>();
+    bits.push('1');
+
+    while bits.len() % 8 != 0 {
+        bits.push('0');
+    }
+
+    (0..bits.len() / 8)
+        .map(|i| u8::from_str_radix(&bits[i * 8..i * 8 + 8], 2).unwrap())
+        .collect()
+}
+
+/// Builds an Annex B NAL unit with start code, header byte and the given RBSP `bits`, inserting emulation prevention bytes.
+pub fn nal(header: u8, bits: &str) -> Vec<u8> {
+    let mut nal = vec![0, 0, 0, 1, header];
+    let mut zeros = 0;
+
+    for byte in rbsp(bits) {
+        if zeros >= 2 && byte <= 3 {
+            nal.push(3);
+            zeros = 0;
+        }
+
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+        nal.push(byte);
+    }
+
+    nal
+}
+
+/// A non-IDR P frame slice with the given `frame_num` and `nal_ref_idc`, everything else zeroed.
+pub fn slice_header(frame_num: u16, nal_ref_idc: u8) -> SliceHeader {
+    SliceHeader {
+        nal_ref_idc,
+        idr: false,
+        first_mb_in_slice: 0,
+        slice_type: SliceType::P,
+        pic_parameter_set_id: 0,
+        seq_parameter_set_id: 0,
+        colour_plane_id: 0,
+        frame_num,
+        field_pic_flag: false,
+        bottom_field_flag: false,
+        idr_pic_id: 0,
+        pic_order_cnt_lsb: 0,
+        delta_pic_order_cnt_bottom: 0,
+        delta_pic_order_cnt: [0; 2],
+        redundant_pic_cnt: 0,
+        direct_spatial_mv_pred_flag: false,
+        num_ref_idx_l0_active_minus1: 0,
+        num_ref_idx_l1_active_minus1: 0,
+        ref_pic_list_modification_l0: Vec::new(),
+        ref_pic_list_modification_l1: Vec::new(),
+        dec_ref_pic_marking: (nal_ref_idc != 0).then_some(DecRefPicMarking::SlidingWindow),
+        cabac_init_idc: 0,
+        slice_qp_delta: 0,
+        sp_for_switch_flag: false,
+        slice_qs_delta: 0,
+        disable_deblocking_filter_idc: 0,
+        slice_alpha_c0_offset_div2: 0,
+        slice_beta_offset_div2: 0,
+    }
+}
+
+/// An IDR slice with the given `idr_pic_id`.
+pub fn idr_slice_header(idr_pic_id: u16) -> SliceHeader {
+    SliceHeader {
+        idr: true,
+        idr_pic_id,
+        slice_type: SliceType::I,
+        dec_ref_pic_marking: Some(DecRefPicMarking::Idr {
+            no_output_of_prior_pics_flag: false,
+            long_term_reference_flag: false,
+        }),
+        ..slice_header(0, 3)
+    }
+}
+
+/// Parses a SPS from RBSP `bits`.
+pub fn sps(bits: &str) -> SeqParameterSet {
+    SeqParameterSet::from_bits(BitReader::new(&rbsp(bits)[..])).unwrap()
+}
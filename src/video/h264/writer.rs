@@ -0,0 +1,678 @@
+//! Serialization of parameter sets, access unit delimiters and slice headers, the counterpart of parsing.
+use crate::error;
+use crate::error::{Error, Variant};
+use crate::video::h264::stdvideo::{std_aspect_ratio_idc, std_chroma_format_idc, std_video_format};
+use crate::video::h264::{BitWriter, DecRefPicMarking, MemoryManagementControlOperation, RefPicListModification, SliceHeader, SliceType};
+use ash::vk::native::StdVideoH264ScalingLists;
+use h264_reader::nal::pps::PicParameterSet;
+use h264_reader::nal::sps::{
+    ChromaFormat, FrameMbsFlags, HrdParameters, OverscanAppropriate, PicOrderCntType, SeqParameterSet, VuiParameters,
+};
+
+/// Writes the RBSP of a SPS (7.3.2.1.1), including trailing bits.
+///
+/// `h264-reader` does not keep scaling matrices, so they are passed separately, e.g., as parsed by the
+/// [`H264StreamInspector`](crate::video::h264::H264StreamInspector).
+pub fn write_sps(w: &mut BitWriter, sps: &SeqParameterSet, scaling_lists: Option<&StdVideoH264ScalingLists>) -> Result<(), Error> {
+    w.write_bits(u8::from(sps.profile_idc).into(), 8);
+    w.write_bits(u8::from(sps.constraint_flags).into(), 8);
+    w.write_bits(sps.level_idc.into(), 8);
+    w.write_ue(sps.id().id().into());
+
+    if sps.profile_idc.has_chroma_info() {
+        if let ChromaFormat::Invalid(x) = sps.chroma_info.chroma_format {
+            return Err(error!(Variant::InvalidSps, "Invalid chroma_format_idc {}", x));
+        }
+
+        w.write_ue(std_chroma_format_idc(sps.chroma_info.chroma_format));
+
+        if sps.chroma_info.chroma_format == ChromaFormat::YUV444 {
+            w.write_bool(sps.chroma_info.separate_colour_plane_flag);
+        }
+
+        w.write_ue(sps.chroma_info.bit_depth_luma_minus8.into());
+        w.write_ue(sps.chroma_info.bit_depth_chroma_minus8.into());
+        w.write_bool(sps.chroma_info.qpprime_y_zero_transform_bypass_flag);
+        w.write_bool(scaling_lists.is_some());
+
+        if let Some(scaling_lists) = scaling_lists {
+            let count = if sps.chroma_info.chroma_format == ChromaFormat::YUV444 {
+                12
+            } else {
+                8
+            };
+            write_scaling_lists(w, scaling_lists, count);
+        }
+    } else if scaling_lists.is_some() {
+        return Err(error!(
+            Variant::InvalidSps,
+            "profile_idc {} has no scaling matrices",
+            u8::from(sps.profile_idc)
+        ));
+    }
+
+    w.write_ue(sps.log2_max_frame_num_minus4.into());
+
+    match &sps.pic_order_cnt {
+        PicOrderCntType::TypeZero {
+            log2_max_pic_order_cnt_lsb_minus4,
+        } => {
+            w.write_ue(0);
+            w.write_ue((*log2_max_pic_order_cnt_lsb_minus4).into());
+        }
+        PicOrderCntType::TypeOne {
+            delta_pic_order_always_zero_flag,
+            offset_for_non_ref_pic,
+            offset_for_top_to_bottom_field,
+            offsets_for_ref_frame,
+        } => {
+            w.write_ue(1);
+            w.write_bool(*delta_pic_order_always_zero_flag);
+            w.write_se(*offset_for_non_ref_pic);
+            w.write_se(*offset_for_top_to_bottom_field);
+            w.write_ue(offsets_for_ref_frame.len() as u32);
+            offsets_for_ref_frame.iter().for_each(|x| w.write_se(*x));
+        }
+        PicOrderCntType::TypeTwo => w.write_ue(2),
+    }
+
+    w.write_ue(sps.max_num_ref_frames);
+    w.write_bool(sps.gaps_in_frame_num_value_allowed_flag);
+    w.write_ue(sps.pic_width_in_mbs_minus1);
+    w.write_ue(sps.pic_height_in_map_units_minus1);
+
+    match sps.frame_mbs_flags {
+        FrameMbsFlags::Frames => w.write_bool(true),
+        FrameMbsFlags::Fields {
+            mb_adaptive_frame_field_flag,
+        } => {
+            w.write_bool(false);
+            w.write_bool(mb_adaptive_frame_field_flag);
+        }
+    }
+
+    w.write_bool(sps.direct_8x8_inference_flag);
+    w.write_bool(sps.frame_cropping.is_some());
+
+    if let Some(crop) = &sps.frame_cropping {
+        w.write_ue(crop.left_offset);
+        w.write_ue(crop.right_offset);
+        w.write_ue(crop.top_offset);
+        w.write_ue(crop.bottom_offset);
+    }
+
+    w.write_bool(sps.vui_parameters.is_some());
+
+    if let Some(vui) = &sps.vui_parameters {
+        write_vui(w, vui);
+    }
+
+    w.write_trailing_bits();
+
+    Ok(())
+}
+
+/// Writes the RBSP of a PPS (7.3.2.2), including trailing bits.
+///
+/// The `sps` must be the one referenced by the PPS, as the number of 8x8 scaling lists depends on its chroma format.
+/// Slice groups (FMO) are not supported, neither by parsing nor by Vulkan.
+pub fn write_pps(
+    w: &mut BitWriter,
+    pps: &PicParameterSet,
+    sps: &SeqParameterSet,
+    scaling_lists: Option<&StdVideoH264ScalingLists>,
+) -> Result<(), Error> {
+    if pps.slice_groups.is_some() {
+        return Err(error!(Variant::UnsupportedFeature, "PPS uses slice groups (FMO)"));
+    }
+
+    if pps.extension.is_none() && scaling_lists.is_some() {
+        return Err(error!(
+            Variant::InvalidPps,
+            "Scaling matrices need transform_8x8_mode_flag and friends"
+        ));
+    }
+
+    w.write_ue(pps.pic_parameter_set_id.id().into());
+    w.write_ue(pps.seq_parameter_set_id.id().into());
+    w.write_bool(pps.entropy_coding_mode_flag);
+    w.write_bool(pps.bottom_field_pic_order_in_frame_present_flag);
+    w.write_ue(0);
+    w.write_ue(pps.num_ref_idx_l0_default_active_minus1);
+    w.write_ue(pps.num_ref_idx_l1_default_active_minus1);
+    w.write_bool(pps.weighted_pred_flag);
+    w.write_bits(pps.weighted_bipred_idc.into(), 2);
+    w.write_se(pps.pic_init_qp_minus26);
+    w.write_se(pps.pic_init_qs_minus26);
+    w.write_se(pps.chroma_qp_index_offset);
+    w.write_bool(pps.deblocking_filter_control_present_flag);
+    w.write_bool(pps.constrained_intra_pred_flag);
+    w.write_bool(pps.redundant_pic_cnt_present_flag);
+
+    if let Some(extension) = &pps.extension {
+        w.write_bool(extension.transform_8x8_mode_flag);
+        w.write_bool(scaling_lists.is_some());
+
+        if let Some(scaling_lists) = scaling_lists {
+            let count = match (extension.transform_8x8_mode_flag, sps.chroma_info.chroma_format) {
+                (false, _) => 6,
+                (true, ChromaFormat::YUV444) => 12,
+                (true, _) => 8,
+            };
+
+            write_scaling_lists(w, scaling_lists, count);
+        }
+
+        w.write_se(extension.second_chroma_qp_index_offset);
+    }
+
+    w.write_trailing_bits();
+
+    Ok(())
+}
+
+/// Writes the RBSP of an access unit delimiter (7.3.2.4), including trailing bits.
+///
+/// `primary_pic_type` is `0..=7`, which slice types the picture may contain (Table 7-5).
+pub fn write_aud(w: &mut BitWriter, primary_pic_type: u8) {
+    w.write_bits(primary_pic_type.into(), 3);
+    w.write_trailing_bits();
+}
+
+/// Writes a slice header (7.3.3), the slice data has to follow.
+///
+/// `sps` and `pps` must be the ones the slice refers to. Slices needing a `pred_weight_table()` are not
+/// supported, as [`SliceHeader`] does not keep the weights.
+pub fn write_slice_header(w: &mut BitWriter, slice: &SliceHeader, sps: &SeqParameterSet, pps: &PicParameterSet) -> Result<(), Error> {
+    if pps.pic_parameter_set_id.id() != slice.pic_parameter_set_id || pps.seq_parameter_set_id != sps.id() {
+        return Err(error!(
+            Variant::InvalidSliceHeader,
+            "Slice refers to PPS {}, got PPS {} of SPS {}",
+            slice.pic_parameter_set_id,
+            pps.pic_parameter_set_id.id(),
+            sps.id().id()
+        ));
+    }
+
+    if (pps.weighted_pred_flag && matches!(slice.slice_type, SliceType::P | SliceType::SP))
+        || (pps.weighted_bipred_idc == 1 && slice.slice_type == SliceType::B)
+    {
+        return Err(error!(Variant::UnsupportedFeature, "Writing pred_weight_table() is not supported"));
+    }
+
+    let slice_type_id = match slice.slice_type {
+        SliceType::P => 0,
+        SliceType::B => 1,
+        SliceType::I => 2,
+        SliceType::SP => 3,
+        SliceType::SI => 4,
+    };
+
+    w.write_ue(slice.first_mb_in_slice);
+    w.write_ue(slice_type_id);
+    w.write_ue(slice.pic_parameter_set_id.into());
+
+    if sps.chroma_info.separate_colour_plane_flag {
+        w.write_bits(slice.colour_plane_id.into(), 2);
+    }
+
+    w.write_bits(slice.frame_num.into(), sps.log2_max_frame_num().into());
+
+    if let FrameMbsFlags::Fields { .. } = sps.frame_mbs_flags {
+        w.write_bool(slice.field_pic_flag);
+
+        if slice.field_pic_flag {
+            w.write_bool(slice.bottom_field_flag);
+        }
+    }
+
+    if slice.idr {
+        w.write_ue(slice.idr_pic_id.into());
+    }
+
+    match sps.pic_order_cnt {
+        PicOrderCntType::TypeZero {
+            log2_max_pic_order_cnt_lsb_minus4,
+        } => {
+            w.write_bits(slice.pic_order_cnt_lsb.into(), u32::from(log2_max_pic_order_cnt_lsb_minus4) + 4);
+
+            if pps.bottom_field_pic_order_in_frame_present_flag && !slice.field_pic_flag {
+                w.write_se(slice.delta_pic_order_cnt_bottom);
+            }
+        }
+        PicOrderCntType::TypeOne {
+            delta_pic_order_always_zero_flag: false,
+            ..
+        } => {
+            w.write_se(slice.delta_pic_order_cnt[0]);
+
+            if pps.bottom_field_pic_order_in_frame_present_flag && !slice.field_pic_flag {
+                w.write_se(slice.delta_pic_order_cnt[1]);
+            }
+        }
+        _ => {}
+    }
+
+    if pps.redundant_pic_cnt_present_flag {
+        w.write_ue(slice.redundant_pic_cnt);
+    }
+
+    if slice.slice_type == SliceType::B {
+        w.write_bool(slice.direct_spatial_mv_pred_flag);
+    }
+
+    if slice.slice_type.is_inter() {
+        let override_l0 = slice.num_ref_idx_l0_active_minus1 != pps.num_ref_idx_l0_default_active_minus1;
+        let override_l1 = slice.num_ref_idx_l1_active_minus1 != pps.num_ref_idx_l1_default_active_minus1;
+        let num_ref_idx_active_override_flag = override_l0 || (slice.slice_type == SliceType::B && override_l1);
+
+        w.write_bool(num_ref_idx_active_override_flag);
+
+        if num_ref_idx_active_override_flag {
+            w.write_ue(slice.num_ref_idx_l0_active_minus1);
+
+            if slice.slice_type == SliceType::B {
+                w.write_ue(slice.num_ref_idx_l1_active_minus1);
+            }
+        }
+    }
+
+    if !matches!(slice.slice_type, SliceType::I | SliceType::SI) {
+        write_ref_pic_list_modification(w, &slice.ref_pic_list_modification_l0);
+    }
+
+    if slice.slice_type == SliceType::B {
+        write_ref_pic_list_modification(w, &slice.ref_pic_list_modification_l1);
+    }
+
+    if slice.is_reference() {
+        let marking = slice
+            .dec_ref_pic_marking
+            .as_ref()
+            .ok_or_else(|| error!(Variant::InvalidSliceHeader, "Reference slice without dec_ref_pic_marking"))?;
+
+        write_dec_ref_pic_marking(w, marking, slice.idr)?;
+    }
+
+    if pps.entropy_coding_mode_flag && slice.slice_type.is_inter() {
+        w.write_ue(slice.cabac_init_idc);
+    }
+
+    w.write_se(slice.slice_qp_delta);
+
+    if matches!(slice.slice_type, SliceType::SP | SliceType::SI) {
+        if slice.slice_type == SliceType::SP {
+            w.write_bool(slice.sp_for_switch_flag);
+        }
+
+        w.write_se(slice.slice_qs_delta);
+    }
+
+    if pps.deblocking_filter_control_present_flag {
+        w.write_ue(slice.disable_deblocking_filter_idc);
+
+        if slice.disable_deblocking_filter_idc != 1 {
+            w.write_se(slice.slice_alpha_c0_offset_div2);
+            w.write_se(slice.slice_beta_offset_div2);
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the first `count` lists of `scaling_lists`, the counterpart of reading them in `scaling.rs`.
+fn write_scaling_lists(w: &mut BitWriter, scaling_lists: &StdVideoH264ScalingLists, count: usize) {
+    for i in 0..count {
+        let present = scaling_lists.scaling_list_present_mask & (1 << i) != 0;
+
+        w.write_bool(present);
+
+        if !present {
+            continue;
+        }
+
+        if scaling_lists.use_default_scaling_matrix_mask & (1 << i) != 0 {
+            // A first `nextScale` of 0 selects the default matrix (7.4.2.1.1.1).
+            w.write_se(-8);
+            continue;
+        }
+
+        let list = match i {
+            0..=5 => &scaling_lists.ScalingList4x4[i][..],
+            _ => &scaling_lists.ScalingList8x8[i - 6][..],
+        };
+
+        let mut last_scale = 8;
+
+        for value in list {
+            let delta_scale = (i32::from(*value) - last_scale + 128).rem_euclid(256) - 128;
+
+            w.write_se(delta_scale);
+            last_scale = i32::from(*value);
+        }
+    }
+}
+
+fn write_vui(w: &mut BitWriter, vui: &VuiParameters) {
+    w.write_bool(vui.aspect_ratio_info.is_some());
+
+    if let Some(aspect_ratio_info) = &vui.aspect_ratio_info {
+        let (aspect_ratio_idc, sar_width, sar_height) = std_aspect_ratio_idc(aspect_ratio_info);
+
+        w.write_bits(aspect_ratio_idc, 8);
+
+        if aspect_ratio_idc == 255 {
+            w.write_bits(sar_width.into(), 16);
+            w.write_bits(sar_height.into(), 16);
+        }
+    }
+
+    match vui.overscan_appropriate {
+        OverscanAppropriate::Unspecified => w.write_bool(false),
+        OverscanAppropriate::Appropriate => w.write_bits(0b11, 2),
+        OverscanAppropriate::Inappropriate => w.write_bits(0b10, 2),
+    }
+
+    w.write_bool(vui.video_signal_type.is_some());
+
+    if let Some(signal_type) = &vui.video_signal_type {
+        w.write_bits(std_video_format(&signal_type.video_format).into(), 3);
+        w.write_bool(signal_type.video_full_range_flag);
+        w.write_bool(signal_type.colour_description.is_some());
+
+        if let Some(colour) = &signal_type.colour_description {
+            w.write_bits(colour.colour_primaries.into(), 8);
+            w.write_bits(colour.transfer_characteristics.into(), 8);
+            w.write_bits(colour.matrix_coefficients.into(), 8);
+        }
+    }
+
+    w.write_bool(vui.chroma_loc_info.is_some());
+
+    if let Some(chroma_loc_info) = &vui.chroma_loc_info {
+        w.write_ue(chroma_loc_info.chroma_sample_loc_type_top_field);
+        w.write_ue(chroma_loc_info.chroma_sample_loc_type_bottom_field);
+    }
+
+    w.write_bool(vui.timing_info.is_some());
+
+    if let Some(timing_info) = &vui.timing_info {
+        w.write_bits(timing_info.num_units_in_tick, 32);
+        w.write_bits(timing_info.time_scale, 32);
+        w.write_bool(timing_info.fixed_frame_rate_flag);
+    }
+
+    for hrd in [&vui.nal_hrd_parameters, &vui.vcl_hrd_parameters] {
+        w.write_bool(hrd.is_some());
+
+        if let Some(hrd) = hrd {
+            write_hrd(w, hrd);
+        }
+    }
+
+    if vui.nal_hrd_parameters.is_some() || vui.vcl_hrd_parameters.is_some() {
+        w.write_bool(vui.low_delay_hrd_flag.unwrap_or_default());
+    }
+
+    w.write_bool(vui.pic_struct_present_flag);
+    w.write_bool(vui.bitstream_restrictions.is_some());
+
+    if let Some(restrictions) = &vui.bitstream_restrictions {
+        w.write_bool(restrictions.motion_vectors_over_pic_boundaries_flag);
+        w.write_ue(restrictions.max_bytes_per_pic_denom);
+        w.write_ue(restrictions.max_bits_per_mb_denom);
+        w.write_ue(restrictions.log2_max_mv_length_horizontal);
+        w.write_ue(restrictions.log2_max_mv_length_vertical);
+        w.write_ue(restrictions.max_num_reorder_frames);
+        w.write_ue(restrictions.max_dec_frame_buffering);
+    }
+}
+
+/// E.1.2
+fn write_hrd(w: &mut BitWriter, hrd: &HrdParameters) {
+    w.write_ue(hrd.cpb_specs.len().saturating_sub(1) as u32);
+    w.write_bits(hrd.bit_rate_scale.into(), 4);
+    w.write_bits(hrd.cpb_size_scale.into(), 4);
+
+    for spec in &hrd.cpb_specs {
+        w.write_ue(spec.bit_rate_value_minus1);
+        w.write_ue(spec.cpb_size_value_minus1);
+        w.write_bool(spec.cbr_flag);
+    }
+
+    w.write_bits(hrd.initial_cpb_removal_delay_length_minus1.into(), 5);
+    w.write_bits(hrd.cpb_removal_delay_length_minus1.into(), 5);
+    w.write_bits(hrd.dpb_output_delay_length_minus1.into(), 5);
+    w.write_bits(hrd.time_offset_length.into(), 5);
+}
+
+/// Writes one list of `ref_pic_list_modification()` (7.3.3.1).
+fn write_ref_pic_list_modification(w: &mut BitWriter, modifications: &[RefPicListModification]) {
+    w.write_bool(!modifications.is_empty());
+
+    if modifications.is_empty() {
+        return;
+    }
+
+    for modification in modifications {
+        match modification {
+            RefPicListModification::ShortTermSubtract(x) => {
+                w.write_ue(0);
+                w.write_ue(*x);
+            }
+            RefPicListModification::ShortTermAdd(x) => {
+                w.write_ue(1);
+                w.write_ue(*x);
+            }
+            RefPicListModification::LongTerm(x) => {
+                w.write_ue(2);
+                w.write_ue(*x);
+            }
+        }
+    }
+
+    w.write_ue(3);
+}
+
+/// Writes `dec_ref_pic_marking()` (7.3.3.3).
+fn write_dec_ref_pic_marking(w: &mut BitWriter, marking: &DecRefPicMarking, idr: bool) -> Result<(), Error> {
+    use MemoryManagementControlOperation as Mmco;
+
+    match (marking, idr) {
+        (
+            DecRefPicMarking::Idr {
+                no_output_of_prior_pics_flag,
+                long_term_reference_flag,
+            },
+            true,
+        ) => {
+            w.write_bool(*no_output_of_prior_pics_flag);
+            w.write_bool(*long_term_reference_flag);
+        }
+        (DecRefPicMarking::SlidingWindow, false) => w.write_bool(false),
+        (DecRefPicMarking::Adaptive(operations), false) => {
+            w.write_bool(true);
+
+            for operation in operations {
+                match *operation {
+                    Mmco::ShortTermUnusedForReference {
+                        difference_of_pic_nums_minus1,
+                    } => {
+                        w.write_ue(1);
+                        w.write_ue(difference_of_pic_nums_minus1);
+                    }
+                    Mmco::LongTermUnusedForReference { long_term_pic_num } => {
+                        w.write_ue(2);
+                        w.write_ue(long_term_pic_num);
+                    }
+                    Mmco::ShortTermToLongTerm {
+                        difference_of_pic_nums_minus1,
+                        long_term_frame_idx,
+                    } => {
+                        w.write_ue(3);
+                        w.write_ue(difference_of_pic_nums_minus1);
+                        w.write_ue(long_term_frame_idx);
+                    }
+                    Mmco::MaxLongTermFrameIdx {
+                        max_long_term_frame_idx_plus1,
+                    } => {
+                        w.write_ue(4);
+                        w.write_ue(max_long_term_frame_idx_plus1);
+                    }
+                    Mmco::AllUnusedForReference => w.write_ue(5),
+                    Mmco::CurrentToLongTerm { long_term_frame_idx } => {
+                        w.write_ue(6);
+                        w.write_ue(long_term_frame_idx);
+                    }
+                }
+            }
+
+            w.write_ue(0);
+        }
+        _ => {
+            return Err(error!(
+                Variant::InvalidSliceHeader,
+                "dec_ref_pic_marking {:?} does not match idr {}", marking, idr
+            ))
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{write_aud, write_pps, write_slice_header, write_sps};
+    use crate::error::Error;
+    use crate::video::h264::testdata::{nal, PPS, SPS};
+    use crate::video::h264::{BitWriter, H264StreamInspector, ParsedNal, SliceHeader};
+    use h264_reader::nal::pps::PicParameterSet;
+    use h264_reader::nal::sps::SeqParameterSet;
+    use h264_reader::nal::UnitType;
+
+    fn parse_sps(inspector: &mut H264StreamInspector, nal: &[u8]) -> Result<SeqParameterSet, Error> {
+        match inspector.feed_nal(nal)? {
+            Some(ParsedNal::Sps(sps)) => Ok(sps),
+            x => panic!("Expected SPS, got {:?}", x),
+        }
+    }
+
+    fn parse_pps(inspector: &mut H264StreamInspector, nal: &[u8]) -> Result<PicParameterSet, Error> {
+        match inspector.feed_nal(nal)? {
+            Some(ParsedNal::Pps(pps)) => Ok(pps),
+            x => panic!("Expected PPS, got {:?}", x),
+        }
+    }
+
+    fn parse_slice(inspector: &mut H264StreamInspector, nal: &[u8]) -> Result<SliceHeader, Error> {
+        match inspector.feed_nal(nal)? {
+            Some(ParsedNal::Slice(slice)) => Ok(slice),
+            x => panic!("Expected slice, got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn parameter_sets_round_trip() -> Result<(), Error> {
+        let mut inspector = H264StreamInspector::new();
+
+        // A real High profile SPS and PPS come out bit-exact.
+        let sps = parse_sps(&mut inspector, &SPS)?;
+        let pps = parse_pps(&mut inspector, &PPS)?;
+
+        let mut w = BitWriter::new();
+        write_sps(&mut w, &sps, inspector.sps_scaling_lists(0))?;
+        assert_eq!(&w.into_nal(3, UnitType::SeqParameterSet)[4..], &SPS);
+
+        let mut w = BitWriter::new();
+        write_pps(&mut w, &pps, &sps, inspector.pps_scaling_lists(0))?;
+        assert_eq!(&w.into_nal(3, UnitType::PicParameterSet)[4..], &PPS);
+
+        // High profile, scaling matrix with a default and an explicit 4x4 list, POC type 1, interlaced, cropped,
+        // VUI with SAR 4:3, overscan, signal type, chroma location, timing, NAL HRD and bitstream restrictions.
+        let sps = nal(
+            0x67,
+            "01100100 00000000 00101000 1 010 1 1 0 1 \
+             1 010 111111111111111 1 000010001 0 0 0 0 0 0 \
+             1 010 0 011 010 011 00100 00101 00100 0 00100 010 0 1 1 1 1 1 010 010 1 \
+             1 00001110 1 1 1 101 1 1 00000001 00000001 00000001 1 010 010 \
+             1 00000000000000000000001111101000 00000000000000001110101001100000 1 \
+             1 1 0010 0011 00101 010 0 10111 10111 10111 11000 0 1 1 \
+             1 1 1 1 1 010 010 00100",
+        );
+
+        let parsed = parse_sps(&mut inspector, &sps)?;
+        let scaling_lists = inspector.sps_scaling_lists(0).copied();
+
+        assert!(scaling_lists.is_some());
+
+        let mut w = BitWriter::new();
+        write_sps(&mut w, &parsed, scaling_lists.as_ref())?;
+
+        let written = w.into_nal(3, UnitType::SeqParameterSet);
+
+        assert_eq!(parse_sps(&mut inspector, &written)?, parsed);
+        assert_eq!(
+            format!("{:?}", inspector.sps_scaling_lists(0)),
+            format!("{:?}", scaling_lists.as_ref())
+        );
+
+        // CABAC PPS with bottom field POC, weighted bipred, 8x8 transform and a 4x4 scaling list.
+        let pps = nal(0x68, "1 1 1 1 1 011 010 0 10 011 1 1 1 0 0 1 1 1 000010001 0 0 0 0 0 0 0 010");
+        let parsed = parse_pps(&mut inspector, &pps)?;
+        let scaling_lists = inspector.pps_scaling_lists(0).copied();
+
+        let mut w = BitWriter::new();
+        write_pps(&mut w, &parsed, inspector.sps(0).unwrap(), scaling_lists.as_ref())?;
+
+        let written = w.into_nal(3, UnitType::PicParameterSet);
+
+        assert_eq!(written, pps);
+        assert_eq!(format!("{:?}", parse_pps(&mut inspector, &written)?), format!("{:?}", parsed));
+
+        Ok(())
+    }
+
+    #[test]
+    fn slice_headers_round_trip() -> Result<(), Error> {
+        let mut inspector = H264StreamInspector::new();
+
+        // Baseline, 4 bit `frame_num`, POC type 0 with 4 bit LSB, 2 ref frames, 4x4 MBs; CAVLC PPS with deblocking control.
+        inspector.feed_nal(&nal(0x67, "01000010 00000000 00011110 1 1 1 1 010 0 00100 00100 1 1 0 0"))?;
+        inspector.feed_nal(&nal(0x68, "1 1 0 0 1 1 1 0 00 1 1 1 1 0 0"))?;
+
+        let slices = [
+            // IDR, long-term.
+            nal(0x65, "1 011 1 0000 011 0000 0 1 1 010"),
+            // P, 2 refs overriding the PPS, list modification, MMCO 1 and 6, QP delta -1, deblocking offsets.
+            nal(
+                0x41,
+                "00101 1 1 0001 0010 1 010 1 1 011 011 010 00100 1 010 011 00111 1 1 011 1 00100 00101",
+            ),
+            // Non-reference B slice with direct spatial prediction, both lists modified.
+            nal(0x01, "1 010 1 0010 0100 1 1 1 010 1 010 1 00100 1 1 00100 00100 1 010"),
+        ];
+
+        for slice in slices {
+            let parsed = parse_slice(&mut inspector, &slice)?;
+
+            let mut w = BitWriter::new();
+            write_slice_header(&mut w, &parsed, inspector.sps(0).unwrap(), inspector.pps(0).unwrap())?;
+            w.write_trailing_bits();
+
+            let written = w.into_nal(slice[4] >> 5, UnitType::for_id(slice[4] & 0x1F).unwrap());
+
+            assert_eq!(written, slice);
+            assert_eq!(format!("{:?}", parse_slice(&mut inspector, &written)?), format!("{:?}", parsed));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn writes_aud() {
+        let mut w = BitWriter::new();
+        write_aud(&mut w, 7);
+
+        assert_eq!(w.into_nal(0, UnitType::AccessUnitDelimiter), vec![0, 0, 0, 1, 0x09, 0xF0]);
+    }
+}
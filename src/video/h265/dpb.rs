@@ -0,0 +1,470 @@
+//! Reference picture set based marking (8.3.2) and DPB slot management.
+use crate::error;
+use crate::error::{Error, Variant};
+use crate::video::h265::output::no_output_of_prior_pics;
+use crate::video::h265::{PocCalculator, SeqParameterSet, SliceSegmentHeader};
+use crate::video::ColorInfo;
+use ash::vk::{Extent2D, Offset2D, Rect2D};
+
+/// A picture in the DPB the current picture can reference, and the slot it lives in.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DpbReference {
+    pub slot_index: u8,
+    pub pic_order_cnt: i32,
+    /// Marked as used for long-term reference.
+    pub long_term: bool,
+}
+
+/// Everything needed to decode a picture: its own parameters, the DPB slot to decode into, and its references.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DpbPicture {
+    /// The slot the picture is decoded into, i.e., Vulkan's setup reference slot.
+    pub slot_index: u8,
+    pub video_parameter_set_id: u8,
+    pub seq_parameter_set_id: u8,
+    pub pic_parameter_set_id: u8,
+    /// `PicOrderCntVal`.
+    pub pic_order_cnt: i32,
+    pub irap: bool,
+    pub idr: bool,
+    /// An IRAP picture starting a new coded video sequence, which drops all references (8.1.3).
+    pub no_rasl_output_flag: bool,
+    /// Unset for sub-layer non-reference pictures.
+    pub reference: bool,
+    pub short_term_ref_pic_set_sps_flag: bool,
+    /// `NumDeltaPocs[RefRpsIdx]` if the slice header's set is predicted, otherwise 0.
+    pub num_delta_pocs_of_ref_rps_idx: u8,
+    pub num_bits_for_st_ref_pic_set_in_slice: u16,
+    /// Slots of `RefPicSetStCurrBefore`, the short-term references before the current picture in output order.
+    pub st_curr_before: Vec<u8>,
+    /// Slots of `RefPicSetStCurrAfter`, the short-term references after the current picture in output order.
+    pub st_curr_after: Vec<u8>,
+    /// Slots of `RefPicSetLtCurr`, the long-term references.
+    pub lt_curr: Vec<u8>,
+    /// The part of the decoded image to display, i.e., the conformance window.
+    pub display_rect: Rect2D,
+    /// How to convert the decoded samples to RGB.
+    pub color_info: ColorInfo,
+    /// All pictures in the reference picture set, including those kept only for following pictures.
+    pub references: Vec<DpbReference>,
+}
+
+impl DpbPicture {
+    /// How later pictures will see this picture when referencing it, i.e., Vulkan's setup reference.
+    pub fn as_reference(&self) -> DpbReference {
+        DpbReference {
+            slot_index: self.slot_index,
+            pic_order_cnt: self.pic_order_cnt,
+            long_term: false,
+        }
+    }
+}
+
+// The slots of one of the `RefPicSet*Curr` lists.
+type CurrSlots = Vec<u8>;
+
+/// Tracks references and assigns Vulkan DPB slots to pictures.
+///
+/// Each picture in decoding order is passed to [`Dpb::add_picture`], which computes its picture order count,
+/// applies its reference picture set, and returns the slot to decode into along with its references. Unlike H.264,
+/// every picture carries the complete set of references it and the following pictures need, so all other pictures
+/// are dropped.
+///
+/// A slot is only reused once its picture is no longer a reference and has been output, so the next picture can't
+/// overwrite a picture still waiting in the [`OutputQueue`](crate::video::h265::OutputQueue). Pass each picture's
+/// slot to [`Dpb::release`] once it has been displayed.
+///
+/// RASL pictures following an IRAP picture that starts a coded video sequence, e.g., the CRA picture we start
+/// decoding at, reference pictures we never saw and are rejected with [`Variant::MissingReferences`].
+#[derive(Debug)]
+pub struct Dpb {
+    max_slots: u8,
+    entries: Vec<DpbReference>,
+    // Slots of pictures which have not been output yet.
+    awaiting_output: Vec<u8>,
+    poc: PocCalculator,
+    // The last IRAP picture had `NoRaslOutputFlag` set, so its RASL pictures can't be decoded.
+    skip_rasl: bool,
+}
+
+impl Dpb {
+    /// Creates a DPB with `max_slots` Vulkan slots, which should match [`VideoSession::max_dpb_slots`](crate::video::VideoSession::max_dpb_slots).
+    pub fn new(max_slots: u8) -> Self {
+        Self {
+            max_slots,
+            entries: Vec::new(),
+            awaiting_output: Vec::new(),
+            poc: PocCalculator::new(),
+            skip_rasl: false,
+        }
+    }
+
+    /// Drops all references and forgets about pictures waiting for output, decoding restarts at the next IRAP picture.
+    pub fn reset(&mut self) {
+        self.awaiting_output.clear();
+        self.entries.clear();
+        self.poc = PocCalculator::new();
+        self.skip_rasl = false;
+    }
+
+    /// Frees the slot of a picture once it has been output, i.e., returned by [`OutputQueue`](crate::video::h265::OutputQueue).
+    pub fn release(&mut self, slot_index: u8) {
+        if let Some(i) = self.awaiting_output.iter().position(|x| *x == slot_index) {
+            self.awaiting_output.swap_remove(i);
+        }
+    }
+
+    /// Makes the next IRAP picture start a new coded video sequence, call this for end of sequence NAL units.
+    pub fn end_of_sequence(&mut self) {
+        self.poc.end_of_sequence();
+    }
+
+    /// Returns all pictures currently marked as used for reference.
+    pub fn references(&self) -> Vec<DpbReference> {
+        self.entries.clone()
+    }
+
+    /// Assigns a slot to the picture starting with `slice`, returns what is needed to decode it, and marks it as
+    /// short-term reference for the following pictures.
+    ///
+    /// Fails with [`Variant::MissingReferences`] if the picture references pictures that are not in the DPB, e.g.,
+    /// after starting to decode in the middle of a stream.
+    pub fn add_picture(&mut self, sps: &SeqParameterSet, slice: &SliceSegmentHeader) -> Result<DpbPicture, Error> {
+        let nal_unit_type = slice.nal_unit_type;
+        let no_rasl_output_flag = self.poc.no_rasl_output_flag(slice);
+        let pic_order_cnt = self.poc.compute(sps, slice);
+
+        if nal_unit_type.is_irap() {
+            self.skip_rasl = no_rasl_output_flag;
+
+            if no_rasl_output_flag {
+                self.entries.clear();
+
+                // The output queue discards these pictures, nobody is going to release them.
+                if no_output_of_prior_pics(slice) {
+                    self.awaiting_output.clear();
+                }
+            }
+        }
+
+        if nal_unit_type.is_rasl() && self.skip_rasl {
+            return Err(error!(
+                Variant::MissingReferences,
+                "Skipping RASL picture {} of a random access point", pic_order_cnt
+            ));
+        }
+
+        let (st_curr_before, st_curr_after, lt_curr) = self.apply_reference_picture_set(sps, slice, pic_order_cnt)?;
+        let references = self.references();
+        let slot_index = self.free_slot()?;
+
+        if slice.pic_output_flag {
+            self.awaiting_output.push(slot_index);
+        }
+
+        let num_delta_pocs_of_ref_rps_idx = match &slice.short_term_ref_pic_set {
+            Some(set) if set.inter_ref_pic_set_prediction_flag => {
+                let ref_rps_idx = sps.short_term_ref_pic_sets.len() - (set.delta_idx_minus1 as usize + 1);
+                sps.short_term_ref_pic_sets[ref_rps_idx].num_delta_pocs() as u8
+            }
+            _ => 0,
+        };
+
+        let picture = DpbPicture {
+            slot_index,
+            video_parameter_set_id: sps.sps_video_parameter_set_id,
+            seq_parameter_set_id: slice.slice_seq_parameter_set_id,
+            pic_parameter_set_id: slice.slice_pic_parameter_set_id,
+            pic_order_cnt,
+            irap: nal_unit_type.is_irap(),
+            idr: nal_unit_type.is_idr(),
+            no_rasl_output_flag,
+            reference: !nal_unit_type.is_sub_layer_non_reference(),
+            short_term_ref_pic_set_sps_flag: slice.short_term_ref_pic_set_sps_flag,
+            num_delta_pocs_of_ref_rps_idx,
+            num_bits_for_st_ref_pic_set_in_slice: slice.num_bits_for_st_ref_pic_set_in_slice as u16,
+            st_curr_before,
+            st_curr_after,
+            lt_curr,
+            display_rect: display_rect(sps),
+            color_info: color_info(sps),
+            references,
+        };
+
+        // After decoding, every picture is a short-term reference until a reference picture set drops it.
+        self.entries.push(picture.as_reference());
+
+        Ok(picture)
+    }
+
+    /// 8.3.2, marks the pictures in the reference picture set of `slice` and drops all others.
+    ///
+    /// Returns the slots of `RefPicSetStCurrBefore`, `RefPicSetStCurrAfter` and `RefPicSetLtCurr`.
+    fn apply_reference_picture_set(
+        &mut self,
+        sps: &SeqParameterSet,
+        slice: &SliceSegmentHeader,
+        pic_order_cnt: i32,
+    ) -> Result<(CurrSlots, CurrSlots, CurrSlots), Error> {
+        let Some(set) = slice.short_term_ref_pic_set(sps) else {
+            self.entries.clear();
+            return Ok(Default::default());
+        };
+
+        let max_pic_order_cnt_lsb = sps.max_pic_order_cnt_lsb() as i32;
+        let mut kept = Vec::new();
+        let mut missing = 0;
+        let mut lt_curr = Vec::new();
+        let mut st_curr_before = Vec::new();
+        let mut st_curr_after = Vec::new();
+
+        // Long-term pictures first (8-5), they may be found among all references.
+        for lt in &slice.long_term_refs {
+            let mut poc = lt.poc_lsb_lt as i32;
+
+            if lt.delta_poc_msb_present_flag {
+                poc += pic_order_cnt
+                    - lt.delta_poc_msb_cycle_lt as i32 * max_pic_order_cnt_lsb
+                    - (pic_order_cnt & (max_pic_order_cnt_lsb - 1));
+            }
+
+            let found = self.entries.iter().position(|x| match lt.delta_poc_msb_present_flag {
+                true => x.pic_order_cnt == poc,
+                false => x.pic_order_cnt & (max_pic_order_cnt_lsb - 1) == poc,
+            });
+
+            match found {
+                Some(i) => {
+                    let mut entry = self.entries.remove(i);
+                    entry.long_term = true;
+
+                    if lt.used_by_curr_pic_lt_flag {
+                        lt_curr.push(entry.slot_index);
+                    }
+
+                    kept.push(entry);
+                }
+                None if lt.used_by_curr_pic_lt_flag => missing += 1,
+                None => {}
+            }
+        }
+
+        // Short-term pictures (8-6), which must not have become long-term ones.
+        let short_term = [
+            (&set.delta_poc_s0, &set.used_by_curr_pic_s0, &mut st_curr_before),
+            (&set.delta_poc_s1, &set.used_by_curr_pic_s1, &mut st_curr_after),
+        ];
+
+        for (delta_pocs, used_by_curr_pic, list) in short_term {
+            for (delta_poc, used) in delta_pocs.iter().zip(used_by_curr_pic) {
+                let poc = pic_order_cnt + delta_poc;
+                let found = self.entries.iter().position(|x| !x.long_term && x.pic_order_cnt == poc);
+
+                match found {
+                    Some(i) => {
+                        let entry = self.entries.remove(i);
+
+                        if *used {
+                            list.push(entry.slot_index);
+                        }
+
+                        kept.push(entry);
+                    }
+                    None if *used => missing += 1,
+                    None => {}
+                }
+            }
+        }
+
+        // Everything not in the set is no longer used for reference.
+        self.entries = kept;
+
+        if missing > 0 {
+            return Err(error!(
+                Variant::MissingReferences,
+                "Picture {} misses {} of its references", pic_order_cnt, missing
+            ));
+        }
+
+        Ok((st_curr_before, st_curr_after, lt_curr))
+    }
+
+    fn free_slot(&self) -> Result<u8, Error> {
+        (0..self.max_slots)
+            .find(|x| self.entries.iter().all(|e| e.slot_index != *x) && !self.awaiting_output.contains(x))
+            .ok_or_else(|| {
+                error!(
+                    Variant::NoFreeDpbSlot,
+                    "All {} DPB slots hold references or pictures waiting for output", self.max_slots
+                )
+            })
+    }
+}
+
+/// The conformance window of pictures coded with `sps`, in luma samples of the decoded image (7-10).
+fn display_rect(sps: &SeqParameterSet) -> Rect2D {
+    let (sub_width_c, sub_height_c) = sps.sub_width_height_c();
+    let width = sps.pic_width_in_luma_samples;
+    let height = sps.pic_height_in_luma_samples;
+
+    let left = sps.conf_win_left_offset.saturating_mul(sub_width_c).min(width);
+    let right = sps.conf_win_right_offset.saturating_mul(sub_width_c).min(width - left);
+    let top = sps.conf_win_top_offset.saturating_mul(sub_height_c).min(height);
+    let bottom = sps.conf_win_bottom_offset.saturating_mul(sub_height_c).min(height - top);
+
+    Rect2D {
+        offset: Offset2D {
+            x: left as i32,
+            y: top as i32,
+        },
+        extent: Extent2D {
+            width: width - left - right,
+            height: height - top - bottom,
+        },
+    }
+}
+
+/// The colour description of the VUI, with the values E.3.1 infers when absent.
+fn color_info(sps: &SeqParameterSet) -> ColorInfo {
+    let vui = sps.vui_parameters.clone().unwrap_or_default();
+
+    // Table E-1.
+    let sample_aspect_ratio = match vui.aspect_ratio_idc {
+        _ if !vui.aspect_ratio_info_present_flag => None,
+        1 => Some((1, 1)),
+        2 => Some((12, 11)),
+        3 => Some((10, 11)),
+        4 => Some((16, 11)),
+        5 => Some((40, 33)),
+        6 => Some((24, 11)),
+        7 => Some((20, 11)),
+        8 => Some((32, 11)),
+        9 => Some((80, 33)),
+        10 => Some((18, 11)),
+        11 => Some((15, 11)),
+        12 => Some((64, 33)),
+        13 => Some((160, 99)),
+        14 => Some((4, 3)),
+        15 => Some((3, 2)),
+        16 => Some((2, 1)),
+        255 if vui.sar_width != 0 && vui.sar_height != 0 => Some((vui.sar_width, vui.sar_height)),
+        _ => None,
+    };
+
+    ColorInfo {
+        colour_primaries: vui.colour_primaries,
+        transfer_characteristics: vui.transfer_characteristics,
+        matrix_coefficients: vui.matrix_coeffs,
+        full_range: vui.video_full_range_flag,
+        chroma_sample_loc_type_top_field: vui.chroma_sample_loc_type_top_field,
+        chroma_sample_loc_type_bottom_field: vui.chroma_sample_loc_type_bottom_field,
+        sample_aspect_ratio,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Dpb;
+    use crate::error::Variant;
+    use crate::video::h265::nal::NalUnitType;
+    use crate::video::h265::testdata::{slice_segment_header, sps};
+    use crate::video::h265::{OutputQueue, SliceSegmentHeader};
+
+    /// A slice using SPS set `idx`, i.e., `{-1}` for 0 and `{-1, -2}` for 1.
+    fn trail(poc_lsb: u32, idx: u8) -> SliceSegmentHeader {
+        let mut slice = slice_segment_header(NalUnitType::TRAIL_R, poc_lsb);
+        slice.short_term_ref_pic_set_idx = idx;
+        slice
+    }
+
+    #[test]
+    fn applies_reference_picture_sets() {
+        let sps = sps();
+        let mut dpb = Dpb::new(4);
+
+        let idr = dpb.add_picture(&sps, &slice_segment_header(NalUnitType::IDR_W_RADL, 0)).unwrap();
+        let p1 = dpb.add_picture(&sps, &trail(1, 0)).unwrap();
+        let p2 = dpb.add_picture(&sps, &trail(2, 1)).unwrap();
+
+        for x in [&idr, &p1, &p2] {
+            dpb.release(x.slot_index);
+        }
+
+        let p3 = dpb.add_picture(&sps, &trail(3, 0)).unwrap();
+
+        assert!(idr.idr && idr.no_rasl_output_flag && idr.references.is_empty());
+        assert_eq!((idr.slot_index, idr.display_rect.extent.height), (0, 56));
+        assert_eq!((p1.slot_index, p1.st_curr_before.clone()), (1, vec![0]));
+        assert_eq!((p2.slot_index, p2.st_curr_before.clone()), (2, vec![1, 0]));
+
+        // Picture 3 only keeps picture 2, so the slots of 0 and 1 are free again.
+        assert_eq!((p3.slot_index, p3.st_curr_before.clone()), (0, vec![2]));
+        assert_eq!(dpb.references().len(), 2);
+        assert_eq!(p3.pic_order_cnt, 3);
+    }
+
+    #[test]
+    fn holds_slots_until_output() {
+        let sps = sps();
+        let mut dpb = Dpb::new(3);
+        let mut queue = OutputQueue::new();
+
+        let mut push = |dpb: &mut Dpb, slice: &SliceSegmentHeader| {
+            let picture = dpb.add_picture(&sps, slice).unwrap();
+
+            for slot_index in queue.push(&sps, slice, &picture, picture.slot_index) {
+                dpb.release(slot_index);
+            }
+
+            picture
+        };
+
+        // Picture 1 is never output, so its slot is free once no picture references it.
+        let hidden = SliceSegmentHeader {
+            pic_output_flag: false,
+            ..trail(1, 0)
+        };
+
+        push(&mut dpb, &slice_segment_header(NalUnitType::IDR_W_RADL, 0));
+        push(&mut dpb, &hidden);
+
+        let p2 = push(&mut dpb, &trail(2, 1));
+        let p3 = push(&mut dpb, &trail(3, 0));
+
+        // The SPS allows no reordering, so all other pictures were output and released right away.
+        assert_eq!((p2.slot_index, p3.slot_index), (2, 0));
+
+        // Without output, pictures 4 and 5 keep their slots after the following picture dropped them as reference.
+        let p4 = dpb.add_picture(&sps, &trail(4, 0)).unwrap();
+        let p5 = dpb.add_picture(&sps, &trail(5, 0)).unwrap();
+        let p6 = dpb.add_picture(&sps, &trail(6, 0)).unwrap();
+        let error = dpb.add_picture(&sps, &trail(7, 0)).unwrap_err();
+
+        assert_eq!((p4.slot_index, p5.slot_index, p6.slot_index), (1, 0, 2));
+        assert!(matches!(error.variant(), Variant::NoFreeDpbSlot));
+    }
+
+    #[test]
+    fn rejects_missing_references() {
+        let sps = sps();
+        let mut dpb = Dpb::new(4);
+
+        // Starting at a CRA picture, its RASL pictures can't be decoded but the trailing ones can.
+        let mut cra = slice_segment_header(NalUnitType::CRA_NUT, 8);
+        cra.short_term_ref_pic_set_sps_flag = false;
+        cra.short_term_ref_pic_set = Some(Default::default());
+
+        let cra = dpb.add_picture(&sps, &cra).unwrap();
+        let rasl = dpb.add_picture(&sps, &slice_segment_header(NalUnitType::RASL_N, 7)).unwrap_err();
+        let trailing = dpb.add_picture(&sps, &trail(9, 0)).unwrap();
+
+        assert!(cra.irap && cra.no_rasl_output_flag);
+        assert!(matches!(rasl.variant(), Variant::MissingReferences));
+        assert_eq!(trailing.st_curr_before, vec![cra.slot_index]);
+
+        // Pictures 10 and 11 were lost, so 12 can't be decoded.
+        let error = dpb.add_picture(&sps, &trail(12, 1)).unwrap_err();
+
+        assert!(matches!(error.variant(), Variant::MissingReferences));
+    }
+}
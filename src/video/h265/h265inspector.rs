@@ -0,0 +1,346 @@
+use crate::error;
+use crate::error::{Error, Variant};
+use crate::video::h264::fingerprint;
+use crate::video::h265::nal::{NalHeader, NalUnitType};
+use crate::video::h265::stdvideo::{StdPps, StdSps, StdVps};
+use crate::video::h265::{PicParameterSet, SeqParameterSet, SliceSegmentHeader, VideoParameterSet};
+use crate::video::profile::{component_bit_depth, VideoProfileInfoBundle};
+use crate::video::{strip_annexb, SessionRequirements};
+use ash::vk::native::{
+    StdVideoH265ProfileIdc_STD_VIDEO_H265_PROFILE_IDC_FORMAT_RANGE_EXTENSIONS, StdVideoH265ProfileIdc_STD_VIDEO_H265_PROFILE_IDC_MAIN,
+    StdVideoH265ProfileIdc_STD_VIDEO_H265_PROFILE_IDC_MAIN_10, StdVideoH265ProfileIdc_STD_VIDEO_H265_PROFILE_IDC_MAIN_STILL_PICTURE,
+};
+use ash::vk::{VideoChromaSubsamplingFlagsKHR, VideoCodecOperationFlagsKHR, VideoComponentBitDepthFlagsKHR, VideoProfileListInfoKHR};
+use h264_reader::rbsp::{decode_nal, BitReader};
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::ptr::addr_of;
+
+/// Parses H.265 NAL units and returns meta data we need to feed into Vulkan.
+///
+/// Only the base layer is decoded, NAL units of other layers are ignored.
+#[derive(Default)]
+pub struct H265StreamInspector {
+    vps: HashMap<u8, VideoParameterSet>,
+    sps: HashMap<u8, SeqParameterSet>,
+    pps: HashMap<u8, PicParameterSet>,
+    last_sps_id: Option<u8>,
+    // A hash of each parameter set's content by id, so session parameters can tell what changed.
+    vps_fingerprints: HashMap<u8, u64>,
+    sps_fingerprints: HashMap<u8, u64>,
+    pps_fingerprints: HashMap<u8, u64>,
+}
+
+/// A NAL unit [`H265StreamInspector::feed_nal`] understood and extracted information from.
+#[derive(Debug)]
+pub enum ParsedNal {
+    Vps(VideoParameterSet),
+    Sps(SeqParameterSet),
+    Pps(PicParameterSet),
+    Slice(SliceSegmentHeader),
+    /// The next IRAP picture starts a new coded video sequence, see [`Dpb::end_of_sequence`](crate::video::h265::Dpb::end_of_sequence).
+    EndOfSequence,
+}
+
+impl H265StreamInspector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds a single NAL unit, with or without Annex B start code, and records any parameter sets in it.
+    ///
+    /// Returns `Ok(None)` for NAL units we don't care about. Malformed or unsupported input results
+    /// in an error and leaves the inspector unchanged, so callers can skip the offending NAL and continue.
+    pub fn feed_nal(&mut self, nal: &[u8]) -> Result<Option<ParsedNal>, Error> {
+        let payload = strip_annexb(nal);
+
+        if payload.is_empty() {
+            return Err(error!(Variant::MalformedNalHeader, "NAL unit is empty"));
+        }
+
+        let header = NalHeader::new(payload)?;
+        let nal_unit_type = header.nal_unit_type;
+
+        if header.nuh_layer_id != 0 {
+            return Ok(None);
+        }
+
+        if nal_unit_type == NalUnitType::EOS_NUT {
+            return Ok(Some(ParsedNal::EndOfSequence));
+        }
+
+        let interesting = matches!(nal_unit_type, NalUnitType::VPS_NUT | NalUnitType::SPS_NUT | NalUnitType::PPS_NUT);
+
+        if !interesting && !nal_unit_type.is_vcl() {
+            return Ok(None);
+        }
+
+        // `decode_nal` skips a single header byte, so hand it the second one.
+        let rbsp = decode_nal(&payload[1..]).map_err(|e| error!(Variant::MalformedNalHeader, "Invalid emulation prevention: {:?}", e))?;
+        let bits = BitReader::new(&rbsp[..]);
+
+        match nal_unit_type {
+            NalUnitType::VPS_NUT => {
+                let vps = VideoParameterSet::from_bits(bits)?;
+
+                self.vps_fingerprints.insert(vps.vps_video_parameter_set_id, fingerprint(payload));
+                self.vps.insert(vps.vps_video_parameter_set_id, vps.clone());
+
+                Ok(Some(ParsedNal::Vps(vps)))
+            }
+            NalUnitType::SPS_NUT => {
+                let sps = SeqParameterSet::from_bits(bits)?;
+
+                self.last_sps_id = Some(sps.sps_seq_parameter_set_id);
+                self.sps_fingerprints.insert(sps.sps_seq_parameter_set_id, fingerprint(payload));
+                self.sps.insert(sps.sps_seq_parameter_set_id, sps.clone());
+
+                Ok(Some(ParsedNal::Sps(sps)))
+            }
+            NalUnitType::PPS_NUT => {
+                let pps = PicParameterSet::from_bits(bits)?;
+
+                self.pps_fingerprints.insert(pps.pps_pic_parameter_set_id, fingerprint(payload));
+                self.pps.insert(pps.pps_pic_parameter_set_id, pps.clone());
+
+                Ok(Some(ParsedNal::Pps(pps)))
+            }
+            x if x.is_irap() || x.0 <= NalUnitType::RASL_R.0 => {
+                let slice = SliceSegmentHeader::from_bits(header, bits, &self.sps, &self.pps)?;
+
+                Ok(Some(ParsedNal::Slice(slice)))
+            }
+            // Reserved VCL NAL unit types.
+            _ => Ok(None),
+        }
+    }
+
+    /// The most recent VPS with the given id.
+    pub fn vps(&self, id: u8) -> Option<&VideoParameterSet> {
+        self.vps.get(&id)
+    }
+
+    /// The most recent SPS with the given id.
+    pub fn sps(&self, id: u8) -> Option<&SeqParameterSet> {
+        self.sps.get(&id)
+    }
+
+    /// The most recent PPS with the given id.
+    pub fn pps(&self, id: u8) -> Option<&PicParameterSet> {
+        self.pps.get(&id)
+    }
+
+    /// Returns all VPS seen so far, translated for Vulkan.
+    pub(crate) fn std_vps(&self) -> Vec<StdVps> {
+        self.vps.values().map(StdVps::new).collect()
+    }
+
+    /// Returns all SPS seen so far, translated for Vulkan.
+    pub(crate) fn std_sps(&self) -> Vec<StdSps> {
+        self.sps.values().map(StdSps::new).collect()
+    }
+
+    /// Returns all PPS seen so far, translated for Vulkan.
+    ///
+    /// Vulkan identifies a PPS by the VPS of its SPS as well, PPS of unknown SPS are given VPS 0.
+    pub(crate) fn std_pps(&self) -> Vec<StdPps> {
+        self.pps
+            .values()
+            .map(|pps| {
+                let sps = self.sps(pps.pps_seq_parameter_set_id);
+                StdPps::new(pps, sps.map_or(0, |x| x.sps_video_parameter_set_id))
+            })
+            .collect()
+    }
+
+    /// Identifies the content of each VPS seen so far, by id.
+    pub(crate) fn vps_fingerprints(&self) -> &HashMap<u8, u64> {
+        &self.vps_fingerprints
+    }
+
+    /// Identifies the content of each SPS seen so far, by id.
+    pub(crate) fn sps_fingerprints(&self) -> &HashMap<u8, u64> {
+        &self.sps_fingerprints
+    }
+
+    /// Identifies the content of each PPS seen so far, by id.
+    pub(crate) fn pps_fingerprints(&self) -> &HashMap<u8, u64> {
+        &self.pps_fingerprints
+    }
+
+    /// The most recently seen SPS.
+    fn last_sps(&self) -> Option<&SeqParameterSet> {
+        self.last_sps_id.and_then(|id| self.sps(id))
+    }
+
+    /// Returns the session resources needed for the most recently seen SPS.
+    pub(crate) fn session_requirements(&self) -> Result<SessionRequirements, Error> {
+        self.last_sps()
+            .map(SessionRequirements::new_h265)
+            .ok_or_else(|| error!(Variant::InvalidSps, "No SPS seen yet, cannot size a video session"))
+    }
+
+    /// Returns the Vulkan video profile of the most recently seen SPS.
+    ///
+    /// Sessions, images and buffers must all be created with the same profile, so they should all get it from here.
+    pub fn profiles<'f>(&self) -> Result<Pin<Box<VideoProfileInfoBundle<'f>>>, Error> {
+        let sps = self
+            .last_sps()
+            .ok_or_else(|| error!(Variant::InvalidSps, "No SPS seen yet, cannot derive a video profile"))?;
+
+        let std_profile_idc = match sps.profile_tier_level.general_profile_idc {
+            1 => StdVideoH265ProfileIdc_STD_VIDEO_H265_PROFILE_IDC_MAIN,
+            2 => StdVideoH265ProfileIdc_STD_VIDEO_H265_PROFILE_IDC_MAIN_10,
+            3 => StdVideoH265ProfileIdc_STD_VIDEO_H265_PROFILE_IDC_MAIN_STILL_PICTURE,
+            4 => StdVideoH265ProfileIdc_STD_VIDEO_H265_PROFILE_IDC_FORMAT_RANGE_EXTENSIONS,
+            x => {
+                return Err(error!(
+                    Variant::UnsupportedFeature,
+                    "general_profile_idc {} has no Vulkan video profile", x
+                ))
+            }
+        };
+
+        let chroma_subsampling = match sps.chroma_format_idc {
+            0 => VideoChromaSubsamplingFlagsKHR::MONOCHROME,
+            1 => VideoChromaSubsamplingFlagsKHR::TYPE_420,
+            2 => VideoChromaSubsamplingFlagsKHR::TYPE_422,
+            _ => VideoChromaSubsamplingFlagsKHR::TYPE_444,
+        };
+
+        let luma_bit_depth = component_bit_depth(sps.bit_depth_luma_minus8)?;
+        let chroma_bit_depth = match sps.chroma_format_idc {
+            0 => VideoComponentBitDepthFlagsKHR::INVALID,
+            _ => component_bit_depth(sps.bit_depth_chroma_minus8)?,
+        };
+
+        let mut inner = Box::pin(VideoProfileInfoBundle::default());
+
+        let m = unsafe { inner.as_mut().get_unchecked_mut() };
+
+        m.info_h265.std_profile_idc = std_profile_idc;
+
+        m.info.p_next = addr_of!(m.info_h265).cast();
+        m.info.video_codec_operation = VideoCodecOperationFlagsKHR::DECODE_H265;
+        m.info.chroma_subsampling = chroma_subsampling;
+        m.info.luma_bit_depth = luma_bit_depth;
+        m.info.chroma_bit_depth = chroma_bit_depth;
+
+        m.list = VideoProfileListInfoKHR {
+            p_profiles: addr_of!(m.info),
+            profile_count: 1,
+            ..Default::default()
+        };
+
+        Ok(inner)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::{Error, Variant};
+    use crate::video::h265::nal::NalUnitType;
+    use crate::video::h265::testdata::{inspector, nal, SPS};
+    use crate::video::h265::{H265StreamInspector, ParsedNal};
+    use ash::vk::native::StdVideoH265ProfileIdc_STD_VIDEO_H265_PROFILE_IDC_MAIN;
+    use ash::vk::{
+        VideoChromaSubsamplingFlagsKHR, VideoCodecOperationFlagsKHR, VideoComponentBitDepthFlagsKHR, VideoDecodeH265ProfileInfoKHR,
+    };
+
+    #[test]
+    fn get_profile_info_list() -> Result<(), Error> {
+        let mut inspector = H265StreamInspector::new();
+
+        assert!(matches!(
+            inspector.profiles().map(|_| ()).unwrap_err().variant(),
+            Variant::InvalidSps
+        ));
+
+        inspector.feed_nal(&nal(NalUnitType::SPS_NUT, SPS))?;
+
+        let mut profiles = inspector.profiles()?;
+        let infos = unsafe { &mut profiles.as_mut().get_unchecked_mut().list };
+
+        unsafe {
+            let info = &*infos.p_profiles;
+            let info_h265 = &*info.p_next.cast::<VideoDecodeH265ProfileInfoKHR>();
+
+            assert_eq!(infos.profile_count, 1);
+            assert_eq!(info.video_codec_operation, VideoCodecOperationFlagsKHR::DECODE_H265);
+            assert_eq!(info.chroma_subsampling, VideoChromaSubsamplingFlagsKHR::TYPE_420);
+            assert_eq!(info.luma_bit_depth, VideoComponentBitDepthFlagsKHR::TYPE_8);
+            assert_eq!(info_h265.std_profile_idc, StdVideoH265ProfileIdc_STD_VIDEO_H265_PROFILE_IDC_MAIN);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn parses_nal_units() -> Result<(), Error> {
+        let mut inspector = inspector();
+
+        assert_eq!(inspector.std_vps().len(), 1);
+        assert_eq!((inspector.std_sps().len(), inspector.std_pps().len()), (1, 1));
+
+        // An IDR slice, an end of sequence and an AUD.
+        let slice = inspector.feed_nal(&nal(NalUnitType::IDR_W_RADL, "1 0 1 011"))?;
+
+        assert!(matches!(slice, Some(ParsedNal::Slice(x)) if x.nal_unit_type == NalUnitType::IDR_W_RADL));
+        assert!(matches!(
+            inspector.feed_nal(&[0, 0, 1, 0x48, 0x01])?,
+            Some(ParsedNal::EndOfSequence)
+        ));
+        assert!(inspector.feed_nal(&[0, 0, 1, 0x46, 0x01, 0x50])?.is_none());
+
+        // Other layers are ignored, even if they would not parse.
+        assert!(inspector.feed_nal(&[0, 0, 1, 0x42, 0x09, 0xFF])?.is_none());
+
+        // Repeating a parameter set keeps its fingerprint.
+        let fingerprint = inspector.sps_fingerprints()[&0];
+
+        inspector.feed_nal(&nal(NalUnitType::SPS_NUT, SPS))?;
+        assert_eq!(inspector.sps_fingerprints()[&0], fingerprint);
+        assert_eq!(inspector.vps_fingerprints().len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_malformed_nals() {
+        let mut inspector = H265StreamInspector::new();
+
+        let empty = inspector.feed_nal(&[0x00, 0x00, 0x01]).unwrap_err();
+        let short = inspector.feed_nal(&[0x00, 0x00, 0x01, 0x42]).unwrap_err();
+        let truncated_sps = inspector.feed_nal(&[0x00, 0x00, 0x01, 0x42, 0x01, 0x01]).unwrap_err();
+        let unknown_pps = inspector.feed_nal(&nal(NalUnitType::TRAIL_R, "1 1")).unwrap_err();
+
+        assert!(matches!(empty.variant(), Variant::MalformedNalHeader));
+        assert!(matches!(short.variant(), Variant::MalformedNalHeader));
+        assert!(matches!(truncated_sps.variant(), Variant::Bitstream(_) | Variant::InvalidSps));
+        assert!(matches!(unknown_pps.variant(), Variant::MissingParameterSet));
+    }
+
+    #[test]
+    fn survives_garbage() {
+        let mut inspector = inspector();
+        let mut state = 0x2545_F491_u32;
+
+        // Random payloads behind every NAL unit type, with parameter sets present.
+        for i in 0..20_000 {
+            let len = 1 + (i % 64);
+            let mut nal = vec![0, 0, 1, ((i % 64) as u8) << 1, 1];
+
+            for _ in 0..len {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                nal.push(state as u8);
+            }
+
+            _ = inspector.feed_nal(&nal);
+            _ = inspector.std_vps();
+            _ = inspector.std_sps();
+            _ = inspector.std_pps();
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//! Limits derived from `general_level_idc` (Annex A).
+use crate::video::h265::SeqParameterSet;
+use crate::video::SessionRequirements;
+use ash::vk::VideoCodecOperationFlagsKHR;
+
+/// `MaxLumaPs` of Table A.8 for the level of `sps`.
+pub(crate) fn max_luma_ps(sps: &SeqParameterSet) -> u32 {
+    match sps.profile_tier_level.general_level_idc {
+        0..=30 => 36864,
+        31..=60 => 122880,
+        61..=63 => 245760,
+        64..=90 => 552960,
+        91..=93 => 983040,
+        94..=123 => 2228224,
+        124..=156 => 8912896,
+        _ => 35651584,
+    }
+}
+
+/// `MaxDpbSize` (A.4.2), i.e., how many pictures of this size fit into the level's DPB, at most 16.
+pub(crate) fn max_dpb_size(sps: &SeqParameterSet) -> u32 {
+    const MAX_DPB_PIC_BUF: u32 = 6;
+
+    let max_luma_ps = max_luma_ps(sps);
+    let pic_size_in_samples_y = sps.pic_width_in_luma_samples.saturating_mul(sps.pic_height_in_luma_samples);
+
+    let max_dpb_size = if pic_size_in_samples_y <= max_luma_ps >> 2 {
+        4 * MAX_DPB_PIC_BUF
+    } else if pic_size_in_samples_y <= max_luma_ps >> 1 {
+        2 * MAX_DPB_PIC_BUF
+    } else if pic_size_in_samples_y <= (3 * max_luma_ps) >> 2 {
+        (4 * MAX_DPB_PIC_BUF) / 3
+    } else {
+        MAX_DPB_PIC_BUF
+    };
+
+    max_dpb_size.min(16)
+}
+
+impl SessionRequirements {
+    /// What a video session needs to decode a H.265 stream of `sps`.
+    ///
+    /// `sps_max_dec_pic_buffering_minus1 + 1` already counts the picture being decoded.
+    pub fn new_h265(sps: &SeqParameterSet) -> Self {
+        let max_dec_pic_buffering = u32::from(sps.highest_sub_layer_ordering().max_dec_pic_buffering_minus1) + 1;
+        let max_active_reference_pictures = (max_dec_pic_buffering - 1).max(1);
+        let min_dpb_slots = max_active_reference_pictures + 1;
+
+        Self {
+            coded_width: sps.pic_width_in_luma_samples,
+            coded_height: sps.pic_height_in_luma_samples,
+            max_dpb_slots: max_dpb_size(sps).max(min_dpb_slots),
+            min_dpb_slots,
+            max_active_reference_pictures,
+            codec: VideoCodecOperationFlagsKHR::DECODE_H265,
+            profile_idc: sps.profile_tier_level.general_profile_idc,
+            chroma_format_idc: sps.chroma_format_idc,
+            bit_depth_luma_minus8: sps.bit_depth_luma_minus8,
+            bit_depth_chroma_minus8: sps.bit_depth_chroma_minus8,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::max_dpb_size;
+    use crate::video::h265::testdata::sps;
+    use crate::video::SessionRequirements;
+    use ash::vk::VideoCodecOperationFlagsKHR;
+
+    #[test]
+    fn session_requirements_follow_level() {
+        let mut sps = sps();
+        let requirements = SessionRequirements::new_h265(&sps);
+
+        // Level 3.1 and 64x64 pictures, so the DPB is as large as it gets.
+        assert_eq!((requirements.coded_width, requirements.coded_height), (64, 64));
+        assert_eq!(requirements.codec, VideoCodecOperationFlagsKHR::DECODE_H265);
+        assert_eq!(
+            (
+                requirements.max_dpb_slots,
+                requirements.min_dpb_slots,
+                requirements.max_active_reference_pictures
+            ),
+            (16, 3, 2)
+        );
+
+        // Level 1 holds six pictures close to its maximum size, more of smaller ones.
+        sps.profile_tier_level.general_level_idc = 30;
+        sps.pic_width_in_luma_samples = 176;
+        sps.pic_height_in_luma_samples = 176;
+        assert_eq!(max_dpb_size(&sps), 6);
+
+        sps.pic_height_in_luma_samples = 144;
+        assert_eq!(max_dpb_size(&sps), 8);
+
+        sps.pic_height_in_luma_samples = 104;
+        assert_eq!(max_dpb_size(&sps), 12);
+
+        assert!(!requirements.same_format(&SessionRequirements::new_h265(&sps)));
+    }
+}
@@ -0,0 +1,26 @@
+//! Operations related to H.265 codecs.
+mod dpb;
+mod h265inspector;
+mod level;
+mod nal;
+mod output;
+mod poc;
+mod pps;
+mod scaling;
+mod slice;
+mod sps;
+mod stdvideo;
+#[cfg(test)]
+pub(crate) mod testdata;
+mod vps;
+
+pub use dpb::{Dpb, DpbPicture, DpbReference};
+pub use h265inspector::{H265StreamInspector, ParsedNal};
+pub use nal::{NalHeader, NalUnitType};
+pub use output::OutputQueue;
+pub use poc::PocCalculator;
+pub use pps::{PicParameterSet, PpsRangeExtension};
+pub use slice::{LongTermRef, SliceSegmentHeader, SliceType};
+pub use sps::{SeqParameterSet, ShortTermRefPicSet, SpsRangeExtension, VuiParameters};
+pub(crate) use stdvideo::{std_picture_info, std_reference_info, StdPps, StdSps, StdVps};
+pub use vps::{ProfileTierLevel, SubLayerOrdering, VideoParameterSet};
@@ -0,0 +1,133 @@
+//! NAL unit headers (7.3.1.2) and unit types (Table 7-1).
+use crate::error;
+use crate::error::{Error, Variant};
+
+/// The `nal_unit_type` of a NAL unit (Table 7-1).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct NalUnitType(pub u8);
+
+impl NalUnitType {
+    pub const TRAIL_N: Self = Self(0);
+    pub const TRAIL_R: Self = Self(1);
+    pub const TSA_N: Self = Self(2);
+    pub const TSA_R: Self = Self(3);
+    pub const STSA_N: Self = Self(4);
+    pub const STSA_R: Self = Self(5);
+    pub const RADL_N: Self = Self(6);
+    pub const RADL_R: Self = Self(7);
+    pub const RASL_N: Self = Self(8);
+    pub const RASL_R: Self = Self(9);
+    pub const BLA_W_LP: Self = Self(16);
+    pub const BLA_W_RADL: Self = Self(17);
+    pub const BLA_N_LP: Self = Self(18);
+    pub const IDR_W_RADL: Self = Self(19);
+    pub const IDR_N_LP: Self = Self(20);
+    pub const CRA_NUT: Self = Self(21);
+    pub const VPS_NUT: Self = Self(32);
+    pub const SPS_NUT: Self = Self(33);
+    pub const PPS_NUT: Self = Self(34);
+    pub const AUD_NUT: Self = Self(35);
+    pub const EOS_NUT: Self = Self(36);
+    pub const EOB_NUT: Self = Self(37);
+    pub const FD_NUT: Self = Self(38);
+    pub const PREFIX_SEI_NUT: Self = Self(39);
+    pub const SUFFIX_SEI_NUT: Self = Self(40);
+
+    /// If this is a slice segment, including reserved types.
+    pub fn is_vcl(self) -> bool {
+        self.0 < 32
+    }
+
+    /// Intra random access point, i.e., BLA, IDR, CRA or a reserved IRAP type.
+    pub fn is_irap(self) -> bool {
+        (16..=23).contains(&self.0)
+    }
+
+    pub fn is_idr(self) -> bool {
+        self == Self::IDR_W_RADL || self == Self::IDR_N_LP
+    }
+
+    pub fn is_bla(self) -> bool {
+        (16..=18).contains(&self.0)
+    }
+
+    pub fn is_cra(self) -> bool {
+        self == Self::CRA_NUT
+    }
+
+    /// Random access skipped leading picture, which references pictures before its IRAP picture.
+    pub fn is_rasl(self) -> bool {
+        self == Self::RASL_N || self == Self::RASL_R
+    }
+
+    /// Random access decodable leading picture.
+    pub fn is_radl(self) -> bool {
+        self == Self::RADL_N || self == Self::RADL_R
+    }
+
+    /// A sub-layer non-reference picture, which pictures of the same temporal sub-layer never reference.
+    pub fn is_sub_layer_non_reference(self) -> bool {
+        self.0 <= 14 && self.0.is_multiple_of(2)
+    }
+}
+
+/// The two byte header of a NAL unit (7.3.1.2).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct NalHeader {
+    pub nal_unit_type: NalUnitType,
+    pub nuh_layer_id: u8,
+    /// `TemporalId`, i.e., `nuh_temporal_id_plus1 - 1`.
+    pub temporal_id: u8,
+}
+
+impl NalHeader {
+    /// Parses the header at the start of `payload`, a NAL unit without start code.
+    pub fn new(payload: &[u8]) -> Result<Self, Error> {
+        let [first, second, ..] = *payload else {
+            return Err(error!(Variant::MalformedNalHeader, "NAL unit has less than 2 bytes"));
+        };
+
+        if first & 0x80 != 0 {
+            return Err(error!(Variant::MalformedNalHeader, "forbidden_zero_bit set in {:#04x}", first));
+        }
+
+        let nuh_temporal_id_plus1 = second & 0x7;
+
+        if nuh_temporal_id_plus1 == 0 {
+            return Err(error!(Variant::MalformedNalHeader, "nuh_temporal_id_plus1 is 0"));
+        }
+
+        Ok(Self {
+            nal_unit_type: NalUnitType(first >> 1 & 0x3F),
+            nuh_layer_id: (first & 0x1) << 5 | second >> 3,
+            temporal_id: nuh_temporal_id_plus1 - 1,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{NalHeader, NalUnitType};
+    use crate::error::Variant;
+
+    #[test]
+    fn parses_headers() {
+        let sps = NalHeader::new(&[0x42, 0x01]).unwrap();
+        let cra = NalHeader::new(&[0x2A, 0x01]).unwrap();
+        let rasl = NalHeader::new(&[0x10, 0x03]).unwrap();
+
+        assert_eq!(sps.nal_unit_type, NalUnitType::SPS_NUT);
+        assert_eq!((sps.nuh_layer_id, sps.temporal_id), (0, 0));
+        assert!(cra.nal_unit_type.is_irap() && cra.nal_unit_type.is_cra() && !cra.nal_unit_type.is_idr());
+        assert!(rasl.nal_unit_type.is_rasl() && rasl.nal_unit_type.is_sub_layer_non_reference());
+        assert_eq!(rasl.temporal_id, 2);
+
+        let short = NalHeader::new(&[0x40]).unwrap_err();
+        let forbidden = NalHeader::new(&[0xC0, 0x01]).unwrap_err();
+        let temporal_id = NalHeader::new(&[0x40, 0x00]).unwrap_err();
+
+        assert!(matches!(short.variant(), Variant::MalformedNalHeader));
+        assert!(matches!(forbidden.variant(), Variant::MalformedNalHeader));
+        assert!(matches!(temporal_id.variant(), Variant::MalformedNalHeader));
+    }
+}
@@ -0,0 +1,231 @@
+//! Output of decoded pictures in display order, following the bumping process (C.5.2).
+use crate::video::h265::{DpbPicture, SeqParameterSet, SliceSegmentHeader};
+
+/// Holds decoded pictures and releases them in display order.
+///
+/// Pictures are pushed in decoding order together with their slice segment header and [`DpbPicture`], and come out
+/// once `sps_max_num_reorder_pics` later pictures are waiting, or once they waited for `SpsMaxLatencyPictures`
+/// pictures. `T` is whatever the caller uses to represent a decoded picture, e.g., an image or a downloaded buffer.
+///
+/// Pictures with `pic_output_flag` unset are never output, their `frame` is dropped.
+#[derive(Debug)]
+pub struct OutputQueue<T> {
+    // POC, `PicLatencyCount` and picture.
+    pending: Vec<(i32, u32, T)>,
+}
+
+impl<T> Default for OutputQueue<T> {
+    fn default() -> Self {
+        Self { pending: Vec::new() }
+    }
+}
+
+impl<T> OutputQueue<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a decoded picture, returns all pictures that are now ready for display, in display order.
+    pub fn push(&mut self, sps: &SeqParameterSet, slice: &SliceSegmentHeader, picture: &DpbPicture, frame: T) -> Vec<T> {
+        let mut output = Vec::new();
+
+        // An IRAP picture starting a new coded video sequence restarts POC, so everything before it goes first (C.5.2.2).
+        if picture.irap && picture.no_rasl_output_flag {
+            if no_output_of_prior_pics(slice) {
+                self.pending.clear();
+            } else {
+                output.extend(self.flush());
+            }
+        }
+
+        self.bump_while_full(sps, &mut output);
+
+        if slice.pic_output_flag {
+            for (_, latency, _) in &mut self.pending {
+                *latency += 1;
+            }
+
+            self.pending.push((picture.pic_order_cnt, 0, frame));
+        }
+
+        // C.5.2.3, "additional bumping" once the current picture is in the DPB.
+        self.bump_while_full(sps, &mut output);
+
+        output
+    }
+
+    /// Returns all remaining pictures in display order, e.g., at the end of a stream or of a coded video sequence.
+    pub fn flush(&mut self) -> Vec<T> {
+        let mut output = Vec::new();
+
+        while let Some(x) = self.bump() {
+            output.push(x);
+        }
+
+        output
+    }
+
+    /// Number of pictures waiting for output.
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    fn bump_while_full(&mut self, sps: &SeqParameterSet, output: &mut Vec<T>) {
+        let ordering = sps.highest_sub_layer_ordering();
+        let max_num_reorder_pics = usize::from(ordering.max_num_reorder_pics);
+
+        // `SpsMaxLatencyPictures` (7-9), no limit if `sps_max_latency_increase_plus1` is 0.
+        let max_latency_pictures = match ordering.max_latency_increase_plus1 {
+            0 => None,
+            x => Some(u32::from(ordering.max_num_reorder_pics) + x - 1),
+        };
+
+        loop {
+            let too_late = max_latency_pictures.is_some_and(|max| self.pending.iter().any(|(_, latency, _)| *latency >= max));
+
+            if self.pending.len() <= max_num_reorder_pics && !too_late {
+                break;
+            }
+
+            output.extend(self.bump());
+        }
+    }
+
+    /// Removes the picture with the smallest POC, the first one on ties.
+    fn bump(&mut self) -> Option<T> {
+        let index = self
+            .pending
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, (poc, _, _))| *poc)
+            .map(|(i, _)| i)?;
+
+        Some(self.pending.remove(index).2)
+    }
+}
+
+/// `NoOutputOfPriorPicsFlag` of an IRAP picture with `NoRaslOutputFlag` set, i.e., if the pictures waiting for
+/// output are discarded instead of output (C.5.2.2).
+pub(crate) fn no_output_of_prior_pics(slice: &SliceSegmentHeader) -> bool {
+    slice.nal_unit_type.is_cra() || slice.no_output_of_prior_pics_flag
+}
+
+#[cfg(test)]
+mod test {
+    use super::OutputQueue;
+    use crate::video::h265::nal::NalUnitType;
+    use crate::video::h265::testdata::{slice_segment_header, sps};
+    use crate::video::h265::{DpbPicture, SeqParameterSet, SliceSegmentHeader, SubLayerOrdering};
+
+    fn with_ordering(max_num_reorder_pics: u8, max_latency_increase_plus1: u32) -> SeqParameterSet {
+        let mut sps = sps();
+
+        sps.sub_layer_ordering = vec![SubLayerOrdering {
+            max_dec_pic_buffering_minus1: 4,
+            max_num_reorder_pics,
+            max_latency_increase_plus1,
+        }];
+
+        sps
+    }
+
+    fn picture(slice: &SliceSegmentHeader, pic_order_cnt: i32) -> DpbPicture {
+        DpbPicture {
+            slot_index: 0,
+            video_parameter_set_id: 0,
+            seq_parameter_set_id: 0,
+            pic_parameter_set_id: 0,
+            pic_order_cnt,
+            irap: slice.nal_unit_type.is_irap(),
+            idr: slice.nal_unit_type.is_idr(),
+            no_rasl_output_flag: slice.nal_unit_type.is_idr(),
+            reference: true,
+            short_term_ref_pic_set_sps_flag: true,
+            num_delta_pocs_of_ref_rps_idx: 0,
+            num_bits_for_st_ref_pic_set_in_slice: 0,
+            st_curr_before: Vec::new(),
+            st_curr_after: Vec::new(),
+            lt_curr: Vec::new(),
+            display_rect: Default::default(),
+            color_info: Default::default(),
+            references: Vec::new(),
+        }
+    }
+
+    fn push(queue: &mut OutputQueue<i32>, sps: &SeqParameterSet, nal_unit_type: NalUnitType, poc: i32) -> Vec<i32> {
+        let slice = slice_segment_header(nal_unit_type, poc as u32);
+
+        queue.push(sps, &slice, &picture(&slice, poc), poc)
+    }
+
+    #[test]
+    fn reorders_b_pictures() {
+        let sps = with_ordering(1, 0);
+        let mut queue = OutputQueue::new();
+        let mut output = Vec::new();
+
+        // Decode order I0 P4 B2 P8 B6, display order I0 B2 P4 B6 P8.
+        output.extend(push(&mut queue, &sps, NalUnitType::IDR_W_RADL, 0));
+        output.extend(push(&mut queue, &sps, NalUnitType::TRAIL_R, 4));
+        output.extend(push(&mut queue, &sps, NalUnitType::TRAIL_N, 2));
+        output.extend(push(&mut queue, &sps, NalUnitType::TRAIL_R, 8));
+        output.extend(push(&mut queue, &sps, NalUnitType::TRAIL_N, 6));
+
+        assert_eq!(output, vec![0, 2, 4, 6]);
+        assert_eq!(queue.flush(), vec![8]);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn bumps_on_latency() {
+        for (max_latency_increase_plus1, expected) in [(0, vec![2]), (1, vec![2, 4, 16])] {
+            let sps = with_ordering(2, max_latency_increase_plus1);
+            let mut queue = OutputQueue::new();
+            let mut output = Vec::new();
+
+            // Without a limit only the reordering bumps, with `SpsMaxLatencyPictures` 2 picture 16 waited too long.
+            output.extend(push(&mut queue, &sps, NalUnitType::IDR_W_RADL, 16));
+            output.extend(push(&mut queue, &sps, NalUnitType::TRAIL_R, 2));
+            output.extend(push(&mut queue, &sps, NalUnitType::TRAIL_R, 4));
+
+            assert_eq!(output, expected);
+        }
+    }
+
+    #[test]
+    fn irap_flushes_or_discards() {
+        let sps = with_ordering(2, 0);
+        let mut queue = OutputQueue::new();
+
+        push(&mut queue, &sps, NalUnitType::IDR_W_RADL, 0);
+        push(&mut queue, &sps, NalUnitType::TRAIL_R, 4);
+
+        assert_eq!(push(&mut queue, &sps, NalUnitType::IDR_N_LP, 0), vec![0, 4]);
+
+        let no_output = SliceSegmentHeader {
+            no_output_of_prior_pics_flag: true,
+            ..slice_segment_header(NalUnitType::IDR_N_LP, 0)
+        };
+
+        assert!(queue.push(&sps, &no_output, &picture(&no_output, 0), 1).is_empty());
+        assert_eq!(queue.flush(), vec![1]);
+    }
+
+    #[test]
+    fn skips_pictures_not_for_output() {
+        let sps = with_ordering(0, 0);
+        let mut queue = OutputQueue::new();
+        let hidden = SliceSegmentHeader {
+            pic_output_flag: false,
+            ..slice_segment_header(NalUnitType::TRAIL_R, 1)
+        };
+
+        assert_eq!(push(&mut queue, &sps, NalUnitType::IDR_W_RADL, 0), vec![0]);
+        assert!(queue.push(&sps, &hidden, &picture(&hidden, 1), 1).is_empty());
+        assert!(queue.is_empty());
+    }
+}
@@ -0,0 +1,114 @@
+//! Picture order count derivation (8.3.1).
+use crate::video::h265::{SeqParameterSet, SliceSegmentHeader};
+
+/// Computes `PicOrderCntVal` of pictures in decoding order.
+///
+/// The calculator tracks `prevTid0Pic` and whether the next IRAP picture starts a new coded video sequence, i.e.,
+/// is the first picture or follows an end of sequence NAL unit.
+#[derive(Debug)]
+pub struct PocCalculator {
+    // Of `prevTid0Pic`.
+    prev_pic_order_cnt_lsb: i64,
+    prev_pic_order_cnt_msb: i64,
+    new_sequence: bool,
+}
+
+impl Default for PocCalculator {
+    fn default() -> Self {
+        Self {
+            prev_pic_order_cnt_lsb: 0,
+            prev_pic_order_cnt_msb: 0,
+            new_sequence: true,
+        }
+    }
+}
+
+impl PocCalculator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes the next IRAP picture start a new coded video sequence, call this for end of sequence NAL units.
+    pub fn end_of_sequence(&mut self) {
+        self.new_sequence = true;
+    }
+
+    /// `NoRaslOutputFlag` (8.1.3) of the picture starting with `slice`, i.e., if it is an IRAP picture starting a new
+    /// coded video sequence. The RASL pictures following it reference pictures before it and can't be decoded.
+    pub fn no_rasl_output_flag(&self, slice: &SliceSegmentHeader) -> bool {
+        let nal_unit_type = slice.nal_unit_type;
+
+        nal_unit_type.is_irap() && (nal_unit_type.is_idr() || nal_unit_type.is_bla() || self.new_sequence)
+    }
+
+    /// Computes the order count of the picture starting with `slice`, and updates the state for following pictures.
+    pub fn compute(&mut self, sps: &SeqParameterSet, slice: &SliceSegmentHeader) -> i32 {
+        let max_pic_order_cnt_lsb = i64::from(sps.max_pic_order_cnt_lsb());
+        let pic_order_cnt_lsb = i64::from(slice.slice_pic_order_cnt_lsb);
+        let prev_lsb = self.prev_pic_order_cnt_lsb;
+        let prev_msb = self.prev_pic_order_cnt_msb;
+
+        let pic_order_cnt_msb = if self.no_rasl_output_flag(slice) {
+            0
+        } else if pic_order_cnt_lsb < prev_lsb && prev_lsb - pic_order_cnt_lsb >= max_pic_order_cnt_lsb / 2 {
+            prev_msb + max_pic_order_cnt_lsb
+        } else if pic_order_cnt_lsb > prev_lsb && pic_order_cnt_lsb - prev_lsb > max_pic_order_cnt_lsb / 2 {
+            prev_msb - max_pic_order_cnt_lsb
+        } else {
+            prev_msb
+        };
+
+        let nal_unit_type = slice.nal_unit_type;
+        let tid0_pic =
+            slice.temporal_id == 0 && !nal_unit_type.is_rasl() && !nal_unit_type.is_radl() && !nal_unit_type.is_sub_layer_non_reference();
+
+        if tid0_pic {
+            self.prev_pic_order_cnt_lsb = pic_order_cnt_lsb;
+            self.prev_pic_order_cnt_msb = pic_order_cnt_msb;
+        }
+
+        // Streams cut at random points start with pictures that can't be decoded, the sequence starts at the first IRAP.
+        if nal_unit_type.is_irap() {
+            self.new_sequence = false;
+        }
+
+        (pic_order_cnt_msb + pic_order_cnt_lsb) as i32
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PocCalculator;
+    use crate::video::h265::nal::NalUnitType;
+    use crate::video::h265::testdata::{slice_segment_header, sps};
+
+    #[test]
+    fn computes_poc_across_lsb_wraps() {
+        let sps = sps();
+        let mut poc = PocCalculator::new();
+
+        // The SPS has 8 POC LSB bits, so 250 -> 4 wraps around.
+        let cra = slice_segment_header(NalUnitType::CRA_NUT, 250);
+        let trail = slice_segment_header(NalUnitType::TRAIL_R, 4);
+        let rasl = slice_segment_header(NalUnitType::RASL_N, 2);
+
+        assert!(poc.no_rasl_output_flag(&cra));
+        assert_eq!(poc.compute(&sps, &cra), 250);
+        assert_eq!(poc.compute(&sps, &trail), 260);
+        assert_eq!(poc.compute(&sps, &rasl), 258);
+
+        // A CRA picture in the middle of a sequence continues the POC, after an end of sequence it resets it.
+        let cra = slice_segment_header(NalUnitType::CRA_NUT, 8);
+
+        assert!(!poc.no_rasl_output_flag(&cra));
+        assert_eq!(poc.compute(&sps, &cra), 264);
+
+        poc.end_of_sequence();
+
+        assert!(poc.no_rasl_output_flag(&cra));
+        assert_eq!(poc.compute(&sps, &cra), 8);
+
+        // IDR pictures always reset it.
+        assert_eq!(poc.compute(&sps, &slice_segment_header(NalUnitType::IDR_N_LP, 0)), 0);
+    }
+}
@@ -0,0 +1,299 @@
+//! Picture parameter set (7.3.2.3).
+use crate::error;
+use crate::error::{Error, Variant};
+use crate::video::h265::scaling::scaling_list_data;
+use ash::vk::native::StdVideoH265ScalingLists;
+use h264_reader::rbsp::BitRead;
+
+/// The range extension of a PPS (7.3.2.3.2).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PpsRangeExtension {
+    pub log2_max_transform_skip_block_size_minus2: u8,
+    pub cross_component_prediction_enabled_flag: bool,
+    pub chroma_qp_offset_list_enabled_flag: bool,
+    pub diff_cu_chroma_qp_offset_depth: u8,
+    pub chroma_qp_offset_list_len_minus1: u8,
+    /// One entry per list element, up to 6.
+    pub cb_qp_offset_list: Vec<i8>,
+    pub cr_qp_offset_list: Vec<i8>,
+    pub log2_sao_offset_scale_luma: u8,
+    pub log2_sao_offset_scale_chroma: u8,
+}
+
+/// A parsed PPS. Multilayer, 3D and screen content extensions are not parsed.
+#[derive(Clone, Debug)]
+pub struct PicParameterSet {
+    pub pps_pic_parameter_set_id: u8,
+    pub pps_seq_parameter_set_id: u8,
+    pub dependent_slice_segments_enabled_flag: bool,
+    pub output_flag_present_flag: bool,
+    pub num_extra_slice_header_bits: u8,
+    pub sign_data_hiding_enabled_flag: bool,
+    pub cabac_init_present_flag: bool,
+    pub num_ref_idx_l0_default_active_minus1: u8,
+    pub num_ref_idx_l1_default_active_minus1: u8,
+    pub init_qp_minus26: i8,
+    pub constrained_intra_pred_flag: bool,
+    pub transform_skip_enabled_flag: bool,
+    pub cu_qp_delta_enabled_flag: bool,
+    pub diff_cu_qp_delta_depth: u8,
+    pub pps_cb_qp_offset: i8,
+    pub pps_cr_qp_offset: i8,
+    pub pps_slice_chroma_qp_offsets_present_flag: bool,
+    pub weighted_pred_flag: bool,
+    pub weighted_bipred_flag: bool,
+    pub transquant_bypass_enabled_flag: bool,
+    pub tiles_enabled_flag: bool,
+    pub entropy_coding_sync_enabled_flag: bool,
+    pub num_tile_columns_minus1: u8,
+    pub num_tile_rows_minus1: u8,
+    pub uniform_spacing_flag: bool,
+    /// Only present without uniform spacing, one entry less than there are columns.
+    pub column_width_minus1: Vec<u16>,
+    pub row_height_minus1: Vec<u16>,
+    pub loop_filter_across_tiles_enabled_flag: bool,
+    pub pps_loop_filter_across_slices_enabled_flag: bool,
+    pub deblocking_filter_control_present_flag: bool,
+    pub deblocking_filter_override_enabled_flag: bool,
+    pub pps_deblocking_filter_disabled_flag: bool,
+    pub pps_beta_offset_div2: i8,
+    pub pps_tc_offset_div2: i8,
+    pub pps_scaling_list_data_present_flag: bool,
+    /// Transmitted lists if `pps_scaling_list_data_present_flag` is set, otherwise the SPS lists apply.
+    pub scaling_lists: Option<StdVideoH265ScalingLists>,
+    pub lists_modification_present_flag: bool,
+    pub log2_parallel_merge_level_minus2: u8,
+    pub slice_segment_header_extension_present_flag: bool,
+    pub pps_extension_present_flag: bool,
+    pub range_extension: Option<PpsRangeExtension>,
+}
+
+impl PicParameterSet {
+    /// Parses a PPS from its RBSP.
+    pub fn from_bits<R: BitRead>(mut r: R) -> Result<Self, Error> {
+        let pps_pic_parameter_set_id = read_ue_max(&mut r, "pps_pic_parameter_set_id", 63)?;
+        let pps_seq_parameter_set_id = read_ue_max(&mut r, "pps_seq_parameter_set_id", 15)?;
+        let dependent_slice_segments_enabled_flag = r.read_bool("dependent_slice_segments_enabled_flag")?;
+        let output_flag_present_flag = r.read_bool("output_flag_present_flag")?;
+        let num_extra_slice_header_bits = r.read_u8(3, "num_extra_slice_header_bits")?;
+        let sign_data_hiding_enabled_flag = r.read_bool("sign_data_hiding_enabled_flag")?;
+        let cabac_init_present_flag = r.read_bool("cabac_init_present_flag")?;
+        let num_ref_idx_l0_default_active_minus1 = read_ue_max(&mut r, "num_ref_idx_l0_default_active_minus1", 14)?;
+        let num_ref_idx_l1_default_active_minus1 = read_ue_max(&mut r, "num_ref_idx_l1_default_active_minus1", 14)?;
+        let init_qp_minus26 = read_se_range(&mut r, "init_qp_minus26", -62, 25)?;
+        let constrained_intra_pred_flag = r.read_bool("constrained_intra_pred_flag")?;
+        let transform_skip_enabled_flag = r.read_bool("transform_skip_enabled_flag")?;
+        let cu_qp_delta_enabled_flag = r.read_bool("cu_qp_delta_enabled_flag")?;
+        let mut diff_cu_qp_delta_depth = 0;
+
+        if cu_qp_delta_enabled_flag {
+            diff_cu_qp_delta_depth = read_ue_max(&mut r, "diff_cu_qp_delta_depth", 3)?;
+        }
+
+        let pps_cb_qp_offset = read_se_range(&mut r, "pps_cb_qp_offset", -12, 12)?;
+        let pps_cr_qp_offset = read_se_range(&mut r, "pps_cr_qp_offset", -12, 12)?;
+        let pps_slice_chroma_qp_offsets_present_flag = r.read_bool("pps_slice_chroma_qp_offsets_present_flag")?;
+        let weighted_pred_flag = r.read_bool("weighted_pred_flag")?;
+        let weighted_bipred_flag = r.read_bool("weighted_bipred_flag")?;
+        let transquant_bypass_enabled_flag = r.read_bool("transquant_bypass_enabled_flag")?;
+        let tiles_enabled_flag = r.read_bool("tiles_enabled_flag")?;
+        let entropy_coding_sync_enabled_flag = r.read_bool("entropy_coding_sync_enabled_flag")?;
+        let mut num_tile_columns_minus1 = 0;
+        let mut num_tile_rows_minus1 = 0;
+        let mut uniform_spacing_flag = true;
+        let mut column_width_minus1 = Vec::new();
+        let mut row_height_minus1 = Vec::new();
+        let mut loop_filter_across_tiles_enabled_flag = true;
+
+        if tiles_enabled_flag {
+            // Vulkan has room for 20 columns and 22 rows.
+            num_tile_columns_minus1 = read_ue_max(&mut r, "num_tile_columns_minus1", 19)?;
+            num_tile_rows_minus1 = read_ue_max(&mut r, "num_tile_rows_minus1", 21)?;
+            uniform_spacing_flag = r.read_bool("uniform_spacing_flag")?;
+
+            if !uniform_spacing_flag {
+                for _ in 0..num_tile_columns_minus1 {
+                    column_width_minus1.push(read_ue_max(&mut r, "column_width_minus1", u16::MAX)?);
+                }
+
+                for _ in 0..num_tile_rows_minus1 {
+                    row_height_minus1.push(read_ue_max(&mut r, "row_height_minus1", u16::MAX)?);
+                }
+            }
+
+            loop_filter_across_tiles_enabled_flag = r.read_bool("loop_filter_across_tiles_enabled_flag")?;
+        }
+
+        let pps_loop_filter_across_slices_enabled_flag = r.read_bool("pps_loop_filter_across_slices_enabled_flag")?;
+        let deblocking_filter_control_present_flag = r.read_bool("deblocking_filter_control_present_flag")?;
+        let mut deblocking_filter_override_enabled_flag = false;
+        let mut pps_deblocking_filter_disabled_flag = false;
+        let mut pps_beta_offset_div2 = 0;
+        let mut pps_tc_offset_div2 = 0;
+
+        if deblocking_filter_control_present_flag {
+            deblocking_filter_override_enabled_flag = r.read_bool("deblocking_filter_override_enabled_flag")?;
+            pps_deblocking_filter_disabled_flag = r.read_bool("pps_deblocking_filter_disabled_flag")?;
+
+            if !pps_deblocking_filter_disabled_flag {
+                pps_beta_offset_div2 = read_se_range(&mut r, "pps_beta_offset_div2", -6, 6)?;
+                pps_tc_offset_div2 = read_se_range(&mut r, "pps_tc_offset_div2", -6, 6)?;
+            }
+        }
+
+        let pps_scaling_list_data_present_flag = r.read_bool("pps_scaling_list_data_present_flag")?;
+        let scaling_lists = match pps_scaling_list_data_present_flag {
+            true => Some(scaling_list_data(&mut r, Variant::InvalidPps)?),
+            false => None,
+        };
+
+        let lists_modification_present_flag = r.read_bool("lists_modification_present_flag")?;
+        let log2_parallel_merge_level_minus2 = read_ue_max(&mut r, "log2_parallel_merge_level_minus2", 4)?;
+        let slice_segment_header_extension_present_flag = r.read_bool("slice_segment_header_extension_present_flag")?;
+        let pps_extension_present_flag = r.read_bool("pps_extension_present_flag")?;
+        let mut range_extension = None;
+
+        if pps_extension_present_flag {
+            let pps_range_extension_flag = r.read_bool("pps_range_extension_flag")?;
+            let _pps_multilayer_extension_flag = r.read_bool("pps_multilayer_extension_flag")?;
+            let _pps_3d_extension_flag = r.read_bool("pps_3d_extension_flag")?;
+            let _pps_scc_extension_flag = r.read_bool("pps_scc_extension_flag")?;
+            let _pps_extension_4bits = r.read_u8(4, "pps_extension_4bits")?;
+
+            if pps_range_extension_flag {
+                range_extension = Some(PpsRangeExtension::from_bits(&mut r, transform_skip_enabled_flag)?);
+            }
+        }
+
+        Ok(Self {
+            pps_pic_parameter_set_id,
+            pps_seq_parameter_set_id,
+            dependent_slice_segments_enabled_flag,
+            output_flag_present_flag,
+            num_extra_slice_header_bits,
+            sign_data_hiding_enabled_flag,
+            cabac_init_present_flag,
+            num_ref_idx_l0_default_active_minus1,
+            num_ref_idx_l1_default_active_minus1,
+            init_qp_minus26,
+            constrained_intra_pred_flag,
+            transform_skip_enabled_flag,
+            cu_qp_delta_enabled_flag,
+            diff_cu_qp_delta_depth,
+            pps_cb_qp_offset,
+            pps_cr_qp_offset,
+            pps_slice_chroma_qp_offsets_present_flag,
+            weighted_pred_flag,
+            weighted_bipred_flag,
+            transquant_bypass_enabled_flag,
+            tiles_enabled_flag,
+            entropy_coding_sync_enabled_flag,
+            num_tile_columns_minus1,
+            num_tile_rows_minus1,
+            uniform_spacing_flag,
+            column_width_minus1,
+            row_height_minus1,
+            loop_filter_across_tiles_enabled_flag,
+            pps_loop_filter_across_slices_enabled_flag,
+            deblocking_filter_control_present_flag,
+            deblocking_filter_override_enabled_flag,
+            pps_deblocking_filter_disabled_flag,
+            pps_beta_offset_div2,
+            pps_tc_offset_div2,
+            pps_scaling_list_data_present_flag,
+            scaling_lists,
+            lists_modification_present_flag,
+            log2_parallel_merge_level_minus2,
+            slice_segment_header_extension_present_flag,
+            pps_extension_present_flag,
+            range_extension,
+        })
+    }
+}
+
+impl PpsRangeExtension {
+    fn from_bits<R: BitRead>(r: &mut R, transform_skip_enabled_flag: bool) -> Result<Self, Error> {
+        let mut extension = Self::default();
+
+        if transform_skip_enabled_flag {
+            extension.log2_max_transform_skip_block_size_minus2 = read_ue_max(r, "log2_max_transform_skip_block_size_minus2", 3)?;
+        }
+
+        extension.cross_component_prediction_enabled_flag = r.read_bool("cross_component_prediction_enabled_flag")?;
+        extension.chroma_qp_offset_list_enabled_flag = r.read_bool("chroma_qp_offset_list_enabled_flag")?;
+
+        if extension.chroma_qp_offset_list_enabled_flag {
+            extension.diff_cu_chroma_qp_offset_depth = read_ue_max(r, "diff_cu_chroma_qp_offset_depth", 3)?;
+            extension.chroma_qp_offset_list_len_minus1 = read_ue_max(r, "chroma_qp_offset_list_len_minus1", 5)?;
+
+            for _ in 0..=extension.chroma_qp_offset_list_len_minus1 {
+                extension.cb_qp_offset_list.push(read_se_range(r, "cb_qp_offset_list", -12, 12)?);
+                extension.cr_qp_offset_list.push(read_se_range(r, "cr_qp_offset_list", -12, 12)?);
+            }
+        }
+
+        extension.log2_sao_offset_scale_luma = read_ue_max(r, "log2_sao_offset_scale_luma", 6)?;
+        extension.log2_sao_offset_scale_chroma = read_ue_max(r, "log2_sao_offset_scale_chroma", 6)?;
+
+        Ok(extension)
+    }
+}
+
+/// Reads `ue(v)` and checks it against its maximum value.
+fn read_ue_max<R: BitRead, T: TryFrom<u32> + Into<u32>>(r: &mut R, name: &'static str, max: T) -> Result<T, Error> {
+    let x = r.read_ue(name)?;
+
+    if x > max.into() {
+        return Err(error!(Variant::InvalidPps, "Invalid {} {}", name, x));
+    }
+
+    T::try_from(x).map_err(|_| error!(Variant::InvalidPps, "Invalid {} {}", name, x))
+}
+
+/// Reads `se(v)` and checks it against its range.
+fn read_se_range<R: BitRead>(r: &mut R, name: &'static str, min: i8, max: i8) -> Result<i8, Error> {
+    let x = r.read_se(name)?;
+
+    if x < i32::from(min) || x > i32::from(max) {
+        return Err(error!(Variant::InvalidPps, "Invalid {} {}", name, x));
+    }
+
+    Ok(x as i8)
+}
+
+#[cfg(test)]
+mod test {
+    use super::PicParameterSet;
+    use crate::error::Variant;
+    use crate::video::h265::testdata::{rbsp, PPS};
+    use h264_reader::rbsp::BitReader;
+
+    #[test]
+    fn parses_pps() {
+        let pps = PicParameterSet::from_bits(BitReader::new(&rbsp(PPS)[..])).unwrap();
+
+        assert_eq!((pps.pps_pic_parameter_set_id, pps.pps_seq_parameter_set_id), (0, 0));
+        assert_eq!(pps.init_qp_minus26, 0);
+        assert!(pps.pps_loop_filter_across_slices_enabled_flag);
+        assert!(!pps.tiles_enabled_flag && pps.uniform_spacing_flag);
+        assert!(pps.range_extension.is_none());
+    }
+
+    #[test]
+    fn parses_tiles() {
+        let pps = |columns: &str| format!("1 1 0 0 000 0 0 1 1 1 0 0 0 1 1 0 0 0 0 1 0 {columns} 1 0 0 0 1 0 0");
+
+        // 2 columns of 3 and 4 CTBs, 1 row.
+        let tiles = PicParameterSet::from_bits(BitReader::new(&rbsp(&pps("010 1 0 011 1"))[..])).unwrap();
+
+        assert!(tiles.tiles_enabled_flag && !tiles.uniform_spacing_flag);
+        assert_eq!((tiles.num_tile_columns_minus1, tiles.num_tile_rows_minus1), (1, 0));
+        assert_eq!(tiles.column_width_minus1, vec![2]);
+        assert!(tiles.row_height_minus1.is_empty());
+
+        // 21 columns don't fit into Vulkan's parameters.
+        let error = PicParameterSet::from_bits(BitReader::new(&rbsp(&pps("000010101 1 1"))[..])).unwrap_err();
+
+        assert!(matches!(error.variant(), Variant::InvalidPps));
+    }
+}
@@ -0,0 +1,175 @@
+//! Scaling list parsing (7.3.4) and the default lists (Table 7-5, 7-6).
+use crate::error;
+use crate::error::{Error, Variant};
+use ash::vk::native::StdVideoH265ScalingLists;
+use h264_reader::rbsp::BitRead;
+
+/// Default 8x8 intra list, also the base of 16x16 and 32x32 intra lists (Table 7-6).
+const DEFAULT_INTRA: [u8; 64] = [
+    16, 16, 16, 16, 16, 16, 16, 16, 16, 16, 17, 16, 17, 16, 17, 18, 17, 18, 18, 17, 18, 21, 19, 20, 21, 20, 19, 21, 24, 22, 22, 24, 24, 22,
+    22, 24, 25, 25, 27, 30, 27, 25, 25, 29, 31, 35, 35, 31, 29, 36, 41, 44, 41, 36, 47, 54, 54, 47, 65, 70, 65, 88, 88, 115,
+];
+
+/// Default 8x8 inter list, also the base of 16x16 and 32x32 inter lists (Table 7-6).
+const DEFAULT_INTER: [u8; 64] = [
+    16, 16, 16, 16, 16, 16, 16, 16, 16, 16, 17, 17, 17, 17, 17, 18, 18, 18, 18, 18, 18, 20, 20, 20, 20, 20, 20, 20, 24, 24, 24, 24, 24, 24,
+    24, 24, 25, 25, 25, 25, 25, 25, 25, 28, 28, 28, 28, 28, 28, 33, 33, 33, 33, 33, 41, 41, 41, 41, 54, 54, 54, 71, 71, 91,
+];
+
+/// The lists used when `scaling_list_enabled_flag` is set but no lists are transmitted.
+pub(crate) fn default_scaling_lists() -> StdVideoH265ScalingLists {
+    let default = |matrix_id: usize| if matrix_id < 3 { DEFAULT_INTRA } else { DEFAULT_INTER };
+
+    StdVideoH265ScalingLists {
+        ScalingList4x4: [[16; 16]; 6],
+        ScalingList8x8: std::array::from_fn(default),
+        ScalingList16x16: std::array::from_fn(default),
+        ScalingList32x32: [DEFAULT_INTRA, DEFAULT_INTER],
+        ScalingListDCCoef16x16: [16; 6],
+        ScalingListDCCoef32x32: [16; 2],
+    }
+}
+
+/// Reads `scaling_list_data()` (7.3.4) into its Vulkan representation.
+///
+/// List values are stored in the order they appear in the bitstream (up-right diagonal scan), which is what
+/// Vulkan expects. The 32x32 lists only exist for matrices 0 and 3, i.e., luma.
+pub(crate) fn scaling_list_data<R: BitRead>(r: &mut R, invalid: Variant) -> Result<StdVideoH265ScalingLists, Error> {
+    let mut lists = default_scaling_lists();
+
+    for size_id in 0..4 {
+        let step = if size_id == 3 { 3 } else { 1 };
+
+        for matrix_id in (0..6).step_by(step) {
+            let pred_mode_flag = r.read_bool("scaling_list_pred_mode_flag")?;
+
+            if !pred_mode_flag {
+                let delta = r.read_ue("scaling_list_pred_matrix_id_delta")? as usize * step;
+
+                if delta > matrix_id {
+                    return Err(error!(
+                        invalid,
+                        "Invalid scaling_list_pred_matrix_id_delta for matrix {}", matrix_id
+                    ));
+                }
+
+                // A delta of 0 selects the default list, anything else copies an earlier list of the same size.
+                let (list, dc) = match delta {
+                    0 => (default_list(size_id, matrix_id), 16),
+                    _ => (
+                        get_list(&lists, size_id, matrix_id - delta),
+                        get_dc(&lists, size_id, matrix_id - delta),
+                    ),
+                };
+
+                set_list(&mut lists, size_id, matrix_id, &list, dc);
+                continue;
+            }
+
+            let coef_num = (1usize << (4 + (size_id << 1))).min(64);
+            let mut next_coef = 8;
+            let mut dc = 16;
+
+            if size_id > 1 {
+                let dc_coef_minus8 = r.read_se("scaling_list_dc_coef_minus8")?;
+
+                if !(-7..=247).contains(&dc_coef_minus8) {
+                    return Err(error!(invalid, "Invalid scaling_list_dc_coef_minus8 {}", dc_coef_minus8));
+                }
+
+                next_coef = dc_coef_minus8 + 8;
+                dc = next_coef as u8;
+            }
+
+            let mut list = [0; 64];
+
+            for value in list.iter_mut().take(coef_num) {
+                let delta_coef = r.read_se("scaling_list_delta_coef")?;
+
+                if !(-128..=127).contains(&delta_coef) {
+                    return Err(error!(invalid, "Invalid scaling_list_delta_coef {}", delta_coef));
+                }
+
+                next_coef = (next_coef + delta_coef + 256).rem_euclid(256);
+                *value = next_coef as u8;
+            }
+
+            set_list(&mut lists, size_id, matrix_id, &list[..coef_num], dc);
+        }
+    }
+
+    Ok(lists)
+}
+
+fn default_list(size_id: usize, matrix_id: usize) -> Vec<u8> {
+    match (size_id, matrix_id) {
+        (0, _) => vec![16; 16],
+        (_, 0..=2) => DEFAULT_INTRA.to_vec(),
+        _ => DEFAULT_INTER.to_vec(),
+    }
+}
+
+fn get_list(lists: &StdVideoH265ScalingLists, size_id: usize, matrix_id: usize) -> Vec<u8> {
+    match size_id {
+        0 => lists.ScalingList4x4[matrix_id].to_vec(),
+        1 => lists.ScalingList8x8[matrix_id].to_vec(),
+        2 => lists.ScalingList16x16[matrix_id].to_vec(),
+        _ => lists.ScalingList32x32[matrix_id / 3].to_vec(),
+    }
+}
+
+fn get_dc(lists: &StdVideoH265ScalingLists, size_id: usize, matrix_id: usize) -> u8 {
+    match size_id {
+        2 => lists.ScalingListDCCoef16x16[matrix_id],
+        3 => lists.ScalingListDCCoef32x32[matrix_id / 3],
+        _ => 16,
+    }
+}
+
+fn set_list(lists: &mut StdVideoH265ScalingLists, size_id: usize, matrix_id: usize, list: &[u8], dc: u8) {
+    match size_id {
+        0 => lists.ScalingList4x4[matrix_id].copy_from_slice(list),
+        1 => lists.ScalingList8x8[matrix_id].copy_from_slice(list),
+        2 => {
+            lists.ScalingList16x16[matrix_id].copy_from_slice(list);
+            lists.ScalingListDCCoef16x16[matrix_id] = dc;
+        }
+        _ => {
+            lists.ScalingList32x32[matrix_id / 3].copy_from_slice(list);
+            lists.ScalingListDCCoef32x32[matrix_id / 3] = dc;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{scaling_list_data, DEFAULT_INTER, DEFAULT_INTRA};
+    use crate::error::Variant;
+    use crate::video::h265::testdata::rbsp;
+    use h264_reader::rbsp::BitReader;
+
+    #[test]
+    fn reads_scaling_lists() {
+        // 4x4 matrix 0 explicit with all deltas +1 (9, 10, ...), matrix 1 copies matrix 0, all others use the defaults.
+        let mut bits = String::from("1");
+        bits.push_str(&"010 ".repeat(16));
+        bits.push_str("0 010 ");
+        bits.push_str(&"0 1 ".repeat(4));
+        bits.push_str(&"0 1 ".repeat(6 + 6 + 2));
+
+        let lists = scaling_list_data(&mut BitReader::new(&rbsp(&bits)[..]), Variant::InvalidSps).unwrap();
+
+        assert_eq!(lists.ScalingList4x4[0][..3], [9, 10, 11]);
+        assert_eq!(lists.ScalingList4x4[1], lists.ScalingList4x4[0]);
+        assert_eq!(lists.ScalingList4x4[2], [16; 16]);
+        assert_eq!(lists.ScalingList8x8[0], DEFAULT_INTRA);
+        assert_eq!(lists.ScalingList16x16[3], DEFAULT_INTER);
+        assert_eq!(lists.ScalingList32x32[1], DEFAULT_INTER);
+        assert_eq!(lists.ScalingListDCCoef32x32, [16, 16]);
+
+        // Matrix 0 cannot copy from a matrix before it.
+        let error = scaling_list_data(&mut BitReader::new(&rbsp("0 010")[..]), Variant::InvalidSps).unwrap_err();
+
+        assert!(matches!(error.variant(), Variant::InvalidSps));
+    }
+}
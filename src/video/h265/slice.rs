@@ -0,0 +1,334 @@
+//! Slice segment header parsing (7.3.6.1), up to what decoding with Vulkan needs.
+use crate::error;
+use crate::error::{Error, Variant};
+use crate::video::h265::nal::{NalHeader, NalUnitType};
+use crate::video::h265::pps::PicParameterSet;
+use crate::video::h265::sps::{SeqParameterSet, ShortTermRefPicSet};
+use h264_reader::rbsp::BitRead;
+use std::collections::HashMap;
+
+/// The type of a slice (Table 7-7).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SliceType {
+    B,
+    P,
+    I,
+}
+
+impl SliceType {
+    fn from_id(id: u32) -> Option<Self> {
+        match id {
+            0 => Some(Self::B),
+            1 => Some(Self::P),
+            2 => Some(Self::I),
+            _ => None,
+        }
+    }
+}
+
+/// A long-term reference picture entry of a slice header, resolved against the SPS candidates.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LongTermRef {
+    /// `PocLsbLt`, the POC LSBs of the referenced picture.
+    pub poc_lsb_lt: u32,
+    /// `UsedByCurrPicLt`.
+    pub used_by_curr_pic_lt_flag: bool,
+    pub delta_poc_msb_present_flag: bool,
+    /// `DeltaPocMsbCycleLt`, i.e., accumulated over the entries as in (7-52).
+    pub delta_poc_msb_cycle_lt: u32,
+}
+
+/// A parsed slice segment header, resolved against the SPS and PPS it refers to.
+///
+/// Parsing stops after `slice_temporal_mvp_enabled_flag`, everything after it is only needed to decode slice data.
+/// Dependent slice segments inherit everything after `slice_segment_address` from the segment before them, these
+/// fields are left at their defaults.
+#[derive(Clone, Debug)]
+pub struct SliceSegmentHeader {
+    pub nal_unit_type: NalUnitType,
+    pub temporal_id: u8,
+    pub first_slice_segment_in_pic_flag: bool,
+    pub no_output_of_prior_pics_flag: bool,
+    pub slice_pic_parameter_set_id: u8,
+    pub slice_seq_parameter_set_id: u8,
+    pub dependent_slice_segment_flag: bool,
+    pub slice_segment_address: u32,
+    pub slice_type: SliceType,
+    pub pic_output_flag: bool,
+    pub colour_plane_id: u8,
+    pub slice_pic_order_cnt_lsb: u32,
+    pub short_term_ref_pic_set_sps_flag: bool,
+    pub short_term_ref_pic_set_idx: u8,
+    /// The set coded in the slice header, if `short_term_ref_pic_set_sps_flag` is unset in a non-IDR picture.
+    pub short_term_ref_pic_set: Option<ShortTermRefPicSet>,
+    /// The bits taken by `short_term_ref_pic_set`, i.e., Vulkan's `NumBitsForSTRefPicSetInSlice`.
+    pub num_bits_for_st_ref_pic_set_in_slice: u32,
+    pub num_long_term_sps: u8,
+    pub long_term_refs: Vec<LongTermRef>,
+    pub slice_temporal_mvp_enabled_flag: bool,
+}
+
+impl SliceSegmentHeader {
+    /// Parses the header of a slice segment NAL unit with the given `header`, `r` reading its RBSP.
+    pub(crate) fn from_bits<R: BitRead>(
+        header: NalHeader,
+        mut r: R,
+        sps: &HashMap<u8, SeqParameterSet>,
+        pps: &HashMap<u8, PicParameterSet>,
+    ) -> Result<Self, Error> {
+        let nal_unit_type = header.nal_unit_type;
+        let first_slice_segment_in_pic_flag = r.read_bool("first_slice_segment_in_pic_flag")?;
+        let no_output_of_prior_pics_flag = nal_unit_type.is_irap() && r.read_bool("no_output_of_prior_pics_flag")?;
+        let slice_pic_parameter_set_id = r.read_ue("slice_pic_parameter_set_id")?;
+
+        let pps = u8::try_from(slice_pic_parameter_set_id)
+            .ok()
+            .and_then(|x| pps.get(&x))
+            .ok_or_else(|| error!(Variant::MissingParameterSet, "Unknown PPS {}", slice_pic_parameter_set_id))?;
+
+        let sps = sps
+            .get(&pps.pps_seq_parameter_set_id)
+            .ok_or_else(|| error!(Variant::MissingParameterSet, "Unknown SPS {}", pps.pps_seq_parameter_set_id))?;
+
+        let mut slice = Self {
+            nal_unit_type,
+            temporal_id: header.temporal_id,
+            first_slice_segment_in_pic_flag,
+            no_output_of_prior_pics_flag,
+            slice_pic_parameter_set_id: pps.pps_pic_parameter_set_id,
+            slice_seq_parameter_set_id: sps.sps_seq_parameter_set_id,
+            dependent_slice_segment_flag: false,
+            slice_segment_address: 0,
+            slice_type: SliceType::I,
+            pic_output_flag: true,
+            colour_plane_id: 0,
+            slice_pic_order_cnt_lsb: 0,
+            short_term_ref_pic_set_sps_flag: false,
+            short_term_ref_pic_set_idx: 0,
+            short_term_ref_pic_set: None,
+            num_bits_for_st_ref_pic_set_in_slice: 0,
+            num_long_term_sps: 0,
+            long_term_refs: Vec::new(),
+            slice_temporal_mvp_enabled_flag: false,
+        };
+
+        if !first_slice_segment_in_pic_flag {
+            if pps.dependent_slice_segments_enabled_flag {
+                slice.dependent_slice_segment_flag = r.read_bool("dependent_slice_segment_flag")?;
+            }
+
+            let bits = ceil_log2(sps.pic_size_in_ctbs_y());
+            slice.slice_segment_address = r.read_u32(bits, "slice_segment_address")?;
+
+            if slice.slice_segment_address >= sps.pic_size_in_ctbs_y() {
+                return Err(error!(
+                    Variant::InvalidSliceHeader,
+                    "Invalid slice_segment_address {}", slice.slice_segment_address
+                ));
+            }
+        }
+
+        if slice.dependent_slice_segment_flag {
+            return Ok(slice);
+        }
+
+        for _ in 0..pps.num_extra_slice_header_bits {
+            let _slice_reserved_flag = r.read_bool("slice_reserved_flag")?;
+        }
+
+        let slice_type_id = r.read_ue("slice_type")?;
+        slice.slice_type =
+            SliceType::from_id(slice_type_id).ok_or_else(|| error!(Variant::InvalidSliceHeader, "Invalid slice_type {}", slice_type_id))?;
+
+        if pps.output_flag_present_flag {
+            slice.pic_output_flag = r.read_bool("pic_output_flag")?;
+        }
+
+        if sps.separate_colour_plane_flag {
+            slice.colour_plane_id = r.read_u8(2, "colour_plane_id")?;
+        }
+
+        if nal_unit_type.is_idr() {
+            return Ok(slice);
+        }
+
+        let log2_max_pic_order_cnt_lsb = u32::from(sps.log2_max_pic_order_cnt_lsb_minus4) + 4;
+        let num_short_term_ref_pic_sets = sps.short_term_ref_pic_sets.len();
+
+        slice.slice_pic_order_cnt_lsb = r.read_u32(log2_max_pic_order_cnt_lsb, "slice_pic_order_cnt_lsb")?;
+        slice.short_term_ref_pic_set_sps_flag = r.read_bool("short_term_ref_pic_set_sps_flag")?;
+
+        if !slice.short_term_ref_pic_set_sps_flag {
+            let (set, bits) = ShortTermRefPicSet::from_bits(
+                &mut r,
+                num_short_term_ref_pic_sets,
+                num_short_term_ref_pic_sets,
+                &sps.short_term_ref_pic_sets,
+                Variant::InvalidSliceHeader,
+            )?;
+
+            slice.short_term_ref_pic_set = Some(set);
+            slice.num_bits_for_st_ref_pic_set_in_slice = bits;
+        } else if num_short_term_ref_pic_sets == 0 {
+            return Err(error!(Variant::InvalidSliceHeader, "SPS has no short-term reference picture sets"));
+        } else if num_short_term_ref_pic_sets > 1 {
+            let bits = ceil_log2(num_short_term_ref_pic_sets as u32);
+            slice.short_term_ref_pic_set_idx = r.read_u8(bits, "short_term_ref_pic_set_idx")?;
+
+            if usize::from(slice.short_term_ref_pic_set_idx) >= num_short_term_ref_pic_sets {
+                return Err(error!(
+                    Variant::InvalidSliceHeader,
+                    "Invalid short_term_ref_pic_set_idx {}", slice.short_term_ref_pic_set_idx
+                ));
+            }
+        }
+
+        if sps.long_term_ref_pics_present_flag {
+            let num_long_term_ref_pics_sps = sps.lt_ref_pic_poc_lsb_sps.len();
+
+            if num_long_term_ref_pics_sps > 0 {
+                let num_long_term_sps = r.read_ue("num_long_term_sps")?;
+
+                if num_long_term_sps as usize > num_long_term_ref_pics_sps {
+                    return Err(error!(
+                        Variant::InvalidSliceHeader,
+                        "Invalid num_long_term_sps {}", num_long_term_sps
+                    ));
+                }
+
+                slice.num_long_term_sps = num_long_term_sps as u8;
+            }
+
+            let num_long_term_pics = r.read_ue("num_long_term_pics")?;
+
+            if u32::from(slice.num_long_term_sps) + num_long_term_pics > 16 {
+                return Err(error!(
+                    Variant::InvalidSliceHeader,
+                    "Invalid num_long_term_pics {}", num_long_term_pics
+                ));
+            }
+
+            let mut delta_poc_msb_cycle_lt = 0;
+
+            for i in 0..u32::from(slice.num_long_term_sps) + num_long_term_pics {
+                let (poc_lsb_lt, used_by_curr_pic_lt_flag) = if i < u32::from(slice.num_long_term_sps) {
+                    let mut lt_idx_sps = 0;
+
+                    if num_long_term_ref_pics_sps > 1 {
+                        lt_idx_sps = r.read_u32(ceil_log2(num_long_term_ref_pics_sps as u32), "lt_idx_sps")? as usize;
+                    }
+
+                    let Some(poc_lsb_lt) = sps.lt_ref_pic_poc_lsb_sps.get(lt_idx_sps) else {
+                        return Err(error!(Variant::InvalidSliceHeader, "Invalid lt_idx_sps {}", lt_idx_sps));
+                    };
+
+                    (*poc_lsb_lt, sps.used_by_curr_pic_lt_sps_flag[lt_idx_sps])
+                } else {
+                    let poc_lsb_lt = r.read_u32(log2_max_pic_order_cnt_lsb, "poc_lsb_lt")?;
+                    (poc_lsb_lt, r.read_bool("used_by_curr_pic_lt_flag")?)
+                };
+
+                let delta_poc_msb_present_flag = r.read_bool("delta_poc_msb_present_flag")?;
+                let cycle = match delta_poc_msb_present_flag {
+                    true => r.read_ue("delta_poc_msb_cycle_lt")?,
+                    false => 0,
+                };
+
+                // The cycles accumulate, except for the first entry of each of the SPS and slice header parts.
+                if i == 0 || i == u32::from(slice.num_long_term_sps) {
+                    delta_poc_msb_cycle_lt = cycle;
+                } else {
+                    delta_poc_msb_cycle_lt += cycle;
+                }
+
+                slice.long_term_refs.push(LongTermRef {
+                    poc_lsb_lt,
+                    used_by_curr_pic_lt_flag,
+                    delta_poc_msb_present_flag,
+                    delta_poc_msb_cycle_lt,
+                });
+            }
+        }
+
+        if sps.sps_temporal_mvp_enabled_flag {
+            slice.slice_temporal_mvp_enabled_flag = r.read_bool("slice_temporal_mvp_enabled_flag")?;
+        }
+
+        Ok(slice)
+    }
+
+    /// The short-term reference picture set of the picture, from the slice header or the SPS. `None` for IDR pictures.
+    pub fn short_term_ref_pic_set<'a>(&'a self, sps: &'a SeqParameterSet) -> Option<&'a ShortTermRefPicSet> {
+        match (self.nal_unit_type.is_idr(), &self.short_term_ref_pic_set) {
+            (true, _) => None,
+            (false, Some(set)) => Some(set),
+            (false, None) => sps.short_term_ref_pic_sets.get(usize::from(self.short_term_ref_pic_set_idx)),
+        }
+    }
+}
+
+/// `Ceil(Log2(x))`, the number of bits needed for values `0..x`.
+fn ceil_log2(x: u32) -> u32 {
+    32 - x.saturating_sub(1).leading_zeros()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SliceSegmentHeader, SliceType};
+    use crate::error::{Error, Variant};
+    use crate::video::h265::nal::{NalHeader, NalUnitType};
+    use crate::video::h265::pps::PicParameterSet;
+    use crate::video::h265::sps::SeqParameterSet;
+    use crate::video::h265::testdata::{rbsp, PPS, SPS};
+    use h264_reader::rbsp::BitReader;
+    use std::collections::HashMap;
+
+    fn sps() -> SeqParameterSet {
+        SeqParameterSet::from_bits(BitReader::new(&rbsp(SPS)[..])).unwrap()
+    }
+
+    fn parse(nal_unit_type: NalUnitType, bits: &str) -> Result<SliceSegmentHeader, Error> {
+        let sps = HashMap::from([(0, sps())]);
+        let pps = HashMap::from([(0, PicParameterSet::from_bits(BitReader::new(&rbsp(PPS)[..])).unwrap())]);
+        let header = NalHeader {
+            nal_unit_type,
+            nuh_layer_id: 0,
+            temporal_id: 0,
+        };
+
+        SliceSegmentHeader::from_bits(header, BitReader::new(&rbsp(bits)[..]), &sps, &pps)
+    }
+
+    #[test]
+    fn parses_slice_headers() {
+        // First slice of an IDR picture, no_output_of_prior_pics_flag, PPS 0, I slice.
+        let idr = parse(NalUnitType::IDR_W_RADL, "1 0 1 011").unwrap();
+
+        assert!(idr.first_slice_segment_in_pic_flag);
+        assert_eq!(idr.slice_type, SliceType::I);
+        assert!(idr.short_term_ref_pic_set(&sps()).is_none());
+
+        // P slice at address 2 with POC LSB 4, SPS set 1, one long-term picture from the SPS and temporal MVP.
+        let trail = parse(NalUnitType::TRAIL_R, "0 1 10 010 00000100 1 1 010 1 0 1").unwrap();
+
+        assert_eq!(trail.slice_segment_address, 2);
+        assert_eq!(trail.slice_type, SliceType::P);
+        assert_eq!(trail.slice_pic_order_cnt_lsb, 4);
+        assert_eq!((trail.short_term_ref_pic_set_sps_flag, trail.short_term_ref_pic_set_idx), (true, 1));
+        assert_eq!(trail.num_long_term_sps, 1);
+        assert_eq!(trail.long_term_refs[0].poc_lsb_lt, 0);
+        assert!(trail.slice_temporal_mvp_enabled_flag);
+
+        // A set in the slice header, predicted from SPS set 1.
+        let predicted = parse(NalUnitType::TRAIL_R, "1 1 010 00000100 0 1 1 1 1 1 1 1 1 1 0").unwrap();
+        let set = predicted.short_term_ref_pic_set.as_ref().unwrap();
+
+        assert_eq!(set.delta_poc_s0, vec![-1, -2, -3]);
+        assert_eq!(predicted.num_bits_for_st_ref_pic_set_in_slice, 7);
+
+        // Unknown PPS.
+        let error = parse(NalUnitType::TRAIL_R, "1 010").unwrap_err();
+
+        assert!(matches!(error.variant(), Variant::MissingParameterSet));
+    }
+}
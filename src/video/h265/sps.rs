@@ -0,0 +1,820 @@
+//! Sequence parameter set (7.3.2.2), including short-term reference picture sets (7.3.7) and VUI (E.2.1).
+use crate::error;
+use crate::error::{Error, Variant};
+use crate::video::h265::scaling::{default_scaling_lists, scaling_list_data};
+use crate::video::h265::vps::{skip_bits, ProfileTierLevel, SubLayerOrdering};
+use ash::vk::native::StdVideoH265ScalingLists;
+use h264_reader::rbsp::BitRead;
+
+/// A `st_ref_pic_set()` (7.3.7), with its syntax elements as Vulkan wants them and the derived POC deltas.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ShortTermRefPicSet {
+    pub inter_ref_pic_set_prediction_flag: bool,
+    /// Only set for sets in slice headers, otherwise the set is predicted from the one right before it.
+    pub delta_idx_minus1: u32,
+    pub delta_rps_sign: bool,
+    pub abs_delta_rps_minus1: u32,
+    /// One entry per picture of the reference set plus one, only for predicted sets.
+    pub used_by_curr_pic_flag: Vec<bool>,
+    pub use_delta_flag: Vec<bool>,
+    /// `DeltaPocS0`, i.e., how far each picture before the current one is, closest first (7-61, 7-67).
+    pub delta_poc_s0: Vec<i32>,
+    /// `UsedByCurrPicS0`, if the current picture references the respective picture or only keeps it.
+    pub used_by_curr_pic_s0: Vec<bool>,
+    /// `DeltaPocS1`, i.e., how far each picture after the current one is, closest first (7-62, 7-68).
+    pub delta_poc_s1: Vec<i32>,
+    pub used_by_curr_pic_s1: Vec<bool>,
+}
+
+impl ShortTermRefPicSet {
+    /// `NumDeltaPocs`, i.e., all pictures of the set.
+    pub fn num_delta_pocs(&self) -> usize {
+        self.delta_poc_s0.len() + self.delta_poc_s1.len()
+    }
+
+    /// Reads `st_ref_pic_set(st_rps_idx)`, where `sets` are the sets of the SPS, or all sets before it while parsing
+    /// the SPS. The set is in a slice header if `st_rps_idx` is `num_short_term_ref_pic_sets`. Returns the set and
+    /// the number of bits it took.
+    pub(crate) fn from_bits<R: BitRead>(
+        r: &mut R,
+        st_rps_idx: usize,
+        num_short_term_ref_pic_sets: usize,
+        sets: &[Self],
+        invalid: Variant,
+    ) -> Result<(Self, u32), Error> {
+        let mut r = BitCounter { r, bits: 0 };
+        let mut set = Self::default();
+
+        if st_rps_idx != 0 {
+            set.inter_ref_pic_set_prediction_flag = r.read_bool("inter_ref_pic_set_prediction_flag")?;
+        }
+
+        if set.inter_ref_pic_set_prediction_flag {
+            if st_rps_idx == num_short_term_ref_pic_sets {
+                set.delta_idx_minus1 = r.read_ue("delta_idx_minus1")?;
+            }
+
+            set.delta_rps_sign = r.read_bool("delta_rps_sign")?;
+            set.abs_delta_rps_minus1 = r.read_ue("abs_delta_rps_minus1")?;
+
+            let ref_rps_idx = st_rps_idx.checked_sub(set.delta_idx_minus1 as usize + 1);
+            let Some(reference) = ref_rps_idx.and_then(|x| sets.get(x)) else {
+                return Err(error!(invalid, "Invalid delta_idx_minus1 {}", set.delta_idx_minus1));
+            };
+
+            if set.abs_delta_rps_minus1 > 0x7FFF {
+                return Err(error!(invalid, "Invalid abs_delta_rps_minus1 {}", set.abs_delta_rps_minus1));
+            }
+
+            for _ in 0..=reference.num_delta_pocs() {
+                let used_by_curr_pic_flag = r.read_bool("used_by_curr_pic_flag")?;
+                let use_delta_flag = used_by_curr_pic_flag || r.read_bool("use_delta_flag")?;
+
+                set.used_by_curr_pic_flag.push(used_by_curr_pic_flag);
+                set.use_delta_flag.push(use_delta_flag);
+            }
+
+            set.predict(reference);
+        } else {
+            let num_negative_pics = r.read_ue("num_negative_pics")?;
+            let num_positive_pics = r.read_ue("num_positive_pics")?;
+
+            if num_negative_pics > 16 || num_positive_pics > 16 - num_negative_pics {
+                return Err(error!(
+                    invalid,
+                    "Invalid num_negative_pics {} or num_positive_pics {}", num_negative_pics, num_positive_pics
+                ));
+            }
+
+            let mut poc = 0;
+
+            for i in 0..num_negative_pics + num_positive_pics {
+                let delta_poc_minus1 = r.read_ue("delta_poc_minus1")?;
+                let used_by_curr_pic_flag = r.read_bool("used_by_curr_pic_flag")?;
+
+                if delta_poc_minus1 > 0x7FFF {
+                    return Err(error!(invalid, "Invalid delta_poc_minus1 {}", delta_poc_minus1));
+                }
+
+                // Deltas accumulate away from the current picture, restarting at it for the pictures after it.
+                if i == num_negative_pics {
+                    poc = 0;
+                }
+
+                if i < num_negative_pics {
+                    poc -= delta_poc_minus1 as i32 + 1;
+                    set.delta_poc_s0.push(poc);
+                    set.used_by_curr_pic_s0.push(used_by_curr_pic_flag);
+                } else {
+                    poc += delta_poc_minus1 as i32 + 1;
+                    set.delta_poc_s1.push(poc);
+                    set.used_by_curr_pic_s1.push(used_by_curr_pic_flag);
+                }
+            }
+        }
+
+        if set.num_delta_pocs() > 16 {
+            return Err(error!(invalid, "Reference picture set has {} pictures", set.num_delta_pocs()));
+        }
+
+        Ok((set, r.bits))
+    }
+
+    /// Derives the POC deltas of a set predicted from `reference` (7-61, 7-62).
+    fn predict(&mut self, reference: &Self) {
+        let delta_rps = (1 - 2 * i32::from(self.delta_rps_sign)) * (self.abs_delta_rps_minus1 as i32 + 1);
+        let num_negative = reference.delta_poc_s0.len();
+        let num_delta_pocs = reference.num_delta_pocs();
+        let use_delta = self.use_delta_flag.clone();
+        let used = self.used_by_curr_pic_flag.clone();
+
+        // Candidates in the order they end up in the lists, with their index into the flags.
+        let s0 = (0..reference.delta_poc_s1.len())
+            .rev()
+            .map(|j| (reference.delta_poc_s1[j] + delta_rps, num_negative + j))
+            .chain(std::iter::once((delta_rps, num_delta_pocs)))
+            .chain((0..num_negative).map(|j| (reference.delta_poc_s0[j] + delta_rps, j)));
+
+        let s1 = (0..num_negative)
+            .rev()
+            .map(|j| (reference.delta_poc_s0[j] + delta_rps, j))
+            .chain(std::iter::once((delta_rps, num_delta_pocs)))
+            .chain((0..reference.delta_poc_s1.len()).map(|j| (reference.delta_poc_s1[j] + delta_rps, num_negative + j)));
+
+        for (delta_poc, i) in s0 {
+            if delta_poc < 0 && use_delta[i] {
+                self.delta_poc_s0.push(delta_poc);
+                self.used_by_curr_pic_s0.push(used[i]);
+            }
+        }
+
+        for (delta_poc, i) in s1 {
+            if delta_poc > 0 && use_delta[i] {
+                self.delta_poc_s1.push(delta_poc);
+                self.used_by_curr_pic_s1.push(used[i]);
+            }
+        }
+    }
+}
+
+/// Counts the bits read for `st_ref_pic_set()`, as Vulkan needs `NumBitsForSTRefPicSetInSlice`.
+struct BitCounter<'r, R> {
+    r: &'r mut R,
+    bits: u32,
+}
+
+impl<R: BitRead> BitCounter<'_, R> {
+    fn read_bool(&mut self, name: &'static str) -> Result<bool, Error> {
+        self.bits += 1;
+        Ok(self.r.read_bool(name)?)
+    }
+
+    fn read_ue(&mut self, name: &'static str) -> Result<u32, Error> {
+        let x = self.r.read_ue(name)?;
+        self.bits += 2 * (63 - (u64::from(x) + 1).leading_zeros()) + 1;
+        Ok(x)
+    }
+}
+
+/// The range extension of a SPS (7.3.2.2.2).
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SpsRangeExtension {
+    pub transform_skip_rotation_enabled_flag: bool,
+    pub transform_skip_context_enabled_flag: bool,
+    pub implicit_rdpcm_enabled_flag: bool,
+    pub explicit_rdpcm_enabled_flag: bool,
+    pub extended_precision_processing_flag: bool,
+    pub intra_smoothing_disabled_flag: bool,
+    pub high_precision_offsets_enabled_flag: bool,
+    pub persistent_rice_adaptation_enabled_flag: bool,
+    pub cabac_bypass_alignment_enabled_flag: bool,
+}
+
+/// VUI parameters (E.2.1), with absent values as E.3.1 infers them. HRD parameters are skipped.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VuiParameters {
+    pub aspect_ratio_info_present_flag: bool,
+    pub aspect_ratio_idc: u8,
+    pub sar_width: u16,
+    pub sar_height: u16,
+    pub overscan_info_present_flag: bool,
+    pub overscan_appropriate_flag: bool,
+    pub video_signal_type_present_flag: bool,
+    pub video_format: u8,
+    pub video_full_range_flag: bool,
+    pub colour_description_present_flag: bool,
+    pub colour_primaries: u8,
+    pub transfer_characteristics: u8,
+    pub matrix_coeffs: u8,
+    pub chroma_loc_info_present_flag: bool,
+    pub chroma_sample_loc_type_top_field: u8,
+    pub chroma_sample_loc_type_bottom_field: u8,
+    pub neutral_chroma_indication_flag: bool,
+    pub field_seq_flag: bool,
+    pub frame_field_info_present_flag: bool,
+    pub default_display_window_flag: bool,
+    pub def_disp_win_left_offset: u32,
+    pub def_disp_win_right_offset: u32,
+    pub def_disp_win_top_offset: u32,
+    pub def_disp_win_bottom_offset: u32,
+    pub vui_timing_info_present_flag: bool,
+    pub vui_num_units_in_tick: u32,
+    pub vui_time_scale: u32,
+    pub vui_poc_proportional_to_timing_flag: bool,
+    pub vui_num_ticks_poc_diff_one_minus1: u32,
+    pub vui_hrd_parameters_present_flag: bool,
+    pub bitstream_restriction_flag: bool,
+    pub tiles_fixed_structure_flag: bool,
+    pub motion_vectors_over_pic_boundaries_flag: bool,
+    pub restricted_ref_pic_lists_flag: bool,
+    pub min_spatial_segmentation_idc: u16,
+    pub max_bytes_per_pic_denom: u8,
+    pub max_bits_per_min_cu_denom: u8,
+    pub log2_max_mv_length_horizontal: u8,
+    pub log2_max_mv_length_vertical: u8,
+}
+
+impl Default for VuiParameters {
+    fn default() -> Self {
+        Self {
+            aspect_ratio_info_present_flag: false,
+            aspect_ratio_idc: 0,
+            sar_width: 0,
+            sar_height: 0,
+            overscan_info_present_flag: false,
+            overscan_appropriate_flag: false,
+            video_signal_type_present_flag: false,
+            video_format: 5,
+            video_full_range_flag: false,
+            colour_description_present_flag: false,
+            colour_primaries: 2,
+            transfer_characteristics: 2,
+            matrix_coeffs: 2,
+            chroma_loc_info_present_flag: false,
+            chroma_sample_loc_type_top_field: 0,
+            chroma_sample_loc_type_bottom_field: 0,
+            neutral_chroma_indication_flag: false,
+            field_seq_flag: false,
+            frame_field_info_present_flag: false,
+            default_display_window_flag: false,
+            def_disp_win_left_offset: 0,
+            def_disp_win_right_offset: 0,
+            def_disp_win_top_offset: 0,
+            def_disp_win_bottom_offset: 0,
+            vui_timing_info_present_flag: false,
+            vui_num_units_in_tick: 0,
+            vui_time_scale: 0,
+            vui_poc_proportional_to_timing_flag: false,
+            vui_num_ticks_poc_diff_one_minus1: 0,
+            vui_hrd_parameters_present_flag: false,
+            bitstream_restriction_flag: false,
+            tiles_fixed_structure_flag: false,
+            motion_vectors_over_pic_boundaries_flag: true,
+            restricted_ref_pic_lists_flag: false,
+            min_spatial_segmentation_idc: 0,
+            max_bytes_per_pic_denom: 2,
+            max_bits_per_min_cu_denom: 1,
+            log2_max_mv_length_horizontal: 15,
+            log2_max_mv_length_vertical: 15,
+        }
+    }
+}
+
+impl VuiParameters {
+    fn from_bits<R: BitRead>(r: &mut R, max_sub_layers_minus1: u8) -> Result<Self, Error> {
+        let mut vui = Self {
+            aspect_ratio_info_present_flag: r.read_bool("aspect_ratio_info_present_flag")?,
+            ..Self::default()
+        };
+
+        if vui.aspect_ratio_info_present_flag {
+            vui.aspect_ratio_idc = r.read_u8(8, "aspect_ratio_idc")?;
+
+            if vui.aspect_ratio_idc == 255 {
+                vui.sar_width = r.read_u16(16, "sar_width")?;
+                vui.sar_height = r.read_u16(16, "sar_height")?;
+            }
+        }
+
+        vui.overscan_info_present_flag = r.read_bool("overscan_info_present_flag")?;
+
+        if vui.overscan_info_present_flag {
+            vui.overscan_appropriate_flag = r.read_bool("overscan_appropriate_flag")?;
+        }
+
+        vui.video_signal_type_present_flag = r.read_bool("video_signal_type_present_flag")?;
+
+        if vui.video_signal_type_present_flag {
+            vui.video_format = r.read_u8(3, "video_format")?;
+            vui.video_full_range_flag = r.read_bool("video_full_range_flag")?;
+            vui.colour_description_present_flag = r.read_bool("colour_description_present_flag")?;
+
+            if vui.colour_description_present_flag {
+                vui.colour_primaries = r.read_u8(8, "colour_primaries")?;
+                vui.transfer_characteristics = r.read_u8(8, "transfer_characteristics")?;
+                vui.matrix_coeffs = r.read_u8(8, "matrix_coeffs")?;
+            }
+        }
+
+        vui.chroma_loc_info_present_flag = r.read_bool("chroma_loc_info_present_flag")?;
+
+        if vui.chroma_loc_info_present_flag {
+            let top = r.read_ue("chroma_sample_loc_type_top_field")?;
+            let bottom = r.read_ue("chroma_sample_loc_type_bottom_field")?;
+
+            if top > 5 || bottom > 5 {
+                return Err(error!(
+                    Variant::InvalidSps,
+                    "Invalid chroma sample location types {} {}", top, bottom
+                ));
+            }
+
+            vui.chroma_sample_loc_type_top_field = top as u8;
+            vui.chroma_sample_loc_type_bottom_field = bottom as u8;
+        }
+
+        vui.neutral_chroma_indication_flag = r.read_bool("neutral_chroma_indication_flag")?;
+        vui.field_seq_flag = r.read_bool("field_seq_flag")?;
+        vui.frame_field_info_present_flag = r.read_bool("frame_field_info_present_flag")?;
+        vui.default_display_window_flag = r.read_bool("default_display_window_flag")?;
+
+        if vui.default_display_window_flag {
+            vui.def_disp_win_left_offset = r.read_ue("def_disp_win_left_offset")?;
+            vui.def_disp_win_right_offset = r.read_ue("def_disp_win_right_offset")?;
+            vui.def_disp_win_top_offset = r.read_ue("def_disp_win_top_offset")?;
+            vui.def_disp_win_bottom_offset = r.read_ue("def_disp_win_bottom_offset")?;
+        }
+
+        vui.vui_timing_info_present_flag = r.read_bool("vui_timing_info_present_flag")?;
+
+        if vui.vui_timing_info_present_flag {
+            vui.vui_num_units_in_tick = r.read_u32(32, "vui_num_units_in_tick")?;
+            vui.vui_time_scale = r.read_u32(32, "vui_time_scale")?;
+            vui.vui_poc_proportional_to_timing_flag = r.read_bool("vui_poc_proportional_to_timing_flag")?;
+
+            if vui.vui_poc_proportional_to_timing_flag {
+                vui.vui_num_ticks_poc_diff_one_minus1 = r.read_ue("vui_num_ticks_poc_diff_one_minus1")?;
+            }
+
+            vui.vui_hrd_parameters_present_flag = r.read_bool("vui_hrd_parameters_present_flag")?;
+
+            if vui.vui_hrd_parameters_present_flag {
+                skip_hrd_parameters(r, max_sub_layers_minus1)?;
+            }
+        }
+
+        vui.bitstream_restriction_flag = r.read_bool("bitstream_restriction_flag")?;
+
+        if vui.bitstream_restriction_flag {
+            vui.tiles_fixed_structure_flag = r.read_bool("tiles_fixed_structure_flag")?;
+            vui.motion_vectors_over_pic_boundaries_flag = r.read_bool("motion_vectors_over_pic_boundaries_flag")?;
+            vui.restricted_ref_pic_lists_flag = r.read_bool("restricted_ref_pic_lists_flag")?;
+
+            let min_spatial_segmentation_idc = r.read_ue("min_spatial_segmentation_idc")?;
+            let max_bytes_per_pic_denom = r.read_ue("max_bytes_per_pic_denom")?;
+            let max_bits_per_min_cu_denom = r.read_ue("max_bits_per_min_cu_denom")?;
+            let log2_max_mv_length_horizontal = r.read_ue("log2_max_mv_length_horizontal")?;
+            let log2_max_mv_length_vertical = r.read_ue("log2_max_mv_length_vertical")?;
+
+            if min_spatial_segmentation_idc > 4095
+                || max_bytes_per_pic_denom > 16
+                || max_bits_per_min_cu_denom > 16
+                || log2_max_mv_length_horizontal > 15
+                || log2_max_mv_length_vertical > 15
+            {
+                return Err(error!(Variant::InvalidSps, "Invalid bitstream restrictions"));
+            }
+
+            vui.min_spatial_segmentation_idc = min_spatial_segmentation_idc as u16;
+            vui.max_bytes_per_pic_denom = max_bytes_per_pic_denom as u8;
+            vui.max_bits_per_min_cu_denom = max_bits_per_min_cu_denom as u8;
+            vui.log2_max_mv_length_horizontal = log2_max_mv_length_horizontal as u8;
+            vui.log2_max_mv_length_vertical = log2_max_mv_length_vertical as u8;
+        }
+
+        Ok(vui)
+    }
+}
+
+/// Reads past `hrd_parameters(1, max_sub_layers_minus1)` (E.2.2).
+fn skip_hrd_parameters<R: BitRead>(r: &mut R, max_sub_layers_minus1: u8) -> Result<(), Error> {
+    let nal_hrd_parameters_present_flag = r.read_bool("nal_hrd_parameters_present_flag")?;
+    let vcl_hrd_parameters_present_flag = r.read_bool("vcl_hrd_parameters_present_flag")?;
+    let mut sub_pic_hrd_params_present_flag = false;
+
+    if nal_hrd_parameters_present_flag || vcl_hrd_parameters_present_flag {
+        sub_pic_hrd_params_present_flag = r.read_bool("sub_pic_hrd_params_present_flag")?;
+
+        if sub_pic_hrd_params_present_flag {
+            // `tick_divisor_minus2` up to `dpb_output_delay_du_length_minus1`.
+            skip_bits(r, 8 + 5 + 1 + 5, "sub_pic_hrd_params")?;
+        }
+
+        // `bit_rate_scale` and `cpb_size_scale`, maybe `cpb_size_du_scale`, and the delay lengths.
+        let scales = if sub_pic_hrd_params_present_flag { 12 } else { 8 };
+        skip_bits(r, scales + 15, "hrd_scales_and_lengths")?;
+    }
+
+    for _ in 0..=max_sub_layers_minus1 {
+        let fixed_pic_rate_general_flag = r.read_bool("fixed_pic_rate_general_flag")?;
+        let fixed_pic_rate_within_cvs_flag = fixed_pic_rate_general_flag || r.read_bool("fixed_pic_rate_within_cvs_flag")?;
+        let mut low_delay_hrd_flag = false;
+        let mut cpb_cnt_minus1 = 0;
+
+        if fixed_pic_rate_within_cvs_flag {
+            let _elemental_duration_in_tc_minus1 = r.read_ue("elemental_duration_in_tc_minus1")?;
+        } else {
+            low_delay_hrd_flag = r.read_bool("low_delay_hrd_flag")?;
+        }
+
+        if !low_delay_hrd_flag {
+            cpb_cnt_minus1 = r.read_ue("cpb_cnt_minus1")?;
+        }
+
+        if cpb_cnt_minus1 > 31 {
+            return Err(error!(Variant::InvalidSps, "Invalid cpb_cnt_minus1 {}", cpb_cnt_minus1));
+        }
+
+        let hrds = u32::from(nal_hrd_parameters_present_flag) + u32::from(vcl_hrd_parameters_present_flag);
+
+        // `sub_layer_hrd_parameters()` (E.2.3) for NAL and VCL.
+        for _ in 0..hrds * (cpb_cnt_minus1 + 1) {
+            let _bit_rate_value_minus1 = r.read_ue("bit_rate_value_minus1")?;
+            let _cpb_size_value_minus1 = r.read_ue("cpb_size_value_minus1")?;
+
+            if sub_pic_hrd_params_present_flag {
+                let _cpb_size_du_value_minus1 = r.read_ue("cpb_size_du_value_minus1")?;
+                let _bit_rate_du_value_minus1 = r.read_ue("bit_rate_du_value_minus1")?;
+            }
+
+            let _cbr_flag = r.read_bool("cbr_flag")?;
+        }
+    }
+
+    Ok(())
+}
+
+/// A parsed SPS. Multilayer, 3D and screen content extensions are not parsed.
+#[derive(Clone, Debug)]
+pub struct SeqParameterSet {
+    pub sps_video_parameter_set_id: u8,
+    pub sps_max_sub_layers_minus1: u8,
+    pub sps_temporal_id_nesting_flag: bool,
+    pub profile_tier_level: ProfileTierLevel,
+    pub sps_seq_parameter_set_id: u8,
+    pub chroma_format_idc: u8,
+    pub separate_colour_plane_flag: bool,
+    /// The size of decoded pictures, a multiple of the minimum coding block size.
+    pub pic_width_in_luma_samples: u32,
+    pub pic_height_in_luma_samples: u32,
+    pub conformance_window_flag: bool,
+    /// Offsets of the conformance window in chroma samples, see [`SeqParameterSet::sub_width_height_c`].
+    pub conf_win_left_offset: u32,
+    pub conf_win_right_offset: u32,
+    pub conf_win_top_offset: u32,
+    pub conf_win_bottom_offset: u32,
+    pub bit_depth_luma_minus8: u8,
+    pub bit_depth_chroma_minus8: u8,
+    pub log2_max_pic_order_cnt_lsb_minus4: u8,
+    pub sps_sub_layer_ordering_info_present_flag: bool,
+    /// One entry per sub-layer.
+    pub sub_layer_ordering: Vec<SubLayerOrdering>,
+    pub log2_min_luma_coding_block_size_minus3: u8,
+    pub log2_diff_max_min_luma_coding_block_size: u8,
+    pub log2_min_luma_transform_block_size_minus2: u8,
+    pub log2_diff_max_min_luma_transform_block_size: u8,
+    pub max_transform_hierarchy_depth_inter: u8,
+    pub max_transform_hierarchy_depth_intra: u8,
+    pub scaling_list_enabled_flag: bool,
+    pub sps_scaling_list_data_present_flag: bool,
+    /// Transmitted or default lists if `scaling_list_enabled_flag` is set.
+    pub scaling_lists: Option<StdVideoH265ScalingLists>,
+    pub amp_enabled_flag: bool,
+    pub sample_adaptive_offset_enabled_flag: bool,
+    pub pcm_enabled_flag: bool,
+    pub pcm_sample_bit_depth_luma_minus1: u8,
+    pub pcm_sample_bit_depth_chroma_minus1: u8,
+    pub log2_min_pcm_luma_coding_block_size_minus3: u8,
+    pub log2_diff_max_min_pcm_luma_coding_block_size: u8,
+    pub pcm_loop_filter_disabled_flag: bool,
+    pub short_term_ref_pic_sets: Vec<ShortTermRefPicSet>,
+    pub long_term_ref_pics_present_flag: bool,
+    pub lt_ref_pic_poc_lsb_sps: Vec<u32>,
+    pub used_by_curr_pic_lt_sps_flag: Vec<bool>,
+    pub sps_temporal_mvp_enabled_flag: bool,
+    pub strong_intra_smoothing_enabled_flag: bool,
+    pub vui_parameters: Option<VuiParameters>,
+    pub sps_extension_present_flag: bool,
+    pub range_extension: Option<SpsRangeExtension>,
+}
+
+impl SeqParameterSet {
+    /// Parses a SPS from its RBSP.
+    pub fn from_bits<R: BitRead>(mut r: R) -> Result<Self, Error> {
+        let sps_video_parameter_set_id = r.read_u8(4, "sps_video_parameter_set_id")?;
+        let sps_max_sub_layers_minus1 = r.read_u8(3, "sps_max_sub_layers_minus1")?;
+        let sps_temporal_id_nesting_flag = r.read_bool("sps_temporal_id_nesting_flag")?;
+
+        if sps_max_sub_layers_minus1 > 6 {
+            return Err(error!(
+                Variant::InvalidSps,
+                "Invalid sps_max_sub_layers_minus1 {}", sps_max_sub_layers_minus1
+            ));
+        }
+
+        let profile_tier_level = ProfileTierLevel::from_bits(&mut r, sps_max_sub_layers_minus1)?;
+        let sps_seq_parameter_set_id = read_ue_max(&mut r, "sps_seq_parameter_set_id", 15)?;
+        let chroma_format_idc = read_ue_max(&mut r, "chroma_format_idc", 3)?;
+        let separate_colour_plane_flag = chroma_format_idc == 3 && r.read_bool("separate_colour_plane_flag")?;
+        let pic_width_in_luma_samples = r.read_ue("pic_width_in_luma_samples")?;
+        let pic_height_in_luma_samples = r.read_ue("pic_height_in_luma_samples")?;
+        let conformance_window_flag = r.read_bool("conformance_window_flag")?;
+        let mut conf_win = [0; 4];
+
+        if conformance_window_flag {
+            for offset in &mut conf_win {
+                *offset = r.read_ue("conf_win_offset")?;
+            }
+        }
+
+        let bit_depth_luma_minus8 = read_ue_max(&mut r, "bit_depth_luma_minus8", 8)?;
+        let bit_depth_chroma_minus8 = read_ue_max(&mut r, "bit_depth_chroma_minus8", 8)?;
+        let log2_max_pic_order_cnt_lsb_minus4 = read_ue_max(&mut r, "log2_max_pic_order_cnt_lsb_minus4", 12)?;
+        let (sps_sub_layer_ordering_info_present_flag, sub_layer_ordering) =
+            SubLayerOrdering::from_bits(&mut r, sps_max_sub_layers_minus1, Variant::InvalidSps)?;
+        let log2_min_luma_coding_block_size_minus3 = read_ue_max(&mut r, "log2_min_luma_coding_block_size_minus3", 3)?;
+        let log2_diff_max_min_luma_coding_block_size = read_ue_max(&mut r, "log2_diff_max_min_luma_coding_block_size", 3)?;
+        let log2_min_luma_transform_block_size_minus2 = read_ue_max(&mut r, "log2_min_luma_transform_block_size_minus2", 3)?;
+        let log2_diff_max_min_luma_transform_block_size = read_ue_max(&mut r, "log2_diff_max_min_luma_transform_block_size", 3)?;
+        let max_transform_hierarchy_depth_inter = read_ue_max(&mut r, "max_transform_hierarchy_depth_inter", 4)?;
+        let max_transform_hierarchy_depth_intra = read_ue_max(&mut r, "max_transform_hierarchy_depth_intra", 4)?;
+
+        // `CtbLog2SizeY` must be 4 to 6, and pictures consist of whole minimum coding blocks.
+        let ctb_log2_size_y = log2_min_luma_coding_block_size_minus3 + 3 + log2_diff_max_min_luma_coding_block_size;
+        let min_cb_size_y = 1 << (log2_min_luma_coding_block_size_minus3 + 3);
+
+        if !(4..=6).contains(&ctb_log2_size_y) {
+            return Err(error!(Variant::InvalidSps, "Invalid coding tree block size 2^{}", ctb_log2_size_y));
+        }
+
+        if pic_width_in_luma_samples == 0
+            || pic_height_in_luma_samples == 0
+            || pic_width_in_luma_samples % min_cb_size_y != 0
+            || pic_height_in_luma_samples % min_cb_size_y != 0
+            || pic_width_in_luma_samples > 16888
+            || pic_height_in_luma_samples > 16888
+        {
+            return Err(error!(
+                Variant::InvalidSps,
+                "Invalid picture size {}x{}", pic_width_in_luma_samples, pic_height_in_luma_samples
+            ));
+        }
+
+        let scaling_list_enabled_flag = r.read_bool("scaling_list_enabled_flag")?;
+        let mut sps_scaling_list_data_present_flag = false;
+        let mut scaling_lists = None;
+
+        if scaling_list_enabled_flag {
+            sps_scaling_list_data_present_flag = r.read_bool("sps_scaling_list_data_present_flag")?;
+
+            scaling_lists = Some(match sps_scaling_list_data_present_flag {
+                true => scaling_list_data(&mut r, Variant::InvalidSps)?,
+                false => default_scaling_lists(),
+            });
+        }
+
+        let amp_enabled_flag = r.read_bool("amp_enabled_flag")?;
+        let sample_adaptive_offset_enabled_flag = r.read_bool("sample_adaptive_offset_enabled_flag")?;
+        let pcm_enabled_flag = r.read_bool("pcm_enabled_flag")?;
+        let mut pcm = [0; 4];
+        let mut pcm_loop_filter_disabled_flag = false;
+
+        if pcm_enabled_flag {
+            pcm[0] = r.read_u8(4, "pcm_sample_bit_depth_luma_minus1")?;
+            pcm[1] = r.read_u8(4, "pcm_sample_bit_depth_chroma_minus1")?;
+            pcm[2] = read_ue_max(&mut r, "log2_min_pcm_luma_coding_block_size_minus3", 2)?;
+            pcm[3] = read_ue_max(&mut r, "log2_diff_max_min_pcm_luma_coding_block_size", 2)?;
+            pcm_loop_filter_disabled_flag = r.read_bool("pcm_loop_filter_disabled_flag")?;
+        }
+
+        let num_short_term_ref_pic_sets = read_ue_max(&mut r, "num_short_term_ref_pic_sets", 64)?;
+        let mut short_term_ref_pic_sets = Vec::new();
+
+        for i in 0..usize::from(num_short_term_ref_pic_sets) {
+            let (set, _) = ShortTermRefPicSet::from_bits(
+                &mut r,
+                i,
+                num_short_term_ref_pic_sets.into(),
+                &short_term_ref_pic_sets,
+                Variant::InvalidSps,
+            )?;
+            short_term_ref_pic_sets.push(set);
+        }
+
+        let long_term_ref_pics_present_flag = r.read_bool("long_term_ref_pics_present_flag")?;
+        let mut lt_ref_pic_poc_lsb_sps = Vec::new();
+        let mut used_by_curr_pic_lt_sps_flag = Vec::new();
+
+        if long_term_ref_pics_present_flag {
+            let num_long_term_ref_pics_sps = read_ue_max(&mut r, "num_long_term_ref_pics_sps", 32)?;
+
+            for _ in 0..num_long_term_ref_pics_sps {
+                lt_ref_pic_poc_lsb_sps.push(r.read_u32(u32::from(log2_max_pic_order_cnt_lsb_minus4) + 4, "lt_ref_pic_poc_lsb_sps")?);
+                used_by_curr_pic_lt_sps_flag.push(r.read_bool("used_by_curr_pic_lt_sps_flag")?);
+            }
+        }
+
+        let sps_temporal_mvp_enabled_flag = r.read_bool("sps_temporal_mvp_enabled_flag")?;
+        let strong_intra_smoothing_enabled_flag = r.read_bool("strong_intra_smoothing_enabled_flag")?;
+        let vui_parameters = match r.read_bool("vui_parameters_present_flag")? {
+            true => Some(VuiParameters::from_bits(&mut r, sps_max_sub_layers_minus1)?),
+            false => None,
+        };
+
+        let sps_extension_present_flag = r.read_bool("sps_extension_present_flag")?;
+        let mut range_extension = None;
+
+        if sps_extension_present_flag {
+            let sps_range_extension_flag = r.read_bool("sps_range_extension_flag")?;
+            let _sps_multilayer_extension_flag = r.read_bool("sps_multilayer_extension_flag")?;
+            let _sps_3d_extension_flag = r.read_bool("sps_3d_extension_flag")?;
+            let _sps_scc_extension_flag = r.read_bool("sps_scc_extension_flag")?;
+            let _sps_extension_4bits = r.read_u8(4, "sps_extension_4bits")?;
+
+            if sps_range_extension_flag {
+                range_extension = Some(SpsRangeExtension {
+                    transform_skip_rotation_enabled_flag: r.read_bool("transform_skip_rotation_enabled_flag")?,
+                    transform_skip_context_enabled_flag: r.read_bool("transform_skip_context_enabled_flag")?,
+                    implicit_rdpcm_enabled_flag: r.read_bool("implicit_rdpcm_enabled_flag")?,
+                    explicit_rdpcm_enabled_flag: r.read_bool("explicit_rdpcm_enabled_flag")?,
+                    extended_precision_processing_flag: r.read_bool("extended_precision_processing_flag")?,
+                    intra_smoothing_disabled_flag: r.read_bool("intra_smoothing_disabled_flag")?,
+                    high_precision_offsets_enabled_flag: r.read_bool("high_precision_offsets_enabled_flag")?,
+                    persistent_rice_adaptation_enabled_flag: r.read_bool("persistent_rice_adaptation_enabled_flag")?,
+                    cabac_bypass_alignment_enabled_flag: r.read_bool("cabac_bypass_alignment_enabled_flag")?,
+                });
+            }
+        }
+
+        Ok(Self {
+            sps_video_parameter_set_id,
+            sps_max_sub_layers_minus1,
+            sps_temporal_id_nesting_flag,
+            profile_tier_level,
+            sps_seq_parameter_set_id,
+            chroma_format_idc,
+            separate_colour_plane_flag,
+            pic_width_in_luma_samples,
+            pic_height_in_luma_samples,
+            conformance_window_flag,
+            conf_win_left_offset: conf_win[0],
+            conf_win_right_offset: conf_win[1],
+            conf_win_top_offset: conf_win[2],
+            conf_win_bottom_offset: conf_win[3],
+            bit_depth_luma_minus8,
+            bit_depth_chroma_minus8,
+            log2_max_pic_order_cnt_lsb_minus4,
+            sps_sub_layer_ordering_info_present_flag,
+            sub_layer_ordering,
+            log2_min_luma_coding_block_size_minus3,
+            log2_diff_max_min_luma_coding_block_size,
+            log2_min_luma_transform_block_size_minus2,
+            log2_diff_max_min_luma_transform_block_size,
+            max_transform_hierarchy_depth_inter,
+            max_transform_hierarchy_depth_intra,
+            scaling_list_enabled_flag,
+            sps_scaling_list_data_present_flag,
+            scaling_lists,
+            amp_enabled_flag,
+            sample_adaptive_offset_enabled_flag,
+            pcm_enabled_flag,
+            pcm_sample_bit_depth_luma_minus1: pcm[0],
+            pcm_sample_bit_depth_chroma_minus1: pcm[1],
+            log2_min_pcm_luma_coding_block_size_minus3: pcm[2],
+            log2_diff_max_min_pcm_luma_coding_block_size: pcm[3],
+            pcm_loop_filter_disabled_flag,
+            short_term_ref_pic_sets,
+            long_term_ref_pics_present_flag,
+            lt_ref_pic_poc_lsb_sps,
+            used_by_curr_pic_lt_sps_flag,
+            sps_temporal_mvp_enabled_flag,
+            strong_intra_smoothing_enabled_flag,
+            vui_parameters,
+            sps_extension_present_flag,
+            range_extension,
+        })
+    }
+
+    /// `MaxPicOrderCntLsb` (7-8).
+    pub fn max_pic_order_cnt_lsb(&self) -> u32 {
+        1 << (self.log2_max_pic_order_cnt_lsb_minus4 + 4)
+    }
+
+    /// `CtbLog2SizeY` (7-11).
+    pub fn ctb_log2_size_y(&self) -> u32 {
+        u32::from(self.log2_min_luma_coding_block_size_minus3 + 3 + self.log2_diff_max_min_luma_coding_block_size)
+    }
+
+    /// `PicSizeInCtbsY` (7-19).
+    pub fn pic_size_in_ctbs_y(&self) -> u32 {
+        let ctb_size_y = 1 << self.ctb_log2_size_y();
+
+        self.pic_width_in_luma_samples.div_ceil(ctb_size_y) * self.pic_height_in_luma_samples.div_ceil(ctb_size_y)
+    }
+
+    /// `ChromaArrayType`, i.e., `chroma_format_idc` unless colour planes are coded separately.
+    pub fn chroma_array_type(&self) -> u8 {
+        if self.separate_colour_plane_flag {
+            0
+        } else {
+            self.chroma_format_idc
+        }
+    }
+
+    /// `SubWidthC` and `SubHeightC` (Table 6-1), the units of the conformance window offsets.
+    pub fn sub_width_height_c(&self) -> (u32, u32) {
+        match self.chroma_array_type() {
+            1 => (2, 2),
+            2 => (2, 1),
+            _ => (1, 1),
+        }
+    }
+
+    /// The ordering of the highest temporal sub-layer, which covers the whole stream.
+    pub fn highest_sub_layer_ordering(&self) -> SubLayerOrdering {
+        self.sub_layer_ordering.last().copied().unwrap_or_default()
+    }
+}
+
+/// Reads `ue(v)` and checks it against its maximum value.
+fn read_ue_max<R: BitRead>(r: &mut R, name: &'static str, max: u8) -> Result<u8, Error> {
+    let x = r.read_ue(name)?;
+
+    if x > u32::from(max) {
+        return Err(error!(Variant::InvalidSps, "Invalid {} {}", name, x));
+    }
+
+    Ok(x as u8)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SeqParameterSet, ShortTermRefPicSet};
+    use crate::error::Variant;
+    use crate::video::h265::testdata::{rbsp, SPS};
+    use h264_reader::rbsp::BitReader;
+
+    #[test]
+    fn parses_sps() {
+        let sps = SeqParameterSet::from_bits(BitReader::new(&rbsp(SPS)[..])).unwrap();
+
+        assert_eq!(sps.sps_seq_parameter_set_id, 0);
+        assert_eq!(sps.profile_tier_level.general_profile_idc, 1);
+        assert_eq!(sps.chroma_format_idc, 1);
+        assert_eq!((sps.pic_width_in_luma_samples, sps.pic_height_in_luma_samples), (64, 64));
+        assert_eq!((sps.conf_win_bottom_offset, sps.sub_width_height_c()), (4, (2, 2)));
+        assert_eq!(sps.max_pic_order_cnt_lsb(), 256);
+        assert_eq!(sps.highest_sub_layer_ordering().max_dec_pic_buffering_minus1, 2);
+        assert_eq!(sps.pic_size_in_ctbs_y(), 4);
+        assert_eq!(sps.short_term_ref_pic_sets.len(), 2);
+        assert_eq!(sps.short_term_ref_pic_sets[1].delta_poc_s0, vec![-1, -2]);
+        assert!(sps.long_term_ref_pics_present_flag);
+        assert_eq!(sps.lt_ref_pic_poc_lsb_sps, vec![0]);
+
+        let vui = sps.vui_parameters.unwrap();
+
+        assert!(vui.video_full_range_flag);
+        assert_eq!((vui.colour_primaries, vui.matrix_coeffs), (1, 1));
+        assert_eq!(vui.vui_time_scale, 60);
+    }
+
+    #[test]
+    fn predicts_reference_picture_sets() {
+        // Set 0: one picture at -1. Set 1, predicted from set 0 with deltaRps = -1: keeps -2, adds -1.
+        let data = rbsp("010 1 1 1 1 1 1 1 1");
+        let reader = &mut BitReader::new(&data[..]);
+
+        let (first, first_bits) = ShortTermRefPicSet::from_bits(reader, 0, 2, &[], Variant::InvalidSps).unwrap();
+        let (second, second_bits) = ShortTermRefPicSet::from_bits(reader, 1, 2, std::slice::from_ref(&first), Variant::InvalidSps).unwrap();
+
+        assert_eq!(first.delta_poc_s0, vec![-1]);
+        assert_eq!(first_bits, 6);
+        assert!(second.inter_ref_pic_set_prediction_flag);
+        assert_eq!(second.delta_poc_s0, vec![-1, -2]);
+        assert_eq!(second.used_by_curr_pic_s0, vec![true, true]);
+        assert_eq!(second_bits, 5);
+
+        // In a slice header, `delta_idx_minus1` must point to an existing set.
+        let error = ShortTermRefPicSet::from_bits(
+            &mut BitReader::new(&rbsp("1 010 1 1")[..]),
+            1,
+            1,
+            &[first],
+            Variant::InvalidSliceHeader,
+        )
+        .map(|_| ())
+        .unwrap_err();
+
+        assert!(matches!(error.variant(), Variant::InvalidSliceHeader));
+    }
+}
@@ -0,0 +1,594 @@
+//! Translation of parsed H.265 parameter sets into their Vulkan `StdVideoH265*` counterparts.
+use crate::video::h265::vps::{ProfileTierLevel, SubLayerOrdering};
+use crate::video::h265::{
+    DpbPicture, DpbReference, PicParameterSet, SeqParameterSet, ShortTermRefPicSet, VideoParameterSet, VuiParameters,
+};
+use ash::vk::native::{
+    StdVideoDecodeH265PictureInfo, StdVideoDecodeH265PictureInfoFlags, StdVideoDecodeH265ReferenceInfo,
+    StdVideoDecodeH265ReferenceInfoFlags, StdVideoH265AspectRatioIdc, StdVideoH265ChromaFormatIdc, StdVideoH265DecPicBufMgr,
+    StdVideoH265LevelIdc, StdVideoH265LevelIdc_STD_VIDEO_H265_LEVEL_IDC_1_0, StdVideoH265LevelIdc_STD_VIDEO_H265_LEVEL_IDC_2_0,
+    StdVideoH265LevelIdc_STD_VIDEO_H265_LEVEL_IDC_2_1, StdVideoH265LevelIdc_STD_VIDEO_H265_LEVEL_IDC_3_0,
+    StdVideoH265LevelIdc_STD_VIDEO_H265_LEVEL_IDC_3_1, StdVideoH265LevelIdc_STD_VIDEO_H265_LEVEL_IDC_4_0,
+    StdVideoH265LevelIdc_STD_VIDEO_H265_LEVEL_IDC_4_1, StdVideoH265LevelIdc_STD_VIDEO_H265_LEVEL_IDC_5_0,
+    StdVideoH265LevelIdc_STD_VIDEO_H265_LEVEL_IDC_5_1, StdVideoH265LevelIdc_STD_VIDEO_H265_LEVEL_IDC_5_2,
+    StdVideoH265LevelIdc_STD_VIDEO_H265_LEVEL_IDC_6_0, StdVideoH265LevelIdc_STD_VIDEO_H265_LEVEL_IDC_6_1,
+    StdVideoH265LevelIdc_STD_VIDEO_H265_LEVEL_IDC_6_2, StdVideoH265LevelIdc_STD_VIDEO_H265_LEVEL_IDC_INVALID,
+    StdVideoH265LongTermRefPicsSps, StdVideoH265PictureParameterSet, StdVideoH265PpsFlags, StdVideoH265ProfileIdc,
+    StdVideoH265ProfileTierLevel, StdVideoH265ProfileTierLevelFlags, StdVideoH265ScalingLists, StdVideoH265SequenceParameterSet,
+    StdVideoH265SequenceParameterSetVui, StdVideoH265ShortTermRefPicSet, StdVideoH265ShortTermRefPicSetFlags, StdVideoH265SpsFlags,
+    StdVideoH265SpsVuiFlags, StdVideoH265VideoParameterSet, StdVideoH265VpsFlags,
+};
+use std::ptr::null;
+
+/// A `StdVideoH265VideoParameterSet` that owns everything its pointers refer to.
+///
+/// HRD parameters are not parsed, so none are handed to Vulkan.
+pub struct StdVps {
+    native: StdVideoH265VideoParameterSet,
+    _profile_tier_level: Box<StdVideoH265ProfileTierLevel>,
+    _dec_pic_buf_mgr: Box<StdVideoH265DecPicBufMgr>,
+}
+
+impl StdVps {
+    pub fn new(vps: &VideoParameterSet) -> Self {
+        let mut flags = StdVideoH265VpsFlags {
+            _bitfield_align_1: [],
+            _bitfield_1: Default::default(),
+            __bindgen_padding_0: Default::default(),
+        };
+
+        flags.set_vps_temporal_id_nesting_flag(vps.vps_temporal_id_nesting_flag.into());
+        flags.set_vps_sub_layer_ordering_info_present_flag(vps.vps_sub_layer_ordering_info_present_flag.into());
+        flags.set_vps_timing_info_present_flag(vps.vps_timing_info_present_flag.into());
+        flags.set_vps_poc_proportional_to_timing_flag(vps.vps_poc_proportional_to_timing_flag.into());
+
+        let profile_tier_level = Box::new(std_profile_tier_level(&vps.profile_tier_level));
+        let dec_pic_buf_mgr = Box::new(std_dec_pic_buf_mgr(&vps.sub_layer_ordering));
+
+        let native = StdVideoH265VideoParameterSet {
+            flags,
+            vps_video_parameter_set_id: vps.vps_video_parameter_set_id,
+            vps_max_sub_layers_minus1: vps.vps_max_sub_layers_minus1,
+            reserved1: 0,
+            reserved2: 0,
+            vps_num_units_in_tick: vps.vps_num_units_in_tick,
+            vps_time_scale: vps.vps_time_scale,
+            vps_num_ticks_poc_diff_one_minus1: vps.vps_num_ticks_poc_diff_one_minus1,
+            reserved3: 0,
+            pDecPicBufMgr: &*dec_pic_buf_mgr,
+            pHrdParameters: null(),
+            pProfileTierLevel: &*profile_tier_level,
+        };
+
+        Self {
+            native,
+            _profile_tier_level: profile_tier_level,
+            _dec_pic_buf_mgr: dec_pic_buf_mgr,
+        }
+    }
+
+    pub fn native(&self) -> &StdVideoH265VideoParameterSet {
+        &self.native
+    }
+}
+
+/// A `StdVideoH265SequenceParameterSet` that owns everything its pointers refer to.
+///
+/// All referenced structures live on the heap, so the native struct stays valid when this is moved.
+pub struct StdSps {
+    native: StdVideoH265SequenceParameterSet,
+    _profile_tier_level: Box<StdVideoH265ProfileTierLevel>,
+    _dec_pic_buf_mgr: Box<StdVideoH265DecPicBufMgr>,
+    _scaling_lists: Option<Box<StdVideoH265ScalingLists>>,
+    _short_term_ref_pic_sets: Vec<StdVideoH265ShortTermRefPicSet>,
+    _long_term_ref_pics: Option<Box<StdVideoH265LongTermRefPicsSps>>,
+    _vui: Option<Box<StdVideoH265SequenceParameterSetVui>>,
+}
+
+impl StdSps {
+    pub fn new(sps: &SeqParameterSet) -> Self {
+        let mut flags = StdVideoH265SpsFlags {
+            _bitfield_align_1: [],
+            _bitfield_1: Default::default(),
+        };
+
+        flags.set_sps_temporal_id_nesting_flag(sps.sps_temporal_id_nesting_flag.into());
+        flags.set_separate_colour_plane_flag(sps.separate_colour_plane_flag.into());
+        flags.set_conformance_window_flag(sps.conformance_window_flag.into());
+        flags.set_sps_sub_layer_ordering_info_present_flag(sps.sps_sub_layer_ordering_info_present_flag.into());
+        flags.set_scaling_list_enabled_flag(sps.scaling_list_enabled_flag.into());
+        flags.set_sps_scaling_list_data_present_flag(sps.sps_scaling_list_data_present_flag.into());
+        flags.set_amp_enabled_flag(sps.amp_enabled_flag.into());
+        flags.set_sample_adaptive_offset_enabled_flag(sps.sample_adaptive_offset_enabled_flag.into());
+        flags.set_pcm_enabled_flag(sps.pcm_enabled_flag.into());
+        flags.set_pcm_loop_filter_disabled_flag(sps.pcm_loop_filter_disabled_flag.into());
+        flags.set_long_term_ref_pics_present_flag(sps.long_term_ref_pics_present_flag.into());
+        flags.set_sps_temporal_mvp_enabled_flag(sps.sps_temporal_mvp_enabled_flag.into());
+        flags.set_strong_intra_smoothing_enabled_flag(sps.strong_intra_smoothing_enabled_flag.into());
+        flags.set_vui_parameters_present_flag(sps.vui_parameters.is_some().into());
+        flags.set_sps_extension_present_flag(sps.sps_extension_present_flag.into());
+
+        if let Some(extension) = &sps.range_extension {
+            flags.set_sps_range_extension_flag(1);
+            flags.set_transform_skip_rotation_enabled_flag(extension.transform_skip_rotation_enabled_flag.into());
+            flags.set_transform_skip_context_enabled_flag(extension.transform_skip_context_enabled_flag.into());
+            flags.set_implicit_rdpcm_enabled_flag(extension.implicit_rdpcm_enabled_flag.into());
+            flags.set_explicit_rdpcm_enabled_flag(extension.explicit_rdpcm_enabled_flag.into());
+            flags.set_extended_precision_processing_flag(extension.extended_precision_processing_flag.into());
+            flags.set_intra_smoothing_disabled_flag(extension.intra_smoothing_disabled_flag.into());
+            flags.set_high_precision_offsets_enabled_flag(extension.high_precision_offsets_enabled_flag.into());
+            flags.set_persistent_rice_adaptation_enabled_flag(extension.persistent_rice_adaptation_enabled_flag.into());
+            flags.set_cabac_bypass_alignment_enabled_flag(extension.cabac_bypass_alignment_enabled_flag.into());
+        }
+
+        let profile_tier_level = Box::new(std_profile_tier_level(&sps.profile_tier_level));
+        let dec_pic_buf_mgr = Box::new(std_dec_pic_buf_mgr(&sps.sub_layer_ordering));
+        let scaling_lists = sps.scaling_lists.map(Box::new);
+        let short_term_ref_pic_sets = sps
+            .short_term_ref_pic_sets
+            .iter()
+            .map(std_short_term_ref_pic_set)
+            .collect::<Vec<_>>();
+        let vui = sps.vui_parameters.as_ref().map(|x| Box::new(std_vui(x)));
+
+        let long_term_ref_pics = sps.long_term_ref_pics_present_flag.then(|| {
+            let mut long_term_ref_pics = StdVideoH265LongTermRefPicsSps {
+                used_by_curr_pic_lt_sps_flag: 0,
+                lt_ref_pic_poc_lsb_sps: [0; 32],
+            };
+
+            for (i, (lsb, used)) in sps.lt_ref_pic_poc_lsb_sps.iter().zip(&sps.used_by_curr_pic_lt_sps_flag).enumerate() {
+                long_term_ref_pics.lt_ref_pic_poc_lsb_sps[i] = *lsb;
+                long_term_ref_pics.used_by_curr_pic_lt_sps_flag |= u32::from(*used) << i;
+            }
+
+            Box::new(long_term_ref_pics)
+        });
+
+        let native = StdVideoH265SequenceParameterSet {
+            flags,
+            chroma_format_idc: StdVideoH265ChromaFormatIdc::from(sps.chroma_format_idc),
+            pic_width_in_luma_samples: sps.pic_width_in_luma_samples,
+            pic_height_in_luma_samples: sps.pic_height_in_luma_samples,
+            sps_video_parameter_set_id: sps.sps_video_parameter_set_id,
+            sps_max_sub_layers_minus1: sps.sps_max_sub_layers_minus1,
+            sps_seq_parameter_set_id: sps.sps_seq_parameter_set_id,
+            bit_depth_luma_minus8: sps.bit_depth_luma_minus8,
+            bit_depth_chroma_minus8: sps.bit_depth_chroma_minus8,
+            log2_max_pic_order_cnt_lsb_minus4: sps.log2_max_pic_order_cnt_lsb_minus4,
+            log2_min_luma_coding_block_size_minus3: sps.log2_min_luma_coding_block_size_minus3,
+            log2_diff_max_min_luma_coding_block_size: sps.log2_diff_max_min_luma_coding_block_size,
+            log2_min_luma_transform_block_size_minus2: sps.log2_min_luma_transform_block_size_minus2,
+            log2_diff_max_min_luma_transform_block_size: sps.log2_diff_max_min_luma_transform_block_size,
+            max_transform_hierarchy_depth_inter: sps.max_transform_hierarchy_depth_inter,
+            max_transform_hierarchy_depth_intra: sps.max_transform_hierarchy_depth_intra,
+            num_short_term_ref_pic_sets: short_term_ref_pic_sets.len() as u8,
+            num_long_term_ref_pics_sps: sps.lt_ref_pic_poc_lsb_sps.len() as u8,
+            pcm_sample_bit_depth_luma_minus1: sps.pcm_sample_bit_depth_luma_minus1,
+            pcm_sample_bit_depth_chroma_minus1: sps.pcm_sample_bit_depth_chroma_minus1,
+            log2_min_pcm_luma_coding_block_size_minus3: sps.log2_min_pcm_luma_coding_block_size_minus3,
+            log2_diff_max_min_pcm_luma_coding_block_size: sps.log2_diff_max_min_pcm_luma_coding_block_size,
+            reserved1: 0,
+            reserved2: 0,
+            palette_max_size: 0,
+            delta_palette_max_predictor_size: 0,
+            motion_vector_resolution_control_idc: 0,
+            sps_num_palette_predictor_initializers_minus1: 0,
+            conf_win_left_offset: sps.conf_win_left_offset,
+            conf_win_right_offset: sps.conf_win_right_offset,
+            conf_win_top_offset: sps.conf_win_top_offset,
+            conf_win_bottom_offset: sps.conf_win_bottom_offset,
+            pProfileTierLevel: &*profile_tier_level,
+            pDecPicBufMgr: &*dec_pic_buf_mgr,
+            pScalingLists: scaling_lists.as_deref().map_or(null(), |x| x as *const _),
+            pShortTermRefPicSet: if short_term_ref_pic_sets.is_empty() {
+                null()
+            } else {
+                short_term_ref_pic_sets.as_ptr()
+            },
+            pLongTermRefPicsSps: long_term_ref_pics.as_deref().map_or(null(), |x| x as *const _),
+            pSequenceParameterSetVui: vui.as_deref().map_or(null(), |x| x as *const _),
+            pPredictorPaletteEntries: null(),
+        };
+
+        Self {
+            native,
+            _profile_tier_level: profile_tier_level,
+            _dec_pic_buf_mgr: dec_pic_buf_mgr,
+            _scaling_lists: scaling_lists,
+            _short_term_ref_pic_sets: short_term_ref_pic_sets,
+            _long_term_ref_pics: long_term_ref_pics,
+            _vui: vui,
+        }
+    }
+
+    pub fn native(&self) -> &StdVideoH265SequenceParameterSet {
+        &self.native
+    }
+}
+
+/// A `StdVideoH265PictureParameterSet` that owns its scaling lists.
+pub struct StdPps {
+    native: StdVideoH265PictureParameterSet,
+    _scaling_lists: Option<Box<StdVideoH265ScalingLists>>,
+}
+
+impl StdPps {
+    /// Translates `pps`, which Vulkan identifies together with the VPS of its SPS.
+    pub fn new(pps: &PicParameterSet, sps_video_parameter_set_id: u8) -> Self {
+        let mut flags = StdVideoH265PpsFlags {
+            _bitfield_align_1: [],
+            _bitfield_1: Default::default(),
+        };
+
+        flags.set_dependent_slice_segments_enabled_flag(pps.dependent_slice_segments_enabled_flag.into());
+        flags.set_output_flag_present_flag(pps.output_flag_present_flag.into());
+        flags.set_sign_data_hiding_enabled_flag(pps.sign_data_hiding_enabled_flag.into());
+        flags.set_cabac_init_present_flag(pps.cabac_init_present_flag.into());
+        flags.set_constrained_intra_pred_flag(pps.constrained_intra_pred_flag.into());
+        flags.set_transform_skip_enabled_flag(pps.transform_skip_enabled_flag.into());
+        flags.set_cu_qp_delta_enabled_flag(pps.cu_qp_delta_enabled_flag.into());
+        flags.set_pps_slice_chroma_qp_offsets_present_flag(pps.pps_slice_chroma_qp_offsets_present_flag.into());
+        flags.set_weighted_pred_flag(pps.weighted_pred_flag.into());
+        flags.set_weighted_bipred_flag(pps.weighted_bipred_flag.into());
+        flags.set_transquant_bypass_enabled_flag(pps.transquant_bypass_enabled_flag.into());
+        flags.set_tiles_enabled_flag(pps.tiles_enabled_flag.into());
+        flags.set_entropy_coding_sync_enabled_flag(pps.entropy_coding_sync_enabled_flag.into());
+        flags.set_uniform_spacing_flag(pps.uniform_spacing_flag.into());
+        flags.set_loop_filter_across_tiles_enabled_flag(pps.loop_filter_across_tiles_enabled_flag.into());
+        flags.set_pps_loop_filter_across_slices_enabled_flag(pps.pps_loop_filter_across_slices_enabled_flag.into());
+        flags.set_deblocking_filter_control_present_flag(pps.deblocking_filter_control_present_flag.into());
+        flags.set_deblocking_filter_override_enabled_flag(pps.deblocking_filter_override_enabled_flag.into());
+        flags.set_pps_deblocking_filter_disabled_flag(pps.pps_deblocking_filter_disabled_flag.into());
+        flags.set_pps_scaling_list_data_present_flag(pps.pps_scaling_list_data_present_flag.into());
+        flags.set_lists_modification_present_flag(pps.lists_modification_present_flag.into());
+        flags.set_slice_segment_header_extension_present_flag(pps.slice_segment_header_extension_present_flag.into());
+        flags.set_pps_extension_present_flag(pps.pps_extension_present_flag.into());
+
+        let extension = pps.range_extension.clone().unwrap_or_default();
+        let mut cb_qp_offset_list = [0; 6];
+        let mut cr_qp_offset_list = [0; 6];
+
+        cb_qp_offset_list[..extension.cb_qp_offset_list.len()].copy_from_slice(&extension.cb_qp_offset_list);
+        cr_qp_offset_list[..extension.cr_qp_offset_list.len()].copy_from_slice(&extension.cr_qp_offset_list);
+
+        flags.set_pps_range_extension_flag(pps.range_extension.is_some().into());
+        flags.set_cross_component_prediction_enabled_flag(extension.cross_component_prediction_enabled_flag.into());
+        flags.set_chroma_qp_offset_list_enabled_flag(extension.chroma_qp_offset_list_enabled_flag.into());
+
+        let mut column_width_minus1 = [0; 19];
+        let mut row_height_minus1 = [0; 21];
+
+        column_width_minus1[..pps.column_width_minus1.len()].copy_from_slice(&pps.column_width_minus1);
+        row_height_minus1[..pps.row_height_minus1.len()].copy_from_slice(&pps.row_height_minus1);
+
+        let scaling_lists = pps.scaling_lists.map(Box::new);
+
+        let native = StdVideoH265PictureParameterSet {
+            flags,
+            pps_pic_parameter_set_id: pps.pps_pic_parameter_set_id,
+            pps_seq_parameter_set_id: pps.pps_seq_parameter_set_id,
+            sps_video_parameter_set_id,
+            num_extra_slice_header_bits: pps.num_extra_slice_header_bits,
+            num_ref_idx_l0_default_active_minus1: pps.num_ref_idx_l0_default_active_minus1,
+            num_ref_idx_l1_default_active_minus1: pps.num_ref_idx_l1_default_active_minus1,
+            init_qp_minus26: pps.init_qp_minus26,
+            diff_cu_qp_delta_depth: pps.diff_cu_qp_delta_depth,
+            pps_cb_qp_offset: pps.pps_cb_qp_offset,
+            pps_cr_qp_offset: pps.pps_cr_qp_offset,
+            pps_beta_offset_div2: pps.pps_beta_offset_div2,
+            pps_tc_offset_div2: pps.pps_tc_offset_div2,
+            log2_parallel_merge_level_minus2: pps.log2_parallel_merge_level_minus2,
+            log2_max_transform_skip_block_size_minus2: extension.log2_max_transform_skip_block_size_minus2,
+            diff_cu_chroma_qp_offset_depth: extension.diff_cu_chroma_qp_offset_depth,
+            chroma_qp_offset_list_len_minus1: extension.chroma_qp_offset_list_len_minus1,
+            cb_qp_offset_list,
+            cr_qp_offset_list,
+            log2_sao_offset_scale_luma: extension.log2_sao_offset_scale_luma,
+            log2_sao_offset_scale_chroma: extension.log2_sao_offset_scale_chroma,
+            pps_act_y_qp_offset_plus5: 0,
+            pps_act_cb_qp_offset_plus5: 0,
+            pps_act_cr_qp_offset_plus3: 0,
+            pps_num_palette_predictor_initializers: 0,
+            luma_bit_depth_entry_minus8: 0,
+            chroma_bit_depth_entry_minus8: 0,
+            num_tile_columns_minus1: pps.num_tile_columns_minus1,
+            num_tile_rows_minus1: pps.num_tile_rows_minus1,
+            reserved1: 0,
+            reserved2: 0,
+            column_width_minus1,
+            row_height_minus1,
+            reserved3: 0,
+            pScalingLists: scaling_lists.as_deref().map_or(null(), |x| x as *const _),
+            pPredictorPaletteEntries: null(),
+        };
+
+        Self {
+            native,
+            _scaling_lists: scaling_lists,
+        }
+    }
+
+    pub fn native(&self) -> &StdVideoH265PictureParameterSet {
+        &self.native
+    }
+}
+
+/// Translates a picture about to be decoded into its `StdVideoDecodeH265PictureInfo`.
+pub(crate) fn std_picture_info(picture: &DpbPicture) -> StdVideoDecodeH265PictureInfo {
+    let mut flags = StdVideoDecodeH265PictureInfoFlags {
+        _bitfield_align_1: [],
+        _bitfield_1: Default::default(),
+        __bindgen_padding_0: Default::default(),
+    };
+
+    flags.set_IrapPicFlag(picture.irap.into());
+    flags.set_IdrPicFlag(picture.idr.into());
+    flags.set_IsReference(picture.reference.into());
+    flags.set_short_term_ref_pic_set_sps_flag(picture.short_term_ref_pic_set_sps_flag.into());
+
+    // Unused entries hold `STD_VIDEO_H265_NO_REFERENCE_PICTURE`.
+    let slots = |slots: &[u8]| {
+        let mut native = [0xFF; 8];
+
+        for (native, slot) in native.iter_mut().zip(slots) {
+            *native = *slot;
+        }
+
+        native
+    };
+
+    StdVideoDecodeH265PictureInfo {
+        flags,
+        sps_video_parameter_set_id: picture.video_parameter_set_id,
+        pps_seq_parameter_set_id: picture.seq_parameter_set_id,
+        pps_pic_parameter_set_id: picture.pic_parameter_set_id,
+        NumDeltaPocsOfRefRpsIdx: picture.num_delta_pocs_of_ref_rps_idx,
+        PicOrderCntVal: picture.pic_order_cnt,
+        NumBitsForSTRefPicSetInSlice: picture.num_bits_for_st_ref_pic_set_in_slice,
+        reserved: 0,
+        RefPicSetStCurrBefore: slots(&picture.st_curr_before),
+        RefPicSetStCurrAfter: slots(&picture.st_curr_after),
+        RefPicSetLtCurr: slots(&picture.lt_curr),
+    }
+}
+
+/// Translates a reference into its `StdVideoDecodeH265ReferenceInfo`.
+pub(crate) fn std_reference_info(reference: &DpbReference) -> StdVideoDecodeH265ReferenceInfo {
+    let mut flags = StdVideoDecodeH265ReferenceInfoFlags {
+        _bitfield_align_1: [],
+        _bitfield_1: Default::default(),
+        __bindgen_padding_0: Default::default(),
+    };
+
+    flags.set_used_for_long_term_reference(reference.long_term.into());
+
+    StdVideoDecodeH265ReferenceInfo {
+        flags,
+        PicOrderCntVal: reference.pic_order_cnt,
+    }
+}
+
+fn std_profile_tier_level(profile_tier_level: &ProfileTierLevel) -> StdVideoH265ProfileTierLevel {
+    let mut flags = StdVideoH265ProfileTierLevelFlags {
+        _bitfield_align_1: [],
+        _bitfield_1: Default::default(),
+        __bindgen_padding_0: Default::default(),
+    };
+
+    flags.set_general_tier_flag(profile_tier_level.general_tier_flag.into());
+    flags.set_general_progressive_source_flag(profile_tier_level.general_progressive_source_flag.into());
+    flags.set_general_interlaced_source_flag(profile_tier_level.general_interlaced_source_flag.into());
+    flags.set_general_non_packed_constraint_flag(profile_tier_level.general_non_packed_constraint_flag.into());
+    flags.set_general_frame_only_constraint_flag(profile_tier_level.general_frame_only_constraint_flag.into());
+
+    StdVideoH265ProfileTierLevel {
+        flags,
+        general_profile_idc: StdVideoH265ProfileIdc::from(profile_tier_level.general_profile_idc),
+        general_level_idc: std_level_idc(profile_tier_level.general_level_idc),
+    }
+}
+
+fn std_level_idc(general_level_idc: u8) -> StdVideoH265LevelIdc {
+    match general_level_idc {
+        30 => StdVideoH265LevelIdc_STD_VIDEO_H265_LEVEL_IDC_1_0,
+        60 => StdVideoH265LevelIdc_STD_VIDEO_H265_LEVEL_IDC_2_0,
+        63 => StdVideoH265LevelIdc_STD_VIDEO_H265_LEVEL_IDC_2_1,
+        90 => StdVideoH265LevelIdc_STD_VIDEO_H265_LEVEL_IDC_3_0,
+        93 => StdVideoH265LevelIdc_STD_VIDEO_H265_LEVEL_IDC_3_1,
+        120 => StdVideoH265LevelIdc_STD_VIDEO_H265_LEVEL_IDC_4_0,
+        123 => StdVideoH265LevelIdc_STD_VIDEO_H265_LEVEL_IDC_4_1,
+        150 => StdVideoH265LevelIdc_STD_VIDEO_H265_LEVEL_IDC_5_0,
+        153 => StdVideoH265LevelIdc_STD_VIDEO_H265_LEVEL_IDC_5_1,
+        156 => StdVideoH265LevelIdc_STD_VIDEO_H265_LEVEL_IDC_5_2,
+        180 => StdVideoH265LevelIdc_STD_VIDEO_H265_LEVEL_IDC_6_0,
+        183 => StdVideoH265LevelIdc_STD_VIDEO_H265_LEVEL_IDC_6_1,
+        186 => StdVideoH265LevelIdc_STD_VIDEO_H265_LEVEL_IDC_6_2,
+        _ => StdVideoH265LevelIdc_STD_VIDEO_H265_LEVEL_IDC_INVALID,
+    }
+}
+
+fn std_dec_pic_buf_mgr(sub_layer_ordering: &[SubLayerOrdering]) -> StdVideoH265DecPicBufMgr {
+    let mut dec_pic_buf_mgr = StdVideoH265DecPicBufMgr {
+        max_latency_increase_plus1: [0; 7],
+        max_dec_pic_buffering_minus1: [0; 7],
+        max_num_reorder_pics: [0; 7],
+    };
+
+    for (i, ordering) in sub_layer_ordering.iter().take(7).enumerate() {
+        dec_pic_buf_mgr.max_latency_increase_plus1[i] = ordering.max_latency_increase_plus1;
+        dec_pic_buf_mgr.max_dec_pic_buffering_minus1[i] = ordering.max_dec_pic_buffering_minus1;
+        dec_pic_buf_mgr.max_num_reorder_pics[i] = ordering.max_num_reorder_pics;
+    }
+
+    dec_pic_buf_mgr
+}
+
+/// Vulkan wants both the syntax elements and, for explicitly coded sets, the deltas between neighbouring pictures.
+fn std_short_term_ref_pic_set(set: &ShortTermRefPicSet) -> StdVideoH265ShortTermRefPicSet {
+    let mut flags = StdVideoH265ShortTermRefPicSetFlags {
+        _bitfield_align_1: [],
+        _bitfield_1: Default::default(),
+        __bindgen_padding_0: Default::default(),
+    };
+
+    flags.set_inter_ref_pic_set_prediction_flag(set.inter_ref_pic_set_prediction_flag.into());
+    flags.set_delta_rps_sign(set.delta_rps_sign.into());
+
+    let bits = |flags: &[bool]| flags.iter().enumerate().fold(0u16, |bits, (i, flag)| bits | u16::from(*flag) << i);
+
+    let mut native = StdVideoH265ShortTermRefPicSet {
+        flags,
+        delta_idx_minus1: set.delta_idx_minus1,
+        use_delta_flag: bits(&set.use_delta_flag),
+        abs_delta_rps_minus1: set.abs_delta_rps_minus1 as u16,
+        used_by_curr_pic_flag: bits(&set.used_by_curr_pic_flag),
+        used_by_curr_pic_s0_flag: bits(&set.used_by_curr_pic_s0),
+        used_by_curr_pic_s1_flag: bits(&set.used_by_curr_pic_s1),
+        reserved1: 0,
+        reserved2: 0,
+        reserved3: 0,
+        num_negative_pics: set.delta_poc_s0.len() as u8,
+        num_positive_pics: set.delta_poc_s1.len() as u8,
+        delta_poc_s0_minus1: [0; 16],
+        delta_poc_s1_minus1: [0; 16],
+    };
+
+    let mut prev = 0;
+
+    for (i, delta_poc) in set.delta_poc_s0.iter().enumerate() {
+        native.delta_poc_s0_minus1[i] = (prev - delta_poc - 1) as u16;
+        prev = *delta_poc;
+    }
+
+    prev = 0;
+
+    for (i, delta_poc) in set.delta_poc_s1.iter().enumerate() {
+        native.delta_poc_s1_minus1[i] = (delta_poc - prev - 1) as u16;
+        prev = *delta_poc;
+    }
+
+    native
+}
+
+/// HRD parameters are not parsed, so `vui_hrd_parameters_present_flag` stays unset.
+fn std_vui(vui: &VuiParameters) -> StdVideoH265SequenceParameterSetVui {
+    let mut flags = StdVideoH265SpsVuiFlags {
+        _bitfield_align_1: [],
+        _bitfield_1: Default::default(),
+        __bindgen_padding_0: 0,
+    };
+
+    flags.set_aspect_ratio_info_present_flag(vui.aspect_ratio_info_present_flag.into());
+    flags.set_overscan_info_present_flag(vui.overscan_info_present_flag.into());
+    flags.set_overscan_appropriate_flag(vui.overscan_appropriate_flag.into());
+    flags.set_video_signal_type_present_flag(vui.video_signal_type_present_flag.into());
+    flags.set_video_full_range_flag(vui.video_full_range_flag.into());
+    flags.set_colour_description_present_flag(vui.colour_description_present_flag.into());
+    flags.set_chroma_loc_info_present_flag(vui.chroma_loc_info_present_flag.into());
+    flags.set_neutral_chroma_indication_flag(vui.neutral_chroma_indication_flag.into());
+    flags.set_field_seq_flag(vui.field_seq_flag.into());
+    flags.set_frame_field_info_present_flag(vui.frame_field_info_present_flag.into());
+    flags.set_default_display_window_flag(vui.default_display_window_flag.into());
+    flags.set_vui_timing_info_present_flag(vui.vui_timing_info_present_flag.into());
+    flags.set_vui_poc_proportional_to_timing_flag(vui.vui_poc_proportional_to_timing_flag.into());
+    flags.set_bitstream_restriction_flag(vui.bitstream_restriction_flag.into());
+    flags.set_tiles_fixed_structure_flag(vui.tiles_fixed_structure_flag.into());
+    flags.set_motion_vectors_over_pic_boundaries_flag(vui.motion_vectors_over_pic_boundaries_flag.into());
+    flags.set_restricted_ref_pic_lists_flag(vui.restricted_ref_pic_lists_flag.into());
+
+    StdVideoH265SequenceParameterSetVui {
+        flags,
+        aspect_ratio_idc: StdVideoH265AspectRatioIdc::from(vui.aspect_ratio_idc),
+        sar_width: vui.sar_width,
+        sar_height: vui.sar_height,
+        video_format: vui.video_format,
+        colour_primaries: vui.colour_primaries,
+        transfer_characteristics: vui.transfer_characteristics,
+        matrix_coeffs: vui.matrix_coeffs,
+        chroma_sample_loc_type_top_field: vui.chroma_sample_loc_type_top_field,
+        chroma_sample_loc_type_bottom_field: vui.chroma_sample_loc_type_bottom_field,
+        reserved1: 0,
+        reserved2: 0,
+        def_disp_win_left_offset: vui.def_disp_win_left_offset as u16,
+        def_disp_win_right_offset: vui.def_disp_win_right_offset as u16,
+        def_disp_win_top_offset: vui.def_disp_win_top_offset as u16,
+        def_disp_win_bottom_offset: vui.def_disp_win_bottom_offset as u16,
+        vui_num_units_in_tick: vui.vui_num_units_in_tick,
+        vui_time_scale: vui.vui_time_scale,
+        vui_num_ticks_poc_diff_one_minus1: vui.vui_num_ticks_poc_diff_one_minus1,
+        min_spatial_segmentation_idc: vui.min_spatial_segmentation_idc,
+        reserved3: 0,
+        max_bytes_per_pic_denom: vui.max_bytes_per_pic_denom,
+        max_bits_per_min_cu_denom: vui.max_bits_per_min_cu_denom,
+        log2_max_mv_length_horizontal: vui.log2_max_mv_length_horizontal,
+        log2_max_mv_length_vertical: vui.log2_max_mv_length_vertical,
+        pHrdParameters: null(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{StdPps, StdSps, StdVps};
+    use crate::video::h265::testdata::{rbsp, PPS, SPS, VPS};
+    use crate::video::h265::{PicParameterSet, SeqParameterSet, VideoParameterSet};
+    use ash::vk::native::{StdVideoH265LevelIdc_STD_VIDEO_H265_LEVEL_IDC_3_1, StdVideoH265ProfileIdc_STD_VIDEO_H265_PROFILE_IDC_MAIN};
+    use h264_reader::rbsp::BitReader;
+
+    #[test]
+    fn translates_parameter_sets() {
+        let vps = VideoParameterSet::from_bits(BitReader::new(&rbsp(VPS)[..])).unwrap();
+        let sps = SeqParameterSet::from_bits(BitReader::new(&rbsp(SPS)[..])).unwrap();
+        let pps = PicParameterSet::from_bits(BitReader::new(&rbsp(PPS)[..])).unwrap();
+
+        let std_vps = StdVps::new(&vps);
+        let std_sps = StdSps::new(&sps);
+        let std_pps = StdPps::new(&pps, sps.sps_video_parameter_set_id);
+
+        // Move them around to make sure pointers don't dangle.
+        let (std_vps, std_sps) = (Box::new(std_vps), Box::new(std_sps));
+
+        let native = std_vps.native();
+        let profile_tier_level = unsafe { &*native.pProfileTierLevel };
+        let dec_pic_buf_mgr = unsafe { &*native.pDecPicBufMgr };
+
+        assert_eq!(
+            profile_tier_level.general_profile_idc,
+            StdVideoH265ProfileIdc_STD_VIDEO_H265_PROFILE_IDC_MAIN
+        );
+        assert_eq!(
+            profile_tier_level.general_level_idc,
+            StdVideoH265LevelIdc_STD_VIDEO_H265_LEVEL_IDC_3_1
+        );
+        assert_eq!(dec_pic_buf_mgr.max_dec_pic_buffering_minus1[0], 2);
+        assert!(native.pHrdParameters.is_null());
+
+        let native = std_sps.native();
+        let sets = unsafe { std::slice::from_raw_parts(native.pShortTermRefPicSet, native.num_short_term_ref_pic_sets.into()) };
+        let long_term = unsafe { &*native.pLongTermRefPicsSps };
+        let vui = unsafe { &*native.pSequenceParameterSetVui };
+
+        assert_eq!((native.pic_width_in_luma_samples, native.conf_win_bottom_offset), (64, 4));
+        assert_eq!(native.flags.conformance_window_flag(), 1);
+        assert_eq!(
+            (
+                sets[0].num_negative_pics,
+                sets[0].delta_poc_s0_minus1[0],
+                sets[0].used_by_curr_pic_s0_flag
+            ),
+            (1, 0, 1)
+        );
+        assert_eq!(sets[1].flags.inter_ref_pic_set_prediction_flag(), 1);
+        assert_eq!((sets[1].used_by_curr_pic_flag, sets[1].use_delta_flag), (0b11, 0b11));
+        assert_eq!((sets[1].num_negative_pics, sets[1].delta_poc_s0_minus1[1]), (2, 0));
+        assert_eq!((native.num_long_term_ref_pics_sps, long_term.used_by_curr_pic_lt_sps_flag), (1, 1));
+        assert_eq!((vui.flags.video_full_range_flag(), vui.matrix_coeffs), (1, 1));
+        assert!(native.pScalingLists.is_null());
+
+        let native = std_pps.native();
+
+        assert_eq!((native.pps_pic_parameter_set_id, native.sps_video_parameter_set_id), (0, 0));
+        assert_eq!(native.flags.pps_loop_filter_across_slices_enabled_flag(), 1);
+        assert!(native.pScalingLists.is_null());
+    }
+}
@@ -0,0 +1,91 @@
+//! Helpers to hand-craft H.265 bitstreams in tests.
+use crate::video::h265::nal::NalUnitType;
+use crate::video::h265::{H265StreamInspector, SeqParameterSet, SliceSegmentHeader, SliceType};
+use h264_reader::rbsp::BitReader;
+
+pub use crate::video::h264::testdata::rbsp;
+
+/// A VPS with a single Main profile level 3.1 layer, 3 pictures in the DPB and 30 Hz timing.
+pub const VPS: &str = concat!(
+    "0000 1 1 000000 000 1 1111111111111111",
+    "00 0 00001 01100000000000000000000000000000 1 0 0 1 00000000000000000000000000000000000000000000 01011101",
+    "1 011 1 1 000000 1 1",
+    "00000000000000000000000000000001 00000000000000000000000000011110",
+    "0 1 0"
+);
+
+/// A Main profile SPS, 4:2:0 8-bit, 64x64 cropped to 64x56, with 8 POC LSB bits, two short-term reference picture
+/// sets (the second predicted from the first), a long-term candidate and a full range BT.709 VUI.
+pub const SPS: &str = concat!(
+    "0000 000 1",
+    "00 0 00001 01100000000000000000000000000000 1 0 0 1 00000000000000000000000000000000000000000000 01011101",
+    "1 010 0000001000001 0000001000001 1 1 1 1 00101 1 1 00101 1 011 1 1 1 011 1 00100 1 1 0 1 1 0 011 010 1 1 1 1 1 1 1 1 1 010 ",
+    "00000000 1 1 1 1",
+    "0 0 1 101 1 1 00000001 00000001 00000001 0 0 0 0 0 1",
+    "00000000000000000000000000000001 00000000000000000000000000111100",
+    "0 0 0",
+    "0"
+);
+
+/// A PPS with id 0 belonging to [`SPS`], without tiles or scaling lists.
+pub const PPS: &str = "1 1 0 0 000 0 0 1 1 1 0 0 0 1 1 0 0 0 0 0 0 1 0 0 0 1 0 0";
+
+/// Builds an Annex B NAL unit with start code, a two byte header of the given type and the RBSP `bits`,
+/// inserting emulation prevention bytes.
+pub fn nal(nal_unit_type: NalUnitType, bits: &str) -> Vec<u8> {
+    let mut nal = vec![0, 0, 0, 1, nal_unit_type.0 << 1, 1];
+    let mut zeros = 0;
+
+    for byte in rbsp(bits) {
+        if zeros >= 2 && byte <= 3 {
+            nal.push(3);
+            zeros = 0;
+        }
+
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+        nal.push(byte);
+    }
+
+    nal
+}
+
+/// [`SPS`], parsed.
+pub fn sps() -> SeqParameterSet {
+    SeqParameterSet::from_bits(BitReader::new(&rbsp(SPS)[..])).unwrap()
+}
+
+/// An inspector that has seen [`VPS`], [`SPS`] and [`PPS`], enough to create sessions, images and buffers.
+pub fn inspector() -> H265StreamInspector {
+    let mut inspector = H265StreamInspector::new();
+
+    inspector.feed_nal(&nal(NalUnitType::VPS_NUT, VPS)).unwrap();
+    inspector.feed_nal(&nal(NalUnitType::SPS_NUT, SPS)).unwrap();
+    inspector.feed_nal(&nal(NalUnitType::PPS_NUT, PPS)).unwrap();
+    inspector
+}
+
+/// The first slice segment of a picture using the SPS's first short-term reference picture set, everything else
+/// zeroed. IRAP pictures are I slices, all others P slices.
+pub fn slice_segment_header(nal_unit_type: NalUnitType, slice_pic_order_cnt_lsb: u32) -> SliceSegmentHeader {
+    SliceSegmentHeader {
+        nal_unit_type,
+        temporal_id: 0,
+        first_slice_segment_in_pic_flag: true,
+        no_output_of_prior_pics_flag: false,
+        slice_pic_parameter_set_id: 0,
+        slice_seq_parameter_set_id: 0,
+        dependent_slice_segment_flag: false,
+        slice_segment_address: 0,
+        slice_type: if nal_unit_type.is_irap() { SliceType::I } else { SliceType::P },
+        pic_output_flag: true,
+        colour_plane_id: 0,
+        slice_pic_order_cnt_lsb,
+        short_term_ref_pic_set_sps_flag: true,
+        short_term_ref_pic_set_idx: 0,
+        short_term_ref_pic_set: None,
+        num_bits_for_st_ref_pic_set_in_slice: 0,
+        num_long_term_sps: 0,
+        long_term_refs: Vec::new(),
+        slice_temporal_mvp_enabled_flag: false,
+    }
+}
@@ -0,0 +1,251 @@
+//! Video parameter set (7.3.2.1) and the profile, tier and level syntax it shares with the SPS (7.3.3).
+use crate::error;
+use crate::error::{Error, Variant};
+use h264_reader::rbsp::BitRead;
+
+/// The general part of `profile_tier_level()` (7.3.3). Sub-layer profiles and levels are skipped.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProfileTierLevel {
+    pub general_profile_space: u8,
+    pub general_tier_flag: bool,
+    pub general_profile_idc: u8,
+    pub general_profile_compatibility_flags: u32,
+    pub general_progressive_source_flag: bool,
+    pub general_interlaced_source_flag: bool,
+    pub general_non_packed_constraint_flag: bool,
+    pub general_frame_only_constraint_flag: bool,
+    /// 30 times the level number, e.g., `93` for level 3.1.
+    pub general_level_idc: u8,
+}
+
+impl ProfileTierLevel {
+    pub(crate) fn from_bits<R: BitRead>(r: &mut R, max_sub_layers_minus1: u8) -> Result<Self, Error> {
+        let general_profile_space = r.read_u8(2, "general_profile_space")?;
+        let general_tier_flag = r.read_bool("general_tier_flag")?;
+        let general_profile_idc = r.read_u8(5, "general_profile_idc")?;
+        let general_profile_compatibility_flags = r.read_u32(32, "general_profile_compatibility_flag")?;
+        let general_progressive_source_flag = r.read_bool("general_progressive_source_flag")?;
+        let general_interlaced_source_flag = r.read_bool("general_interlaced_source_flag")?;
+        let general_non_packed_constraint_flag = r.read_bool("general_non_packed_constraint_flag")?;
+        let general_frame_only_constraint_flag = r.read_bool("general_frame_only_constraint_flag")?;
+
+        // The profile specific constraint flags and `general_inbld_flag`.
+        skip_bits(r, 44, "general_reserved_zero_43bits")?;
+
+        let general_level_idc = r.read_u8(8, "general_level_idc")?;
+
+        let mut sub_layer_flags = Vec::new();
+
+        for _ in 0..max_sub_layers_minus1 {
+            let profile_present = r.read_bool("sub_layer_profile_present_flag")?;
+            let level_present = r.read_bool("sub_layer_level_present_flag")?;
+            sub_layer_flags.push((profile_present, level_present));
+        }
+
+        if max_sub_layers_minus1 > 0 {
+            skip_bits(r, 2 * (8 - u32::from(max_sub_layers_minus1)), "reserved_zero_2bits")?;
+        }
+
+        for (profile_present, level_present) in sub_layer_flags {
+            if profile_present {
+                skip_bits(r, 88, "sub_layer_profile")?;
+            }
+
+            if level_present {
+                skip_bits(r, 8, "sub_layer_level_idc")?;
+            }
+        }
+
+        Ok(Self {
+            general_profile_space,
+            general_tier_flag,
+            general_profile_idc,
+            general_profile_compatibility_flags,
+            general_progressive_source_flag,
+            general_interlaced_source_flag,
+            general_non_packed_constraint_flag,
+            general_frame_only_constraint_flag,
+            general_level_idc,
+        })
+    }
+}
+
+/// The DPB size and reordering of one temporal sub-layer, as given in a VPS or SPS.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SubLayerOrdering {
+    /// The DPB size in pictures, including the current picture, minus 1.
+    pub max_dec_pic_buffering_minus1: u8,
+    pub max_num_reorder_pics: u8,
+    pub max_latency_increase_plus1: u32,
+}
+
+impl SubLayerOrdering {
+    /// Reads the orderings of all sub-layers, inferring the ones not present from the highest sub-layer (7.4.3.2.1).
+    ///
+    /// Returns `sub_layer_ordering_info_present_flag` and one ordering per sub-layer, fails with `invalid` if the DPB
+    /// would be too large.
+    pub(crate) fn from_bits<R: BitRead>(r: &mut R, max_sub_layers_minus1: u8, invalid: Variant) -> Result<(bool, Vec<Self>), Error> {
+        let info_present_flag = r.read_bool("sub_layer_ordering_info_present_flag")?;
+        let first = if info_present_flag { 0 } else { max_sub_layers_minus1 };
+        let mut orderings = Vec::new();
+
+        for _ in first..=max_sub_layers_minus1 {
+            let max_dec_pic_buffering_minus1 = r.read_ue("max_dec_pic_buffering_minus1")?;
+            let max_num_reorder_pics = r.read_ue("max_num_reorder_pics")?;
+            let max_latency_increase_plus1 = r.read_ue("max_latency_increase_plus1")?;
+
+            // `MaxDpbSize` is 16 at most (A.4.2).
+            if max_dec_pic_buffering_minus1 > 15 || max_num_reorder_pics > max_dec_pic_buffering_minus1 {
+                return Err(error!(
+                    invalid,
+                    "Invalid max_dec_pic_buffering_minus1 {} or max_num_reorder_pics {}",
+                    max_dec_pic_buffering_minus1,
+                    max_num_reorder_pics
+                ));
+            }
+
+            orderings.push(Self {
+                max_dec_pic_buffering_minus1: max_dec_pic_buffering_minus1 as u8,
+                max_num_reorder_pics: max_num_reorder_pics as u8,
+                max_latency_increase_plus1,
+            });
+        }
+
+        while orderings.len() <= usize::from(max_sub_layers_minus1) {
+            orderings.insert(0, orderings[0]);
+        }
+
+        Ok((info_present_flag, orderings))
+    }
+}
+
+/// A parsed VPS, up to and including its timing information.
+///
+/// Decoding only needs the VPS to hand it to Vulkan, HRD parameters and extensions are not parsed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VideoParameterSet {
+    pub vps_video_parameter_set_id: u8,
+    pub vps_base_layer_internal_flag: bool,
+    pub vps_base_layer_available_flag: bool,
+    pub vps_max_layers_minus1: u8,
+    pub vps_max_sub_layers_minus1: u8,
+    pub vps_temporal_id_nesting_flag: bool,
+    pub profile_tier_level: ProfileTierLevel,
+    pub vps_sub_layer_ordering_info_present_flag: bool,
+    /// One entry per sub-layer.
+    pub sub_layer_ordering: Vec<SubLayerOrdering>,
+    pub vps_max_layer_id: u8,
+    pub vps_num_layer_sets_minus1: u32,
+    pub vps_timing_info_present_flag: bool,
+    pub vps_num_units_in_tick: u32,
+    pub vps_time_scale: u32,
+    pub vps_poc_proportional_to_timing_flag: bool,
+    pub vps_num_ticks_poc_diff_one_minus1: u32,
+}
+
+impl VideoParameterSet {
+    /// Parses a VPS from its RBSP.
+    pub fn from_bits<R: BitRead>(mut r: R) -> Result<Self, Error> {
+        let vps_video_parameter_set_id = r.read_u8(4, "vps_video_parameter_set_id")?;
+        let vps_base_layer_internal_flag = r.read_bool("vps_base_layer_internal_flag")?;
+        let vps_base_layer_available_flag = r.read_bool("vps_base_layer_available_flag")?;
+        let vps_max_layers_minus1 = r.read_u8(6, "vps_max_layers_minus1")?;
+        let vps_max_sub_layers_minus1 = r.read_u8(3, "vps_max_sub_layers_minus1")?;
+        let vps_temporal_id_nesting_flag = r.read_bool("vps_temporal_id_nesting_flag")?;
+        let _vps_reserved_0xffff_16bits = r.read_u16(16, "vps_reserved_0xffff_16bits")?;
+
+        if vps_max_sub_layers_minus1 > 6 {
+            return Err(error!(
+                Variant::InvalidVps,
+                "Invalid vps_max_sub_layers_minus1 {}", vps_max_sub_layers_minus1
+            ));
+        }
+
+        let profile_tier_level = ProfileTierLevel::from_bits(&mut r, vps_max_sub_layers_minus1)?;
+        let (vps_sub_layer_ordering_info_present_flag, sub_layer_ordering) =
+            SubLayerOrdering::from_bits(&mut r, vps_max_sub_layers_minus1, Variant::InvalidVps)?;
+
+        let vps_max_layer_id = r.read_u8(6, "vps_max_layer_id")?;
+        let vps_num_layer_sets_minus1 = r.read_ue("vps_num_layer_sets_minus1")?;
+
+        if vps_num_layer_sets_minus1 > 1023 {
+            return Err(error!(
+                Variant::InvalidVps,
+                "Invalid vps_num_layer_sets_minus1 {}", vps_num_layer_sets_minus1
+            ));
+        }
+
+        for _ in 0..vps_num_layer_sets_minus1 {
+            skip_bits(&mut r, u32::from(vps_max_layer_id) + 1, "layer_id_included_flag")?;
+        }
+
+        let vps_timing_info_present_flag = r.read_bool("vps_timing_info_present_flag")?;
+        let mut vps_num_units_in_tick = 0;
+        let mut vps_time_scale = 0;
+        let mut vps_poc_proportional_to_timing_flag = false;
+        let mut vps_num_ticks_poc_diff_one_minus1 = 0;
+
+        if vps_timing_info_present_flag {
+            vps_num_units_in_tick = r.read_u32(32, "vps_num_units_in_tick")?;
+            vps_time_scale = r.read_u32(32, "vps_time_scale")?;
+            vps_poc_proportional_to_timing_flag = r.read_bool("vps_poc_proportional_to_timing_flag")?;
+
+            if vps_poc_proportional_to_timing_flag {
+                vps_num_ticks_poc_diff_one_minus1 = r.read_ue("vps_num_ticks_poc_diff_one_minus1")?;
+            }
+        }
+
+        Ok(Self {
+            vps_video_parameter_set_id,
+            vps_base_layer_internal_flag,
+            vps_base_layer_available_flag,
+            vps_max_layers_minus1,
+            vps_max_sub_layers_minus1,
+            vps_temporal_id_nesting_flag,
+            profile_tier_level,
+            vps_sub_layer_ordering_info_present_flag,
+            sub_layer_ordering,
+            vps_max_layer_id,
+            vps_num_layer_sets_minus1,
+            vps_timing_info_present_flag,
+            vps_num_units_in_tick,
+            vps_time_scale,
+            vps_poc_proportional_to_timing_flag,
+            vps_num_ticks_poc_diff_one_minus1,
+        })
+    }
+}
+
+/// Skips `bits` bits of syntax elements we don't need.
+pub(crate) fn skip_bits<R: BitRead>(r: &mut R, mut bits: u32, name: &'static str) -> Result<(), Error> {
+    while bits > 0 {
+        let n = bits.min(32);
+        r.read_u32(n, name)?;
+        bits -= n;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::VideoParameterSet;
+    use crate::video::h265::testdata::{rbsp, VPS};
+    use h264_reader::rbsp::BitReader;
+
+    #[test]
+    fn parses_vps() {
+        let vps = VideoParameterSet::from_bits(BitReader::new(&rbsp(VPS)[..])).unwrap();
+
+        assert_eq!(vps.vps_video_parameter_set_id, 0);
+        assert_eq!(vps.vps_max_sub_layers_minus1, 0);
+        assert_eq!(vps.profile_tier_level.general_profile_idc, 1);
+        assert_eq!(vps.profile_tier_level.general_level_idc, 93);
+        assert_eq!(vps.sub_layer_ordering[0].max_dec_pic_buffering_minus1, 2);
+        assert!(vps.vps_timing_info_present_flag);
+        assert_eq!((vps.vps_num_units_in_tick, vps.vps_time_scale), (1, 30));
+
+        // Cut off in the middle of the profile.
+        assert!(VideoParameterSet::from_bits(BitReader::new(&rbsp(VPS)[..6])).is_err());
+    }
+}
@@ -0,0 +1,112 @@
+//! What sessions, images and buffers need to know about a stream, whatever its codec.
+use crate::error::Error;
+use crate::video::h264::{H264StreamInspector, StdPps, StdSps};
+use crate::video::h265::{self, H265StreamInspector};
+use crate::video::profile::VideoProfileInfoBundle;
+use crate::video::SessionRequirements;
+use std::collections::HashMap;
+use std::pin::Pin;
+
+/// A stream inspector video sessions, session parameters, images and buffers can be created for.
+///
+/// Implemented by [`H264StreamInspector`] and [`H265StreamInspector`].
+pub trait StreamInspector: private::Inspector {}
+
+/// The parameter sets of a stream, by id and a hash of their content.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Fingerprints {
+    pub vps: HashMap<u8, u64>,
+    pub sps: HashMap<u8, u64>,
+    pub pps: HashMap<u8, u64>,
+}
+
+/// All parameter sets of a stream, translated for Vulkan.
+pub enum StdParameterSets {
+    H264 {
+        sps: Vec<StdSps>,
+        pps: Vec<StdPps>,
+    },
+    H265 {
+        vps: Vec<h265::StdVps>,
+        sps: Vec<h265::StdSps>,
+        pps: Vec<h265::StdPps>,
+    },
+}
+
+pub(crate) mod private {
+    use super::{Fingerprints, StdParameterSets};
+    use crate::error::Error;
+    use crate::video::profile::VideoProfileInfoBundle;
+    use crate::video::SessionRequirements;
+    use std::pin::Pin;
+
+    /// The actual interface of [`StreamInspector`](super::StreamInspector), which only this crate can implement and call.
+    pub trait Inspector {
+        /// The Vulkan video profile of the most recent SPS.
+        fn profiles<'f>(&self) -> Result<Pin<Box<VideoProfileInfoBundle<'f>>>, Error>;
+
+        /// The session resources needed for the most recent SPS.
+        fn session_requirements(&self) -> Result<SessionRequirements, Error>;
+
+        /// Identifies the content of each parameter set seen so far.
+        fn fingerprints(&self) -> Fingerprints;
+
+        /// All parameter sets seen so far, translated for Vulkan.
+        fn std_parameter_sets(&self) -> StdParameterSets;
+    }
+}
+
+impl StreamInspector for H264StreamInspector {}
+
+impl private::Inspector for H264StreamInspector {
+    fn profiles<'f>(&self) -> Result<Pin<Box<VideoProfileInfoBundle<'f>>>, Error> {
+        H264StreamInspector::profiles(self)
+    }
+
+    fn session_requirements(&self) -> Result<SessionRequirements, Error> {
+        H264StreamInspector::session_requirements(self)
+    }
+
+    fn fingerprints(&self) -> Fingerprints {
+        Fingerprints {
+            vps: HashMap::new(),
+            sps: self.sps_fingerprints().clone(),
+            pps: self.pps_fingerprints().clone(),
+        }
+    }
+
+    fn std_parameter_sets(&self) -> StdParameterSets {
+        StdParameterSets::H264 {
+            sps: self.std_sps(),
+            pps: self.std_pps(),
+        }
+    }
+}
+
+impl StreamInspector for H265StreamInspector {}
+
+impl private::Inspector for H265StreamInspector {
+    fn profiles<'f>(&self) -> Result<Pin<Box<VideoProfileInfoBundle<'f>>>, Error> {
+        H265StreamInspector::profiles(self)
+    }
+
+    fn session_requirements(&self) -> Result<SessionRequirements, Error> {
+        H265StreamInspector::session_requirements(self)
+    }
+
+    fn fingerprints(&self) -> Fingerprints {
+        Fingerprints {
+            vps: self.vps_fingerprints().clone(),
+            sps: self.sps_fingerprints().clone(),
+            pps: self.pps_fingerprints().clone(),
+        }
+    }
+
+    fn std_parameter_sets(&self) -> StdParameterSets {
+        StdParameterSets::H265 {
+            vps: self.std_vps(),
+            sps: self.std_sps(),
+            pps: self.std_pps(),
+        }
+    }
+}
@@ -2,14 +2,23 @@
 
 #![allow(unused_imports)]
 
+mod color;
 pub mod h264;
+pub mod h265;
+pub(crate) mod inspector;
+mod profile;
+mod requirements;
 mod session;
 mod sessionparameters;
 mod utils;
 
+pub use color::ColorInfo;
+pub use inspector::StreamInspector;
 pub use session::VideoSession;
-pub use sessionparameters::VideoSessionParameters;
-pub use utils::nal_units;
+pub use sessionparameters::{SessionChange, VideoSessionParameters};
+pub use utils::{nal_units, NalReader, NalSplitter};
+pub(crate) use utils::{strip_annexb, START_CODE};
 
-pub(crate) use session::VideoSessionShared;
-pub(crate) use sessionparameters::VideoSessionParametersShared;
+pub(crate) use requirements::SessionRequirements;
+pub(crate) use session::{device_profiles, VideoSessionShared};
+pub(crate) use sessionparameters::{check_parameter_sets, VideoSessionParametersShared};
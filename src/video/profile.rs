@@ -0,0 +1,97 @@
+//! Vulkan video profiles, which sessions, images and buffers of a stream must agree on.
+use crate::error;
+use crate::error::{Error, Variant};
+use ash::vk::{
+    Format, VideoChromaSubsamplingFlagsKHR, VideoComponentBitDepthFlagsKHR, VideoDecodeH264ProfileInfoKHR, VideoDecodeH265ProfileInfoKHR,
+    VideoProfileInfoKHR, VideoProfileListInfoKHR,
+};
+use std::marker::PhantomPinned;
+
+/// The video profile of a stream, pinned since its structures point to each other.
+///
+/// `info` chains to the codec specific profile info of its `video_codec_operation`.
+#[derive(Default)]
+pub struct VideoProfileInfoBundle<'a> {
+    pub(crate) info_h264: VideoDecodeH264ProfileInfoKHR<'a>,
+    pub(crate) info_h265: VideoDecodeH265ProfileInfoKHR<'a>,
+    pub(crate) info: VideoProfileInfoKHR<'a>,
+    pub(crate) list: VideoProfileListInfoKHR<'a>,
+    _pinned: PhantomPinned,
+}
+
+pub(crate) fn component_bit_depth(bit_depth_minus8: u8) -> Result<VideoComponentBitDepthFlagsKHR, Error> {
+    match bit_depth_minus8 {
+        0 => Ok(VideoComponentBitDepthFlagsKHR::TYPE_8),
+        2 => Ok(VideoComponentBitDepthFlagsKHR::TYPE_10),
+        4 => Ok(VideoComponentBitDepthFlagsKHR::TYPE_12),
+        x => Err(error!(
+            Variant::UnsupportedFeature,
+            "Bit depth {} has no Vulkan video profile",
+            x + 8
+        )),
+    }
+}
+
+/// The formats pictures of `profile` can be decoded into, i.e., those matching its chroma subsampling and bit depth.
+pub(crate) fn picture_formats(profile: &VideoProfileInfoKHR) -> &'static [Format] {
+    type Chroma = VideoChromaSubsamplingFlagsKHR;
+    type Depth = VideoComponentBitDepthFlagsKHR;
+
+    match (profile.chroma_subsampling, profile.luma_bit_depth) {
+        (Chroma::MONOCHROME, Depth::TYPE_8) => &[Format::R8_UNORM],
+        (Chroma::MONOCHROME, Depth::TYPE_10) => &[Format::R10X6_UNORM_PACK16],
+        (Chroma::MONOCHROME, Depth::TYPE_12) => &[Format::R12X4_UNORM_PACK16],
+        (Chroma::TYPE_420, Depth::TYPE_8) => &[Format::G8_B8R8_2PLANE_420_UNORM, Format::G8_B8_R8_3PLANE_420_UNORM],
+        (Chroma::TYPE_420, Depth::TYPE_10) => &[
+            Format::G10X6_B10X6R10X6_2PLANE_420_UNORM_3PACK16,
+            Format::G10X6_B10X6_R10X6_3PLANE_420_UNORM_3PACK16,
+        ],
+        (Chroma::TYPE_420, Depth::TYPE_12) => &[
+            Format::G12X4_B12X4R12X4_2PLANE_420_UNORM_3PACK16,
+            Format::G12X4_B12X4_R12X4_3PLANE_420_UNORM_3PACK16,
+        ],
+        (Chroma::TYPE_422, Depth::TYPE_8) => &[Format::G8_B8R8_2PLANE_422_UNORM, Format::G8_B8_R8_3PLANE_422_UNORM],
+        (Chroma::TYPE_422, Depth::TYPE_10) => &[
+            Format::G10X6_B10X6R10X6_2PLANE_422_UNORM_3PACK16,
+            Format::G10X6_B10X6_R10X6_3PLANE_422_UNORM_3PACK16,
+        ],
+        (Chroma::TYPE_422, Depth::TYPE_12) => &[
+            Format::G12X4_B12X4R12X4_2PLANE_422_UNORM_3PACK16,
+            Format::G12X4_B12X4_R12X4_3PLANE_422_UNORM_3PACK16,
+        ],
+        (Chroma::TYPE_444, Depth::TYPE_8) => &[Format::G8_B8R8_2PLANE_444_UNORM, Format::G8_B8_R8_3PLANE_444_UNORM],
+        (Chroma::TYPE_444, Depth::TYPE_10) => &[
+            Format::G10X6_B10X6R10X6_2PLANE_444_UNORM_3PACK16,
+            Format::G10X6_B10X6_R10X6_3PLANE_444_UNORM_3PACK16,
+        ],
+        (Chroma::TYPE_444, Depth::TYPE_12) => &[
+            Format::G12X4_B12X4R12X4_2PLANE_444_UNORM_3PACK16,
+            Format::G12X4_B12X4_R12X4_3PLANE_444_UNORM_3PACK16,
+        ],
+        _ => &[],
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::picture_formats;
+    use ash::vk::{Format, VideoChromaSubsamplingFlagsKHR, VideoComponentBitDepthFlagsKHR, VideoProfileInfoKHR};
+
+    #[test]
+    fn picture_formats_follow_profile() {
+        let profile = |chroma_subsampling, luma_bit_depth| VideoProfileInfoKHR {
+            chroma_subsampling,
+            luma_bit_depth,
+            ..Default::default()
+        };
+
+        let main = profile(VideoChromaSubsamplingFlagsKHR::TYPE_420, VideoComponentBitDepthFlagsKHR::TYPE_8);
+        let high_10 = profile(VideoChromaSubsamplingFlagsKHR::TYPE_420, VideoComponentBitDepthFlagsKHR::TYPE_10);
+        let high_422 = profile(VideoChromaSubsamplingFlagsKHR::TYPE_422, VideoComponentBitDepthFlagsKHR::TYPE_8);
+
+        assert_eq!(picture_formats(&main)[0], Format::G8_B8R8_2PLANE_420_UNORM);
+        assert_eq!(picture_formats(&high_10)[0], Format::G10X6_B10X6R10X6_2PLANE_420_UNORM_3PACK16);
+        assert_eq!(picture_formats(&high_422)[0], Format::G8_B8R8_2PLANE_422_UNORM);
+        assert!(picture_formats(&VideoProfileInfoKHR::default()).is_empty());
+    }
+}
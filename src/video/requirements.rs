@@ -0,0 +1,37 @@
+//! What a video session needs to decode a stream, whatever its codec.
+use ash::vk::VideoCodecOperationFlagsKHR;
+
+/// What a video session needs to decode a stream of the most recent SPS, before applying device limits.
+///
+/// Built by `SessionRequirements::new_h264` and `SessionRequirements::new_h265`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SessionRequirements {
+    /// The size of a decoded frame in pixels, before cropping.
+    pub coded_width: u32,
+    pub coded_height: u32,
+    /// All frames the level's DPB can hold, plus the picture being decoded.
+    pub max_dpb_slots: u32,
+    /// The slots the DPB needs at least, i.e., all references plus the picture being decoded.
+    pub min_dpb_slots: u32,
+    pub max_active_reference_pictures: u32,
+    /// What the video profile is derived from, which cannot change within a session.
+    pub codec: VideoCodecOperationFlagsKHR,
+    pub profile_idc: u8,
+    /// `0` for monochrome, `1` for 4:2:0, `2` for 4:2:2 and `3` for 4:4:4, in both codecs.
+    pub chroma_format_idc: u8,
+    pub bit_depth_luma_minus8: u8,
+    pub bit_depth_chroma_minus8: u8,
+}
+
+impl SessionRequirements {
+    /// If profile and picture size are the same, which a session and its DPB images are created for.
+    pub fn same_format(&self, other: &Self) -> bool {
+        self.codec == other.codec
+            && self.profile_idc == other.profile_idc
+            && self.chroma_format_idc == other.chroma_format_idc
+            && self.bit_depth_luma_minus8 == other.bit_depth_luma_minus8
+            && self.bit_depth_chroma_minus8 == other.bit_depth_chroma_minus8
+            && self.coded_width == other.coded_width
+            && self.coded_height == other.coded_height
+    }
+}
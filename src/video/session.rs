@@ -2,19 +2,19 @@ use crate::allocation::{Allocation, MemoryTypeIndex};
 use crate::device::{Device, DeviceShared};
 use crate::error;
 use crate::error::{Error, Variant};
-use crate::video::h264::H264StreamInspector;
+use crate::video::profile::{picture_formats, VideoProfileInfoBundle};
+use crate::video::{SessionRequirements, StreamInspector};
 use ash::khr::{
     video_decode_queue::DeviceFn as KhrVideoDecodeQueueDeviceFn,
     video_queue::{DeviceFn as KhrVideoQueueDeviceFn, InstanceFn as KhrVideoQueueInstanceFn},
 };
-use ash::vk::native::{StdVideoH264ProfileIdc, StdVideoH264ProfileIdc_STD_VIDEO_H264_PROFILE_IDC_BASELINE};
 use ash::vk::{
     self, BindVideoSessionMemoryInfoKHR, ExtensionProperties, Extent2D, Format, ImageUsageFlags, PhysicalDeviceVideoFormatInfoKHR,
-    VideoCapabilitiesKHR, VideoChromaSubsamplingFlagsKHR, VideoCodecOperationFlagsKHR, VideoComponentBitDepthFlagsKHR,
-    VideoDecodeCapabilitiesKHR, VideoDecodeCapabilityFlagsKHR, VideoDecodeH264CapabilitiesKHR, VideoDecodeH264PictureLayoutFlagsKHR,
-    VideoDecodeH264ProfileInfoKHR, VideoFormatPropertiesKHR, VideoProfileInfoKHR, VideoProfileListInfoKHR, VideoSessionCreateFlagsKHR,
-    VideoSessionCreateInfoKHR, VideoSessionKHR, VideoSessionMemoryRequirementsKHR,
+    VideoCapabilitiesKHR, VideoCodecOperationFlagsKHR, VideoDecodeCapabilitiesKHR, VideoDecodeCapabilityFlagsKHR,
+    VideoDecodeH264CapabilitiesKHR, VideoDecodeH264PictureLayoutFlagsKHR, VideoDecodeH265CapabilitiesKHR, VideoFormatPropertiesKHR,
+    VideoProfileListInfoKHR, VideoSessionCreateFlagsKHR, VideoSessionCreateInfoKHR, VideoSessionKHR, VideoSessionMemoryRequirementsKHR,
 };
+use std::pin::Pin;
 use std::ptr::{null, null_mut};
 use std::sync::Arc;
 
@@ -40,10 +40,20 @@ pub(crate) struct VideoSessionShared {
     native_session: VideoSessionKHR,
     // allocations: Vec<Allocation>,
     decode_capabilities: VideoDecodeCapabilities,
+    max_coded_extent: Extent2D,
+    picture_layout: VideoDecodeH264PictureLayoutFlagsKHR,
+    picture_format: Format,
+    reference_picture_format: Format,
+    max_dpb_slots: u8,
+    max_active_reference_pictures: u32,
+    min_bitstream_buffer_offset_alignment: u64,
+    min_bitstream_buffer_size_alignment: u64,
+    // What the stream needed when the session was created.
+    requirements: SessionRequirements,
 }
 
 impl VideoSessionShared {
-    pub fn new(device: &Device, stream_inspector: &H264StreamInspector) -> Result<Self, Error> {
+    pub fn new(device: &Device, stream_inspector: &impl StreamInspector) -> Result<Self, Error> {
         let shared_device = device.shared();
         let shared_instance = shared_device.instance();
 
@@ -51,32 +61,26 @@ impl VideoSessionShared {
         let native_instance = shared_instance.native();
         let native_entry = shared_instance.native_entry();
 
-        let extension_name = c"VK_STD_vulkan_video_codec_h264_decode";
+        let profiles = device_profiles(&shared_device, stream_inspector)?;
+        let requirements = stream_inspector.session_requirements()?;
+        let codec = profiles.info.video_codec_operation;
+
+        let extension_name = match codec {
+            VideoCodecOperationFlagsKHR::DECODE_H265 => c"VK_STD_vulkan_video_codec_h265_decode",
+            _ => c"VK_STD_vulkan_video_codec_h264_decode",
+        };
         let extension_version = vk::make_api_version(0, 1, 0, 0);
 
         let extensions_names = ExtensionProperties::default()
             .spec_version(extension_version)
             .extension_name(extension_name)?;
 
-        let profiles = stream_inspector.profiles();
-
         let queue_family_index = shared_device
             .physical_device()
             .queue_family_infos()
             .any_decode()
             .ok_or_else(|| error!(Variant::QueueNotFound))?;
 
-        let video_session_create_info = VideoSessionCreateInfoKHR::default()
-            .queue_family_index(queue_family_index)
-            .flags(VideoSessionCreateFlagsKHR::empty())
-            .video_profile(&profiles.info)
-            .picture_format(Format::G8_B8R8_2PLANE_420_UNORM)
-            .max_coded_extent(Extent2D { width: 512, height: 512 })
-            .reference_picture_format(Format::G8_B8R8_2PLANE_420_UNORM)
-            .max_dpb_slots(17)
-            .max_active_reference_pictures(16)
-            .std_header_version(&extensions_names);
-
         let result = unsafe {
             let queue_fns = KhrVideoQueueDeviceFn::load(
                 |x| {
@@ -106,55 +110,135 @@ impl VideoSessionShared {
             let bind_video_session_memory = queue_fns.bind_video_session_memory_khr;
             let memory_requirements = queue_fns.get_video_session_memory_requirements_khr;
 
-            let mut video_decode_h264_profile =
-                VideoDecodeH264ProfileInfoKHR::default().std_profile_idc(StdVideoH264ProfileIdc_STD_VIDEO_H264_PROFILE_IDC_BASELINE);
-
-            let video_profile = VideoProfileInfoKHR::default()
-                .push_next(&mut video_decode_h264_profile)
-                .video_codec_operation(VideoCodecOperationFlagsKHR::DECODE_H264)
-                .chroma_subsampling(VideoChromaSubsamplingFlagsKHR::TYPE_420)
-                .chroma_bit_depth(VideoComponentBitDepthFlagsKHR::TYPE_8)
-                .luma_bit_depth(VideoComponentBitDepthFlagsKHR::TYPE_8);
-
             let mut video_decode_h264_capabilities = VideoDecodeH264CapabilitiesKHR::default();
+            let mut video_decode_h265_capabilities = VideoDecodeH265CapabilitiesKHR::default();
 
             let mut video_decode_capabilities = VideoDecodeCapabilitiesKHR::default();
 
             // Does this order matter?  It seems to work without relevant validation failures either way.
-            let mut video_capabilities = VideoCapabilitiesKHR::default()
-                .push_next(&mut video_decode_capabilities)
-                .push_next(&mut video_decode_h264_capabilities);
+            let video_capabilities = VideoCapabilitiesKHR::default().push_next(&mut video_decode_capabilities);
 
-            (get_physical_device_video_capabilities)(shared_device.physical_device().native(), &video_profile, &mut video_capabilities)
+            // The codec specific capabilities must match the profile's codec.
+            let mut video_capabilities = match codec {
+                VideoCodecOperationFlagsKHR::DECODE_H265 => video_capabilities.push_next(&mut video_decode_h265_capabilities),
+                _ => video_capabilities.push_next(&mut video_decode_h264_capabilities),
+            };
+
+            (get_physical_device_video_capabilities)(shared_device.physical_device().native(), &profiles.info, &mut video_capabilities)
                 .result()?;
 
-            let array = &[video_profile];
+            // Size everything for the stream, but give the device's minimum if the stream is smaller.
+            let max_coded_extent = Extent2D {
+                width: requirements.coded_width.max(video_capabilities.min_coded_extent.width),
+                height: requirements.coded_height.max(video_capabilities.min_coded_extent.height),
+            };
+
+            if max_coded_extent.width > video_capabilities.max_coded_extent.width
+                || max_coded_extent.height > video_capabilities.max_coded_extent.height
+            {
+                return Err(error!(
+                    Variant::UnsupportedFeature,
+                    "Stream needs {}x{} pixels, device decodes at most {}x{}",
+                    max_coded_extent.width,
+                    max_coded_extent.height,
+                    video_capabilities.max_coded_extent.width,
+                    video_capabilities.max_coded_extent.height
+                ));
+            }
 
-            let mut video_profile_list_info = VideoProfileListInfoKHR::default().profiles(array);
+            // Slots beyond the references are nice to have, e.g., for frames waiting for output.
+            if video_capabilities.max_dpb_slots < requirements.min_dpb_slots
+                || video_capabilities.max_active_reference_pictures < requirements.max_active_reference_pictures
+            {
+                return Err(error!(
+                    Variant::UnsupportedFeature,
+                    "Stream needs {} DPB slots and {} references, device has {} and {}",
+                    requirements.min_dpb_slots,
+                    requirements.max_active_reference_pictures,
+                    video_capabilities.max_dpb_slots,
+                    video_capabilities.max_active_reference_pictures
+                ));
+            }
 
-            let video_format_info = PhysicalDeviceVideoFormatInfoKHR::default()
-                .image_usage(ImageUsageFlags::VIDEO_DECODE_DPB_KHR)
-                .push_next(&mut video_profile_list_info);
+            let min_bitstream_buffer_offset_alignment = video_capabilities.min_bitstream_buffer_offset_alignment;
+            let min_bitstream_buffer_size_alignment = video_capabilities.min_bitstream_buffer_size_alignment;
+            let max_dpb_slots = requirements.max_dpb_slots.min(video_capabilities.max_dpb_slots);
+            let max_active_reference_pictures = requirements
+                .max_active_reference_pictures
+                .min(video_capabilities.max_active_reference_pictures);
+
+            // The device lists the formats it decodes the profile into, the first one fitting its bit depth and chroma
+            // subsampling is what we use. Pictures double as references if DPB and output coincide.
+            let dpb_and_output_coincide = video_decode_capabilities
+                .flags
+                .contains(VideoDecodeCapabilityFlagsKHR::DPB_AND_OUTPUT_COINCIDE);
+            let picture_usage = if dpb_and_output_coincide {
+                ImageUsageFlags::VIDEO_DECODE_DST_KHR | ImageUsageFlags::VIDEO_DECODE_DPB_KHR
+            } else {
+                ImageUsageFlags::VIDEO_DECODE_DST_KHR
+            };
+
+            let find_format = |usage: ImageUsageFlags| -> Result<Format, Error> {
+                let mut video_profile_list_info = VideoProfileListInfoKHR::default().profiles(std::slice::from_ref(&profiles.info));
+
+                let video_format_info = PhysicalDeviceVideoFormatInfoKHR::default()
+                    .image_usage(usage)
+                    .push_next(&mut video_profile_list_info);
+
+                let mut num_video_format_properties = 0;
+
+                (get_physical_device_video_format_properties_khr)(
+                    shared_device.physical_device().native(),
+                    &video_format_info,
+                    &mut num_video_format_properties,
+                    null_mut(),
+                )
+                .result()?;
 
-            let mut num_video_format_properties = 0;
+                let mut video_format_properties = vec![VideoFormatPropertiesKHR::default(); num_video_format_properties as usize];
 
-            (get_physical_device_video_format_properties_khr)(
-                shared_device.physical_device().native(),
-                &video_format_info,
-                &mut num_video_format_properties,
-                null_mut(),
-            )
-            .result()?;
-
-            let mut video_format_properties = vec![VideoFormatPropertiesKHR::default(); num_video_format_properties as usize];
+                (get_physical_device_video_format_properties_khr)(
+                    shared_device.physical_device().native(),
+                    &video_format_info,
+                    &mut num_video_format_properties,
+                    video_format_properties.as_mut_ptr(),
+                )
+                .result()?;
 
-            (get_physical_device_video_format_properties_khr)(
-                shared_device.physical_device().native(),
-                &video_format_info,
-                &mut num_video_format_properties,
-                video_format_properties.as_mut_ptr(),
-            )
-            .result()?;
+                let formats = picture_formats(&profiles.info);
+
+                video_format_properties[..num_video_format_properties as usize]
+                    .iter()
+                    .map(|x| x.format)
+                    .find(|x| formats.contains(x))
+                    .ok_or_else(|| {
+                        error!(
+                            Variant::UnsupportedFeature,
+                            "Device has no {:?} format for {:?} pictures with {:?} bit luma",
+                            usage,
+                            profiles.info.chroma_subsampling,
+                            profiles.info.luma_bit_depth
+                        )
+                    })
+            };
+
+            let picture_format = find_format(picture_usage)?;
+            let reference_picture_format = if dpb_and_output_coincide {
+                picture_format
+            } else {
+                find_format(ImageUsageFlags::VIDEO_DECODE_DPB_KHR)?
+            };
+
+            let video_session_create_info = VideoSessionCreateInfoKHR::default()
+                .queue_family_index(queue_family_index)
+                .flags(VideoSessionCreateFlagsKHR::empty())
+                .video_profile(&profiles.info)
+                .picture_format(picture_format)
+                .max_coded_extent(max_coded_extent)
+                .reference_picture_format(reference_picture_format)
+                .max_dpb_slots(max_dpb_slots)
+                .max_active_reference_pictures(max_active_reference_pictures)
+                .std_header_version(&extensions_names);
 
             let mut native_session = VideoSessionKHR::default();
             let mut video_session_count = 0;
@@ -202,6 +286,16 @@ impl VideoSessionShared {
                 native_session,
                 // allocations,
                 decode_capabilities: video_decode_capabilities.into(),
+                max_coded_extent,
+                picture_layout: profiles.info_h264.picture_layout,
+                picture_format,
+                reference_picture_format,
+                // At most 17, see `SessionRequirements`.
+                max_dpb_slots: max_dpb_slots as u8,
+                max_active_reference_pictures,
+                min_bitstream_buffer_offset_alignment,
+                min_bitstream_buffer_size_alignment,
+                requirements,
             })
         };
         result
@@ -230,6 +324,111 @@ impl VideoSessionShared {
     pub(crate) fn decode_capabilities(&self) -> &VideoDecodeCapabilities {
         &self.decode_capabilities
     }
+
+    pub(crate) fn max_coded_extent(&self) -> Extent2D {
+        self.max_coded_extent
+    }
+
+    /// How fields of interlaced H.264 are arranged in a picture, `PROGRESSIVE` for everything else.
+    pub(crate) fn picture_layout(&self) -> VideoDecodeH264PictureLayoutFlagsKHR {
+        self.picture_layout
+    }
+
+    pub(crate) fn picture_format(&self) -> Format {
+        self.picture_format
+    }
+
+    pub(crate) fn reference_picture_format(&self) -> Format {
+        self.reference_picture_format
+    }
+
+    pub(crate) fn max_dpb_slots(&self) -> u8 {
+        self.max_dpb_slots
+    }
+
+    pub(crate) fn min_bitstream_buffer_offset_alignment(&self) -> u64 {
+        self.min_bitstream_buffer_offset_alignment
+    }
+
+    pub(crate) fn min_bitstream_buffer_size_alignment(&self) -> u64 {
+        self.min_bitstream_buffer_size_alignment
+    }
+
+    /// If this session can decode the most recent SPS of `stream_inspector`, e.g., after a new SPS arrived.
+    ///
+    /// Interlaced H.264 needs a different picture layout, so switching between progressive and interlaced does not fit.
+    pub(crate) fn supports(&self, stream_inspector: &impl StreamInspector) -> Result<bool, Error> {
+        let requirements = stream_inspector.session_requirements()?;
+        let progressive = VideoDecodeH264PictureLayoutFlagsKHR::PROGRESSIVE;
+        let interlaced = stream_inspector.profiles()?.info_h264.picture_layout != progressive;
+
+        Ok(self.requirements.same_format(&requirements)
+            && interlaced == (self.picture_layout != progressive)
+            && requirements.min_dpb_slots <= u32::from(self.max_dpb_slots)
+            && requirements.max_active_reference_pictures <= self.max_active_reference_pictures)
+    }
+}
+
+/// Returns the profile `stream_inspector`'s stream is decoded with on `shared_device`.
+///
+/// Interlaced H.264 is decoded into interleaved lines if the device can, otherwise into separate planes, i.e., each
+/// field into its own half of the image. Sessions, images and buffers must all use the same profile, so they should
+/// all get it from here.
+pub(crate) fn device_profiles<'f>(
+    shared_device: &DeviceShared,
+    stream_inspector: &impl StreamInspector,
+) -> Result<Pin<Box<VideoProfileInfoBundle<'f>>>, Error> {
+    let mut profiles = stream_inspector.profiles()?;
+
+    if profiles.info.video_codec_operation != VideoCodecOperationFlagsKHR::DECODE_H264
+        || profiles.info_h264.picture_layout == VideoDecodeH264PictureLayoutFlagsKHR::PROGRESSIVE
+    {
+        return Ok(profiles);
+    }
+
+    let shared_instance = shared_device.instance();
+    let native_instance = shared_instance.native();
+    let native_entry = shared_instance.native_entry();
+
+    unsafe {
+        let video_instance_fn = KhrVideoQueueInstanceFn::load(|x| {
+            native_entry
+                .get_instance_proc_addr(native_instance.handle(), x.as_ptr().cast())
+                .expect("Must have function pointer") as *const _
+        });
+
+        let layouts = [
+            VideoDecodeH264PictureLayoutFlagsKHR::INTERLACED_INTERLEAVED_LINES,
+            VideoDecodeH264PictureLayoutFlagsKHR::INTERLACED_SEPARATE_PLANES,
+        ];
+
+        for layout in layouts {
+            profiles.as_mut().get_unchecked_mut().info_h264.picture_layout = layout;
+
+            let mut video_decode_h264_capabilities = VideoDecodeH264CapabilitiesKHR::default();
+            let mut video_decode_capabilities = VideoDecodeCapabilitiesKHR::default();
+            let mut video_capabilities = VideoCapabilitiesKHR::default()
+                .push_next(&mut video_decode_capabilities)
+                .push_next(&mut video_decode_h264_capabilities);
+
+            let result = (video_instance_fn.get_physical_device_video_capabilities_khr)(
+                shared_device.physical_device().native(),
+                &profiles.info,
+                &mut video_capabilities,
+            );
+
+            match result {
+                vk::Result::SUCCESS => return Ok(profiles),
+                vk::Result::ERROR_VIDEO_PICTURE_LAYOUT_NOT_SUPPORTED_KHR => continue,
+                e => return Err(Error::from(e)),
+            }
+        }
+    }
+
+    Err(error!(
+        Variant::UnsupportedFeature,
+        "Device cannot decode interlaced H.264 into interleaved lines or separate planes"
+    ))
 }
 
 impl Drop for VideoSessionShared {
@@ -249,12 +448,50 @@ pub struct VideoSession {
 }
 
 impl VideoSession {
-    pub fn new(device: &Device, stream_inspector: &H264StreamInspector) -> Result<Self, Error> {
+    pub fn new(device: &Device, stream_inspector: &impl StreamInspector) -> Result<Self, Error> {
         let shared = VideoSessionShared::new(device, stream_inspector)?;
 
         Ok(Self { shared: Arc::new(shared) })
     }
 
+    /// The largest picture this session decodes, derived from the stream's SPS.
+    pub fn max_coded_extent(&self) -> Extent2D {
+        self.shared.max_coded_extent()
+    }
+
+    /// How the fields of interlaced H.264 end up in decoded images, see [`VideoDecodeH264PictureLayoutFlagsKHR`].
+    ///
+    /// Interleaved lines if the device supports it. Otherwise separate planes, where the top field is decoded into the
+    /// upper and the bottom field into the lower half of the image.
+    pub fn picture_layout(&self) -> VideoDecodeH264PictureLayoutFlagsKHR {
+        self.shared.picture_layout()
+    }
+
+    /// The format of images pictures are decoded into, which depends on the profile's bit depth and chroma subsampling.
+    pub fn picture_format(&self) -> Format {
+        self.shared.picture_format()
+    }
+
+    /// The format of DPB images, the same as [`VideoSession::picture_format`] if the device decodes into DPB images directly.
+    pub fn reference_picture_format(&self) -> Format {
+        self.shared.reference_picture_format()
+    }
+
+    /// How many DPB slots this session has, i.e., what [`Dpb::new`](crate::video::h264::Dpb::new) should be given.
+    pub fn max_dpb_slots(&self) -> u8 {
+        self.shared.max_dpb_slots()
+    }
+
+    /// What bitstream offsets given to [`DecodeInfo`](crate::ops::DecodeInfo) must be a multiple of.
+    pub fn min_bitstream_buffer_offset_alignment(&self) -> u64 {
+        self.shared.min_bitstream_buffer_offset_alignment()
+    }
+
+    /// What bitstream ranges are padded to a multiple of when decoding.
+    pub fn min_bitstream_buffer_size_alignment(&self) -> u64 {
+        self.shared.min_bitstream_buffer_size_alignment()
+    }
+
     pub(crate) fn shared(&self) -> Arc<VideoSessionShared> {
         self.shared.clone()
     }
@@ -266,7 +503,7 @@ mod test {
     use crate::error::Error;
     use crate::instance::{Instance, InstanceInfo};
     use crate::physicaldevice::PhysicalDevice;
-    use crate::video::h264::H264StreamInspector;
+    use crate::video::h264::testdata;
     use crate::video::session::VideoSession;
 
     #[test]
@@ -276,7 +513,7 @@ mod test {
         let instance = Instance::new(&instance_info)?;
         let physical_device = PhysicalDevice::new_any(&instance)?;
         let device = Device::new(&physical_device)?;
-        let h264inspector = H264StreamInspector::new();
+        let h264inspector = testdata::inspector();
 
         _ = VideoSession::new(&device, &h264inspector)?;
 
@@ -1,162 +1,233 @@
-use crate::error::Error;
-use crate::video::h264::H264StreamInspector;
+use crate::device::Device;
+use crate::error;
+use crate::error::{Error, Variant};
+use crate::video::inspector::{Fingerprints, StdParameterSets};
 use crate::video::session::{VideoSession, VideoSessionShared};
-use ash::vk::native::{
-    StdVideoH264HrdParameters, StdVideoH264PictureParameterSet, StdVideoH264PpsFlags, StdVideoH264ScalingLists,
-    StdVideoH264SequenceParameterSet, StdVideoH264SequenceParameterSetVui, StdVideoH264SpsFlags, StdVideoH264SpsVuiFlags,
-};
+use crate::video::StreamInspector;
 use ash::vk::{
-    VideoDecodeH264SessionParametersAddInfoKHR, VideoDecodeH264SessionParametersCreateInfoKHR, VideoSessionParametersCreateInfoKHR,
-    VideoSessionParametersKHR, VideoSessionParametersUpdateInfoKHR,
+    VideoDecodeH264SessionParametersAddInfoKHR, VideoDecodeH264SessionParametersCreateInfoKHR, VideoDecodeH265SessionParametersAddInfoKHR,
+    VideoDecodeH265SessionParametersCreateInfoKHR, VideoSessionParametersCreateInfoKHR, VideoSessionParametersKHR,
+    VideoSessionParametersUpdateInfoKHR,
 };
-use std::ptr::{addr_of, addr_of_mut, null};
-use std::sync::Arc;
+use std::ptr::null;
+use std::sync::{Arc, Mutex};
+
+/// What has to happen before pictures using the latest parameter sets can be decoded.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SessionChange {
+    /// The parameters have everything the stream uses.
+    None,
+    /// New VPS, SPS or PPS ids arrived, add them with [`VideoSessionParameters::update`].
+    Update,
+    /// A parameter set the parameters hold has new content. Vulkan cannot replace parameter sets, so create new
+    /// [`VideoSessionParameters`] for the same session.
+    NewParameters,
+    /// Profile or picture size changed, or more references are needed. Create a new [`VideoSession`],
+    /// [`VideoSessionParameters`], DPB images and [`Dpb`](crate::video::h264::Dpb), usually at the next IDR picture.
+    ///
+    /// [`VideoSessionParameters::rebuild`] does all but the last two, which it leaves to a callback.
+    NewSession,
+}
+
+// The parameter sets the native parameters were given, by id and fingerprint.
+#[derive(Default)]
+struct ParameterSets {
+    fingerprints: Fingerprints,
+    update_sequence_count: u32,
+}
 
 pub(crate) struct VideoSessionParametersShared {
     shared_session: Arc<VideoSessionShared>,
     native_parameters: VideoSessionParametersKHR,
+    parameter_sets: Mutex<ParameterSets>,
 }
 
 impl VideoSessionParametersShared {
-    pub fn new(shared_session: Arc<VideoSessionShared>, _stream_inspector: &H264StreamInspector) -> Result<Self, Error> {
+    pub fn new(shared_session: Arc<VideoSessionShared>, stream_inspector: &impl StreamInspector) -> Result<Self, Error> {
         let native_session = shared_session.native();
-        let native_device = shared_session.device().native();
-        let native_queue_fns = shared_session.queue_fns();
-
-        let hrd = StdVideoH264HrdParameters {
-            cpb_cnt_minus1: 0,
-            bit_rate_scale: 0,
-            cpb_size_scale: 0,
-            reserved1: 0,
-            bit_rate_value_minus1: Default::default(),
-            cpb_size_value_minus1: Default::default(),
-            cbr_flag: Default::default(),
-            initial_cpb_removal_delay_length_minus1: 23,
-            cpb_removal_delay_length_minus1: 0,
-            dpb_output_delay_length_minus1: 0,
-            time_offset_length: 0,
-        };
 
-        let mut vui_flags = StdVideoH264SpsVuiFlags {
-            _bitfield_align_1: [],
-            _bitfield_1: Default::default(),
-            __bindgen_padding_0: 0,
+        let native_parameters = match stream_inspector.std_parameter_sets() {
+            StdParameterSets::H264 { sps, pps } => {
+                let sps_array = sps.iter().map(|x| *x.native()).collect::<Vec<_>>();
+                let pps_array = pps.iter().map(|x| *x.native()).collect::<Vec<_>>();
+
+                let add_info = VideoDecodeH264SessionParametersAddInfoKHR::default()
+                    .std_sp_ss(&sps_array)
+                    .std_pp_ss(&pps_array);
+
+                let mut video_decode_h264session_parameters_create_info = VideoDecodeH264SessionParametersCreateInfoKHR::default()
+                    .max_std_sps_count(32)
+                    .max_std_pps_count(256)
+                    .parameters_add_info(&add_info);
+
+                let session_create_info = VideoSessionParametersCreateInfoKHR::default()
+                    .video_session(native_session)
+                    .push_next(&mut video_decode_h264session_parameters_create_info);
+
+                create_native_parameters(&shared_session, &session_create_info)?
+            }
+            StdParameterSets::H265 { vps, sps, pps } => {
+                let vps_array = vps.iter().map(|x| *x.native()).collect::<Vec<_>>();
+                let sps_array = sps.iter().map(|x| *x.native()).collect::<Vec<_>>();
+                let pps_array = pps.iter().map(|x| *x.native()).collect::<Vec<_>>();
+
+                let add_info = VideoDecodeH265SessionParametersAddInfoKHR::default()
+                    .std_vp_ss(&vps_array)
+                    .std_sp_ss(&sps_array)
+                    .std_pp_ss(&pps_array);
+
+                let mut video_decode_h265session_parameters_create_info = VideoDecodeH265SessionParametersCreateInfoKHR::default()
+                    .max_std_vps_count(16)
+                    .max_std_sps_count(16)
+                    .max_std_pps_count(64)
+                    .parameters_add_info(&add_info);
+
+                let session_create_info = VideoSessionParametersCreateInfoKHR::default()
+                    .video_session(native_session)
+                    .push_next(&mut video_decode_h265session_parameters_create_info);
+
+                create_native_parameters(&shared_session, &session_create_info)?
+            }
         };
 
-        vui_flags.set_video_signal_type_present_flag(1);
-        vui_flags.set_color_description_present_flag(1);
-        vui_flags.set_bitstream_restriction_flag(1);
-
-        let vui = StdVideoH264SequenceParameterSetVui {
-            flags: vui_flags,
-            aspect_ratio_idc: 0,
-            sar_width: 1,
-            sar_height: 1,
-            video_format: 2,
-            colour_primaries: 5,
-            transfer_characteristics: 6,
-            matrix_coefficients: 6,
-            num_units_in_tick: 0,
-            time_scale: 0,
-            max_num_reorder_frames: 0,
-            max_dec_frame_buffering: 16,
-            chroma_sample_loc_type_top_field: 0,
-            chroma_sample_loc_type_bottom_field: 0,
-            reserved1: 0,
-            pHrdParameters: &hrd,
-        };
+        Ok(Self {
+            shared_session,
+            native_parameters,
+            parameter_sets: Mutex::new(ParameterSets {
+                fingerprints: stream_inspector.fingerprints(),
+                update_sequence_count: 0,
+            }),
+        })
+    }
 
-        let mut flags = StdVideoH264SpsFlags {
-            _bitfield_align_1: [],
-            _bitfield_1: Default::default(),
-            __bindgen_padding_0: 0,
-        };
+    pub fn change(&self, stream_inspector: &impl StreamInspector) -> Result<SessionChange, Error> {
+        if !self.shared_session.supports(stream_inspector)? {
+            return Ok(SessionChange::NewSession);
+        }
 
-        flags.set_frame_mbs_only_flag(1);
-        flags.set_vui_parameters_present_flag(1);
-        flags.set_direct_8x8_inference_flag(1);
-
-        let sps_info = StdVideoH264SequenceParameterSet {
-            flags,
-            profile_idc: 100,
-            level_idc: 8,
-            chroma_format_idc: 1,
-            seq_parameter_set_id: 0,
-            bit_depth_luma_minus8: 0,
-            bit_depth_chroma_minus8: 0,
-            log2_max_frame_num_minus4: 0,
-            pic_order_cnt_type: 2,
-            offset_for_non_ref_pic: 0,
-            offset_for_top_to_bottom_field: 0,
-            log2_max_pic_order_cnt_lsb_minus4: 0,
-            num_ref_frames_in_pic_order_cnt_cycle: 0,
-            max_num_ref_frames: 0,
-            reserved1: 0,
-            pic_width_in_mbs_minus1: 31,
-            pic_height_in_map_units_minus1: 31,
-            frame_crop_left_offset: 0,
-            frame_crop_right_offset: 0,
-            frame_crop_top_offset: 0,
-            frame_crop_bottom_offset: 0,
-            reserved2: 0,
-            pOffsetForRefFrame: null(),
-            pScalingLists: null(),
-            pSequenceParameterSetVui: &vui,
-        };
+        let parameter_sets = self.parameter_sets.lock().expect("Must not be poisoned");
 
-        let mut pps_flags = StdVideoH264PpsFlags {
-            _bitfield_align_1: Default::default(),
-            _bitfield_1: Default::default(),
-            __bindgen_padding_0: Default::default(),
-        };
+        Ok(parameter_change(&parameter_sets.fingerprints, &stream_inspector.fingerprints()))
+    }
+
+    pub fn update(&self, stream_inspector: &impl StreamInspector) -> Result<(), Error> {
+        let supported = self.shared_session.supports(stream_inspector)?;
+
+        // Held until the update is done, so nobody can add the same parameter sets in between.
+        let mut parameter_sets = self.parameter_sets.lock().expect("Must not be poisoned");
+        let held = &parameter_sets.fingerprints;
 
-        pps_flags.set_transform_8x8_mode_flag(1);
-        pps_flags.set_deblocking_filter_control_present_flag(1);
-        pps_flags.set_entropy_coding_mode_flag(1);
-
-        let pps_info = StdVideoH264PictureParameterSet {
-            flags: pps_flags,
-            seq_parameter_set_id: 0,
-            pic_parameter_set_id: 0,
-            num_ref_idx_l0_default_active_minus1: 0,
-            num_ref_idx_l1_default_active_minus1: 0,
-            weighted_bipred_idc: 0,
-            pic_init_qp_minus26: -6,
-            pic_init_qs_minus26: 0,
-            chroma_qp_index_offset: 0,
-            second_chroma_qp_index_offset: 0,
-            pScalingLists: null(),
+        let change = match supported {
+            true => parameter_change(held, &stream_inspector.fingerprints()),
+            false => SessionChange::NewSession,
         };
 
-        let sps_array = &[sps_info];
-        let pps_array = &[pps_info];
+        match change {
+            SessionChange::None => return Ok(()),
+            SessionChange::Update => {}
+            x => {
+                return Err(error!(
+                    Variant::IncompatibleParameters,
+                    "Parameter sets cannot be added to these parameters, needs {:?}", x
+                ))
+            }
+        }
 
-        let create_info = VideoDecodeH264SessionParametersAddInfoKHR::default()
-            .std_sp_ss(sps_array)
-            .std_pp_ss(pps_array);
+        // Each update has to count up by exactly one.
+        let update_sequence_count = parameter_sets.update_sequence_count + 1;
+
+        // Vulkan only allows adding parameter sets, `parameter_change` made sure the ones we have are still the same.
+        match stream_inspector.std_parameter_sets() {
+            StdParameterSets::H264 { sps, pps } => {
+                let sps_array = sps
+                    .iter()
+                    .map(|x| *x.native())
+                    .filter(|x| !held.sps.contains_key(&x.seq_parameter_set_id))
+                    .collect::<Vec<_>>();
+
+                let pps_array = pps
+                    .iter()
+                    .map(|x| *x.native())
+                    .filter(|x| !held.pps.contains_key(&x.pic_parameter_set_id))
+                    .collect::<Vec<_>>();
+
+                let mut add_info = VideoDecodeH264SessionParametersAddInfoKHR::default()
+                    .std_sp_ss(&sps_array)
+                    .std_pp_ss(&pps_array);
+
+                let update_info = VideoSessionParametersUpdateInfoKHR::default()
+                    .update_sequence_count(update_sequence_count)
+                    .push_next(&mut add_info);
+
+                self.update_native_parameters(&update_info)?;
+            }
+            StdParameterSets::H265 { vps, sps, pps } => {
+                let vps_array = vps
+                    .iter()
+                    .map(|x| *x.native())
+                    .filter(|x| !held.vps.contains_key(&x.vps_video_parameter_set_id))
+                    .collect::<Vec<_>>();
+
+                let sps_array = sps
+                    .iter()
+                    .map(|x| *x.native())
+                    .filter(|x| !held.sps.contains_key(&x.sps_seq_parameter_set_id))
+                    .collect::<Vec<_>>();
+
+                let pps_array = pps
+                    .iter()
+                    .map(|x| *x.native())
+                    .filter(|x| !held.pps.contains_key(&x.pps_pic_parameter_set_id))
+                    .collect::<Vec<_>>();
+
+                let mut add_info = VideoDecodeH265SessionParametersAddInfoKHR::default()
+                    .std_vp_ss(&vps_array)
+                    .std_sp_ss(&sps_array)
+                    .std_pp_ss(&pps_array);
+
+                let update_info = VideoSessionParametersUpdateInfoKHR::default()
+                    .update_sequence_count(update_sequence_count)
+                    .push_next(&mut add_info);
+
+                self.update_native_parameters(&update_info)?;
+            }
+        }
 
-        let mut video_decode_h264session_parameters_create_info = VideoDecodeH264SessionParametersCreateInfoKHR::default()
-            .max_std_sps_count(32)
-            .max_std_pps_count(256)
-            .parameters_add_info(&create_info);
+        parameter_sets.update_sequence_count = update_sequence_count;
+        parameter_sets.fingerprints = stream_inspector.fingerprints();
 
-        let session_create_info = VideoSessionParametersCreateInfoKHR::default()
-            .video_session(native_session)
-            .push_next(&mut video_decode_h264session_parameters_create_info);
+        Ok(())
+    }
+
+    fn update_native_parameters(&self, update_info: &VideoSessionParametersUpdateInfoKHR) -> Result<(), Error> {
+        let native_device = self.shared_session.device().native();
+        let update_video_session_parameters = self.shared_session.queue_fns().update_video_session_parameters_khr;
 
         unsafe {
-            let mut native_parameters = VideoSessionParametersKHR::null();
-            let create_video_session_parameters = native_queue_fns.create_video_session_parameters_khr;
-            // let update_video_session_parameters = native_queue_fns.update_video_session_parameters_khr;
+            update_video_session_parameters(native_device.handle(), self.native_parameters, update_info).result()?;
+        }
 
-            create_video_session_parameters(native_device.handle(), &session_create_info, null(), &mut native_parameters).result()?;
-            // update_video_session_parameters(native_device.handle(), native_parameters, &update).result()?;
+        Ok(())
+    }
 
-            Ok(Self {
-                shared_session,
-                native_parameters,
-            })
-        }
+    /// Fails with [`Variant::MissingParameterSet`] unless the VPS, SPS and PPS with these ids were given to Vulkan,
+    /// with the content they had in `seen`. H.264 has no VPS.
+    pub(crate) fn check_parameter_sets(
+        &self,
+        seen: &Fingerprints,
+        video_parameter_set_id: Option<u8>,
+        seq_parameter_set_id: u8,
+        pic_parameter_set_id: u8,
+    ) -> Result<(), Error> {
+        let parameter_sets = self.parameter_sets.lock().expect("Must not be poisoned");
+
+        check_parameter_sets(
+            &parameter_sets.fingerprints,
+            seen,
+            video_parameter_set_id,
+            seq_parameter_set_id,
+            pic_parameter_set_id,
+        )
     }
 
     pub(crate) fn native(&self) -> VideoSessionParametersKHR {
@@ -168,6 +239,80 @@ impl VideoSessionParametersShared {
     }
 }
 
+/// How parameters holding the `held` parameter sets have to change to also decode pictures using the `seen` ones.
+fn parameter_change(held: &Fingerprints, seen: &Fingerprints) -> SessionChange {
+    let mut change = SessionChange::None;
+
+    for (seen, held) in [(&seen.vps, &held.vps), (&seen.sps, &held.sps), (&seen.pps, &held.pps)] {
+        for (id, fingerprint) in seen {
+            match held.get(id) {
+                Some(x) if x == fingerprint => {}
+                Some(_) => return SessionChange::NewParameters,
+                None => change = SessionChange::Update,
+            }
+        }
+    }
+
+    change
+}
+
+/// Fails with [`Variant::MissingParameterSet`] unless `held` has the VPS, SPS and PPS with these ids, with the
+/// content they have in `seen`, i.e., what the picture was parsed with. H.264 pictures have no VPS.
+pub(crate) fn check_parameter_sets(
+    held: &Fingerprints,
+    seen: &Fingerprints,
+    video_parameter_set_id: Option<u8>,
+    seq_parameter_set_id: u8,
+    pic_parameter_set_id: u8,
+) -> Result<(), Error> {
+    let ids = [
+        ("VPS", &held.vps, &seen.vps, video_parameter_set_id),
+        ("SPS", &held.sps, &seen.sps, Some(seq_parameter_set_id)),
+        ("PPS", &held.pps, &seen.pps, Some(pic_parameter_set_id)),
+    ];
+
+    for (name, held, seen, id) in ids {
+        let Some(id) = id else {
+            continue;
+        };
+
+        match held.get(&id) {
+            None => {
+                return Err(error!(
+                    Variant::MissingParameterSet,
+                    "{} {} not in session parameters, see `VideoSessionParameters::update`", name, id
+                ))
+            }
+            // Vulkan would decode with the stale parameter set.
+            Some(x) if seen.get(&id) != Some(x) => {
+                return Err(error!(
+                    Variant::MissingParameterSet,
+                    "{} {} changed since it was added to the session parameters, see `VideoSessionParameters::change`", name, id
+                ))
+            }
+            Some(_) => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn create_native_parameters(
+    shared_session: &VideoSessionShared,
+    create_info: &VideoSessionParametersCreateInfoKHR,
+) -> Result<VideoSessionParametersKHR, Error> {
+    let native_device = shared_session.device().native();
+    let create_video_session_parameters = shared_session.queue_fns().create_video_session_parameters_khr;
+
+    unsafe {
+        let mut native_parameters = VideoSessionParametersKHR::null();
+
+        create_video_session_parameters(native_device.handle(), create_info, null(), &mut native_parameters).result()?;
+
+        Ok(native_parameters)
+    }
+}
+
 impl Drop for VideoSessionParametersShared {
     fn drop(&mut self) {
         let queue_fns = self.shared_session.queue_fns();
@@ -187,12 +332,53 @@ pub struct VideoSessionParameters {
 }
 
 impl VideoSessionParameters {
-    pub fn new(session: &VideoSession, stream_inspector: &H264StreamInspector) -> Result<Self, Error> {
+    pub fn new(session: &VideoSession, stream_inspector: &impl StreamInspector) -> Result<Self, Error> {
         let shared = VideoSessionParametersShared::new(session.shared(), stream_inspector)?;
 
         Ok(Self { shared: Arc::new(shared) })
     }
 
+    /// Checks if these parameters, and their session, can decode pictures using the parameter sets seen so far.
+    ///
+    /// Call this whenever the inspector's `feed_nal` returned a parameter set, as streams may change resolution
+    /// or add parameter sets at any time.
+    pub fn change(&self, stream_inspector: &impl StreamInspector) -> Result<SessionChange, Error> {
+        self.shared.change(stream_inspector)
+    }
+
+    /// Adds all new parameter sets, fails with [`Variant::IncompatibleParameters`] unless [`Self::change`] allows it.
+    pub fn update(&self, stream_inspector: &impl StreamInspector) -> Result<(), Error> {
+        self.shared.update(stream_inspector)
+    }
+
+    /// Brings `session` and these parameters up to date with the stream, doing whatever [`Self::change`] asks for.
+    ///
+    /// Adds new parameter sets, or re-creates the parameters, or re-creates both. After re-creating the session,
+    /// `new_session` is called to re-create everything made for the old one, i.e., DPB images and the `Dpb`. Returns
+    /// what was done.
+    pub fn rebuild(
+        &mut self,
+        device: &Device,
+        session: &mut VideoSession,
+        stream_inspector: &impl StreamInspector,
+        new_session: impl FnOnce(&VideoSession) -> Result<(), Error>,
+    ) -> Result<SessionChange, Error> {
+        let change = self.change(stream_inspector)?;
+
+        match change {
+            SessionChange::None => {}
+            SessionChange::Update => self.update(stream_inspector)?,
+            SessionChange::NewParameters => *self = Self::new(session, stream_inspector)?,
+            SessionChange::NewSession => {
+                *session = VideoSession::new(device, stream_inspector)?;
+                *self = Self::new(session, stream_inspector)?;
+                new_session(session)?;
+            }
+        }
+
+        Ok(change)
+    }
+
     pub(crate) fn shared(&self) -> Arc<VideoSessionParametersShared> {
         self.shared.clone()
     }
@@ -201,12 +387,16 @@ impl VideoSessionParameters {
 #[cfg(test)]
 mod test {
     use crate::device::Device;
-    use crate::error::Error;
+    use crate::error::{Error, Variant};
     use crate::instance::{Instance, InstanceInfo};
     use crate::physicaldevice::PhysicalDevice;
-    use crate::video::h264::H264StreamInspector;
+    use crate::video::h264::testdata;
+    use crate::video::h264::testdata::nal;
+    use crate::video::inspector::private::Inspector;
+    use crate::video::inspector::Fingerprints;
     use crate::video::session::VideoSession;
-    use crate::video::sessionparameters::VideoSessionParameters;
+    use crate::video::sessionparameters::{check_parameter_sets, parameter_change, SessionChange, VideoSessionParameters};
+    use std::collections::HashMap;
 
     #[test]
     #[cfg(not(miri))]
@@ -215,11 +405,89 @@ mod test {
         let instance = Instance::new(&instance_info)?;
         let physical_device = PhysicalDevice::new_any(&instance)?;
         let device = Device::new(&physical_device)?;
-        let h264inspector = H264StreamInspector::new();
+        let h264inspector = testdata::inspector();
         let session = VideoSession::new(&device, &h264inspector)?;
 
         _ = VideoSessionParameters::new(&session, &h264inspector)?;
 
         Ok(())
     }
+
+    #[test]
+    fn compares_fingerprints() {
+        let held = Fingerprints {
+            sps: HashMap::from([(0, 1)]),
+            pps: HashMap::from([(0, 2)]),
+            ..Fingerprints::default()
+        };
+        let added = Fingerprints {
+            pps: HashMap::from([(0, 2), (1, 3)]),
+            ..held.clone()
+        };
+        let changed = Fingerprints {
+            pps: HashMap::from([(0, 4), (1, 3)]),
+            ..held.clone()
+        };
+
+        assert_eq!(parameter_change(&held, &held), SessionChange::None);
+        assert_eq!(parameter_change(&held, &added), SessionChange::Update);
+        assert_eq!(parameter_change(&held, &changed), SessionChange::NewParameters);
+
+        // Pictures are only decoded with parameter sets that have the content they were parsed with.
+        let missing = check_parameter_sets(&held, &added, None, 0, 1).unwrap_err();
+        let stale = check_parameter_sets(&held, &changed, None, 0, 0).unwrap_err();
+
+        assert!(check_parameter_sets(&held, &added, None, 0, 0).is_ok());
+        assert!(matches!(missing.variant(), Variant::MissingParameterSet));
+        assert!(matches!(stale.variant(), Variant::MissingParameterSet));
+    }
+
+    #[test]
+    #[cfg(not(miri))]
+    fn update_session_parameters() -> Result<(), Error> {
+        let instance_info = InstanceInfo::new().app_name("MyApp")?.app_version(100).validation(true);
+        let instance = Instance::new(&instance_info)?;
+        let physical_device = PhysicalDevice::new_any(&instance)?;
+        let device = Device::new(&physical_device)?;
+        let mut h264inspector = testdata::inspector();
+        let session = VideoSession::new(&device, &h264inspector)?;
+        let parameters = VideoSessionParameters::new(&session, &h264inspector)?;
+
+        assert_eq!(parameters.change(&h264inspector)?, SessionChange::None);
+
+        // A PPS with the new id 1 can be added, new content for id 0 cannot.
+        h264inspector.feed_nal(&nal(0x68, "010 1 1 0 1 1 1 0 00 1 1 1 1 0 0"))?;
+        assert_eq!(parameters.change(&h264inspector)?, SessionChange::Update);
+
+        assert!(parameters
+            .shared()
+            .check_parameter_sets(&h264inspector.fingerprints(), None, 0, 1)
+            .is_err());
+
+        parameters.update(&h264inspector)?;
+        assert_eq!(parameters.change(&h264inspector)?, SessionChange::None);
+        parameters
+            .shared()
+            .check_parameter_sets(&h264inspector.fingerprints(), None, 0, 1)?;
+
+        h264inspector.feed_nal(&nal(0x68, "1 1 1 0 1 1 1 0 00 1 1 1 1 0 0"))?;
+        assert_eq!(parameters.change(&h264inspector)?, SessionChange::NewParameters);
+
+        // A different profile and picture size need a new session.
+        h264inspector.feed_nal(&nal(0x67, "01000010 00000000 00011110 1 1 1 1 010 0 00100 00100 1 1 0 0"))?;
+        assert_eq!(parameters.change(&h264inspector)?, SessionChange::NewSession);
+
+        let mut session = session;
+        let mut parameters = parameters;
+        let mut new_sessions = 0;
+        let change = parameters.rebuild(&device, &mut session, &h264inspector, |_| {
+            new_sessions += 1;
+            Ok(())
+        })?;
+
+        assert_eq!((change, new_sessions), (SessionChange::NewSession, 1));
+        assert_eq!(parameters.change(&h264inspector)?, SessionChange::None);
+
+        Ok(())
+    }
 }
@@ -1,25 +1,26 @@
+use crate::error::Error;
+use std::collections::VecDeque;
+use std::io::{ErrorKind, Read};
+
 // How many `0` we have to observe before a `1` means NAL.
 const NAL_MIN_0_COUNT: usize = 2;
 
-/// Given a stream, finds the index of the nth NAL start.
+/// Given a stream, finds the index of the first NAL start.
+///
+/// Instead of inspecting every byte we let `memchr` look for the `1` of a start code and only check
+/// the preceding bytes of each candidate, so each byte is searched once.
 #[inline]
-fn nth_nal_index(stream: &[u8], nth: usize) -> Option<usize> {
-    let mut count_0 = 0;
-    let mut n = 0;
-
-    for (i, byte) in stream.iter().enumerate() {
-        match byte {
-            0 => count_0 += 1,
-            1 if count_0 >= NAL_MIN_0_COUNT => {
-                if n == nth {
-                    return Some(i - NAL_MIN_0_COUNT);
-                } else {
-                    count_0 = 0;
-                    n += 1;
-                }
-            }
-            _ => count_0 = 0,
+fn find_start_code(stream: &[u8]) -> Option<usize> {
+    let mut from = NAL_MIN_0_COUNT;
+
+    while let Some(i) = memchr::memchr(1, stream.get(from..)?) {
+        let i = from + i;
+
+        if stream[i - 2] == 0 && stream[i - 1] == 0 {
+            return Some(i - NAL_MIN_0_COUNT);
         }
+
+        from = i + 1;
     }
 
     None
@@ -46,30 +47,172 @@ fn nth_nal_index(stream: &[u8], nth: usize) -> Option<usize> {
 /// NAL units in the middle are split at their boundaries, the last packet is returned
 /// as-is.
 ///
-pub fn nal_units(mut stream: &[u8]) -> impl Iterator<Item = &[u8]> {
+pub fn nal_units(stream: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let mut next = find_start_code(stream);
+
     std::iter::from_fn(move || {
-        let first = nth_nal_index(stream, 0);
-        let next = nth_nal_index(stream, 1);
-
-        match (first, next) {
-            (Some(f), Some(n)) => {
-                let rval = &stream[f..n];
-                stream = &stream[n..];
-                Some(rval)
+        let start = next?;
+        let payload = start + START_CODE.len();
+
+        next = find_start_code(&stream[payload..]).map(|x| x + payload);
+
+        Some(&stream[start..next.unwrap_or(stream.len())])
+    })
+}
+
+/// The start code prefix of a NAL unit in a byte stream (B.1).
+pub(crate) const START_CODE: [u8; 3] = [0, 0, 1];
+
+/// Removes a leading Annex B start code and any trailing zero bytes, leaving only the NAL unit itself.
+pub(crate) fn strip_annexb(nal: &[u8]) -> &[u8] {
+    let zeros = nal.iter().take_while(|x| **x == 0).count();
+
+    let nal = match nal.get(zeros) {
+        Some(1) if zeros >= 2 => &nal[zeros + 1..],
+        _ => nal,
+    };
+
+    let len = nal.iter().rposition(|x| *x != 0).map_or(0, |x| x + 1);
+
+    &nal[..len]
+}
+
+// How much [`NalReader`] reads at once.
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Splits a bitstream arriving in chunks of any size into NAL units.
+///
+/// Unlike [`nal_units`] this does not need the whole stream in memory, so it can be used on large files or
+/// network packets. Start codes may be split across chunks. Any `trailing_zero_8bits`, including the leading
+/// zero of a 4 byte start code, are removed, so each NAL unit is returned as `001` followed by its payload.
+#[derive(Clone, Debug, Default)]
+pub struct NalSplitter {
+    buffer: Vec<u8>,
+    // If `buffer` holds the payload of a NAL unit, i.e., we have seen a start code.
+    in_nal: bool,
+    // How much of `buffer` was already searched for start codes.
+    scanned: usize,
+}
+
+impl NalSplitter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends the next `chunk` of the stream, returns all NAL units completed by it.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<Vec<u8>> {
+        let mut nal_units = Vec::new();
+        let mut start = 0;
+
+        self.buffer.extend_from_slice(chunk);
+
+        while let Some(i) = find_start_code(&self.buffer[self.scanned..]).map(|x| x + self.scanned) {
+            if self.in_nal {
+                nal_units.extend(complete_nal(&self.buffer[start..i]));
+            }
+
+            self.in_nal = true;
+            start = i + START_CODE.len();
+            self.scanned = start;
+        }
+
+        // The last bytes might be the beginning of a start code, so they are searched again with the next chunk.
+        self.scanned = self.scanned.max(self.buffer.len().saturating_sub(START_CODE.len() - 1));
+
+        // Anything before the first start code is not part of a NAL unit.
+        if !self.in_nal {
+            start = self.scanned;
+        }
+
+        self.buffer.drain(..start);
+        self.scanned -= start;
+
+        nal_units
+    }
+
+    /// Returns the last NAL unit at the end of the stream and resets the splitter.
+    pub fn finish(&mut self) -> Option<Vec<u8>> {
+        let last = if self.in_nal { complete_nal(&self.buffer) } else { None };
+
+        *self = Self::default();
+
+        last
+    }
+}
+
+/// Reads NAL units from a bitstream, e.g., a file or socket.
+///
+/// This is an iterator over a [`NalSplitter`] fed from `reader`, returning NAL units the same way.
+pub struct NalReader<R> {
+    reader: R,
+    splitter: NalSplitter,
+    nal_units: VecDeque<Vec<u8>>,
+    chunk: Vec<u8>,
+    done: bool,
+}
+
+impl<R: Read> NalReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            splitter: NalSplitter::new(),
+            nal_units: VecDeque::new(),
+            chunk: vec![0; READ_CHUNK_SIZE],
+            done: false,
+        }
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read> Iterator for NalReader<R> {
+    type Item = Result<Vec<u8>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(nal) = self.nal_units.pop_front() {
+                return Some(Ok(nal));
+            }
+
+            if self.done {
+                return None;
             }
-            (Some(f), None) => {
-                let rval = &stream[f..];
-                stream = &stream[f + NAL_MIN_0_COUNT..];
-                Some(rval)
+
+            match self.reader.read(&mut self.chunk) {
+                Ok(0) => {
+                    self.done = true;
+                    self.nal_units.extend(self.splitter.finish());
+                }
+                Ok(n) => self.nal_units.extend(self.splitter.push(&self.chunk[..n])),
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e.into()));
+                }
             }
-            _ => None,
         }
-    })
+    }
+}
+
+/// Turns the bytes between two start codes into a NAL unit, if there is one.
+fn complete_nal(payload: &[u8]) -> Option<Vec<u8>> {
+    let len = payload.iter().rposition(|x| *x != 0)? + 1;
+    let mut nal = Vec::with_capacity(START_CODE.len() + len);
+
+    nal.extend_from_slice(&START_CODE);
+    nal.extend_from_slice(&payload[..len]);
+
+    Some(nal)
 }
 
 #[cfg(test)]
 mod test {
-    use super::nal_units;
+    use super::{nal_units, strip_annexb, NalReader, NalSplitter};
+    use crate::error::{Error, Variant};
+    use std::io::{Cursor, Read};
 
     #[test]
     fn splits_at_nal() {
@@ -108,5 +251,68 @@ mod test {
         assert_eq!(split.next().unwrap(), &[0, 0, 1, 2, 3]);
         assert_eq!(split.next().unwrap(), &[0, 0, 1]);
         assert!(split.next().is_none());
+
+        let stream = [1, 0, 1, 0, 0, 1, 5, 1, 0, 1, 1, 0, 0, 1, 1];
+        let mut split = nal_units(&stream);
+        assert_eq!(split.next().unwrap(), &[0, 0, 1, 5, 1, 0, 1, 1]);
+        assert_eq!(split.next().unwrap(), &[0, 0, 1, 1]);
+        assert!(split.next().is_none());
+    }
+
+    // Has 3 and 4 byte start codes, trailing zeros, and leading garbage.
+    const STREAM: [u8; 22] = [7, 0, 0, 0, 1, 0x67, 1, 0, 0, 1, 0x68, 0, 0, 0, 0, 0, 1, 0x65, 0, 3, 1, 0];
+
+    fn expected() -> Vec<Vec<u8>> {
+        vec![vec![0, 0, 1, 0x67, 1], vec![0, 0, 1, 0x68], vec![0, 0, 1, 0x65, 0, 3, 1]]
+    }
+
+    #[test]
+    fn splits_chunks_at_nal() {
+        for chunk_size in 1..=STREAM.len() {
+            let mut splitter = NalSplitter::new();
+            let mut nals = Vec::new();
+
+            for chunk in STREAM.chunks(chunk_size) {
+                nals.extend(splitter.push(chunk));
+            }
+
+            nals.extend(splitter.finish());
+
+            assert_eq!(nals, expected(), "chunk size {}", chunk_size);
+        }
+
+        let mut splitter = NalSplitter::new();
+        assert!(splitter.push(&[1, 2, 0, 0]).is_empty());
+        assert!(splitter.push(&[0]).is_empty());
+        assert!(splitter.finish().is_none());
+    }
+
+    #[test]
+    fn reads_nal_units() -> Result<(), Error> {
+        let nals = NalReader::new(Cursor::new(STREAM)).collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(nals, expected());
+
+        struct Broken;
+
+        impl Read for Broken {
+            fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::ErrorKind::UnexpectedEof.into())
+            }
+        }
+
+        let mut reader = NalReader::new(Broken);
+        assert!(matches!(reader.next(), Some(Err(e)) if matches!(e.variant(), Variant::Io(_))));
+        assert!(reader.next().is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn strips_annexb() {
+        assert_eq!(strip_annexb(&[0, 0, 1, 0x65, 0x88, 0, 0]), &[0x65, 0x88]);
+        assert_eq!(strip_annexb(&[0, 0, 0, 1, 0x09, 0xF0]), &[0x09, 0xF0]);
+        assert_eq!(strip_annexb(&[0x67, 0x42, 0]), &[0x67, 0x42]);
+        assert_eq!(strip_annexb(&[0, 1, 0x67]), &[0, 1, 0x67]);
+        assert!(strip_annexb(&[0, 0, 1]).is_empty());
     }
 }